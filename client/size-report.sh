@@ -0,0 +1,12 @@
+#!/bin/sh
+# Build the client as it ships and show what its size is made of
+set -e
+cd "$(dirname "$0")"
+wasm-pack build --target web --profile web
+wasm=pkg/client_bg.wasm
+echo "$wasm: $(wc -c < "$wasm") bytes, $(gzip -9 < "$wasm" | wc -c) gzipped"
+if command -v twiggy > /dev/null; then
+    twiggy top -n 25 "$wasm"
+else
+    echo "Install twiggy (cargo install twiggy) for the biggest functions"
+fi
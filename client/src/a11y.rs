@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Document, Element};
+
+use crate::i18n::{fill, strings};
+
+// Announcements kept in the live region; screen readers only read new ones
+const MAX_ANNOUNCEMENTS: u32 = 5;
+// Shortest time between two score announcements for the same player, in
+// milliseconds, so point rush zones and food don't flood the screen reader
+const SCORE_ANNOUNCE_MS: f64 = 5000.0;
+
+// A visually hidden aria-live region that screen readers read game events from
+pub struct LiveRegion {
+    document: Document,
+    region: Element,
+    // When each player's score was last announced
+    scores_announced: HashMap<String, f64>,
+}
+
+impl LiveRegion {
+    pub fn new(document: &Document, parent: &Element) -> Result<LiveRegion, JsValue> {
+        let region = document.create_element("div")?;
+        region.set_class_name("game-sr-only");
+        region.set_attribute("role", "log")?;
+        region.set_attribute("aria-live", "polite")?;
+        parent.append_child(&region)?;
+        Ok(LiveRegion {
+            document: document.clone(),
+            region,
+            scores_announced: HashMap::new(),
+        })
+    }
+
+    pub fn announce(&self, text: &str) {
+        let Ok(line) = self.document.create_element("p") else {
+            return;
+        };
+        line.set_text_content(Some(text));
+        let _ = self.region.append_child(&line);
+        while self.region.child_element_count() > MAX_ANNOUNCEMENTS {
+            if let Some(first) = self.region.first_element_child() {
+                first.remove();
+            }
+        }
+    }
+
+    // Announce a player's new score, unless theirs was announced a moment ago
+    pub fn announce_score(&mut self, player_id: &str, name: &str, score: u32, now: f64) {
+        if let Some(last) = self.scores_announced.get(player_id) {
+            if now - last < SCORE_ANNOUNCE_MS {
+                return;
+            }
+        }
+        self.scores_announced.insert(player_id.to_string(), now);
+        self.announce(&fill(strings().player_scored, &[&name, &score]));
+    }
+
+    pub fn clear(&mut self) {
+        self.region.set_text_content(None);
+        self.scores_announced.clear();
+    }
+}
+
+// Whether the player asked their system for less animation
+pub fn prefers_reduced_motion() -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media("(prefers-reduced-motion: reduce)").ok()?)
+        .is_some_and(|query| query.matches())
+}
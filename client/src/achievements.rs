@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use web_sys::CanvasRenderingContext2d;
+
+use crate::i18n::strings;
+use crate::theme;
+
+// How long each badge stays up, in milliseconds; badges earned together take turns
+const BADGE_MS: f64 = 4000.0;
+
+// Must match the server definition
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstPickup,
+    Traveler,
+    RoundWinner,
+}
+
+impl Achievement {
+    pub fn name(self) -> &'static str {
+        match self {
+            Achievement::FirstPickup => strings().achievement_first_pickup,
+            Achievement::Traveler => strings().achievement_traveler,
+            Achievement::RoundWinner => strings().achievement_round_winner,
+        }
+    }
+}
+
+// An achievement one of our players earned, popping up at the top of the canvas
+pub struct Badge {
+    achievement: Achievement,
+    shown_at: f64,
+}
+
+// Queue a badge to show once the ones before it are done
+pub fn push(badges: &mut Vec<Badge>, achievement: Achievement, now: f64) {
+    let shown_at = badges.last().map_or(now, |last| now.max(last.shown_at + BADGE_MS));
+    badges.push(Badge { achievement, shown_at });
+}
+
+pub fn expire(badges: &mut Vec<Badge>, now: f64) {
+    badges.retain(|badge| now - badge.shown_at < BADGE_MS);
+}
+
+pub fn render(context: &CanvasRenderingContext2d, badges: &[Badge], now: f64) {
+    let Some(badge) = badges.iter().find(|badge| badge.shown_at <= now) else {
+        return;
+    };
+    let palette = theme::palette();
+    context.set_fill_style_str(palette.banner_background);
+    context.fill_rect(250.0, 50.0, 300.0, 60.0);
+    context.set_fill_style_str("gold");
+    context.begin_path();
+    let _ = context.arc(280.0, 80.0, 18.0, 0.0, std::f64::consts::TAU);
+    context.fill();
+    context.set_fill_style_str(palette.banner_text);
+    context.set_font("12px Arial");
+    let _ = context.fill_text(strings().achievement_unlocked, 310.0, 72.0);
+    context.set_font("bold 16px Arial");
+    let _ = context.fill_text(badge.achievement.name(), 310.0, 94.0);
+}
@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::{theme, Map, WORLD_HEIGHT, WORLD_WIDTH};
+
+// Widest the cached picture gets, in pixels, however far in the camera zooms
+const MAX_WIDTH: f64 = 4096.0;
+
+// The parts of the map that don't change from frame to frame (the ground and
+// the portals), drawn once into a canvas off the page and copied from there.
+// It's drawn again when the map or theme changes, or when the camera needs it
+// sharper or smaller.
+#[derive(Default)]
+pub struct Background {
+    cached: RefCell<Option<Cached>>,
+}
+
+struct Cached {
+    canvas: HtmlCanvasElement,
+    // Pixels per world unit it was drawn at, and in which colors
+    resolution: f64,
+    ground: &'static str,
+}
+
+impl Background {
+    // Drop the picture, like when a new map came in
+    pub fn invalidate(&self) {
+        *self.cached.borrow_mut() = None;
+    }
+
+    // Draw the world's background on a context already transformed to world
+    // coordinates, sharp at `resolution` pixels per world unit
+    pub fn draw(&self, context: &CanvasRenderingContext2d, map: &Map, resolution: f64) {
+        // Re-drawn in steps, not on every bit of zoom
+        let resolution = ((resolution * 4.0).ceil() / 4.0).min(MAX_WIDTH / WORLD_WIDTH);
+        let ground = theme::palette().background;
+        let mut cached = self.cached.borrow_mut();
+        if cached.as_ref().is_none_or(|cached| cached.resolution != resolution || cached.ground != ground) {
+            *cached = match render(map, resolution) {
+                Ok(canvas) => Some(Cached { canvas, resolution, ground }),
+                Err(err) => {
+                    log_warn!("Failed to cache the map background: {:?}", err);
+                    None
+                }
+            };
+        }
+        match cached.as_ref() {
+            Some(cached) => {
+                let _ = context.draw_image_with_html_canvas_element_and_dw_and_dh(
+                    &cached.canvas,
+                    0.0,
+                    0.0,
+                    WORLD_WIDTH,
+                    WORLD_HEIGHT,
+                );
+            }
+            None => paint(context, map),
+        }
+    }
+}
+
+fn render(map: &Map, resolution: f64) -> Result<HtmlCanvasElement, JsValue> {
+    let document = web_sys::window().and_then(|window| window.document()).ok_or("no document")?;
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.dyn_into()?;
+    canvas.set_width((WORLD_WIDTH * resolution).round() as u32);
+    canvas.set_height((WORLD_HEIGHT * resolution).round() as u32);
+    let context: CanvasRenderingContext2d = canvas.get_context("2d")?.ok_or("no 2d context")?.dyn_into()?;
+    context.scale(resolution, resolution)?;
+    paint(&context, map);
+    Ok(canvas)
+}
+
+fn paint(context: &CanvasRenderingContext2d, map: &Map) {
+    context.set_fill_style_str(theme::palette().background);
+    context.fill_rect(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT);
+    for portal in &map.portals {
+        context.set_fill_style_str("rgba(128, 0, 255, 0.3)");
+        context.fill_rect(portal.x as f64, portal.y as f64, portal.width as f64, portal.height as f64);
+        context.set_stroke_style_str("rebeccapurple");
+        context.stroke_rect(portal.x as f64, portal.y as f64, portal.width as f64, portal.height as f64);
+    }
+}
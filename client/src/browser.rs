@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Document, Element, HtmlButtonElement, MouseEvent, WebSocket};
+
+use crate::i18n::{fill, strings};
+use crate::json;
+use crate::mount::Handles;
+
+// Port of the server's HTTP API, where the listing is fetched from by default
+const DEFAULT_LISTING_PORT: u16 = 8081;
+
+// A joinable world from the listing - must match the server definition
+#[derive(Clone, Deserialize)]
+struct ServerEntry {
+    name: String,
+    url: Option<String>,
+    world: String,
+    players: usize,
+    max_players: usize,
+    map: Option<String>,
+}
+
+// Where Join connects to
+#[derive(Clone)]
+pub struct Target {
+    pub url: String,
+    pub world: Option<String>,
+}
+
+// http://<page host>:8081/api/servers, or https:// for pages served over https
+pub fn default_listing_url(location: &web_sys::Location) -> String {
+    let scheme = location.protocol().unwrap_or_default();
+    let scheme = if scheme == "https:" { "https" } else { "http" };
+    let host = location.hostname().ok().filter(|host| !host.is_empty());
+    format!(
+        "{}://{}:{}/api/servers",
+        scheme,
+        host.as_deref().unwrap_or("127.0.0.1"),
+        DEFAULT_LISTING_PORT
+    )
+}
+
+// Add the server list to `parent`. Picking an entry changes `target`; entries
+// without a URL of their own use `default_url`.
+pub fn build_panel(
+    document: &Document,
+    parent: &Element,
+    listing_url: String,
+    default_url: String,
+    target: Rc<RefCell<Target>>,
+    handles: &Handles,
+) -> Result<(), JsValue> {
+    let panel = document.create_element("details")?;
+    panel.set_attribute("open", "")?;
+    let summary = document.create_element("summary")?;
+    summary.set_text_content(Some(strings().servers));
+    panel.append_child(&summary)?;
+
+    let refresh = document.create_element("button")?.dyn_into::<HtmlButtonElement>()?;
+    refresh.set_text_content(Some(strings().refresh));
+    panel.append_child(&refresh)?;
+    let selected = document.create_element("span")?;
+    panel.append_child(&selected)?;
+    let table = document.create_element("table")?;
+    panel.append_child(&table)?;
+    parent.append_child(&panel)?;
+
+    let panel = Panel {
+        document: document.clone(),
+        table,
+        selected,
+        listing_url,
+        default_url,
+        target,
+    };
+    panel.show_selected();
+    panel.refresh();
+
+    // One listener for the Select buttons of every row, which come and go with each refresh
+    let picked = panel.clone();
+    let on_pick = Closure::wrap(Box::new(move |e: MouseEvent| {
+        let button = e
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|target| target.closest("button").ok().flatten());
+        if let Some(button) = button {
+            if let (Some(url), Some(world)) = (button.get_attribute("data-url"), button.get_attribute("data-world")) {
+                *picked.target.borrow_mut() = Target { url, world: Some(world) };
+                picked.show_selected();
+            }
+        }
+    }) as Box<dyn FnMut(MouseEvent)>);
+    handles.listen(&panel.table, "click", on_pick)?;
+
+    let on_refresh = Closure::wrap(Box::new(move || panel.refresh()) as Box<dyn FnMut()>);
+    refresh.set_onclick(Some(on_refresh.as_ref().unchecked_ref()));
+    handles.keep(on_refresh);
+    Ok(())
+}
+
+#[derive(Clone)]
+struct Panel {
+    document: Document,
+    table: Element,
+    selected: Element,
+    listing_url: String,
+    default_url: String,
+    target: Rc<RefCell<Target>>,
+}
+
+impl Panel {
+    fn show_selected(&self) {
+        let target = self.target.borrow();
+        let world = target.world.as_deref().unwrap_or(strings().default_world);
+        self.selected
+            .set_text_content(Some(&fill(strings().join_target, &[&world, &target.url])));
+    }
+
+    // Fetch the listing again and rebuild the table
+    fn refresh(&self) {
+        let panel = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            panel.table.set_inner_html("<tr><td>Loading...</td></tr>");
+            match fetch_entries(&panel.listing_url).await {
+                Ok(entries) => {
+                    if let Err(e) = panel.show(entries) {
+                        log_error!("Error showing servers: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log_warn!("Couldn't fetch servers from {}: {:?}", panel.listing_url, e);
+                    panel.table.set_inner_html("<tr><td>No server list available</td></tr>");
+                }
+            }
+        });
+    }
+
+    fn show(&self, entries: Vec<ServerEntry>) -> Result<(), JsValue> {
+        self.table.set_inner_html("");
+        let header = self.document.create_element("tr")?;
+        for column in strings().server_columns.into_iter().chain([""]) {
+            let cell = self.document.create_element("th")?;
+            cell.set_text_content(Some(column));
+            header.append_child(&cell)?;
+        }
+        self.table.append_child(&header)?;
+        if entries.is_empty() {
+            let row = self.document.create_element("tr")?;
+            let cell = self.document.create_element("td")?;
+            cell.set_text_content(Some(strings().no_servers));
+            row.append_child(&cell)?;
+            self.table.append_child(&row)?;
+        }
+        for entry in entries {
+            let url = entry.url.clone().unwrap_or_else(|| self.default_url.clone());
+            let row = self.document.create_element("tr")?;
+            let players = format!("{}/{}", entry.players, entry.max_players);
+            for text in [&entry.name, &entry.world, entry.map.as_deref().unwrap_or("-"), &players] {
+                let cell = self.document.create_element("td")?;
+                cell.set_text_content(Some(text));
+                row.append_child(&cell)?;
+            }
+            let ping = self.document.create_element("td")?;
+            ping.set_text_content(Some("..."));
+            row.append_child(&ping)?;
+            measure_ping(&url, ping);
+
+            let pick = self.document.create_element("button")?;
+            pick.set_text_content(Some(strings().select));
+            pick.set_attribute("data-url", &url)?;
+            pick.set_attribute("data-world", &entry.world)?;
+            let cell = self.document.create_element("td")?;
+            cell.append_child(&pick)?;
+            row.append_child(&cell)?;
+            self.table.append_child(&row)?;
+        }
+        Ok(())
+    }
+}
+
+async fn fetch_entries(url: &str) -> Result<Vec<ServerEntry>, JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url)).await?.dyn_into()?;
+    if !response.ok() {
+        return Err(JsValue::from_str(&format!("HTTP {}", response.status())));
+    }
+    let text = JsFuture::from(response.text()?).await?;
+    json::parse(&text.as_string().unwrap_or_default())
+}
+
+// Time how long opening a connection takes and show it in `cell`. The socket is
+// closed right away, without joining.
+fn measure_ping(url: &str, cell: Element) {
+    let ws = match WebSocket::new(url) {
+        Ok(ws) => ws,
+        Err(_) => {
+            cell.set_text_content(Some("-"));
+            return;
+        }
+    };
+    let started = js_sys::Date::now();
+    let socket = ws.clone();
+    let open_cell = cell.clone();
+    let onopen = Closure::once_into_js(move || {
+        open_cell.set_text_content(Some(&format!("{:.0} ms", js_sys::Date::now() - started)));
+        let _ = socket.close_with_code_and_reason(1000, "Ping");
+    });
+    ws.set_onopen(Some(onopen.unchecked_ref()));
+    let onerror = Closure::once_into_js(move || cell.set_text_content(Some(strings().offline)));
+    ws.set_onerror(Some(onerror.unchecked_ref()));
+}
@@ -0,0 +1,235 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, TouchEvent, WheelEvent};
+
+use crate::mount::Handles;
+use crate::{WORLD_HEIGHT, WORLD_WIDTH};
+
+// How far the view can zoom out and in
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 3.0;
+// Zoom the view goes to at least when it starts following another player
+pub const FOLLOW_ZOOM: f64 = 2.0;
+// Zoom change per pixel of wheel scrolling
+const WHEEL_ZOOM_SPEED: f64 = 0.001;
+// How fast the view catches up with what it follows unless the page sets its
+// own: the share of the distance left that's covered per second is 1 - e^-easing
+pub const DEFAULT_EASING: f64 = 8.0;
+// Jumps further than this, like through a portal or around a wrapping edge, are cut to
+const SNAP_DISTANCE: f64 = 200.0;
+// How long a shake takes to die down
+const SHAKE_MS: f64 = 300.0;
+
+// What part of the world the canvas shows
+pub struct Camera {
+    pub zoom: f64,
+    // Canvas pixels per world unit at zoom 1: the CSS size of the canvas (bigger
+    // in fullscreen) times devicePixelRatio, so it stays sharp on high-DPI screens
+    scale: f64,
+    // 0 to stay right on the focus; see DEFAULT_EASING
+    easing: f64,
+    // Where the view is centered on, easing toward the focus, and when it last moved
+    position: Option<(f64, f64)>,
+    updated_at: f64,
+    shake: Option<Shake>,
+    // The shake's displacement this frame, in world units
+    offset: (f64, f64),
+}
+
+// A jolt of the view that fades out by `until`
+struct Shake {
+    strength: f64,
+    until: f64,
+}
+
+impl Camera {
+    pub fn new(easing: f64) -> Camera {
+        Camera {
+            zoom: 1.0,
+            scale: 1.0,
+            easing: easing.max(0.0),
+            position: None,
+            updated_at: 0.0,
+            shake: None,
+            offset: (0.0, 0.0),
+        }
+    }
+
+    // Size the canvas backing store for its CSS size and the screen's current
+    // devicePixelRatio, which changes when the window moves to another screen,
+    // the page is zoomed or the canvas goes fullscreen
+    pub fn fit_canvas(&mut self, canvas: &HtmlCanvasElement) {
+        let ratio = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio()).max(1.0);
+        // Fullscreen canvases can be letterboxed, so fit the smaller side
+        let css_scale = match (canvas.client_width(), canvas.client_height()) {
+            (0, _) | (_, 0) => 1.0,
+            (width, height) => (width as f64 / WORLD_WIDTH).min(height as f64 / WORLD_HEIGHT),
+        };
+        let scale = css_scale * ratio;
+        let width = (WORLD_WIDTH * scale).round() as u32;
+        if scale == self.scale && canvas.width() == width {
+            return;
+        }
+        self.scale = scale;
+        canvas.set_width(width);
+        canvas.set_height((WORLD_HEIGHT * scale).round() as u32);
+    }
+
+    // Draw in the unzoomed world's units, for overlays that don't move with the world
+    pub fn reset(&self, context: &CanvasRenderingContext2d) {
+        let _ = context.set_transform(self.scale, 0.0, 0.0, self.scale, 0.0, 0.0);
+    }
+
+    pub fn zoom_by(&mut self, factor: f64) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    // Move toward `focus` for a frame drawn at `now`, and on with any shake
+    pub fn update(&mut self, focus: Option<(f64, f64)>, now: f64) {
+        let elapsed = ((now - self.updated_at) / 1000.0).clamp(0.0, 1.0);
+        self.updated_at = now;
+        self.position = match (self.position, focus) {
+            (Some((x, y)), Some((to_x, to_y))) if self.easing > 0.0 && (to_x - x).hypot(to_y - y) < SNAP_DISTANCE => {
+                let share = 1.0 - (-self.easing * elapsed).exp();
+                Some((x + (to_x - x) * share, y + (to_y - y) * share))
+            }
+            _ => focus,
+        };
+        self.offset = match &self.shake {
+            Some(shake) if now < shake.until => {
+                // Two waves that don't line up, so it doesn't just rock back and forth
+                let amount = shake.strength * (shake.until - now) / SHAKE_MS;
+                (amount * (now * 0.071).sin(), amount * (now * 0.093).cos())
+            }
+            _ => {
+                self.shake = None;
+                (0.0, 0.0)
+            }
+        };
+    }
+
+    // Jolt the view by up to `strength` world units, like when a player gets hit.
+    // A stronger shake takes over from a weaker one.
+    pub fn shake(&mut self, strength: f64, now: f64) {
+        let strength = self.shake.as_ref().map_or(strength, |shake| shake.strength.max(strength));
+        self.shake = Some(Shake { strength, until: now + SHAKE_MS });
+    }
+
+    // Transform world coordinates to the canvas. Zoomed out, the whole world is
+    // centered; zoomed in, the view follows its position without leaving the
+    // world. A shake moves everything drawn after this.
+    pub fn apply(&self, context: &CanvasRenderingContext2d) {
+        let center = (WORLD_WIDTH / 2.0, WORLD_HEIGHT / 2.0);
+        let (x, y) = self.center();
+        let scale = self.zoom * self.scale;
+        let _ = context.set_transform(
+            scale,
+            0.0,
+            0.0,
+            scale,
+            (center.0 - x * self.zoom) * self.scale,
+            (center.1 - y * self.zoom) * self.scale,
+        );
+    }
+
+    // The part of the world on the canvas after `apply`
+    pub fn view(&self) -> View {
+        let (x, y) = self.center();
+        let (width, height) = (WORLD_WIDTH / self.zoom, WORLD_HEIGHT / self.zoom);
+        View { x: x - width / 2.0, y: y - height / 2.0, width, height }
+    }
+
+    // Canvas pixels per world unit after `apply`
+    pub fn resolution(&self) -> f64 {
+        self.zoom * self.scale
+    }
+
+    // The world point in the middle of the canvas, shake included
+    fn center(&self) -> (f64, f64) {
+        let center = (WORLD_WIDTH / 2.0, WORLD_HEIGHT / 2.0);
+        let (x, y) = match self.position {
+            Some((x, y)) if self.zoom > 1.0 => {
+                let (half_width, half_height) = (center.0 / self.zoom, center.1 / self.zoom);
+                (x.clamp(half_width, WORLD_WIDTH - half_width), y.clamp(half_height, WORLD_HEIGHT - half_height))
+            }
+            _ => center,
+        };
+        (x + self.offset.0, y + self.offset.1)
+    }
+}
+
+// A rectangle of the world the camera shows
+#[derive(Clone, Copy)]
+pub struct View {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+// How far outside the view things still get drawn, for outlines, name tags
+// and such that stick out of what they belong to
+const VIEW_MARGIN: f64 = 60.0;
+
+impl View {
+    // Whether any of the rectangle at (x, y) is near enough to the view to draw
+    pub fn shows(&self, x: f64, y: f64, width: f64, height: f64) -> bool {
+        x + width >= self.x - VIEW_MARGIN
+            && y + height >= self.y - VIEW_MARGIN
+            && x <= self.x + self.width + VIEW_MARGIN
+            && y <= self.y + self.height + VIEW_MARGIN
+    }
+
+    // Whether all of it is inside the world, so the world covers the canvas
+    pub fn within_world(&self) -> bool {
+        self.x >= 0.0 && self.y >= 0.0 && self.x + self.width <= WORLD_WIDTH && self.y + self.height <= WORLD_HEIGHT
+    }
+}
+
+// The largest size with the world's aspect ratio that fits into width x height
+pub fn fit_world(width: f64, height: f64) -> (f64, f64) {
+    let scale = (width / WORLD_WIDTH).min(height / WORLD_HEIGHT).max(0.1);
+    ((WORLD_WIDTH * scale).floor(), (WORLD_HEIGHT * scale).floor())
+}
+
+// Zoom with the mouse wheel and by pinching with two fingers
+pub fn attach_controls(canvas: &HtmlCanvasElement, camera: Rc<RefCell<Camera>>, handles: &Handles) -> Result<(), JsValue> {
+    let wheel_camera = camera.clone();
+    let on_wheel = Closure::wrap(Box::new(move |e: WheelEvent| {
+        e.prevent_default();
+        wheel_camera.borrow_mut().zoom_by((-e.delta_y() * WHEEL_ZOOM_SPEED).exp());
+    }) as Box<dyn FnMut(WheelEvent)>);
+    handles.listen(canvas, "wheel", on_wheel)?;
+
+    // Distance between the two fingers at the last touch event of a pinch
+    let pinch: Rc<RefCell<Option<f64>>> = Rc::new(RefCell::new(None));
+    let pinch_start = pinch.clone();
+    let on_touch_start = Closure::wrap(Box::new(move |e: TouchEvent| {
+        *pinch_start.borrow_mut() = finger_distance(&e);
+    }) as Box<dyn FnMut(TouchEvent)>);
+    handles.listen(canvas, "touchstart", on_touch_start)?;
+
+    let on_touch_move = Closure::wrap(Box::new(move |e: TouchEvent| {
+        let Some(distance) = finger_distance(&e) else {
+            return;
+        };
+        e.prevent_default();
+        let mut last = pinch.borrow_mut();
+        if let Some(last) = *last {
+            camera.borrow_mut().zoom_by(distance / last);
+        }
+        *last = Some(distance);
+    }) as Box<dyn FnMut(TouchEvent)>);
+    handles.listen(canvas, "touchmove", on_touch_move)?;
+    Ok(())
+}
+
+// How far apart the first two fingers are, if two are down
+fn finger_distance(e: &TouchEvent) -> Option<f64> {
+    let touches = e.touches();
+    let (a, b) = (touches.get(0)?, touches.get(1)?);
+    let (dx, dy) = ((a.client_x() - b.client_x()) as f64, (a.client_y() - b.client_y()) as f64);
+    Some((dx * dx + dy * dy).sqrt().max(1.0))
+}
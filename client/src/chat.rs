@@ -0,0 +1,144 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlInputElement, KeyboardEvent};
+
+use crate::i18n::strings;
+use crate::mount::Handles;
+
+// Lines kept in the chat log; older ones are removed
+const MAX_LINES: u32 = 100;
+
+// What the player typed into the chat box
+pub enum Command {
+    Say(String),
+    // "/w <name> <text>"
+    Whisper { to: String, text: String },
+    // "/mute <name>", "/unmute <name>" and "/muted" to list them; handled locally
+    Mute(String),
+    Unmute(String),
+    ListMuted,
+    // For a room's host: "/kick <name>", "/start" and "/set <setting> <value>"
+    Kick(String),
+    StartMatch,
+    Set { setting: String, value: String },
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Option<Command> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        if line == "/muted" {
+            return Some(Command::ListMuted);
+        }
+        if line == "/start" {
+            return Some(Command::StartMatch);
+        }
+        if let Some(name) = line.strip_prefix("/kick ") {
+            return Some(Command::Kick(name.trim().to_string()));
+        }
+        if let Some(rest) = line.strip_prefix("/set ") {
+            let (setting, value) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+            return Some(Command::Set {
+                setting: setting.to_lowercase(),
+                value: value.trim().to_lowercase(),
+            });
+        }
+        if let Some(name) = line.strip_prefix("/mute ") {
+            return Some(Command::Mute(name.trim().to_string()));
+        }
+        if let Some(name) = line.strip_prefix("/unmute ") {
+            return Some(Command::Unmute(name.trim().to_string()));
+        }
+        let whisper = line.strip_prefix("/w ").or_else(|| line.strip_prefix("/whisper "));
+        match whisper {
+            Some(rest) => {
+                let (to, text) = rest.trim_start().split_once(' ')?;
+                Some(Command::Whisper {
+                    to: to.to_string(),
+                    text: text.trim().to_string(),
+                })
+            }
+            None => Some(Command::Say(line.to_string())),
+        }
+    }
+}
+
+// The chat log below the canvas with a box to type into
+pub struct Chat {
+    document: Document,
+    log: Element,
+    input: HtmlInputElement,
+}
+
+impl Chat {
+    pub fn new(document: &Document, parent: &Element) -> Result<Chat, JsValue> {
+        let panel = document.create_element("div")?;
+        panel.set_class_name("game-chat");
+        let log = document.create_element("div")?;
+        log.set_class_name("game-chat-log");
+        panel.append_child(&log)?;
+        let input = document.create_element("input")?.dyn_into::<HtmlInputElement>()?;
+        input.set_placeholder(strings().chat_placeholder);
+        input.set_class_name("game-chat-input");
+        input.set_max_length(200);
+        panel.append_child(&input)?;
+        parent.append_child(&panel)?;
+
+        Ok(Chat {
+            document: document.clone(),
+            log,
+            input,
+        })
+    }
+
+    // Call `on_send` with every line the player enters
+    pub fn on_send(&self, handles: &Handles, mut on_send: impl FnMut(Command) + 'static) -> Result<(), JsValue> {
+        let field = self.input.clone();
+        let on_key = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+            if e.key() != "Enter" {
+                return;
+            }
+            if let Some(command) = Command::parse(&field.value()) {
+                on_send(command);
+            }
+            field.set_value("");
+        }) as Box<dyn FnMut(KeyboardEvent)>);
+        handles.listen(&self.input, "keydown", on_key)
+    }
+
+    // The box to type into is hidden on servers with chat turned off
+    pub fn set_enabled(&self, enabled: bool) {
+        self.input.set_hidden(!enabled);
+    }
+
+    pub fn add_message(&self, name: &str, text: &str) {
+        self.add_line(&format!("{}: {}", name, text), "");
+    }
+
+    // Whispers stand out from the public chat
+    pub fn add_whisper(&self, label: &str, text: &str) {
+        self.add_line(&format!("{} {}", label, text), "game-whisper");
+    }
+
+    pub fn add_notice(&self, text: &str) {
+        self.add_line(text, "game-notice");
+    }
+
+    fn add_line(&self, text: &str, class: &str) {
+        let Ok(line) = self.document.create_element("div") else {
+            return;
+        };
+        // Text content, so nobody can inject markup
+        line.set_text_content(Some(text));
+        line.set_class_name(class);
+        let _ = self.log.append_child(&line);
+        while self.log.child_element_count() > MAX_LINES {
+            if let Some(first) = self.log.first_element_child() {
+                first.remove();
+            }
+        }
+        self.log.set_scroll_top(self.log.scroll_height());
+    }
+}
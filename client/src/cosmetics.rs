@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::strings;
+use crate::settings::Settings;
+use crate::shapes::Shape;
+
+// Must match the server's color for GoldColor
+const GOLD: &str = "#ffd700";
+
+// A look that has to be unlocked - must match the server definition
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cosmetic {
+    CircleShape,
+    TriangleShape,
+    GoldColor,
+}
+
+impl Cosmetic {
+    // How to get it, for telling the player why their pick didn't apply
+    fn requirement(self) -> &'static str {
+        match self {
+            Cosmetic::CircleShape => strings().unlock_circle,
+            Cosmetic::TriangleShape => strings().unlock_triangle,
+            Cosmetic::GoldColor => strings().unlock_gold,
+        }
+    }
+}
+
+// Requirements of what the player picked in the settings but hasn't unlocked;
+// the server swapped those for the defaults
+pub fn locked_picks(settings: &Settings, unlocks: &[Cosmetic]) -> Vec<&'static str> {
+    let shape = match settings.shape {
+        Shape::Square => None,
+        Shape::Circle => Some(Cosmetic::CircleShape),
+        Shape::Triangle => Some(Cosmetic::TriangleShape),
+    };
+    let color = settings.color.eq_ignore_ascii_case(GOLD).then_some(Cosmetic::GoldColor);
+    [shape, color]
+        .into_iter()
+        .flatten()
+        .filter(|cosmetic| !unlocks.contains(cosmetic))
+        .map(Cosmetic::requirement)
+        .collect()
+}
@@ -0,0 +1,50 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{Document, Element};
+
+use crate::i18n::strings;
+
+// Entries kept in the feed; older ones are removed
+const MAX_ENTRIES: u32 = 20;
+
+// A scrolling log of what happened in the world: tags, pickups, eliminations
+// and round results, newest at the bottom
+pub struct Feed {
+    document: Document,
+    list: Element,
+}
+
+impl Feed {
+    pub fn new(document: &Document, parent: &Element) -> Result<Feed, JsValue> {
+        let panel = document.create_element("details")?;
+        panel.set_attribute("open", "")?;
+        let summary = document.create_element("summary")?;
+        summary.set_text_content(Some(strings().feed));
+        panel.append_child(&summary)?;
+        let list = document.create_element("div")?;
+        list.set_class_name("game-feed");
+        panel.append_child(&list)?;
+        parent.append_child(&panel)?;
+        Ok(Feed {
+            document: document.clone(),
+            list,
+        })
+    }
+
+    pub fn add(&self, text: &str) {
+        let Ok(entry) = self.document.create_element("div") else {
+            return;
+        };
+        entry.set_text_content(Some(text));
+        let _ = self.list.append_child(&entry);
+        while self.list.child_element_count() > MAX_ENTRIES {
+            if let Some(first) = self.list.first_element_child() {
+                first.remove();
+            }
+        }
+        self.list.set_scroll_top(self.list.scroll_height());
+    }
+
+    pub fn clear(&self) {
+        self.list.set_text_content(None);
+    }
+}
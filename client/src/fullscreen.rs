@@ -0,0 +1,65 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlButtonElement, HtmlCanvasElement};
+
+use crate::camera;
+use crate::mount::Handles;
+use crate::i18n::strings;
+
+// Key that toggles fullscreen, unless it's bound to movement
+pub const FULLSCREEN_KEY: &str = "f";
+
+// Add a Fullscreen button to `parent` and keep the canvas sized to the screen
+// while it's fullscreen. The camera picks the new size up on the next frame.
+pub fn attach(document: &Document, parent: &Element, canvas: &HtmlCanvasElement, handles: &Handles) -> Result<(), JsValue> {
+    let button = document.create_element("button")?.dyn_into::<HtmlButtonElement>()?;
+    button.set_text_content(Some(strings().fullscreen));
+    parent.append_child(&button)?;
+    let target = canvas.clone();
+    let on_click = Closure::wrap(Box::new(move || toggle(&target)) as Box<dyn FnMut()>);
+    button.set_onclick(Some(on_click.as_ref().unchecked_ref()));
+    handles.keep(on_click);
+
+    // The size the page gave the canvas, if any
+    let normal_style = canvas.get_attribute("style");
+    let watched = canvas.clone();
+    let fullscreen_document = document.clone();
+    let on_change = Closure::wrap(Box::new(move || {
+        let is_fullscreen = fullscreen_document
+            .fullscreen_element()
+            .is_some_and(|element| element.is_same_node(Some(&watched)));
+        let _ = match (is_fullscreen, &normal_style) {
+            (true, _) => watched.set_attribute("style", &fit_screen_style()),
+            (false, Some(style)) => watched.set_attribute("style", style),
+            // The size comes from the stylesheet again
+            (false, None) => watched.remove_attribute("style"),
+        };
+    }) as Box<dyn FnMut()>);
+    handles.listen(document, "fullscreenchange", on_change)
+}
+
+// Enter fullscreen with the canvas, or leave it
+pub fn toggle(canvas: &HtmlCanvasElement) {
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let result = if document.fullscreen_element().is_some() {
+        document.exit_fullscreen();
+        Ok(())
+    } else {
+        canvas.request_fullscreen()
+    };
+    if let Err(e) = result {
+        log_warn!("Couldn't toggle fullscreen: {:?}", e);
+    }
+}
+
+// The largest size with the world's aspect ratio that fits the screen, centered
+fn fit_screen_style() -> String {
+    let window = web_sys::window();
+    let size = |value: Option<Result<JsValue, JsValue>>| value.and_then(|v| v.ok()?.as_f64()).unwrap_or(0.0);
+    let width = size(window.as_ref().map(|window| window.inner_width()));
+    let height = size(window.as_ref().map(|window| window.inner_height()));
+    let (width, height) = camera::fit_world(width, height);
+    format!("width: {}px; height: {}px; margin: auto", width, height)
+}
@@ -0,0 +1,104 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use js_sys::Function;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::json;
+
+// Game events the embedding page can listen to
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hook {
+    PlayerJoin,
+    PlayerLeave,
+    ScoreChange,
+    ConnectionState,
+}
+
+thread_local! {
+    static CALLBACKS: RefCell<HashMap<Hook, Vec<Function>>> = RefCell::new(HashMap::new());
+}
+
+// A player came into our world: `{ id, name, online }`, with the number of
+// players online afterwards
+#[derive(Serialize)]
+pub struct PlayerEvent<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub online: usize,
+}
+
+// A player's score went up or down: `{ id, name, score }`
+#[derive(Serialize)]
+pub struct ScoreEvent<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub score: u32,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+}
+
+// `{ state, reason }`, the reason only for disconnects that came with one
+#[derive(Serialize)]
+pub struct ConnectionEvent<'a> {
+    pub state: ConnectionState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+}
+
+// Called with `{ id, name, online }` whenever a player joins our world
+#[wasm_bindgen]
+pub fn on_player_join(callback: Function) {
+    add(Hook::PlayerJoin, callback);
+}
+
+// Called with `{ id, name, online }` whenever a player leaves our world
+#[wasm_bindgen]
+pub fn on_player_leave(callback: Function) {
+    add(Hook::PlayerLeave, callback);
+}
+
+// Called with `{ id, name, score }` whenever a player's score changes
+#[wasm_bindgen]
+pub fn on_score_change(callback: Function) {
+    add(Hook::ScoreChange, callback);
+}
+
+// Called with `{ state, reason }` as the connection goes "connecting",
+// "connected" and "disconnected"
+#[wasm_bindgen]
+pub fn on_connection_state(callback: Function) {
+    add(Hook::ConnectionState, callback);
+}
+
+// Call every callback registered for `hook` with `event` as a JS object
+pub fn emit(hook: Hook, event: &impl Serialize) {
+    // Copied out, so callbacks can register more callbacks
+    let listeners = CALLBACKS.with(|callbacks| callbacks.borrow().get(&hook).cloned().unwrap_or_default());
+    if listeners.is_empty() {
+        return;
+    }
+    let Ok(value) = json::to_js(event) else {
+        return;
+    };
+    for callback in listeners {
+        if let Err(e) = callback.call1(&JsValue::NULL, &value) {
+            log_warn!("Event callback failed: {:?}", e);
+        }
+    }
+}
+
+pub fn connection_state(state: ConnectionState, reason: Option<&str>) {
+    emit(Hook::ConnectionState, &ConnectionEvent { state, reason });
+}
+
+fn add(hook: Hook, callback: Function) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().entry(hook).or_default().push(callback));
+}
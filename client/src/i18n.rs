@@ -0,0 +1,365 @@
+use std::cell::Cell;
+use std::fmt::Display;
+
+// Every text the client shows. `{}` in a string is filled in with `fill`, in order.
+pub struct Strings {
+    pub instructions: &'static str,
+    pub join: &'static str,
+    pub leave: &'static str,
+    pub log_in: &'static str,
+    pub fullscreen: &'static str,
+    pub not_connected: &'static str,
+    pub connecting: &'static str,
+    pub connected: &'static str,
+    pub couldnt_connect: &'static str,
+    pub disconnected: &'static str,
+    pub servers: &'static str,
+    pub refresh: &'static str,
+    pub select: &'static str,
+    pub no_servers: &'static str,
+    pub offline: &'static str,
+    pub default_world: &'static str,
+    pub join_target: &'static str,
+    // Column headers of the server list
+    pub server_columns: [&'static str; 5],
+    // The lobby, between connecting and joining
+    pub rooms: &'static str,
+    pub room_columns: [&'static str; 3],
+    pub loading_rooms: &'static str,
+    pub create_room: &'static str,
+    pub room_name: &'static str,
+    pub room_mode: &'static str,
+    pub room_speed: &'static str,
+    pub room_max_players: &'static str,
+    pub room_friendly_fire: &'static str,
+    pub create: &'static str,
+    pub room_name_missing: &'static str,
+    pub creating_room: &'static str,
+    pub players_online: &'static str,
+    pub settings: &'static str,
+    pub name_setting: &'static str,
+    pub color_setting: &'static str,
+    pub shape_setting: &'static str,
+    pub key_up: &'static str,
+    pub key_left: &'static str,
+    pub key_down: &'static str,
+    pub key_right: &'static str,
+    pub mute_sounds: &'static str,
+    pub show_ping: &'static str,
+    pub theme_setting: &'static str,
+    pub second_player: &'static str,
+    pub chat_placeholder: &'static str,
+    pub mute_usage: &'static str,
+    pub muted: &'static str,
+    pub already_muted: &'static str,
+    pub unmuted: &'static str,
+    pub not_muted: &'static str,
+    pub nobody_muted: &'static str,
+    pub muted_list: &'static str,
+    pub whisper_to: &'static str,
+    pub whisper_from: &'static str,
+    pub you_joined: &'static str,
+    pub you_teleported: &'static str,
+    pub player_joined: &'static str,
+    pub player_left: &'static str,
+    pub player_says: &'static str,
+    pub whisper_announcement: &'static str,
+    pub player_scored: &'static str,
+    pub round_won: &'static str,
+    pub round_no_winner: &'static str,
+    pub round_stats: &'static str,
+    // Column headers of the stats shown between rounds
+    pub stats_columns: [&'static str; 5],
+    pub seconds: &'static str,
+    pub announcement: &'static str,
+    // An announcement counting down, with the time left
+    pub announcement_countdown: &'static str,
+    pub time_scale: &'static str,
+    // A room's name, mode, speed and player cap
+    pub room_rules: &'static str,
+    pub room_no_friendly_fire: &'static str,
+    pub room_refused: &'static str,
+    // A room's match, and the Ready button with how many are and have to be
+    pub ready: &'static str,
+    pub not_ready: &'static str,
+    pub ready_count: &'static str,
+    pub ready_mark: &'static str,
+    pub not_ready_mark: &'static str,
+    pub match_starting: &'static str,
+    pub match_countdown: &'static str,
+    pub match_started: &'static str,
+    pub match_called_off: &'static str,
+    pub host_mark: &'static str,
+    pub you_host: &'static str,
+    pub host_changed: &'static str,
+    pub kick_usage: &'static str,
+    pub set_usage: &'static str,
+    // The link to a private room
+    pub room_invite: &'static str,
+    pub room_private: &'static str,
+    pub feed: &'static str,
+    pub feed_tag: &'static str,
+    pub feed_pickup: &'static str,
+    pub feed_eliminated: &'static str,
+    pub feed_achievement: &'static str,
+    pub achievement_unlocked: &'static str,
+    pub achievement_first_pickup: &'static str,
+    pub achievement_traveler: &'static str,
+    pub achievement_round_winner: &'static str,
+    pub unlock_circle: &'static str,
+    pub unlock_triangle: &'static str,
+    pub unlock_gold: &'static str,
+    pub ping: &'static str,
+    pub speed_boost: &'static str,
+}
+
+const EN: Strings = Strings {
+    instructions: "Use WASD keys (or your own bindings below) to move your square",
+    join: "Join",
+    leave: "Leave",
+    log_in: "Log in",
+    fullscreen: "Fullscreen",
+    not_connected: "Not connected",
+    connecting: "Connecting...",
+    connected: "Connected",
+    couldnt_connect: "Couldn't connect",
+    disconnected: "Disconnected: {}",
+    servers: "Servers",
+    refresh: "Refresh",
+    select: "Select",
+    no_servers: "No servers",
+    offline: "offline",
+    default_world: "a world picked after connecting",
+    join_target: " Join goes to {} on {}",
+    server_columns: ["Server", "World", "Map", "Players", "Ping"],
+    rooms: "Rooms",
+    room_columns: ["Room", "Mode", "Players"],
+    loading_rooms: "Loading rooms...",
+    create_room: "Create a room",
+    room_name: "Name",
+    room_mode: "Mode",
+    room_speed: "Speed (empty for the default)",
+    room_max_players: "Players (empty for the default)",
+    room_friendly_fire: "Players can take each other out",
+    create: "Create",
+    room_name_missing: "Give the room a name first",
+    creating_room: "Creating the room...",
+    players_online: "Players online: {}",
+    settings: "Settings",
+    name_setting: "Name (next join)",
+    color_setting: "Color (next join)",
+    shape_setting: "Shape (next join)",
+    key_up: "Up",
+    key_left: "Left",
+    key_down: "Down",
+    key_right: "Right",
+    mute_sounds: "Mute sounds",
+    show_ping: "Show ping",
+    theme_setting: "Theme",
+    second_player: "Second player on the arrow keys (next join)",
+    chat_placeholder: "Say something, /w <name> <message> to whisper, /mute <name> to hide someone",
+    mute_usage: "Usage: /mute <name> or /unmute <name>",
+    muted: "Muted {}",
+    already_muted: "{} is already muted",
+    unmuted: "Unmuted {}",
+    not_muted: "{} isn't muted",
+    nobody_muted: "Nobody is muted",
+    muted_list: "Muted: {}",
+    whisper_to: "[to {}]",
+    whisper_from: "[from {}]",
+    you_joined: "You joined the game",
+    you_teleported: "You teleported",
+    player_joined: "{} joined",
+    player_left: "{} left",
+    player_says: "{} says: {}",
+    whisper_announcement: "Whisper {} {}",
+    player_scored: "{} scored, now at {}",
+    round_won: "{} wins the round!",
+    round_no_winner: "Round over, nobody survived",
+    round_stats: "Stats so far",
+    stats_columns: ["Player", "Distance", "Food", "Alive", "Messages"],
+    seconds: "{}s",
+    announcement: "Announcement: {}",
+    announcement_countdown: "Announcement: {} (in {})",
+    time_scale: "Time now runs at {}x",
+    room_rules: "Room {}: {} mode, speed {}, up to {} players",
+    room_no_friendly_fire: "Players can't take each other out in this room",
+    room_refused: "Can't create the room: {}",
+    room_invite: "Invite others with {}",
+    ready: "Ready",
+    not_ready: "Not ready",
+    ready_count: "{} ({}/{} ready)",
+    ready_mark: " - ready",
+    not_ready_mark: " - not ready",
+    match_starting: "The match starts",
+    match_countdown: "The match starts in {}",
+    match_started: "Go!",
+    match_called_off: "Not enough players are ready, the countdown stopped",
+    host_mark: " (host)",
+    you_host: "You're the room's host: /kick <name>, /start to start without waiting, /set speed|players|friendlyfire <value> to change the rules",
+    host_changed: "The host left, {} hosts the room now",
+    kick_usage: "Usage: /kick <name>",
+    set_usage: "Usage: /set speed <number>, /set players <number> or /set friendlyfire on|off",
+    room_private: "Private, joined with an invite link",
+    feed: "Events",
+    feed_tag: "{} tagged {}",
+    feed_pickup: "{} ate {} food",
+    feed_eliminated: "{} is out",
+    feed_achievement: "{} earned {}",
+    achievement_unlocked: "Achievement unlocked",
+    achievement_first_pickup: "First bite",
+    achievement_traveler: "Traveler",
+    achievement_round_winner: "Last one standing",
+    unlock_circle: "The circle unlocks after 30 minutes of play, you're a square for now",
+    unlock_triangle: "The triangle unlocks with the Traveler achievement, you're a square for now",
+    unlock_gold: "Gold unlocks with the Last one standing achievement, you got another color for now",
+    ping: "Ping: {} ms",
+    speed_boost: "Speed boost x{} ({}s)",
+};
+
+const DE: Strings = Strings {
+    instructions: "Beweg dein Quadrat mit WASD (oder deiner eigenen Belegung unten)",
+    join: "Beitreten",
+    leave: "Verlassen",
+    log_in: "Anmelden",
+    fullscreen: "Vollbild",
+    not_connected: "Nicht verbunden",
+    connecting: "Verbinde...",
+    connected: "Verbunden",
+    couldnt_connect: "Verbindung fehlgeschlagen",
+    disconnected: "Getrennt: {}",
+    servers: "Server",
+    refresh: "Aktualisieren",
+    select: "Auswählen",
+    no_servers: "Keine Server",
+    offline: "offline",
+    default_world: "eine Welt nach dem Verbinden",
+    join_target: " Beitreten geht zu {} auf {}",
+    server_columns: ["Server", "Welt", "Karte", "Spieler", "Ping"],
+    rooms: "Räume",
+    room_columns: ["Raum", "Modus", "Spieler"],
+    loading_rooms: "Räume werden geladen...",
+    create_room: "Raum erstellen",
+    room_name: "Name",
+    room_mode: "Modus",
+    room_speed: "Tempo (leer für den Standard)",
+    room_max_players: "Spieler (leer für den Standard)",
+    room_friendly_fire: "Spieler können sich gegenseitig ausschalten",
+    create: "Erstellen",
+    room_name_missing: "Gib dem Raum zuerst einen Namen",
+    creating_room: "Raum wird erstellt...",
+    players_online: "Spieler online: {}",
+    settings: "Einstellungen",
+    name_setting: "Name (nächster Beitritt)",
+    color_setting: "Farbe (nächster Beitritt)",
+    shape_setting: "Form (nächster Beitritt)",
+    key_up: "Hoch",
+    key_left: "Links",
+    key_down: "Runter",
+    key_right: "Rechts",
+    mute_sounds: "Töne aus",
+    show_ping: "Ping anzeigen",
+    theme_setting: "Design",
+    second_player: "Zweiter Spieler auf den Pfeiltasten (nächster Beitritt)",
+    chat_placeholder: "Schreib etwas, /w <Name> <Nachricht> zum Flüstern, /mute <Name> zum Ausblenden",
+    mute_usage: "Benutzung: /mute <Name> oder /unmute <Name>",
+    muted: "{} stummgeschaltet",
+    already_muted: "{} ist schon stummgeschaltet",
+    unmuted: "{} nicht mehr stummgeschaltet",
+    not_muted: "{} ist nicht stummgeschaltet",
+    nobody_muted: "Niemand ist stummgeschaltet",
+    muted_list: "Stummgeschaltet: {}",
+    whisper_to: "[an {}]",
+    whisper_from: "[von {}]",
+    you_joined: "Du bist dem Spiel beigetreten",
+    you_teleported: "Du wurdest teleportiert",
+    player_joined: "{} ist beigetreten",
+    player_left: "{} ist gegangen",
+    player_says: "{} sagt: {}",
+    whisper_announcement: "Geflüstert {} {}",
+    player_scored: "{} hat gepunktet, jetzt {}",
+    round_won: "{} gewinnt die Runde!",
+    round_no_winner: "Runde vorbei, niemand hat überlebt",
+    round_stats: "Bisherige Statistik",
+    stats_columns: ["Spieler", "Strecke", "Futter", "Am Leben", "Nachrichten"],
+    seconds: "{} s",
+    announcement: "Durchsage: {}",
+    announcement_countdown: "Durchsage: {} (in {})",
+    time_scale: "Die Zeit läuft jetzt {}-fach",
+    room_rules: "Raum {}: Modus {}, Tempo {}, bis zu {} Spieler",
+    room_no_friendly_fire: "In diesem Raum kann niemand andere ausschalten",
+    room_refused: "Raum kann nicht erstellt werden: {}",
+    room_invite: "Lade andere ein mit {}",
+    ready: "Bereit",
+    not_ready: "Nicht bereit",
+    ready_count: "{} ({}/{} bereit)",
+    ready_mark: " - bereit",
+    not_ready_mark: " - nicht bereit",
+    match_starting: "Das Spiel beginnt",
+    match_countdown: "Das Spiel beginnt in {}",
+    match_started: "Los!",
+    match_called_off: "Nicht genug Spieler sind bereit, der Countdown wurde abgebrochen",
+    host_mark: " (Gastgeber)",
+    you_host: "Du bist Gastgeber des Raums: /kick <Name>, /start zum Starten ohne zu warten, /set speed|players|friendlyfire <Wert> zum Ändern der Regeln",
+    host_changed: "Der Gastgeber ist gegangen, {} ist jetzt Gastgeber",
+    kick_usage: "Verwendung: /kick <Name>",
+    set_usage: "Verwendung: /set speed <Zahl>, /set players <Zahl> oder /set friendlyfire on|off",
+    room_private: "Privat, Beitritt über einen Einladungslink",
+    feed: "Ereignisse",
+    feed_tag: "{} hat {} erwischt",
+    feed_pickup: "{} hat {} Futter gegessen",
+    feed_eliminated: "{} ist raus",
+    feed_achievement: "{} hat {} erreicht",
+    achievement_unlocked: "Erfolg freigeschaltet",
+    achievement_first_pickup: "Erster Happen",
+    achievement_traveler: "Weltenbummler",
+    achievement_round_winner: "Letzter Überlebender",
+    unlock_circle: "Den Kreis gibt es nach 30 Minuten Spielzeit, bis dahin bist du ein Quadrat",
+    unlock_triangle: "Das Dreieck gibt es mit dem Erfolg Weltenbummler, bis dahin bist du ein Quadrat",
+    unlock_gold: "Gold gibt es mit dem Erfolg Letzter Überlebender, bis dahin bekommst du eine andere Farbe",
+    ping: "Ping: {} ms",
+    speed_boost: "Tempo x{} ({}s)",
+};
+
+// Languages with a table, by their primary language subtag
+const LOCALES: [(&str, &Strings); 2] = [("en", &EN), ("de", &DE)];
+
+thread_local! {
+    static STRINGS: Cell<&'static Strings> = const { Cell::new(&EN) };
+}
+
+// Pick the language: `lang` if given (like ?lang=de), else the first of the
+// browser's preferred languages we have a table for, else English
+pub fn init(lang: Option<String>) {
+    let mut tags: Vec<String> = lang.into_iter().collect();
+    if let Some(navigator) = web_sys::window().map(|window| window.navigator()) {
+        tags.extend(navigator.languages().iter().filter_map(|language| language.as_string()));
+        tags.extend(navigator.language());
+    }
+    let strings = tags.iter().find_map(|tag| lookup(tag)).unwrap_or(&EN);
+    STRINGS.with(|current| current.set(strings));
+}
+
+// The strings of the language in use
+pub fn strings() -> &'static Strings {
+    STRINGS.with(Cell::get)
+}
+
+// Replace each `{}` in `template` with the next of `args`
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut filled = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            filled.push_str(&arg.to_string());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+// A table for a language tag like "de-AT"
+fn lookup(tag: &str) -> Option<&'static Strings> {
+    let language = tag.split(['-', '_']).next()?.to_lowercase();
+    LOCALES.iter().find(|(code, _)| *code == language).map(|(_, strings)| *strings)
+}
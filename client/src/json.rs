@@ -0,0 +1,21 @@
+// JSON that isn't a message - the init config, settings, hook events, traces
+// and the server list - through the browser's own JSON rather than serde_json,
+// so a build without the JSON codecs can leave serde_json out
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+pub fn parse<T: DeserializeOwned>(text: &str) -> Result<T, JsValue> {
+    Ok(serde_wasm_bindgen::from_value(js_sys::JSON::parse(text)?)?)
+}
+
+pub fn stringify<T: Serialize>(value: &T) -> Result<String, JsValue> {
+    Ok(js_sys::JSON::stringify(&to_js(value)?)?.into())
+}
+
+// A value as the JS object JSON.parse would have made of it, with maps as
+// plain objects and None as null
+pub fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
@@ -1,50 +1,664 @@
 use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
+
+#[macro_use]
+mod logging;
+mod a11y;
+mod achievements;
+mod background;
+mod browser;
+mod camera;
+mod chat;
+mod cosmetics;
+mod feed;
+mod fullscreen;
+mod hooks;
+mod i18n;
+mod json;
+mod local;
+mod lockstep;
+mod mount;
+mod packed;
+mod particles;
+mod pow;
+mod prediction;
+mod protocol;
+mod replica;
+mod rooms;
+mod roster;
+mod settings;
+mod shapes;
+mod socket;
+mod sound;
+mod stats;
+mod text;
+mod theme;
+mod trace;
+
+use a11y::LiveRegion;
+use achievements::{Achievement, Badge};
+use background::Background;
+use browser::Target;
+use camera::{Camera, View};
+use chat::{Chat, Command};
+use cosmetics::Cosmetic;
+use feed::Feed;
+use hooks::{ConnectionState, Hook, PlayerEvent, ScoreEvent};
+use i18n::{fill, strings};
+use mount::{Handles, Instance};
+use particles::Particles;
+use protocol::{Codec, Frame};
+use replica::Replica;
+use rooms::{Lobby, Match, RoomConfig, RoomEntry, RoomSettings};
+use roster::{Roster, RosterEntry};
+use settings::{KeyBindings, Settings};
+use shapes::Shape;
+use socket::{Event, Socket};
+use sim::{Body, Boundary, Movement, KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP};
+use stats::{PlayerStats, RoundStats, ROUND_STATS_MS};
+use text::Labels;
+
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement, KeyboardEvent, WebSocket};
+use web_sys::{CanvasRenderingContext2d, Document, Element, HtmlButtonElement, HtmlCanvasElement, KeyboardEvent};
 
 // Game state types - must match server definitions
 #[derive(Clone, Serialize, Deserialize)]
 struct Player {
     id: String,
+    #[serde(default)]
+    name: String,
     x: u32,
     y: u32,
     color: String,
+    #[serde(default)]
+    shape: Shape,
+    #[serde(default = "default_size")]
+    size: u32,
+    #[serde(default)]
+    eliminated: bool,
+    // Their window is in the background
+    #[serde(default)]
+    afk: bool,
+    // Held in place by an admin
+    #[serde(default)]
+    frozen: bool,
+    // Other players pass through them
+    #[serde(default)]
+    ghost: bool,
+    // Knockback after bouncing off an edge; the server doesn't send it, so
+    // only lockstep worlds, where we simulate it ourselves, have it
+    #[serde(skip)]
+    bounce: (i64, i64),
+    #[serde(default)]
+    score: u32,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+fn default_size() -> u32 {
+    sim::PLAYER_SIZE
+}
+
+impl Player {
+    // The part of the player the shared simulation works on
+    fn body(&self) -> Body {
+        Body {
+            x: self.x,
+            y: self.y,
+            size: self.size,
+            bounce: self.bounce,
+        }
+    }
+
+    // Run a piece of the shared simulation on the player
+    fn simulate<R>(&mut self, step: impl FnOnce(&mut Body) -> R) -> R {
+        let mut body = self.body();
+        let result = step(&mut body);
+        (self.x, self.y, self.bounce) = (body.x, body.y, body.bounce);
+        result
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 struct GameState {
     players: HashMap<String, Player>,
+    #[serde(default)]
+    food: Vec<Food>,
+    #[serde(default)]
+    trails: HashMap<String, Vec<(u32, u32)>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Food {
+    x: u32,
+    y: u32,
 }
 
+// Side of a food pellet
+const FOOD_SIZE: f64 = 10.0;
+
 #[derive(Serialize, Deserialize)]
 enum ClientMessage {
-    Move { direction: String },
-    Join,
+    Input { seq: u32, keys: u8 },
+    Join(JoinRequest),
+    Ping { sent_at: f64 },
+    Leave,
+    Chat { text: String },
+    Whisper { to: String, text: String },
+    Hash { tick: u64, hash: u64 },
+    Afk { afk: bool },
+    CreateRoom(RoomConfig),
+    ListRooms,
+    Ready { ready: bool },
+    Kick { player_id: String },
+    StartMatch,
+    ChangeRoom(RoomSettings),
+}
+
+// Size of the world in world coordinates
+const WORLD_WIDTH: f64 = 800.0;
+const WORLD_HEIGHT: f64 = 600.0;
+
+// How often held keys are sent to the server (about 30 Hz)
+const INPUT_INTERVAL_MS: i32 = 33;
+// How often the round trip time is measured
+const PING_INTERVAL_MS: i32 = 2000;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct JoinRequest {
+    world: Option<String>,
+    invite: Option<String>,
+    name: Option<String>,
+    color: Option<String>,
+    shape: Option<String>,
+    session: Option<String>,
+    // Answer to ServerMessage::Challenge
+    proof: Option<u64>,
+    codecs: Vec<String>,
+    compression: Vec<String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Map {
+    portals: Vec<Portal>,
+    // Players move a whole cell at a time on grid maps
+    cell_size: Option<u32>,
+    boundary: Boundary,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Portal {
+    id: String,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    target: String,
+}
+
+// What the server has on; servers from before features were sent have chat
+// and the world size we know. It lists more that we don't show anything for.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Features {
+    chat: bool,
+    world_size: (u32, u32),
+}
+
+impl Default for Features {
+    fn default() -> Features {
+        Features {
+            chat: true,
+            world_size: (WORLD_WIDTH as u32, WORLD_HEIGHT as u32),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct ServerMessage {
-    game_state: GameState,
+enum ServerMessage {
+    State { game_state: GameState },
+    // Game state in one of the binary layouts, which the replica reads in place
+    #[serde(skip)]
+    Packed { data: Vec<u8> },
+    Map { map: Map },
+    Teleported { player_id: String, from: (u32, u32), to: (u32, u32) },
+    WorldEvent { event: WorldEvent },
+    Session {
+        player_id: String,
+        token: String,
+        #[serde(default)]
+        unlocks: Vec<Cosmetic>,
+        #[serde(default)]
+        movement: Option<Movement>,
+        #[serde(default)]
+        features: Features,
+    },
+    TimeScale { time_scale: f64, movement: Movement },
+    Challenge { challenge: String, bits: u32 },
+    Roster { players: Vec<RosterEntry> },
+    PlayerJoined { player: RosterEntry },
+    PlayerLeft { player_id: String },
+    Palette { colors: Vec<(u32, String)> },
+    Pings { pings: HashMap<String, u32> },
+    Chat { from: String, name: String, text: String },
+    Whisper { from: String, name: String, to: String, text: String },
+    Notice { text: String },
+    Pong { sent_at: f64 },
+    GameEvent { event: GameEvent },
+    RoundOver {
+        winner: Option<String>,
+        #[serde(default)]
+        stats: HashMap<String, PlayerStats>,
+    },
+    Achievement { player_id: String, achievement: Achievement },
+    Announcement {
+        text: String,
+        #[serde(default)]
+        remaining_ms: Option<u64>,
+    },
+    Tick { tick: u64, speed: u32, inputs: HashMap<String, u8> },
+    Warning { text: String },
+    Reconnect { url: String },
+    RoomConfig { config: RoomConfig },
+    RoomRefused { reason: String },
+    Rooms { rooms: Vec<RoomEntry>, can_create: bool },
+    Match {
+        phase: String,
+        #[serde(default)]
+        remaining_ms: Option<u64>,
+        ready: Vec<String>,
+        needed: usize,
+    },
+    Host { player_id: Option<String> },
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum EventKind {
+    PointRush { zone: Rect, points_per_second: u32 },
+    SpeedBoost { multiplier: f32 },
+    ShrinkingArea { from: Rect, to: Rect },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct WorldEvent {
+    id: String,
+    kind: EventKind,
+    duration_ms: u64,
+    remaining_ms: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+enum GameEvent {
+    Tag { by: String, target: String },
+    Pickup { player_id: String, count: u32 },
+    Eliminated { player_id: String },
+}
+
+// A world event that is currently running, with its end in local time
+struct ActiveEvent {
+    kind: EventKind,
+    duration_ms: f64,
+    ends_at: f64,
+}
+
+impl ActiveEvent {
+    // How far through the event we are, 0..=1
+    fn progress(&self, now: f64) -> f64 {
+        if self.duration_ms <= 0.0 {
+            return 1.0;
+        }
+        (1.0 - (self.ends_at - now) / self.duration_ms).clamp(0.0, 1.0)
+    }
+}
+
+// How long an admin announcement stays on screen, in milliseconds; with a
+// countdown, how long after it reaches zero
+const ANNOUNCEMENT_MS: f64 = 5000.0;
+
+// An admin message shown at the bottom of the canvas
+struct Announcement {
+    text: String,
+    shown_at: f64,
+    // When its countdown reaches zero, if it has one
+    ends_at: Option<f64>,
+}
+
+impl Announcement {
+    fn is_over(&self, now: f64) -> bool {
+        now - self.ends_at.unwrap_or(self.shown_at) > ANNOUNCEMENT_MS
+    }
+
+    // What's left of the countdown as m:ss
+    fn countdown(&self, now: f64) -> Option<String> {
+        let secs = ((self.ends_at? - now) / 1000.0).ceil().max(0.0) as u64;
+        Some(format!("{}:{:02}", secs / 60, secs % 60))
+    }
+}
+
+// How long join/leave notifications stay up, and how long of that they take to fade out
+const TOAST_MS: f64 = 4000.0;
+const TOAST_FADE_MS: f64 = 1000.0;
+
+// A "so-and-so joined" notification in the top right corner
+struct Toast {
+    text: String,
+    shown_at: f64,
+}
+
+impl Toast {
+    fn opacity(&self, now: f64) -> f64 {
+        ((TOAST_MS - (now - self.shown_at)) / TOAST_FADE_MS).clamp(0.0, 1.0)
+    }
+}
+
+// How long the teleport flash lasts, in milliseconds
+const TELEPORT_EFFECT_MS: f64 = 400.0;
+
+// A short-lived visual effect, drawn on top of the players
+struct Effect {
+    x: f64,
+    y: f64,
+    started_at: f64,
+}
+
+// How far the view shakes, in world units, when one of ours tags or is tagged, and is eliminated
+const TAG_SHAKE: f64 = 6.0;
+const ELIMINATED_SHAKE: f64 = 14.0;
+
+impl Effect {
+    fn is_active(&self, now: f64) -> bool {
+        now - self.started_at < TELEPORT_EFFECT_MS
+    }
+}
+
+// Everything the renderer needs
+struct ClientState {
+    replica: Replica,
+    effects: Vec<Effect>,
+    particles: Particles,
+    labels: Labels,
+    background: Background,
+    world_events: HashMap<String, ActiveEvent>,
+    announcement: Option<Announcement>,
+    toasts: Vec<Toast>,
+    // Everyone's stats, shown for a while after a round ends
+    round_stats: Option<RoundStats>,
+    // Achievements our players earned, shown one after another
+    badges: Vec<Badge>,
+    settings: Rc<RefCell<Settings>>,
+    roster: Roster,
+    chat: Chat,
+    feed: Feed,
+    camera: Rc<RefCell<Camera>>,
+    live: LiveRegion,
+    // Script of the worker our connections run in, if any
+    worker_url: Option<String>,
+    // What our connections ask the server for, preferred first
+    codecs: Vec<&'static dyn Codec>,
+    // The server our server handed us over to, to join from now on
+    moved_to: Option<String>,
+    // The world we joined or are joining; None while picking one in the lobby
+    world: Option<String>,
+    // The invite of the private room we joined or are joining
+    invite: Option<String>,
+    lobby: Lobby,
+    // In a room, its match
+    round: Option<Match>,
+    // The player hosting the room we're in
+    host: Option<String>,
+}
+
+impl ClientState {
+    // Join with the name, color and shape from the settings
+    fn join_request(&self, world: Option<String>) -> JoinRequest {
+        let settings = self.settings.borrow();
+        JoinRequest {
+            world,
+            invite: self.invite.clone(),
+            name: settings.name(),
+            color: settings.color(),
+            shape: Some(settings.shape.name().to_string()),
+            session: settings::session_token(),
+            proof: None,
+            codecs: protocol::codec_names(),
+            compression: vec![protocol::COMPRESSION.to_string()],
+        }
+    }
+
+    // Sparkles where a player picked something up and a burst where one was
+    // eliminated
+    fn spawn_particles(&mut self, event: &GameEvent) {
+        if self.replica.reduced_motion {
+            return;
+        }
+        let now = js_sys::Date::now();
+        let center = |player: &Player| {
+            let (x, y) = self.replica.drawn_position(player, now);
+            (x + player.size as f64 / 2.0, y + player.size as f64 / 2.0)
+        };
+        match event {
+            GameEvent::Pickup { player_id, .. } => {
+                if let Some((x, y)) = self.replica.game_state.players.get(player_id).map(center) {
+                    self.particles.sparkle(x, y, now);
+                }
+            }
+            GameEvent::Eliminated { player_id } => {
+                let burst = self.replica.game_state.players.get(player_id).map(|player| (center(player), player.color.clone()));
+                if let Some(((x, y), color)) = burst {
+                    self.particles.burst(x, y, &color, now);
+                }
+            }
+            GameEvent::Tag { .. } => {}
+        }
+    }
+
+    // Dust behind the players still moving, then particles on to `now`
+    fn update_particles(&mut self, now: f64) {
+        if !self.replica.reduced_motion {
+            let feet: Vec<_> = self
+                .replica
+                .game_state
+                .players
+                .values()
+                .filter(|player| !player.eliminated)
+                .map(|player| {
+                    let (x, y) = self.replica.drawn_position(player, now);
+                    let size = player.size as f64;
+                    (player.id.as_str(), x + size / 2.0, y + size, size)
+                })
+                .collect();
+            self.particles.track(feet.into_iter(), now);
+        }
+        self.particles.update(now);
+    }
+
+    // The middle of the player the camera follows: the one picked in the
+    // roster, or our own
+    fn camera_focus(&self, now: f64) -> Option<(f64, f64)> {
+        let own_player = self.replica.player_id.as_ref().and_then(|id| self.replica.game_state.players.get(id));
+        let followed = self.roster.followed().and_then(|id| self.replica.game_state.players.get(id));
+        followed.or(own_player).map(|player| {
+            let (x, y) = self.replica.drawn_position(player, now);
+            (x + player.size as f64 / 2.0, y + player.size as f64 / 2.0)
+        })
+    }
+
+    // Announce scores that went up in a new game state, before the replica takes it
+    fn announce_scores(&mut self, now: f64) {
+        for player in self.replica.game_state.players.values() {
+            let Some(old) = self.replica.previous.players.get(&player.id).filter(|old| old.score != player.score) else {
+                continue;
+            };
+            let name = if player.name.is_empty() { &player.id } else { &player.name };
+            hooks::emit(Hook::ScoreChange, &ScoreEvent { id: &player.id, name, score: player.score });
+            if player.score > old.score {
+                self.live.announce_score(&player.id, name, player.score, now);
+            }
+        }
+    }
+
+    // How much faster than usual our players move right now, with speed boosts
+    fn speed_boost(&self) -> f32 {
+        self.world_events
+            .values()
+            .map(|event| match event.kind {
+                EventKind::SpeedBoost { multiplier } => multiplier,
+                _ => 1.0,
+            })
+            .product()
+    }
+
+    // Forget everything about the world we were in, keeping the settings
+    fn reset(&mut self) {
+        self.replica.reset();
+        self.background.invalidate();
+        self.effects.clear();
+        self.particles.clear();
+        self.world_events.clear();
+        self.announcement = None;
+        self.toasts.clear();
+        self.round_stats = None;
+        self.badges.clear();
+        self.roster.clear();
+        self.live.clear();
+        self.feed.clear();
+        self.round = None;
+        self.host = None;
+    }
+
+    // Whether our player said it's ready for the room's match
+    fn is_ready(&self) -> bool {
+        let player_id = self.replica.player_id.as_ref();
+        self.round.as_ref().is_some_and(|round| player_id.is_some_and(|id| round.ready.contains(id)))
+    }
+
+    // A player's display name, or their ID if they aren't in the roster
+    fn display_name<'a>(&'a self, player_id: &'a str) -> &'a str {
+        self.roster.get(player_id).map_or(player_id, |player| player.display_name())
+    }
+}
+
+// Game server port assumed when the URL is derived from the page location
+const DEFAULT_SERVER_PORT: u16 = 8080;
+
+// What the embedding page passes to init(); everything is optional
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct InitConfig {
+    // WebSocket URL of the game server; ?server= in the page URL overrides it
+    server_url: Option<String>,
+    // `mount`, the element to put the game into, isn't JSON; see mount::resolve
+    // Size of the canvas on the page in CSS pixels, shrunk to keep the world's
+    // aspect ratio. Given just one, the other follows from it.
+    width: Option<f64>,
+    height: Option<f64>,
+    // Movement keys for players who haven't saved their own in the settings
+    keys: Option<KeyBindings>,
+    // The game server's /auth/login, for a Log in link next to Join
+    login_url: Option<String>,
+    // worker.js, to run the connection in a Web Worker; on the page's thread when unset
+    worker: Option<String>,
+    // Codec to ask the server for ahead of the default ones, like squaregame.v1.msgpack
+    codec: Option<String>,
+    // How fast the camera catches up with the player it follows; 0 to keep it right on them
+    camera_easing: Option<f64>,
 }
 
-// When the wasm module is instantiated
-#[wasm_bindgen(start)]
-pub fn start() -> Result<(), JsValue> {
+// Entry point, called from JS once the wasm module is loaded, with an object
+// like `{ serverUrl, mount, width, height, keys: { up, left, down, right } }`.
+// Without a server URL the game server is expected on the host the page came from.
+// Calling it again for the same mount element replaces the game running there.
+#[wasm_bindgen]
+pub fn init(config: JsValue) -> Result<(), JsValue> {
+    let config_value = config;
+    let config: InitConfig = if config_value.is_undefined() || config_value.is_null() {
+        InitConfig::default()
+    } else {
+        let json = String::from(js_sys::JSON::stringify(&config_value)?);
+        json::parse(&json).map_err(|e| JsValue::from_str(&format!("Invalid config: {:?}", e)))?
+    };
+
+    // Log level from the page URL (?log=debug), if given
+    if let Some(level) = query_param("log") {
+        if let Err(e) = logging::set_log_level(&level) {
+            log_warn!("{}", e.as_string().unwrap_or_default());
+        }
+    }
+
+    // Record messages from the start with ?trace in the page URL, or
+    // ?trace=500 to keep just the last 500
+    if let Some(capacity) = query_param("trace") {
+        if !trace::is_tracing() {
+            trace::start_trace(capacity.parse().ok());
+        }
+    }
+
+    // Language from the page URL (?lang=de), else the browser's
+    i18n::init(query_param("lang"));
+
     // Get window and document
     let window = web_sys::window().expect("no global `window` exists");
     let document = window.document().expect("should have a document on window");
+    settings::take_login_token(&window.location());
 
-    // Set up game canvas
-    setup_game(&document)?;
+    let server_url = query_param("server")
+        .or(config.server_url.clone())
+        .unwrap_or_else(|| default_server_url(&window.location()));
+    // Server list from ?listing= in the page URL, or the API of the default server
+    let listing_url = query_param("listing").unwrap_or_else(|| browser::default_listing_url(&window.location()));
+
+    // Set up the game in the mount element, replacing any that's already there
+    let mount = mount::resolve(&document, &config_value)?;
+    mount::unmount(&mount);
+    let instance = setup_game(&document, &mount, &config, &server_url, listing_url)?;
+    mount::register(instance);
 
     Ok(())
 }
 
-fn setup_game(document: &Document) -> Result<(), JsValue> {
-    // Set up the UI
-    let body = document.body().expect("document should have a body");
+// Stop every game on the page: its players leave, its listeners, timers and
+// UI are removed, and what they held on to is let go. init() can start over
+// afterwards, like when a single-page app mounts the game again.
+#[wasm_bindgen]
+pub fn shutdown() {
+    mount::unmount_all();
+}
+
+// ws://<page host>:8080, or wss:// for pages served over https
+fn default_server_url(location: &web_sys::Location) -> String {
+    let scheme = match location.protocol().as_deref() {
+        Ok("https:") => "wss",
+        _ => "ws",
+    };
+    // Pages opened from a file have no host
+    let host = location.hostname().ok().filter(|host| !host.is_empty());
+    format!("{}://{}:{}", scheme, host.as_deref().unwrap_or("127.0.0.1"), DEFAULT_SERVER_PORT)
+}
+
+// Build the UI in a new element inside `mount` and start the game there
+fn setup_game(
+    document: &Document,
+    mount: &Element,
+    config: &InitConfig,
+    server_url: &str,
+    listing_url: String,
+) -> Result<Instance, JsValue> {
+    let parent = &document.create_element("div")?;
+    parent.set_class_name("game-root");
+    mount.append_child(parent)?;
+    let handles = Rc::new(Handles::default());
 
     // Create canvas
     let canvas = document
@@ -52,12 +666,16 @@ fn setup_game(document: &Document) -> Result<(), JsValue> {
         .dyn_into::<HtmlCanvasElement>()?;
     canvas.set_width(800);
     canvas.set_height(600);
-    canvas.set_id("game-canvas");
-
-    // Set border using attribute
-    canvas.set_attribute("style", "border: 1px solid black")?;
+    canvas.set_class_name("game-canvas");
+    if config.width.is_some() || config.height.is_some() {
+        let (width, height) = camera::fit_world(
+            config.width.unwrap_or(f64::INFINITY),
+            config.height.unwrap_or(f64::INFINITY),
+        );
+        canvas.set_attribute("style", &format!("width: {}px; height: {}px", width, height))?;
+    }
 
-    body.append_child(&canvas)?;
+    parent.append_child(&canvas)?;
 
     // Get canvas context for drawing
     let context = canvas
@@ -67,140 +685,1144 @@ fn setup_game(document: &Document) -> Result<(), JsValue> {
 
     // Add instructions
     let instructions = document.create_element("p")?;
-    instructions.set_text_content(Some("Use WASD keys to move your square"));
-    body.append_child(&instructions)?;
+    instructions.set_text_content(Some(strings().instructions));
+    parent.append_child(&instructions)?;
 
-    // Create WebSocket connection
-    let ws = WebSocket::new("ws://127.0.0.1:8080")?;
-    let ws_clone = ws.clone();
+    // Join and Leave buttons; nothing connects until Join is pressed
+    let controls = Controls::new(document, parent)?;
+    fullscreen::attach(document, &controls.row, &canvas, &handles)?;
+    if let Some(login_url) = &config.login_url {
+        let link = document.create_element("a")?;
+        link.set_attribute("href", login_url)?;
+        link.set_text_content(Some(strings().log_in));
+        controls.row.append_child(&link)?;
+    }
 
-    // Create a shared reference to the game state
-    let game_state = std::rc::Rc::new(std::cell::RefCell::new(GameState {
-        players: HashMap::new(),
-    }));
+    // The worlds to pick from once connected, unless one was picked already
+    let lobby = Lobby::new(document, parent)?;
 
-    // Clone for the render loop
-    let game_state_clone = game_state.clone();
-    let context_clone = context.clone();
+    let default_keys = config.keys.clone().unwrap_or_default();
+    let settings = Rc::new(RefCell::new(Settings::load(default_keys)));
+    theme::install(document, settings.borrow().theme)?;
+    settings::build_panel(document, parent, settings.clone(), &handles)?;
 
-    // Set up WebSocket message handler
-    let onmessage_callback = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
-        if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
-            let text = String::from(txt);
-            match serde_json::from_str::<ServerMessage>(&text) {
-                Ok(msg) => {
-                    // Update game state
-                    *game_state.borrow_mut() = msg.game_state;
+    // Create a shared reference to the client state
+    let state = Rc::new(RefCell::new(ClientState {
+        replica: Replica::new(a11y::prefers_reduced_motion()),
+        effects: Vec::new(),
+        particles: Particles::default(),
+        labels: Labels::default(),
+        background: Background::default(),
+        world_events: HashMap::new(),
+        announcement: None,
+        toasts: Vec::new(),
+        round_stats: None,
+        badges: Vec::new(),
+        settings: settings.clone(),
+        roster: Roster::new(document, parent)?,
+        chat: Chat::new(document, parent)?,
+        feed: Feed::new(document, parent)?,
+        camera: Rc::new(RefCell::new(Camera::new(config.camera_easing.unwrap_or(camera::DEFAULT_EASING)))),
+        live: LiveRegion::new(document, parent)?,
+        worker_url: config.worker.clone(),
+        codecs: protocol::requested(config.codec.as_deref()),
+        moved_to: None,
+        world: None,
+        invite: None,
+        lobby: lobby.clone(),
+        round: None,
+        host: None,
+    }));
+    camera::attach_controls(&canvas, state.borrow().camera.clone(), &handles)?;
+    let connection: Connection = Rc::new(RefCell::new(None));
 
-                    // Render the updated game state
-                    render_game(&context, &game_state.borrow());
-                }
-                Err(e) => {
-                    console_log!("Error parsing server message: {:?}", e);
+    // Whispers and kicks go to a player picked by name; anything else is taken as their ID.
+    // Mutes only change what this client shows.
+    let chat_connection = connection.clone();
+    let chat_state = state.clone();
+    state.borrow().chat.on_send(&handles, move |command| {
+        let msg = match command {
+            Command::Say(text) => ClientMessage::Chat { text },
+            Command::Whisper { to, text } => {
+                let state = chat_state.borrow();
+                let to = state.roster.find_by_name(&to).map_or(to, |player| player.id.clone());
+                ClientMessage::Whisper { to, text }
+            }
+            Command::Mute(name) | Command::Unmute(name) if name.is_empty() => {
+                chat_state.borrow().chat.add_notice(strings().mute_usage);
+                return;
+            }
+            Command::Mute(name) => {
+                let state = chat_state.borrow();
+                let notice = if state.settings.borrow_mut().set_muted(&name, true) {
+                    fill(strings().muted, &[&name])
+                } else {
+                    fill(strings().already_muted, &[&name])
+                };
+                state.chat.add_notice(&notice);
+                return;
+            }
+            Command::Unmute(name) => {
+                let state = chat_state.borrow();
+                let notice = if state.settings.borrow_mut().set_muted(&name, false) {
+                    fill(strings().unmuted, &[&name])
+                } else {
+                    fill(strings().not_muted, &[&name])
+                };
+                state.chat.add_notice(&notice);
+                return;
+            }
+            Command::Kick(name) if name.is_empty() => {
+                chat_state.borrow().chat.add_notice(strings().kick_usage);
+                return;
+            }
+            Command::Kick(name) => {
+                let state = chat_state.borrow();
+                let player_id = state.roster.find_by_name(&name).map_or(name, |player| player.id.clone());
+                ClientMessage::Kick { player_id }
+            }
+            Command::StartMatch => ClientMessage::StartMatch,
+            Command::Set { setting, value } => match RoomSettings::parse(&setting, &value) {
+                Some(settings) => ClientMessage::ChangeRoom(settings),
+                None => {
+                    chat_state.borrow().chat.add_notice(strings().set_usage);
+                    return;
                 }
+            },
+            Command::ListMuted => {
+                let state = chat_state.borrow();
+                let muted = state.settings.borrow().muted_players.join(", ");
+                let notice = if muted.is_empty() {
+                    strings().nobody_muted.to_string()
+                } else {
+                    fill(strings().muted_list, &[&muted])
+                };
+                state.chat.add_notice(&notice);
+                return;
             }
+        };
+        if !send(&chat_connection, &msg) {
+            chat_state.borrow().chat.add_notice(strings().not_connected);
+        }
+    })?;
+
+    // Clicking a player in the roster makes the camera follow them; clicking
+    // them again goes back to our own player
+    let follow_state = state.clone();
+    state.borrow().roster.on_pick(&handles, move |player_id| {
+        let mut state = follow_state.borrow_mut();
+        let followed = (state.roster.followed() != Some(player_id.as_str())).then_some(player_id);
+        follow(&mut state, followed);
+    })?;
+
+    // Join the world named in the page URL (?world=arena), or the server's default,
+    // until another one is picked from the server list
+    let target = Rc::new(RefCell::new(Target {
+        url: server_url.to_string(),
+        world: query_param("world"),
+    }));
+    browser::build_panel(document, parent, listing_url, server_url.to_string(), target.clone(), &handles)?;
+    let lobby_target = target.clone();
+
+    // The second local player has a connection of its own
+    let second: Connection = Rc::new(RefCell::new(None));
+    let join_connection = connection.clone();
+    let join_second = second.clone();
+    let join_state = state.clone();
+    let join_context = context.clone();
+    let join_controls = controls.clone();
+    let join_callback = Closure::wrap(Box::new(move || {
+        if join_connection.borrow().is_some() {
+            return;
+        }
+        // Handed over to another server, we go back into the world we were in
+        let moved_to = join_state.borrow_mut().moved_to.take();
+        let mut world = target.borrow().world.clone();
+        let mut invite = query_param("room");
+        if let Some(url) = moved_to {
+            target.borrow_mut().url = url;
+            let state = join_state.borrow();
+            world = state.world.clone().or(world);
+            invite = state.invite.clone().or(invite);
+        }
+        join_state.borrow_mut().invite = invite;
+        let url = target.borrow().url.clone();
+        let joined = connect(
+            &url,
+            world,
+            &join_connection,
+            &join_second,
+            &join_state,
+            &join_context,
+            &join_controls,
+        );
+        if let Err(err) = joined {
+            log_error!("Error connecting to {}: {:?}", url, err);
+            join_controls.set_connected(false, strings().couldnt_connect);
+            hooks::connection_state(ConnectionState::Disconnected, None);
         }
-    }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+    }) as Box<dyn FnMut()>);
+    controls.join.set_onclick(Some(join_callback.as_ref().unchecked_ref()));
+    handles.keep(join_callback);
 
-    ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-    onmessage_callback.forget();
+    // Picking a world in the lobby joins it; a room we created is joined once it's open
+    let (lobby_connection, lobby_second, lobby_state) = (connection.clone(), second.clone(), state.clone());
+    lobby.on_join(&handles, move |world| {
+        let url = lobby_target.borrow().url.clone();
+        join_world(&url, Some(world), None, &lobby_connection, &lobby_second, &lobby_state);
+    })?;
+    let create_connection = connection.clone();
+    lobby.on_create(&handles, move |room| {
+        send(&create_connection, &ClientMessage::CreateRoom(room));
+    })?;
+    let refresh_connection = connection.clone();
+    lobby.on_refresh(&handles, move || {
+        send(&refresh_connection, &ClientMessage::ListRooms);
+    })?;
+    let (list_connection, list_lobby) = (connection.clone(), lobby.clone());
+    let list_callback = Closure::wrap(Box::new(move || {
+        if list_lobby.is_open() {
+            send(&list_connection, &ClientMessage::ListRooms);
+        }
+    }) as Box<dyn FnMut()>);
+    handles.every(rooms::REFRESH_MS, list_callback)?;
+
+    // Say whether we're ready for the room's match; the server's answer updates the button
+    let (ready_connection, ready_state) = (connection.clone(), state.clone());
+    let ready_callback = Closure::wrap(Box::new(move || {
+        let ready = !ready_state.borrow().is_ready();
+        send(&ready_connection, &ClientMessage::Ready { ready });
+    }) as Box<dyn FnMut()>);
+    controls.ready.set_onclick(Some(ready_callback.as_ref().unchecked_ref()));
+    handles.keep(ready_callback);
+
+    // Say goodbye, then close; the close handler cleans up
+    let leave_connections = [connection.clone(), second.clone()];
+    let leave_callback = Closure::wrap(Box::new(move || {
+        leave(&leave_connections);
+    }) as Box<dyn FnMut()>);
+    controls.leave.set_onclick(Some(leave_callback.as_ref().unchecked_ref()));
+    handles.keep(leave_callback);
+
+    // Same when the page goes away, so the server drops our players right
+    // away instead of when the socket times out. pagehide also fires when the
+    // page goes into the back/forward cache, where unload wouldn't.
+    let unload_connections = [connection.clone(), second.clone()];
+    let unload_callback = Closure::wrap(Box::new(move || {
+        leave(&unload_connections);
+    }) as Box<dyn FnMut()>);
+    let window = web_sys::window().expect("no global `window` exists");
+    handles.listen(&window, "pagehide", unload_callback)?;
 
-    // Set up keyboard event handler
+    // Track which movement keys are held, for each local player; they're sent
+    // on a fixed interval below. The second player only takes the arrow keys
+    // while it's enabled in the settings.
+    let held_keys = Rc::new(Cell::new(0u8));
+    let second_held = Rc::new(Cell::new(0u8));
+    let second_keys = Rc::new(KeyBindings::arrows());
+    let held_down = held_keys.clone();
+    let second_down = second_held.clone();
+    let second_keys_down = second_keys.clone();
+    let fullscreen_canvas = canvas.clone();
+    let settings_down = settings.clone();
+    let follow_keys = state.clone();
     let keydown_callback = Closure::wrap(Box::new(move |e: KeyboardEvent| {
-        let key = e.key();
-        match key.as_str() {
-            "w" | "a" | "s" | "d" => {
-                // Send movement command to server
-                let msg = ClientMessage::Move { direction: key };
-
-                if let Ok(json) = serde_json::to_string(&msg) {
-                    if let Err(err) = ws_clone.send_with_str(&json) {
-                        console_log!("Error sending move command: {:?}", err);
-                    }
+        // Typing into the chat or settings doesn't move the player
+        if is_typing(&e) {
+            return;
+        }
+        let settings = settings_down.borrow();
+        let bit = settings.key_bit(&e.key());
+        let second_bit = if settings.second_player { second_keys_down.bit(&e.key()) } else { 0 };
+        if bit == 0 && second_bit == 0 {
+            match e.key().as_str() {
+                key if key.eq_ignore_ascii_case(fullscreen::FULLSCREEN_KEY) => fullscreen::toggle(&fullscreen_canvas),
+                // Cycle the camera through the players
+                "[" | "]" => {
+                    let mut state = follow_keys.borrow_mut();
+                    state.roster.follow_next(if e.key() == "[" { -1 } else { 1 });
+                    let followed = state.roster.followed().map(str::to_string);
+                    follow(&mut state, followed);
                 }
+                _ => {}
             }
-            _ => {}
         }
+        if second_bit != 0 {
+            // Don't scroll the page with the arrow keys
+            e.prevent_default();
+        }
+        held_down.set(held_down.get() | bit);
+        second_down.set(second_down.get() | second_bit);
+    }) as Box<dyn FnMut(KeyboardEvent)>);
+    handles.listen(document, "keydown", keydown_callback)?;
+
+    let held_up = held_keys.clone();
+    let second_up = second_held.clone();
+    let settings_up = settings.clone();
+    let keyup_callback = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+        held_up.set(held_up.get() & !settings_up.borrow().key_bit(&e.key()));
+        second_up.set(second_up.get() & !second_keys.bit(&e.key()));
     }) as Box<dyn FnMut(KeyboardEvent)>);
+    handles.listen(document, "keyup", keyup_callback)?;
 
-    document
-        .add_event_listener_with_callback("keydown", keydown_callback.as_ref().unchecked_ref())?;
-    keydown_callback.forget();
+    // While the window is in the background keyup never arrives, so held keys
+    // are let go. The server is told too, and shows our players dimmed.
+    let away = Rc::new(Cell::new(false));
+    let away_held = [held_keys.clone(), second_held.clone()];
+    let away_connections = [connection.clone(), second.clone()];
+    let set_away = Rc::new(move |afk: bool| {
+        if away.replace(afk) == afk {
+            return;
+        }
+        if afk {
+            for held in &away_held {
+                held.set(0);
+            }
+        }
+        for connection in &away_connections {
+            send(connection, &ClientMessage::Afk { afk });
+        }
+    });
+    let targets: [(&web_sys::EventTarget, &str, Option<bool>); 3] = [
+        (window.as_ref(), "blur", Some(true)),
+        (window.as_ref(), "focus", Some(false)),
+        // Hidden tabs don't always lose focus first
+        (document.as_ref(), "visibilitychange", None),
+    ];
+    for (target, event, afk) in targets {
+        let set_away = set_away.clone();
+        let document = document.clone();
+        let callback = Closure::wrap(Box::new(move || {
+            set_away(afk.unwrap_or_else(|| document.hidden()));
+        }) as Box<dyn FnMut()>);
+        handles.listen(target, event, callback)?;
+    }
 
-    // Set up onopen handler to send Join message
-    let ws_join = ws.clone();
-    let onopen_callback = Closure::wrap(Box::new(move |_| {
-        console_log!("WebSocket connection established");
+    let predict_keys = [held_keys.clone(), second_held.clone()];
+    let predict_state = state.clone();
+    // Send the held keys at a fixed rate while any are down, plus once when they're released
+    // Each local player's connection, held keys, and input sequence number and
+    // keys last sent over it
+    let mut inputs = [(connection.clone(), held_keys, 0u32, 0u8), (second.clone(), second_held, 0, 0)];
+    let input_callback = Closure::wrap(Box::new(move || {
+        // Our players move right away instead of a round trip later, once a room's match started
+        let playing = predict_state.borrow().round.as_ref().is_none_or(Match::is_playing);
+        let keys = if playing { [predict_keys[0].get(), predict_keys[1].get()] } else { [0, 0] };
+        let boost = predict_state.borrow().speed_boost();
+        predict_state.borrow_mut().replica.predict(keys, boost, js_sys::Date::now());
 
-        // Send join message
-        let msg = ClientMessage::Join;
-        if let Ok(json) = serde_json::to_string(&msg) {
-            if let Err(err) = ws_join.send_with_str(&json) {
-                console_log!("Error sending join command: {:?}", err);
+        for (connection, held, seq, last_sent) in &mut inputs {
+            let keys = held.get();
+            if keys == 0 && *last_sent == 0 {
+                continue;
+            }
+            *seq = seq.wrapping_add(1);
+            if send(connection, &ClientMessage::Input { seq: *seq, keys }) {
+                *last_sent = keys;
             }
         }
-    }) as Box<dyn FnMut(JsValue)>);
+    }) as Box<dyn FnMut()>);
+    handles.every(INPUT_INTERVAL_MS, input_callback)?;
 
-    ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-    onopen_callback.forget();
+    // Measure the round trip time; the reply is handled with the other server messages
+    let ping_connection = connection.clone();
+    let ping_callback = Closure::wrap(Box::new(move || {
+        send(&ping_connection, &ClientMessage::Ping {
+            sent_at: js_sys::Date::now(),
+        });
+    }) as Box<dyn FnMut()>);
+    handles.every(PING_INTERVAL_MS, ping_callback)?;
 
     // Set up animation frame loop for smooth rendering
-    setup_render_loop(game_state_clone, context_clone)?;
+    setup_render_loop(state, context, &handles)?;
 
-    Ok(())
+    Ok(Instance {
+        mount: mount.clone(),
+        root: parent.clone(),
+        connections: vec![connection, second],
+        handles,
+    })
 }
 
-fn render_game(context: &CanvasRenderingContext2d, game_state: &GameState) {
-    // Clear the canvas
-    context.clear_rect(0.0, 0.0, 800.0, 600.0);
+// Point the camera at a player, zooming in so it can follow them, or back at our own
+fn follow(state: &mut ClientState, player_id: Option<String>) {
+    if player_id.is_some() {
+        let mut camera = state.camera.borrow_mut();
+        camera.zoom = camera.zoom.max(camera::FOLLOW_ZOOM);
+    }
+    state.roster.follow(player_id);
+}
 
-    // Draw each player
-    for (_, player) in &game_state.players {
-        // Use fill_style property instead of set_fill_style method
-        context.set_fill_style(&JsValue::from_str(&player.color));
-        context.fill_rect(player.x as f64, player.y as f64, 50.0, 50.0);
+fn is_typing(e: &KeyboardEvent) -> bool {
+    e.target()
+        .is_some_and(|target| target.dyn_ref::<web_sys::HtmlInputElement>().is_some())
+}
 
-        // Draw player ID
-        context.set_fill_style(&JsValue::from_str("white"));
-        context.set_font("14px Arial");
-        context
-            .fill_text(&player.id, player.x as f64 + 5.0, player.y as f64 + 25.0)
-            .unwrap();
+// The socket we're playing over, None while not connected
+type Connection = Rc<RefCell<Option<Socket>>>;
+
+// The Join and Leave buttons with a status line next to them
+#[derive(Clone)]
+struct Controls {
+    // The row holding the buttons, for more of them
+    row: Element,
+    join: HtmlButtonElement,
+    leave: HtmlButtonElement,
+    // Only shown in rooms, before their match starts
+    ready: HtmlButtonElement,
+    status: Element,
+}
+
+impl Controls {
+    fn new(document: &Document, parent: &Element) -> Result<Controls, JsValue> {
+        let row = document.create_element("p")?;
+        let button = |label: &str| -> Result<HtmlButtonElement, JsValue> {
+            let button = document.create_element("button")?.dyn_into::<HtmlButtonElement>()?;
+            button.set_text_content(Some(label));
+            row.append_child(&button)?;
+            Ok(button)
+        };
+        let join = button(strings().join)?;
+        let leave = button(strings().leave)?;
+        let ready = button(strings().ready)?;
+        ready.set_hidden(true);
+        let status = document.create_element("span")?;
+        row.append_child(&status)?;
+        parent.append_child(&row)?;
+
+        let controls = Controls { row, join, leave, ready, status };
+        controls.set_connected(false, strings().not_connected);
+        Ok(controls)
+    }
+
+    // The Ready button says whether we are and how many others are, until the match starts
+    fn show_match(&self, state: &ClientState) {
+        let Some(round) = state.round.as_ref().filter(|round| !round.is_playing()) else {
+            self.ready.set_hidden(true);
+            return;
+        };
+        let label = if state.is_ready() { strings().not_ready } else { strings().ready };
+        self.ready.set_text_content(Some(&fill(strings().ready_count, &[&label, &round.ready.len(), &round.needed])));
+        self.ready.set_hidden(false);
+    }
+
+    fn set_connected(&self, connected: bool, status: &str) {
+        self.join.set_disabled(connected);
+        self.leave.set_disabled(!connected);
+        self.status.set_text_content(Some(&format!(" {}", status)));
     }
 }
 
-fn setup_render_loop(
-    game_state: std::rc::Rc<std::cell::RefCell<GameState>>,
-    context: CanvasRenderingContext2d,
+// Open a connection and, once it's up, join `world` or the room we have an
+// invite to, or without either show the lobby to pick one in. The second local player's connection, if any, is
+// closed along with it.
+fn connect(
+    server_url: &str,
+    world: Option<String>,
+    connection: &Connection,
+    second: &Connection,
+    state: &Rc<RefCell<ClientState>>,
+    context: &CanvasRenderingContext2d,
+    controls: &Controls,
 ) -> Result<(), JsValue> {
-    let f = std::rc::Rc::new(std::cell::RefCell::new(None));
-    let g = f.clone();
+    log_info!("Connecting to {}", server_url);
+    controls.set_connected(true, strings().connecting);
+    hooks::connection_state(ConnectionState::Connecting, None);
 
-    *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        // Render the current game state
-        render_game(&context, &game_state.borrow());
+    let event_connection = connection.clone();
+    let second = second.clone();
+    let state = state.clone();
+    let context = context.clone();
+    let controls = controls.clone();
+    let (worker_url, codecs) = {
+        let state = state.borrow();
+        (state.worker_url.clone(), state.codecs.clone())
+    };
+    let url = server_url.to_string();
+    let socket = Socket::open(server_url, worker_url.as_deref(), &codecs, move |event| match event {
+        Event::Open => {
+            log_info!("WebSocket connection established");
+            controls.set_connected(true, strings().connected);
+            hooks::connection_state(ConnectionState::Connected, None);
 
-        // Schedule the next frame
-        request_animation_frame(f.borrow().as_ref().unwrap());
-    }) as Box<dyn FnMut()>));
+            let invite = state.borrow().invite.clone();
+            if world.is_some() || invite.is_some() {
+                join_world(&url, world.clone(), invite, &event_connection, &second, &state);
+            } else {
+                state.borrow_mut().world = None;
+                state.borrow().lobby.open();
+                send(&event_connection, &ClientMessage::ListRooms);
+            }
+        }
+        Event::Frame(frame) => {
+            if let Frame::Text(text) = &frame {
+                log_debug!("Received {}", text);
+            }
+            let decoded = protocol::decode(frame, connection_codec(&event_connection));
+            match decoded {
+                // Join again, once we've done the work the server asked for
+                Ok(ServerMessage::Challenge { challenge, bits }) => {
+                    let world = state.borrow().world.clone();
+                    let mut join = state.borrow().join_request(world);
+                    join.proof = Some(pow::solve(&challenge, bits));
+                    send(&event_connection, &ClientMessage::Join(join));
+                }
+                // The server is going away and handed us to another one; the
+                // close handler joins that
+                Ok(ServerMessage::Reconnect { url }) => {
+                    log_info!("Moving to {}", url);
+                    state.borrow_mut().moved_to = Some(url);
+                    if let Some(socket) = event_connection.borrow().as_ref() {
+                        socket.close(1000, "Moving to another server");
+                    }
+                }
+                Ok(ServerMessage::Rooms { rooms, can_create }) => {
+                    if let Err(e) = state.borrow().lobby.show(&rooms, can_create) {
+                        log_error!("Error showing rooms: {:?}", e);
+                    }
+                }
+                Ok(ServerMessage::RoomRefused { reason }) => {
+                    log_warn!("Room refused: {}", reason);
+                    state.borrow().lobby.refused(&reason);
+                }
+                // The room we asked for is open: go in
+                Ok(ServerMessage::RoomConfig { config }) if state.borrow().lobby.is_open() => {
+                    join_world(&url, Some(config.name), config.code, &event_connection, &second, &state);
+                }
+                Ok(msg @ ServerMessage::Match { .. }) => {
+                    apply_server_message(&mut state.borrow_mut(), msg);
+                    controls.show_match(&state.borrow());
+                }
+                Ok(msg) => {
+                    apply_server_message(&mut state.borrow_mut(), msg);
+                    let hash = state.borrow_mut().replica.lockstep.take_hash();
+                    if let Some((tick, hash)) = hash {
+                        send(&event_connection, &ClientMessage::Hash { tick, hash });
+                    }
 
-    request_animation_frame(g.borrow().as_ref().unwrap());
+                    // Render the updated game state
+                    render_game(&context, &state.borrow(), js_sys::Date::now());
+                }
+                Err(e) => {
+                    log_warn!("Error parsing server message: {:?}", e);
+                }
+            }
+        }
+        // Whoever closed it, forget the connection and the world we were in
+        Event::Close { code, reason } => {
+            log_info!("Connection closed: {} {}", code, reason);
+            event_connection.borrow_mut().take();
+            if let Some(socket) = second.borrow().as_ref() {
+                socket.close(1000, "Leaving");
+            }
+            state.borrow().lobby.close();
+            state.borrow_mut().reset();
+            controls.show_match(&state.borrow());
+            let status = if reason.is_empty() {
+                strings().not_connected.to_string()
+            } else {
+                fill(strings().disconnected, &[&reason])
+            };
+            controls.set_connected(false, &status);
+            hooks::connection_state(ConnectionState::Disconnected, (!reason.is_empty()).then_some(reason.as_str()));
+            if state.borrow().moved_to.is_some() {
+                controls.join.click();
+            }
+        }
+    })?;
 
+    *connection.borrow_mut() = Some(socket);
     Ok(())
 }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-    web_sys::window()
-        .unwrap()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .unwrap();
+// Join `world`, or the room `invite` is for, over the open connection, along
+// with the second local player over its own if it's on
+fn join_world(
+    server_url: &str,
+    world: Option<String>,
+    invite: Option<String>,
+    connection: &Connection,
+    second: &Connection,
+    state: &Rc<RefCell<ClientState>>,
+) {
+    state.borrow().lobby.close();
+    state.borrow_mut().world = world.clone();
+    state.borrow_mut().invite = invite;
+    let msg = ClientMessage::Join(state.borrow().join_request(world.clone()));
+    send(connection, &msg);
+    let second_player = state.borrow().settings.borrow().second_player;
+    if second_player && second.borrow().is_none() {
+        if let Err(err) = local::connect(server_url, world, second, state) {
+            log_error!("Error connecting the second player to {}: {:?}", server_url, err);
+        }
+    }
 }
 
-// Helper macro for logging to console
-#[macro_export]
-macro_rules! console_log {
-    ($($t:tt)*) => {
-        web_sys::console::log_1(&format!($($t)*).into());
+// Say goodbye, then close with a normal close code
+fn leave(connections: &[Connection]) {
+    for connection in connections {
+        send(connection, &ClientMessage::Leave);
+        if let Some(socket) = connection.borrow().as_ref() {
+            socket.close(1000, "Leaving");
+        }
+    }
+}
+
+// The codec a connection talks, once it's open
+fn connection_codec(connection: &Connection) -> &'static dyn Codec {
+    connection.borrow().as_ref().map_or(protocol::FALLBACK, Socket::codec)
+}
+
+// Send a message if the connection is open; false if it wasn't sent
+fn send(connection: &Connection, msg: &ClientMessage) -> bool {
+    let connection = connection.borrow();
+    let Some(socket) = connection.as_ref().filter(|socket| socket.is_open()) else {
+        return false;
+    };
+    let Ok(frame) = protocol::encode(msg, socket.codec()) else {
+        return false;
+    };
+    if let Err(err) = socket.send(&frame) {
+        log_error!("Error sending message: {:?}", err);
+        return false;
     }
+    true
+}
+
+// This page with ?room=<code>, for others to join a private room with
+fn invite_link(code: &str) -> String {
+    let location = web_sys::window().map(|window| window.location());
+    let page = location.map_or(String::new(), |location| {
+        format!("{}{}", location.origin().unwrap_or_default(), location.pathname().unwrap_or_default())
+    });
+    format!("{}?room={}", page, code)
+}
+
+// Read a query parameter from the page URL
+fn query_param(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    web_sys::UrlSearchParams::new_with_str(&search).ok()?.get(name)
 }
 
+// The replica takes in what a message says about the world first; the rest
+// is for the page
+fn apply_server_message(state: &mut ClientState, msg: ServerMessage) {
+    let now = js_sys::Date::now();
+    let snapshot = matches!(msg, ServerMessage::State { .. } | ServerMessage::Packed { .. });
+    let boost = state.speed_boost();
+    let msg = match state.replica.apply(msg, boost, now) {
+        Ok(msg) => {
+            if snapshot {
+                state.announce_scores(now);
+            }
+            msg
+        }
+        Err(e) => {
+            log_warn!("Couldn't read a snapshot: {}", e);
+            return;
+        }
+    };
+    let Some(msg) = msg else {
+        return;
+    };
+    match msg {
+        ServerMessage::State { .. } | ServerMessage::Packed { .. } | ServerMessage::Tick { .. } | ServerMessage::Palette { .. } | ServerMessage::Pong { .. } => {}
+        ServerMessage::Map { .. } => state.background.invalidate(),
+        ServerMessage::Teleported { player_id, from, to } => {
+            // Flash both ends so the jump reads as a teleport, not a glitch
+            play_sound(state, 660.0);
+            if state.replica.player_id.as_ref() == Some(&player_id) {
+                state.live.announce(strings().you_teleported);
+            }
+            if state.replica.reduced_motion {
+                return;
+            }
+            for (x, y) in [from, to] {
+                state.effects.push(Effect {
+                    x: x as f64 + 25.0,
+                    y: y as f64 + 25.0,
+                    started_at: now,
+                });
+            }
+        }
+        ServerMessage::WorldEvent { event } => {
+            if event.remaining_ms == 0 {
+                state.world_events.remove(&event.id);
+            } else {
+                let active = ActiveEvent {
+                    kind: event.kind,
+                    duration_ms: event.duration_ms as f64,
+                    ends_at: now + event.remaining_ms as f64,
+                };
+                state.world_events.insert(event.id, active);
+            }
+        }
+        ServerMessage::Warning { text } => log_warn!("Server warning: {}", text),
+        // Handled by the message handler, which can send
+        ServerMessage::Challenge { .. }
+        | ServerMessage::Reconnect { .. }
+        | ServerMessage::Rooms { .. }
+        | ServerMessage::RoomRefused { .. } => {}
+        ServerMessage::Session { token, unlocks, features, .. } => {
+            settings::save_session_token(&token);
+            state.chat.set_enabled(features.chat);
+            if features.world_size != (WORLD_WIDTH as u32, WORLD_HEIGHT as u32) {
+                log_warn!("The server's world is {:?}, but this client draws {}x{}", features.world_size, WORLD_WIDTH, WORLD_HEIGHT);
+            }
+            state.live.announce(strings().you_joined);
+            for requirement in cosmetics::locked_picks(&state.settings.borrow(), &unlocks) {
+                state.chat.add_notice(requirement);
+            }
+        }
+        ServerMessage::TimeScale { time_scale, .. } => {
+            state.chat.add_notice(&fill(strings().time_scale, &[&time_scale]));
+        }
+        ServerMessage::Roster { players } => state.roster.set_all(players),
+        ServerMessage::PlayerJoined { player } => {
+            if state.replica.player_id.as_ref() != Some(&player.id) {
+                let text = fill(strings().player_joined, &[&player.display_name()]);
+                state.live.announce(&text);
+                state.toasts.push(Toast {
+                    text,
+                    shown_at: now,
+                });
+            }
+            let id = player.id.clone();
+            state.roster.add(player);
+            if let Some(player) = state.roster.get(&id) {
+                let event = PlayerEvent { id: &id, name: player.display_name(), online: state.roster.online() };
+                hooks::emit(Hook::PlayerJoin, &event);
+            }
+        }
+        ServerMessage::PlayerLeft { player_id } => {
+            if let Some(player) = state.roster.remove(&player_id) {
+                let event = PlayerEvent { id: &player.id, name: player.display_name(), online: state.roster.online() };
+                hooks::emit(Hook::PlayerLeave, &event);
+                let text = fill(strings().player_left, &[&player.display_name()]);
+                state.live.announce(&text);
+                state.toasts.push(Toast {
+                    text,
+                    shown_at: now,
+                });
+            }
+        }
+        ServerMessage::Pings { pings } => state.roster.set_pings(pings),
+        ServerMessage::Chat { name, text, .. } => {
+            if !state.settings.borrow().is_muted(&name) {
+                state.chat.add_message(&name, &text);
+                state.live.announce(&fill(strings().player_says, &[&name, &text]));
+            }
+        }
+        ServerMessage::Whisper { from, name, to, text } => {
+            let own = state.replica.player_id.as_ref() == Some(&from);
+            if !own && state.settings.borrow().is_muted(&name) {
+                return;
+            }
+            let label = if own {
+                let to = state.roster.get(&to).map_or(to.as_str(), |player| player.display_name());
+                fill(strings().whisper_to, &[&to])
+            } else {
+                fill(strings().whisper_from, &[&name])
+            };
+            state.chat.add_whisper(&label, &text);
+            state.live.announce(&fill(strings().whisper_announcement, &[&label, &text]));
+        }
+        ServerMessage::Notice { text } => state.chat.add_notice(&text),
+        ServerMessage::RoomConfig { config } => {
+            let speed = config.speed.map_or("?".to_string(), |speed| speed.to_string());
+            let cap = config.max_players.map_or("?".to_string(), |cap| cap.to_string());
+            state.chat.add_notice(&fill(strings().room_rules, &[&config.name, &config.mode, &speed, &cap]));
+            if !config.friendly_fire {
+                state.chat.add_notice(strings().room_no_friendly_fire);
+            }
+            if let Some(code) = config.code {
+                state.chat.add_notice(&fill(strings().room_invite, &[&invite_link(&code)]));
+                state.invite = Some(code);
+            }
+        }
+        ServerMessage::Match { phase, remaining_ms, ready, needed } => {
+            let was = state.round.as_ref().map(|round| round.phase.clone());
+            match (phase.as_str(), remaining_ms) {
+                // Counting down on the banner
+                ("countdown", Some(ms)) => {
+                    let secs = (ms as f64 / 1000.0).ceil().to_string();
+                    state.live.announce(&fill(strings().match_countdown, &[&fill(strings().seconds, &[&secs])]));
+                    state.announcement = Some(Announcement {
+                        text: strings().match_starting.to_string(),
+                        shown_at: now,
+                        ends_at: Some(now + ms as f64),
+                    });
+                }
+                ("playing", _) if was.as_deref() == Some("countdown") => {
+                    play_sound(state, 880.0);
+                    state.live.announce(strings().match_started);
+                    state.announcement = Some(Announcement { text: strings().match_started.to_string(), shown_at: now, ends_at: None });
+                }
+                // Called off, since not enough are ready any more
+                ("waiting", _) if was.as_deref() == Some("countdown") => {
+                    state.announcement = None;
+                    state.live.announce(strings().match_called_off);
+                }
+                _ => {}
+            }
+            let round = Match { phase, ready, needed };
+            state.roster.set_ready((!round.is_playing()).then(|| round.ready.iter().cloned().collect()));
+            state.round = Some(round);
+        }
+        // Hosting after joining an empty room shows what the host can do; taking
+        // over from a host who left is told to everyone
+        ServerMessage::Host { player_id } => {
+            let own = player_id.is_some() && player_id == state.replica.player_id;
+            if own {
+                state.chat.add_notice(strings().you_host);
+            } else if let (Some(_), Some(host)) = (&state.host, &player_id) {
+                let name = state.roster.get(host).map_or(host.as_str(), |player| player.display_name()).to_string();
+                state.chat.add_notice(&fill(strings().host_changed, &[&name]));
+            }
+            state.roster.set_host(player_id.clone());
+            state.host = player_id;
+        }
+        ServerMessage::GameEvent { event } => {
+            let text = match &event {
+                GameEvent::Tag { by, target } => {
+                    fill(strings().feed_tag, &[&state.display_name(by), &state.display_name(target)])
+                }
+                GameEvent::Pickup { player_id, count } => {
+                    fill(strings().feed_pickup, &[&state.display_name(player_id), count])
+                }
+                GameEvent::Eliminated { player_id } => fill(strings().feed_eliminated, &[&state.display_name(player_id)]),
+            };
+            state.feed.add(&text);
+            // The view jolts when one of ours gets hit or hits someone
+            let shake = match &event {
+                GameEvent::Tag { by, target } if state.replica.is_local(by) || state.replica.is_local(target) => TAG_SHAKE,
+                GameEvent::Eliminated { player_id } if state.replica.is_local(player_id) => ELIMINATED_SHAKE,
+                _ => 0.0,
+            };
+            if shake > 0.0 && !state.replica.reduced_motion {
+                state.camera.borrow_mut().shake(shake, now);
+            }
+            state.spawn_particles(&event);
+        }
+        ServerMessage::RoundOver { winner, stats } => {
+            let text = match winner.as_deref().map(|id| state.display_name(id)) {
+                Some(name) => fill(strings().round_won, &[&name]),
+                None => strings().round_no_winner.to_string(),
+            };
+            state.live.announce(&text);
+            state.feed.add(&text);
+            state.toasts.push(Toast {
+                text,
+                shown_at: now,
+            });
+            if !stats.is_empty() {
+                state.round_stats = Some(RoundStats {
+                    stats,
+                    shown_at: now,
+                });
+            }
+        }
+        ServerMessage::Achievement { player_id, achievement } => {
+            let text = fill(strings().feed_achievement, &[&state.display_name(&player_id), &achievement.name()]);
+            state.feed.add(&text);
+            if state.replica.is_local(&player_id) {
+                play_sound(state, 880.0);
+                state.live.announce(&text);
+                achievements::push(&mut state.badges, achievement, now);
+            }
+        }
+        ServerMessage::Announcement { text, remaining_ms } => {
+            play_sound(state, 440.0);
+            match remaining_ms {
+                Some(ms) => {
+                    let secs = (ms as f64 / 1000.0).ceil().to_string();
+                    let wait = fill(strings().seconds, &[&secs]);
+                    state.live.announce(&fill(strings().announcement_countdown, &[&text, &wait]));
+                }
+                None => state.live.announce(&fill(strings().announcement, &[&text])),
+            }
+            state.announcement = Some(Announcement {
+                text,
+                shown_at: now,
+                ends_at: remaining_ms.map(|ms| now + ms as f64),
+            });
+        }
+    }
+}
+
+// A short beep, unless the player muted sounds
+fn play_sound(state: &ClientState, frequency: f32) {
+    if !state.settings.borrow().mute {
+        sound::beep(frequency, 0.15);
+    }
+}
+
+// What gets drawn, bottom to top. Anything new drawn in the world goes into
+// one of these, so it ends up above and below the right things.
+#[derive(Clone, Copy, PartialEq)]
+enum RenderLayer {
+    // The background, the world's edge, portals and zones
+    Map,
+    // Things on the ground: food, trails and dust
+    Items,
+    Players,
+    // Particles, teleport flashes and the shade outside a shrinking area
+    Effects,
+    // Banners, ping, notifications and round stats, drawn without the camera
+    Overlays,
+}
+
+const RENDER_ORDER: [RenderLayer; 5] = [
+    RenderLayer::Map,
+    RenderLayer::Items,
+    RenderLayer::Players,
+    RenderLayer::Effects,
+    RenderLayer::Overlays,
+];
+
+// Things in the world outside the camera's view aren't drawn
+fn render_game(context: &CanvasRenderingContext2d, state: &ClientState, now: f64) {
+    let camera = state.camera.borrow();
+    let view = camera.view();
+    for layer in RENDER_ORDER {
+        match layer {
+            RenderLayer::Map => render_map(context, state, &camera, &view),
+            RenderLayer::Items => render_items(context, state, &view, now),
+            RenderLayer::Players => render_players(context, state, &view, text::label_px(camera.zoom), now),
+            RenderLayer::Effects => render_effects(context, state, &view, now),
+            RenderLayer::Overlays => {
+                camera.reset(context);
+                render_overlays(context, state, now);
+            }
+        }
+    }
+}
+
+fn render_map(context: &CanvasRenderingContext2d, state: &ClientState, camera: &Camera, view: &View) {
+    // Clear the canvas, unless the world covers all of it
+    let palette = theme::palette();
+    if !view.within_world() {
+        camera.reset(context);
+        context.set_fill_style_str(palette.background);
+        context.fill_rect(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT);
+    }
+
+    // The world is drawn through the camera, the overlays on top of it aren't
+    camera.apply(context);
+    state.background.draw(context, &state.replica.map, camera.resolution());
+    if camera.zoom < 1.0 {
+        context.set_stroke_style_str(palette.world_border);
+        context.stroke_rect(0.0, 0.0, WORLD_WIDTH, WORLD_HEIGHT);
+    }
+
+    for event in state.world_events.values() {
+        if let EventKind::PointRush { zone, points_per_second } = &event.kind {
+            context.set_fill_style_str("rgba(255, 215, 0, 0.3)");
+            context.fill_rect(zone.x as f64, zone.y as f64, zone.width as f64, zone.height as f64);
+            context.set_fill_style_str("darkgoldenrod");
+            context.set_font("14px Arial");
+            let _ = context.fill_text(
+                &format!("+{}/s", points_per_second),
+                zone.x as f64 + 5.0,
+                zone.y as f64 + 18.0,
+            );
+        }
+    }
+}
+
+fn render_items(context: &CanvasRenderingContext2d, state: &ClientState, view: &View, now: f64) {
+    context.set_fill_style_str(theme::palette().food);
+    for food in state.replica.game_state.food.iter().filter(|food| view.shows(food.x as f64, food.y as f64, FOOD_SIZE, FOOD_SIZE)) {
+        context.fill_rect(food.x as f64, food.y as f64, FOOD_SIZE, FOOD_SIZE);
+    }
+    render_trails(context, state);
+    state.particles.render(context, particles::Layer::Below, view, now);
+}
+
+// Draw each player, faded while eliminated or AFK. Eliminated players go
+// underneath and our own on top; otherwise by ID, so overlapping players
+// don't swap places from one frame to the next.
+fn render_players(context: &CanvasRenderingContext2d, state: &ClientState, view: &View, label_px: f64, now: f64) {
+    let palette = theme::palette();
+    let mut players: Vec<(&Player, (f64, f64))> = state
+        .replica
+        .game_state
+        .players
+        .values()
+        .map(|player| (player, state.replica.drawn_position(player, now)))
+        .filter(|(player, (x, y))| view.shows(*x, *y, player.size as f64, player.size as f64))
+        .collect();
+    players.sort_by_key(|(player, _)| (!player.eliminated, state.replica.is_local(&player.id), player.id.as_str()));
+    for (player, (x, y)) in players {
+        let size = player.size as f64;
+        let alpha = match (player.eliminated, player.afk, player.ghost) {
+            (true, _, _) => 0.3,
+            (false, _, true) => 0.35,
+            (false, true, false) => 0.5,
+            _ => 1.0,
+        };
+        context.set_global_alpha(alpha);
+        context.set_fill_style_str(&player.color);
+        shapes::fill(context, player.shape, x, y, size);
+        if player.frozen {
+            // Iced over, just outside where our own outline goes
+            context.set_stroke_style_str(palette.frozen_outline);
+            context.set_line_width(3.0);
+            shapes::stroke(context, player.shape, x - 5.0, y - 5.0, size + 10.0);
+            context.set_line_width(1.0);
+        }
+        if state.replica.is_local(&player.id) {
+            // Outline our own players
+            context.set_stroke_style_str(palette.own_outline);
+            shapes::stroke(context, player.shape, x - 2.0, y - 2.0, size + 4.0);
+        }
+
+        // Draw player name, or the ID for players without one
+        let label = if player.name.is_empty() { &player.id } else { &player.name };
+        let label_y = y + 4.0 + label_px;
+        state.labels.draw(context, label, x + 5.0, label_y, label_px, &player.color);
+        state.labels.draw(context, &player.score.to_string(), x + 5.0, label_y + label_px * 1.2, label_px, &player.color);
+    }
+    context.set_global_alpha(1.0);
+}
+
+fn render_effects(context: &CanvasRenderingContext2d, state: &ClientState, view: &View, now: f64) {
+    state.particles.render(context, particles::Layer::Above, view, now);
+    render_event_areas(context, state, now);
+
+    // Teleports as expanding, fading rings
+    for effect in state.effects.iter().filter(|effect| view.shows(effect.x - 50.0, effect.y - 50.0, 100.0, 100.0)) {
+        let progress = ((now - effect.started_at) / TELEPORT_EFFECT_MS).clamp(0.0, 1.0);
+        context.set_stroke_style_str(&format!("rgba(128, 0, 255, {})", 1.0 - progress));
+        context.begin_path();
+        let _ = context.arc(effect.x, effect.y, 10.0 + progress * 40.0, 0.0, std::f64::consts::TAU);
+        context.stroke();
+    }
+}
+
+fn render_overlays(context: &CanvasRenderingContext2d, state: &ClientState, now: f64) {
+    let palette = theme::palette();
+    render_event_banners(context, state, now);
+
+    if let (true, Some(ping_ms)) = (state.settings.borrow().show_ping, state.replica.ping_ms) {
+        context.set_fill_style_str(palette.text);
+        context.set_font("14px Arial");
+        context.set_text_align("right");
+        let _ = context.fill_text(&fill(strings().ping, &[&format!("{:.0}", ping_ms)]), 790.0, 20.0);
+        context.set_text_align("start");
+    }
+
+    // Newest notification first, below the ping
+    context.set_font("14px Arial");
+    context.set_text_align("right");
+    for (i, toast) in state.toasts.iter().rev().enumerate() {
+        context.set_fill_style_str(&format!("rgba({}, {})", palette.toast_rgb, toast.opacity(now)));
+        let _ = context.fill_text(&toast.text, 790.0, 40.0 + i as f64 * 18.0);
+    }
+    context.set_text_align("start");
+
+    if let Some(announcement) = &state.announcement {
+        context.set_fill_style_str(palette.banner_background);
+        context.fill_rect(0.0, 560.0, 800.0, 40.0);
+        context.set_fill_style_str(palette.banner_text);
+        context.set_font("bold 16px Arial");
+        let _ = context.fill_text(&announcement.text, 10.0, 585.0);
+        if let Some(countdown) = announcement.countdown(now) {
+            context.set_font("bold 20px monospace");
+            context.set_text_align("right");
+            let _ = context.fill_text(&countdown, 790.0, 587.0);
+            context.set_text_align("start");
+        }
+    }
+
+    if let Some(round) = &state.round_stats {
+        stats::render(context, state, round);
+    }
+    achievements::render(context, &state.badges, now);
+}
+
+// One path per trail, so long trails stay cheap to draw
+fn render_trails(context: &CanvasRenderingContext2d, state: &ClientState) {
+    context.set_line_width(6.0);
+    context.set_line_cap("round");
+    context.set_line_join("round");
+    for (player_id, trail) in &state.replica.game_state.trails {
+        let Some(((first_x, first_y), rest)) = trail.split_first() else {
+            continue;
+        };
+        let color = state.replica.game_state.players.get(player_id).map_or("gray", |player| player.color.as_str());
+        context.set_stroke_style_str(color);
+        context.begin_path();
+        context.move_to(*first_x as f64, *first_y as f64);
+        for (x, y) in rest {
+            context.line_to(*x as f64, *y as f64);
+        }
+        context.stroke();
+    }
+    context.set_line_width(1.0);
+}
+
+// Draw the parts of running world events that cover the world
+fn render_event_areas(context: &CanvasRenderingContext2d, state: &ClientState, now: f64) {
+    for event in state.world_events.values() {
+        if let EventKind::ShrinkingArea { from, to } = &event.kind {
+            // Darken everything outside the current playable area
+            let t = event.progress(now);
+            let mix = |a: u32, b: u32| a as f64 + (b as f64 - a as f64) * t;
+            let (x, y) = (mix(from.x, to.x), mix(from.y, to.y));
+            let (right, bottom) = (x + mix(from.width, to.width), y + mix(from.height, to.height));
+            context.set_fill_style_str(theme::palette().shade);
+            context.fill_rect(0.0, 0.0, 800.0, y);
+            context.fill_rect(0.0, bottom, 800.0, 600.0 - bottom);
+            context.fill_rect(0.0, y, x, bottom - y);
+            context.fill_rect(right, y, 800.0 - right, bottom - y);
+        }
+    }
+}
+
+// Draw the banners of running world events, like a speed boost's countdown
+fn render_event_banners(context: &CanvasRenderingContext2d, state: &ClientState, now: f64) {
+    let mut banner_y = 20.0;
+    for event in state.world_events.values() {
+        if let EventKind::SpeedBoost { multiplier } = &event.kind {
+            let seconds_left = ((event.ends_at - now) / 1000.0).ceil().max(0.0);
+            context.set_fill_style_str("darkorange");
+            context.set_font("bold 16px Arial");
+            let _ = context.fill_text(&fill(strings().speed_boost, &[multiplier, &seconds_left]), 10.0, banner_y);
+            banner_y += 20.0;
+        }
+    }
+}
+
+// Render every frame until the game stops
+fn setup_render_loop(
+    state: Rc<RefCell<ClientState>>,
+    context: CanvasRenderingContext2d,
+    handles: &Rc<Handles>,
+) -> Result<(), JsValue> {
+    handles.animate(move || {
+        // Drop finished effects, then render the current state
+        let now = js_sys::Date::now();
+        {
+            let mut state = state.borrow_mut();
+            state.effects.retain(|effect| effect.is_active(now));
+            state.toasts.retain(|toast| now - toast.shown_at < TOAST_MS);
+            state.world_events.retain(|_, event| event.ends_at > now);
+            if state.announcement.as_ref().is_some_and(|a| a.is_over(now)) {
+                state.announcement = None;
+            }
+            if state.round_stats.as_ref().is_some_and(|round| now - round.shown_at > ROUND_STATS_MS) {
+                state.round_stats = None;
+            }
+            achievements::expire(&mut state.badges, now);
+            state.update_particles(now);
+            let focus = state.camera_focus(now);
+            let mut camera = state.camera.borrow_mut();
+            if let Some(canvas) = context.canvas() {
+                camera.fit_canvas(&canvas);
+            }
+            camera.update(focus, now);
+        }
+        render_game(&context, &state.borrow(), now);
+    })
+}
@@ -0,0 +1,71 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::pow;
+use crate::protocol;
+use crate::socket::{Event, Socket};
+use crate::{connection_codec, send, ClientMessage, ClientState, Connection, JoinRequest, ServerMessage};
+
+// Open the second local player's own connection and join with it. The world is
+// drawn from the first player's connection, so this one only learns which
+// player it is; its session token isn't saved, so a reload only resumes the first.
+pub fn connect(
+    server_url: &str,
+    world: Option<String>,
+    connection: &Connection,
+    state: &Rc<RefCell<ClientState>>,
+) -> Result<(), JsValue> {
+    log_info!("Connecting the second player to {}", server_url);
+    let event_connection = connection.clone();
+    let state = state.clone();
+    let (worker_url, codecs) = {
+        let state = state.borrow();
+        (state.worker_url.clone(), state.codecs.clone())
+    };
+    let socket = Socket::open(server_url, worker_url.as_deref(), &codecs, move |event| match event {
+        Event::Open => {
+            let msg = ClientMessage::Join(join_request(&state.borrow(), world.clone()));
+            send(&event_connection, &msg);
+        }
+        // Its game state isn't used
+        Event::Frame(frame) => match protocol::decode(frame, connection_codec(&event_connection)) {
+            Ok(ServerMessage::Challenge { challenge, bits }) => {
+                let mut join = join_request(&state.borrow(), world.clone());
+                join.proof = Some(pow::solve(&challenge, bits));
+                send(&event_connection, &ClientMessage::Join(join));
+            }
+            Ok(ServerMessage::Session { player_id, .. }) => {
+                state.borrow_mut().replica.second_player_id = Some(player_id);
+            }
+            Ok(ServerMessage::Warning { text }) => log_warn!("Server warning for the second player: {}", text),
+            Ok(_) => {}
+            Err(e) => log_warn!("Error parsing server message: {:?}", e),
+        },
+        Event::Close { code, reason } => {
+            log_info!("Second player's connection closed: {} {}", code, reason);
+            event_connection.borrow_mut().take();
+            state.borrow_mut().replica.second_player_id = None;
+        }
+    })?;
+
+    *connection.borrow_mut() = Some(socket);
+    Ok(())
+}
+
+// Same shape as the first player, with their name and a 2 after it
+fn join_request(state: &ClientState, world: Option<String>) -> JoinRequest {
+    let settings = state.settings.borrow();
+    JoinRequest {
+        world,
+        invite: state.invite.clone(),
+        name: settings.name().map(|name| format!("{} 2", name)),
+        color: None,
+        shape: Some(settings.shape.name().to_string()),
+        session: None,
+        proof: None,
+        codecs: protocol::codec_names(),
+        compression: vec![protocol::COMPRESSION.to_string()],
+    }
+}
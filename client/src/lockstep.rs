@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use crate::{GameState, Map};
+
+// How often we tell the server what our copy of the world hashes to, in milliseconds
+const HASH_INTERVAL_MS: f64 = 500.0;
+
+// Our side of a lockstep world: the server relays who moved each tick, and we
+// move them with the same simulation code it runs
+#[derive(Default)]
+pub struct Lockstep {
+    last_hash_at: f64,
+    // A hash waiting to be sent: the tick and what the world hashed to after it
+    pending: Option<(u64, u64)>,
+}
+
+impl Lockstep {
+    // Run one tick: the players in `inputs` step with the keys they held, and
+    // everyone's knockback carries on
+    pub fn apply(&mut self, state: &mut GameState, map: &Map, tick: u64, speed: u32, inputs: &HashMap<String, u8>, now: f64) {
+        for (player_id, keys) in inputs {
+            if let Some(player) = state.players.get_mut(player_id).filter(|player| !player.eliminated && !player.frozen) {
+                player.simulate(|body| sim::step(body, *keys, speed, map.cell_size, map.boundary));
+            }
+        }
+        for player in state.players.values_mut().filter(|player| !player.frozen) {
+            player.simulate(|body| sim::apply_knockback(body, map.boundary));
+        }
+        if now - self.last_hash_at >= HASH_INTERVAL_MS {
+            self.last_hash_at = now;
+            self.pending = Some((tick, hash(state)));
+        }
+    }
+
+    pub fn take_hash(&mut self) -> Option<(u64, u64)> {
+        self.pending.take()
+    }
+}
+
+// Hashed the way the server does it, sorted by player ID
+fn hash(state: &GameState) -> u64 {
+    let mut players: Vec<_> = state.players.values().collect();
+    players.sort_by(|a, b| a.id.cmp(&b.id));
+    sim::hash(players.into_iter().map(|player| (player.id.as_str(), player.body())))
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Level> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+// Most verbose level that gets printed; release builds only show warnings and errors by default
+static THRESHOLD: AtomicU8 = AtomicU8::new(if cfg!(debug_assertions) { Level::Info as u8 } else { Level::Warn as u8 });
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= THRESHOLD.load(Ordering::Relaxed)
+}
+
+pub fn write(level: Level, message: &str) {
+    let message = JsValue::from_str(message);
+    match level {
+        Level::Error => web_sys::console::error_1(&message),
+        Level::Warn => web_sys::console::warn_1(&message),
+        Level::Info => web_sys::console::info_1(&message),
+        Level::Debug => web_sys::console::debug_1(&message),
+    }
+}
+
+// Set the log threshold from JS: error, warn, info or debug
+#[wasm_bindgen]
+pub fn set_log_level(level: &str) -> Result<(), JsValue> {
+    let level = Level::parse(level).ok_or_else(|| JsValue::from_str(&format!("Unknown log level {}", level)))?;
+    THRESHOLD.store(level as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+// Helper macros for leveled logging to the console; the message is only
+// formatted when its level is enabled
+macro_rules! log_at {
+    ($level:expr, $($t:tt)*) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, &format!($($t)*));
+        }
+    }
+}
+
+macro_rules! log_error {
+    ($($t:tt)*) => { log_at!($crate::logging::Level::Error, $($t)*) }
+}
+
+macro_rules! log_warn {
+    ($($t:tt)*) => { log_at!($crate::logging::Level::Warn, $($t)*) }
+}
+
+macro_rules! log_info {
+    ($($t:tt)*) => { log_at!($crate::logging::Level::Info, $($t)*) }
+}
+
+macro_rules! log_debug {
+    ($($t:tt)*) => { log_at!($crate::logging::Level::Debug, $($t)*) }
+}
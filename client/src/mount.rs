@@ -0,0 +1,152 @@
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, EventTarget};
+
+use crate::Connection;
+
+// A game running inside a page element
+pub struct Instance {
+    // The element the page gave us, and ours inside it that holds all the UI
+    pub mount: Element,
+    pub root: Element,
+    // One per local player
+    pub connections: Vec<Connection>,
+    pub handles: Rc<Handles>,
+}
+
+thread_local! {
+    static INSTANCES: RefCell<Vec<Instance>> = const { RefCell::new(Vec::new()) };
+}
+
+impl Instance {
+    fn stop(self) {
+        crate::leave(&self.connections);
+        self.handles.release();
+        self.root.remove();
+    }
+}
+
+// What a game hooked into the page: its listeners, timers and animation
+// frame, and the closures they call. Stopping the game undoes all of it, so
+// nothing of it keeps running or stays behind.
+#[derive(Default)]
+pub struct Handles {
+    listeners: RefCell<Vec<(EventTarget, &'static str, Function)>>,
+    intervals: RefCell<Vec<i32>>,
+    frame: Cell<Option<i32>>,
+    on_frame: RefCell<Option<Function>>,
+    closures: RefCell<Vec<Box<dyn Any>>>,
+}
+
+impl Handles {
+    // Keep a closure the page may call, like an element's onclick, until the game stops
+    pub fn keep<T: ?Sized + 'static>(&self, closure: Closure<T>) {
+        self.closures.borrow_mut().push(Box::new(closure));
+    }
+
+    // Call `closure` on `event` until the game stops
+    pub fn listen<T: ?Sized + 'static>(&self, target: &EventTarget, event: &'static str, closure: Closure<T>) -> Result<(), JsValue> {
+        let function: Function = closure.as_ref().unchecked_ref::<Function>().clone();
+        target.add_event_listener_with_callback(event, &function)?;
+        self.listeners.borrow_mut().push((target.clone(), event, function));
+        self.keep(closure);
+        Ok(())
+    }
+
+    // Call `closure` every `ms` milliseconds until the game stops
+    pub fn every(&self, ms: i32, closure: Closure<dyn FnMut()>) -> Result<(), JsValue> {
+        let window = web_sys::window().ok_or("no window")?;
+        let handle = window.set_interval_with_callback_and_timeout_and_arguments_0(closure.as_ref().unchecked_ref(), ms)?;
+        self.intervals.borrow_mut().push(handle);
+        self.keep(closure);
+        Ok(())
+    }
+
+    // Call `on_frame` before every repaint until the game stops
+    pub fn animate(self: &Rc<Self>, mut on_frame: impl FnMut() + 'static) -> Result<(), JsValue> {
+        let handles: Weak<Handles> = Rc::downgrade(self);
+        let closure = Closure::wrap(Box::new(move || {
+            on_frame();
+            if let Some(handles) = handles.upgrade() {
+                if let Err(err) = handles.next_frame() {
+                    log_error!("Error scheduling the next frame: {:?}", err);
+                }
+            }
+        }) as Box<dyn FnMut()>);
+        *self.on_frame.borrow_mut() = Some(closure.as_ref().unchecked_ref::<Function>().clone());
+        self.keep(closure);
+        self.next_frame()
+    }
+
+    fn next_frame(&self) -> Result<(), JsValue> {
+        if let Some(on_frame) = self.on_frame.borrow().as_ref() {
+            let window = web_sys::window().ok_or("no window")?;
+            self.frame.set(Some(window.request_animation_frame(on_frame)?));
+        }
+        Ok(())
+    }
+
+    fn release(&self) {
+        if let Some(window) = web_sys::window() {
+            for handle in self.intervals.take() {
+                window.clear_interval_with_handle(handle);
+            }
+            if let Some(frame) = self.frame.take() {
+                let _ = window.cancel_animation_frame(frame);
+            }
+        }
+        for (target, event, function) in self.listeners.take() {
+            let _ = target.remove_event_listener_with_callback(event, &function);
+        }
+        self.on_frame.take();
+        self.closures.take();
+    }
+}
+
+// The element named by the config's `mount`: an element, the ID of one, or
+// the page's body without either
+pub fn resolve(document: &Document, config: &JsValue) -> Result<Element, JsValue> {
+    let mount = if config.is_object() {
+        js_sys::Reflect::get(config, &JsValue::from_str("mount"))?
+    } else {
+        JsValue::UNDEFINED
+    };
+    if let Some(id) = mount.as_string() {
+        return document
+            .get_element_by_id(&id)
+            .ok_or_else(|| JsValue::from_str(&format!("No element with ID {}", id)));
+    }
+    if mount.is_undefined() || mount.is_null() {
+        return document.body().map(Element::from).ok_or_else(|| JsValue::from_str("document has no body"));
+    }
+    mount.dyn_into::<Element>().map_err(|_| JsValue::from_str("mount should be an element or its ID"))
+}
+
+// Stop the game that's running in `mount`, if there is one, and take its UI out of the page
+pub fn unmount(mount: &Element) {
+    let stopped = INSTANCES.with(|instances| {
+        let mut instances = instances.borrow_mut();
+        let index = instances.iter().position(|instance| instance.mount.is_same_node(Some(mount)))?;
+        Some(instances.swap_remove(index))
+    });
+    if let Some(instance) = stopped {
+        instance.stop();
+    }
+}
+
+// Stop every game on the page
+pub fn unmount_all() {
+    let stopped = INSTANCES.with(|instances| std::mem::take(&mut *instances.borrow_mut()));
+    for instance in stopped {
+        instance.stop();
+    }
+}
+
+pub fn register(instance: Instance) {
+    INSTANCES.with(|instances| instances.borrow_mut().push(instance));
+}
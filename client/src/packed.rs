@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use codec::packed::{ArchivedSnapshot, Snapshot, SnapshotPlayer, SnapshotTrail, LAYOUT_VERSION, MAGIC};
+
+use crate::roster::RosterEntry;
+use crate::shapes::Shape;
+use crate::{Food, GameState, Player};
+
+// Must match the server's quantized layout (server/src/packed.rs); the packed
+// one is codec::packed's
+const QUANTIZED_MAGIC: u8 = b'Q';
+const QUANTIZED_VERSION: u8 = 2;
+const FLAG_ELIMINATED: u8 = 1;
+const FLAG_AFK: u8 = 2;
+const FLAG_FROZEN: u8 = 4;
+const FLAG_GHOST: u8 = 8;
+// For a color that isn't in the palette (yet)
+const UNKNOWN_COLOR: &str = "#808080";
+
+// What quantized snapshots call players and colors by, learned from the
+// roster and Palette messages of one connection
+#[derive(Default)]
+pub struct Names {
+    // ID and name by number
+    players: HashMap<u32, (String, String)>,
+    colors: HashMap<u32, String>,
+}
+
+impl Names {
+    pub fn set_players(&mut self, players: &[RosterEntry]) {
+        self.players.clear();
+        for player in players {
+            self.add_player(player);
+        }
+    }
+
+    pub fn add_player(&mut self, player: &RosterEntry) {
+        if let Some(number) = player.number {
+            self.players.insert(number, (player.id.clone(), player.name.clone()));
+        }
+    }
+
+    pub fn remove_player(&mut self, player_id: &str) {
+        self.players.retain(|_, (id, _)| id != player_id);
+    }
+
+    pub fn add_colors(&mut self, colors: Vec<(u32, String)>) {
+        self.colors.extend(colors);
+    }
+}
+
+// Fewest bytes the items of a list can take in each layout, so a count can be
+// checked against what's left before anything is set aside for it
+const QUANTIZED_PLAYER_BYTES: usize = 9;
+const QUANTIZED_FOOD_BYTES: usize = 4;
+const QUANTIZED_TRAIL_BYTES: usize = 2;
+const QUANTIZED_POINT_BYTES: usize = 4;
+
+// Reads the fields of a packed snapshot in order
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < length {
+            return Err("snapshot ends early".to_string());
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    // A count of items at least `item_bytes` long each, which have to fit in
+    // the rest of the snapshot
+    fn checked(&self, count: u32, item_bytes: usize) -> Result<usize, String> {
+        let count = count as usize;
+        if count > self.data.len() / item_bytes {
+            return Err("count runs past the end of the snapshot".to_string());
+        }
+        Ok(count)
+    }
+
+    fn varint_count(&mut self, item_bytes: usize) -> Result<usize, String> {
+        let count = self.varint()?;
+        self.checked(count, item_bytes)
+    }
+
+    fn varint(&mut self) -> Result<u32, String> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint too long".to_string())
+    }
+
+    fn short_str(&mut self) -> Result<&'a str, String> {
+        let length = self.varint()? as usize;
+        std::str::from_utf8(self.take(length)?).map_err(|e| e.to_string())
+    }
+
+    // A number plus one, or 0 and the string it stands for
+    fn reference(&mut self) -> Result<Result<u32, &'a str>, String> {
+        match self.varint()? {
+            0 => Ok(Err(self.short_str()?)),
+            number => Ok(Ok(number - 1)),
+        }
+    }
+
+    // A quantized position back in world units
+    fn position(&mut self) -> Result<(u32, u32), String> {
+        Ok((dequantize(self.u16()?, sim::WORLD_WIDTH), dequantize(self.u16()?, sim::WORLD_HEIGHT)))
+    }
+}
+
+// A player as a snapshot has it, borrowed from the snapshot and the names
+struct Fields<'a> {
+    id: &'a str,
+    name: &'a str,
+    color: &'a str,
+    x: u32,
+    y: u32,
+    size: u32,
+    score: u32,
+    shape: Shape,
+    flags: u8,
+}
+
+fn shape(value: u8) -> Shape {
+    match value {
+        1 => Shape::Circle,
+        2 => Shape::Triangle,
+        _ => Shape::Square,
+    }
+}
+
+fn set_str(target: &mut String, value: &str) {
+    if target != value {
+        value.clone_into(target);
+    }
+}
+
+// Move a player to what the snapshot says, keeping its strings when they
+// haven't changed
+fn update(players: &mut HashMap<String, Player>, fields: Fields) {
+    let (eliminated, afk) = (fields.flags & FLAG_ELIMINATED != 0, fields.flags & FLAG_AFK != 0);
+    let (frozen, ghost) = (fields.flags & FLAG_FROZEN != 0, fields.flags & FLAG_GHOST != 0);
+    let Some(player) = players.get_mut(fields.id) else {
+        let (id, name, color) = (fields.id.to_string(), fields.name.to_string(), fields.color.to_string());
+        let Fields { x, y, size, score, shape, .. } = fields;
+        players.insert(id.clone(), Player { id, name, x, y, color, shape, size, eliminated, afk, frozen, ghost, bounce: (0, 0), score });
+        return;
+    };
+    set_str(&mut player.name, fields.name);
+    set_str(&mut player.color, fields.color);
+    (player.x, player.y, player.size, player.score, player.shape) = (fields.x, fields.y, fields.size, fields.score, fields.shape);
+    (player.eliminated, player.afk, player.frozen, player.ghost) = (eliminated, afk, frozen, ghost);
+    player.bounce = (0, 0);
+}
+
+// The points of a trail, in a vector that held an older one if there is one
+fn trail<'t>(trails: &'t mut HashMap<String, Vec<(u32, u32)>>, owner: &str) -> &'t mut Vec<(u32, u32)> {
+    if !trails.contains_key(owner) {
+        trails.insert(owner.to_string(), Vec::new());
+    }
+    let trail = trails.get_mut(owner).expect("just made sure it's there");
+    trail.clear();
+    trail
+}
+
+// Drop the players and trails the snapshot didn't have
+fn sweep(state: &mut GameState, mut players: Vec<&str>, mut owners: Vec<&str>) {
+    players.sort_unstable();
+    owners.sort_unstable();
+    state.players.retain(|id, _| players.binary_search(&id.as_str()).is_ok());
+    state.trails.retain(|owner, _| owners.binary_search(&owner.as_str()).is_ok());
+}
+
+// Game state in either layout, told apart by its first bytes, read into
+// `state` so its map, vectors and strings get reused rather than built anew
+// for each snapshot. Quantized ones are read with the numbers in `names`. On
+// an error `state` is left part way, so it should be one that isn't shown.
+pub fn unpack_into(data: &[u8], names: &Names, state: &mut GameState) -> Result<(), String> {
+    let mut reader = Reader { data };
+    match (reader.u8()?, reader.u8()?) {
+        (MAGIC, LAYOUT_VERSION) => unpack_packed(codec::packed::read(reader.data)?, state),
+        (QUANTIZED_MAGIC, QUANTIZED_VERSION) => unpack_quantized(reader, names, state),
+        _ => Err("not a packed snapshot this client can read".to_string()),
+    }
+}
+
+// Straight from the archive in the buffer; only what changed gets copied
+fn unpack_packed(snapshot: &ArchivedSnapshot, state: &mut GameState) -> Result<(), String> {
+    let mut seen = Vec::with_capacity(snapshot.players.len());
+    for player in snapshot.players.iter() {
+        let (id, name, color) = (&*player.id, &*player.name, &*player.color);
+        let (x, y, size, score) = (player.x.to_native(), player.y.to_native(), player.size.to_native(), player.score.to_native());
+        let (shape, flags) = (shape(player.shape), player.flags);
+        update(&mut state.players, Fields { id, name, color, x, y, size, score, shape, flags });
+        seen.push(id);
+    }
+
+    state.food.clear();
+    state.food.extend(snapshot.food.iter().map(|food| Food { x: food.0.to_native(), y: food.1.to_native() }));
+
+    let mut owners = Vec::with_capacity(snapshot.trails.len());
+    for archived in snapshot.trails.iter() {
+        let owner = &*archived.owner;
+        let trail = trail(&mut state.trails, owner);
+        trail.extend(archived.points.iter().map(|point| (point.0.to_native(), point.1.to_native())));
+        owners.push(owner);
+    }
+    sweep(state, seen, owners);
+    Ok(())
+}
+
+// Players and trails by a number we weren't told about are left out; they
+// come back once the roster catches up
+fn unpack_quantized(mut reader: Reader, names: &Names, state: &mut GameState) -> Result<(), String> {
+    let count = reader.varint_count(QUANTIZED_PLAYER_BYTES)?;
+    let mut seen = Vec::with_capacity(count);
+    for _ in 0..count {
+        let known = match reader.reference()? {
+            Ok(number) => names.players.get(&number).map(|(id, name)| (id.as_str(), name.as_str())),
+            Err(id) => Some((id, reader.short_str()?)),
+        };
+        let (x, y) = reader.position()?;
+        let (size, score) = (reader.varint()?, reader.varint()?);
+        let packed = reader.u8()?;
+        let color = match reader.reference()? {
+            Ok(number) => names.colors.get(&number).map_or(UNKNOWN_COLOR, String::as_str),
+            Err(color) => color,
+        };
+        if let Some((id, name)) = known {
+            let (shape, flags) = (shape(packed & 3), packed >> 4);
+            update(&mut state.players, Fields { id, name, color, x, y, size, score, shape, flags });
+            seen.push(id);
+        }
+    }
+
+    let count = reader.varint_count(QUANTIZED_FOOD_BYTES)?;
+    state.food.clear();
+    state.food.reserve(count);
+    for _ in 0..count {
+        let (x, y) = reader.position()?;
+        state.food.push(Food { x, y });
+    }
+
+    let count = reader.varint_count(QUANTIZED_TRAIL_BYTES)?;
+    let mut owners = Vec::with_capacity(count);
+    for _ in 0..count {
+        let owner = match reader.reference()? {
+            Ok(number) => names.players.get(&number).map(|(id, _)| id.as_str()),
+            Err(owner) => Some(owner),
+        };
+        let points = reader.varint_count(QUANTIZED_POINT_BYTES)?;
+        let Some(owner) = owner else {
+            reader.take(points * QUANTIZED_POINT_BYTES)?;
+            continue;
+        };
+        let trail = trail(&mut state.trails, owner);
+        trail.reserve(points);
+        for _ in 0..points {
+            trail.push(reader.position()?);
+        }
+        owners.push(owner);
+    }
+    sweep(state, seen, owners);
+    Ok(())
+}
+
+fn dequantize(value: u16, extent: u32) -> u32 {
+    ((value as u64 * extent as u64 + u16::MAX as u64 / 2) / u16::MAX as u64) as u32
+}
+
+// The other way around, for game state the connection worker got as JSON
+pub fn pack(state: &GameState) -> Vec<u8> {
+    let players = state
+        .players
+        .values()
+        .map(|player| SnapshotPlayer {
+            id: &player.id,
+            name: &player.name,
+            color: &player.color,
+            x: player.x,
+            y: player.y,
+            size: player.size,
+            score: player.score,
+            shape: match player.shape {
+                Shape::Square => 0,
+                Shape::Circle => 1,
+                Shape::Triangle => 2,
+            },
+            flags: [
+                (player.eliminated, FLAG_ELIMINATED),
+                (player.afk, FLAG_AFK),
+                (player.frozen, FLAG_FROZEN),
+                (player.ghost, FLAG_GHOST),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |flags, (_, flag)| flags | flag),
+        })
+        .collect();
+    let food = state.food.iter().map(|food| (food.x, food.y)).collect();
+    let trails = state.trails.iter().map(|(owner, points)| SnapshotTrail { owner, points }).collect();
+    codec::packed::write(&Snapshot { players, food, trails })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: &str, x: u32) -> Player {
+        let (id, name, color) = (id.to_string(), format!("{} name", id), "#FF0000".to_string());
+        Player { id, name, x, y: 10, color, shape: Shape::Circle, size: 50, eliminated: false, afk: true, frozen: false, ghost: false, bounce: (0, 0), score: 3 }
+    }
+
+    fn state(players: &[(&str, u32)]) -> GameState {
+        GameState {
+            players: players.iter().map(|&(id, x)| (id.to_string(), player(id, x))).collect(),
+            food: vec![Food { x: 1, y: 2 }],
+            trails: players.iter().map(|&(id, x)| (id.to_string(), vec![(x, 10)])).collect(),
+        }
+    }
+
+    #[test]
+    fn reads_snapshots_over_older_ones() {
+        let mut read = GameState::default();
+        unpack_into(&pack(&state(&[("a", 1), ("b", 2)])), &Names::default(), &mut read).unwrap();
+        unpack_into(&pack(&state(&[("b", 5), ("c", 6)])), &Names::default(), &mut read).unwrap();
+        let mut ids: Vec<_> = read.players.keys().cloned().collect();
+        ids.sort();
+        assert_eq!(ids, ["b", "c"]);
+        assert_eq!((read.players["b"].x, read.players["b"].afk, read.players["c"].name.as_str()), (5, true, "c name"));
+        assert_eq!(read.trails.len(), 2);
+        assert_eq!(read.trails["b"], [(5, 10)]);
+        assert_eq!(read.food.len(), 1);
+    }
+
+    #[test]
+    fn refuses_broken_snapshots() {
+        let mut data = vec![MAGIC, LAYOUT_VERSION];
+        data.extend(u32::MAX.to_le_bytes());
+        assert!(unpack_into(&data, &Names::default(), &mut GameState::default()).is_err());
+
+        let mut data = vec![QUANTIZED_MAGIC, QUANTIZED_VERSION, 0, 0];
+        data.extend([0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(unpack_into(&data, &Names::default(), &mut GameState::default()).is_err());
+
+        let mut data = pack(&state(&[("a", 1)]));
+        data.truncate(data.len() - 1);
+        assert!(unpack_into(&data, &Names::default(), &mut GameState::default()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
+
+use web_sys::CanvasRenderingContext2d;
+
+use crate::camera::View;
+
+// Particles alive at once; the oldest make way for new ones
+const MAX_PARTICLES: usize = 600;
+// How often a moving player kicks up a puff of dust, in milliseconds
+const DUST_INTERVAL_MS: f64 = 70.0;
+// How far a player has to move between frames to count as moving
+const DUST_MIN_STEP: f64 = 0.5;
+// World units per second squared pulling burst particles down
+const GRAVITY: f64 = 220.0;
+
+// Whether particles go under the players, like dust, or over them
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layer {
+    Below,
+    Above,
+}
+
+struct Particle {
+    x: f64,
+    y: f64,
+    // World units per second
+    vx: f64,
+    vy: f64,
+    gravity: f64,
+    size: f64,
+    color: String,
+    layer: Layer,
+    born: f64,
+    life_ms: f64,
+}
+
+// Short-lived specks spawned by what happens in the game. They move with the
+// animation frames, not the server's snapshots, and are only for show.
+pub struct Particles {
+    list: Vec<Particle>,
+    // Where each player was last frame and when they last left dust
+    movers: HashMap<String, Mover>,
+    updated_at: f64,
+    seed: u32,
+}
+
+struct Mover {
+    x: f64,
+    y: f64,
+    dust_at: f64,
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        Particles {
+            list: Vec::new(),
+            movers: HashMap::new(),
+            updated_at: 0.0,
+            seed: 0x9e37_79b9,
+        }
+    }
+}
+
+impl Particles {
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.movers.clear();
+    }
+
+    // Glints rising from where a player picked something up
+    pub fn sparkle(&mut self, x: f64, y: f64, now: f64) {
+        for _ in 0..12 {
+            let angle = self.random() * TAU;
+            let speed = 30.0 + self.random() * 50.0;
+            let color = if self.random() < 0.5 { "gold" } else { "white" };
+            let particle = Particle {
+                x,
+                y,
+                vx: angle.cos() * speed,
+                vy: angle.sin() * speed - 40.0,
+                gravity: 0.0,
+                size: 2.0 + self.random() * 2.0,
+                color: color.to_string(),
+                layer: Layer::Above,
+                born: now,
+                life_ms: 400.0 + self.random() * 300.0,
+            };
+            self.spawn(particle);
+        }
+    }
+
+    // A player breaking apart into pieces of its color
+    pub fn burst(&mut self, x: f64, y: f64, color: &str, now: f64) {
+        for _ in 0..40 {
+            let angle = self.random() * TAU;
+            let speed = 80.0 + self.random() * 160.0;
+            let particle = Particle {
+                x,
+                y,
+                vx: angle.cos() * speed,
+                vy: angle.sin() * speed,
+                gravity: GRAVITY,
+                size: 3.0 + self.random() * 4.0,
+                color: color.to_string(),
+                layer: Layer::Above,
+                born: now,
+                life_ms: 600.0 + self.random() * 500.0,
+            };
+            self.spawn(particle);
+        }
+    }
+
+    // Leave dust behind the players in `positions` that moved since the last
+    // frame: their ID, the middle of their bottom edge, and their size
+    pub fn track<'a>(&mut self, positions: impl Iterator<Item = (&'a str, f64, f64, f64)>, now: f64) {
+        let mut seen = HashSet::new();
+        for (id, x, y, size) in positions {
+            seen.insert(id);
+            let Some(mover) = self.movers.get_mut(id) else {
+                self.movers.insert(id.to_string(), Mover { x, y, dust_at: now });
+                continue;
+            };
+            let moved = (x - mover.x).hypot(y - mover.y);
+            // Teleports and wrapping around the edges aren't moves
+            let walking = moved > DUST_MIN_STEP && moved < size;
+            let (from_x, from_y) = (mover.x, mover.y);
+            mover.x = x;
+            mover.y = y;
+            if !walking || now - mover.dust_at < DUST_INTERVAL_MS {
+                continue;
+            }
+            mover.dust_at = now;
+            // Drifting back the way they came
+            let (back_x, back_y) = ((from_x - x) / moved, (from_y - y) / moved);
+            for _ in 0..2 {
+                let spread = (self.random() - 0.5) * size * 0.6;
+                let particle = Particle {
+                    x: x + spread,
+                    y,
+                    vx: back_x * 20.0 + (self.random() - 0.5) * 15.0,
+                    vy: back_y * 20.0 - self.random() * 10.0,
+                    gravity: 0.0,
+                    size: 3.0 + self.random() * 3.0,
+                    color: "rgba(150, 130, 100, 0.6)".to_string(),
+                    layer: Layer::Below,
+                    born: now,
+                    life_ms: 350.0 + self.random() * 200.0,
+                };
+                self.spawn(particle);
+            }
+        }
+        self.movers.retain(|id, _| seen.contains(id.as_str()));
+    }
+
+    // Move everything on to `now` and drop what burned out
+    pub fn update(&mut self, now: f64) {
+        let elapsed = ((now - self.updated_at) / 1000.0).clamp(0.0, 0.1);
+        self.updated_at = now;
+        self.list.retain(|particle| now - particle.born < particle.life_ms);
+        for particle in &mut self.list {
+            particle.vy += particle.gravity * elapsed;
+            particle.x += particle.vx * elapsed;
+            particle.y += particle.vy * elapsed;
+        }
+    }
+
+    // Draw the particles of one layer, shrinking and fading as they age
+    pub fn render(&self, context: &CanvasRenderingContext2d, layer: Layer, view: &View, now: f64) {
+        let shown = |particle: &&Particle| particle.layer == layer && view.shows(particle.x, particle.y, 0.0, 0.0);
+        for particle in self.list.iter().filter(shown) {
+            let left = (1.0 - (now - particle.born) / particle.life_ms).clamp(0.0, 1.0);
+            let size = particle.size * (0.5 + left / 2.0);
+            context.set_global_alpha(left);
+            context.set_fill_style_str(&particle.color);
+            context.fill_rect(particle.x - size / 2.0, particle.y - size / 2.0, size, size);
+        }
+        context.set_global_alpha(1.0);
+    }
+
+    fn spawn(&mut self, particle: Particle) {
+        if self.list.len() >= MAX_PARTICLES {
+            self.list.remove(0);
+        }
+        self.list.push(particle);
+    }
+
+    // Between 0 and 1; xorshift, since the look doesn't need better
+    fn random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f64 / u32::MAX as f64
+    }
+}
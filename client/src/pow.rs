@@ -0,0 +1,19 @@
+use sha1::{Digest, Sha1};
+
+// Find the number the server asked for before letting us join: written out
+// after its challenge, it has to SHA-1 hash to at least `bits` leading zero bits
+pub fn solve(challenge: &str, bits: u32) -> u64 {
+    (0..).find(|&proof| zero_bits(challenge, proof) >= bits).unwrap_or_default()
+}
+
+fn zero_bits(challenge: &str, proof: u64) -> u32 {
+    let digest = Sha1::new().chain_update(challenge).chain_update(proof.to_string()).finalize();
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
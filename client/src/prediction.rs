@@ -0,0 +1,76 @@
+use sim::{Body, Boundary, Movement};
+
+// Ticks simulated at most to catch up, like after the tab was in the background
+const MAX_CATCH_UP_TICKS: u32 = 10;
+
+// One of our players as we expect the server to have it once our input
+// arrives, moved with the same simulation code the server runs
+pub struct Prediction {
+    pub body: Body,
+    // Local time of the last simulated tick
+    last_tick: f64,
+    // Ticks simulated so far, and the one we last stepped on, for grid maps
+    ticks: u64,
+    last_step: Option<u64>,
+    // When the keys were last held, in local time
+    last_held: f64,
+}
+
+impl Prediction {
+    pub fn new(body: Body, now: f64) -> Prediction {
+        Prediction {
+            body,
+            last_tick: now,
+            ticks: 0,
+            last_step: None,
+            last_held: now,
+        }
+    }
+
+    // Simulate the ticks since the last call with `keys` held. `speed` includes
+    // any speed boost. Returns whether the body moved.
+    pub fn advance(&mut self, now: f64, keys: u8, movement: Movement, speed: u32, cell_size: Option<u32>, boundary: Boundary) -> bool {
+        let tick_ms = movement.tick_ms.max(1) as f64;
+        if keys != 0 {
+            self.last_held = now;
+        }
+        if now - self.last_tick > tick_ms * MAX_CATCH_UP_TICKS as f64 {
+            self.last_tick = now - tick_ms * MAX_CATCH_UP_TICKS as f64;
+        }
+        let before = self.body;
+        while now - self.last_tick >= tick_ms {
+            self.last_tick += tick_ms;
+            self.ticks += 1;
+            if keys != 0 {
+                let ticks_per_step = cell_size.map(|cell_size| sim::grid::ticks_per_step(cell_size, speed) as u64);
+                let waiting = ticks_per_step.is_some_and(|ticks_per_step| {
+                    self.last_step.is_some_and(|last| self.ticks - last < ticks_per_step)
+                });
+                if !waiting {
+                    self.last_step = Some(self.ticks);
+                    sim::step(&mut self.body, keys, speed, cell_size, boundary);
+                }
+            }
+            sim::apply_knockback(&mut self.body, boundary);
+        }
+        self.body != before
+    }
+
+    // Take a snapshot from the server into account. While our input is still on
+    // its way the server is behind us by about a round trip, so small gaps are
+    // expected; bigger ones (a push, a portal, a script) and whatever is left
+    // once we've stood still for a round trip go the server's way.
+    pub fn reconcile(&mut self, server: Body, movement: Movement, speed: u32, cell_size: Option<u32>, ping_ms: f64, now: f64) {
+        self.body.size = server.size;
+        let tick_ms = movement.tick_ms.max(1) as f64;
+        let in_flight = (ping_ms / tick_ms).ceil() as u32 + 2;
+        let step = cell_size.unwrap_or_else(|| sim::speed(speed, server.size));
+        let max_drift = step * in_flight;
+        let drift = self.body.x.abs_diff(server.x).max(self.body.y.abs_diff(server.y));
+        let settled = now - self.last_held > ping_ms + tick_ms && self.body.bounce == (0, 0);
+        if drift > max_drift || (settled && drift > 0) {
+            self.body.x = server.x;
+            self.body.y = server.y;
+        }
+    }
+}
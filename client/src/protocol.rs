@@ -0,0 +1,140 @@
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::WebSocket;
+
+#[cfg(not(feature = "json"))]
+use codec::{Bincode, MessagePack};
+#[cfg(feature = "json")]
+use codec::{PackedSnapshots, QuantizedSnapshots};
+pub use codec::Codec;
+use codec::Snapshots;
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+use crate::ServerMessage;
+
+// Most a compressed frame may inflate to, far more than any message the server
+// sends, so a corrupt or hostile one can't take all the memory
+const MAX_INFLATED_BYTES: usize = 16 * 1024 * 1024;
+
+// The content of a WebSocket data frame. The codec crate has its own, which
+// this converts to and from; this one is ours to add the socket's methods to.
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<codec::Frame> for Frame {
+    fn from(frame: codec::Frame) -> Frame {
+        match frame {
+            codec::Frame::Text(text) => Frame::Text(text),
+            codec::Frame::Binary(data) => Frame::Binary(data),
+        }
+    }
+}
+
+impl From<Frame> for codec::Frame {
+    fn from(frame: Frame) -> codec::Frame {
+        match frame {
+            Frame::Text(text) => codec::Frame::Text(text),
+            Frame::Binary(data) => codec::Frame::Binary(data),
+        }
+    }
+}
+
+impl Frame {
+    // The data of a MessageEvent: a string or an ArrayBuffer, decompressed if
+    // the server compressed it
+    pub fn of(data: &JsValue) -> Option<Frame> {
+        if let Some(text) = data.as_string() {
+            return Some(Frame::Text(text));
+        }
+        let buffer = data.dyn_ref::<js_sys::ArrayBuffer>()?;
+        let data = js_sys::Uint8Array::new(buffer).to_vec();
+        match data.as_slice() {
+            [b'Z', kind, compressed @ ..] => match (kind, decompress_to_vec_with_limit(compressed, MAX_INFLATED_BYTES)) {
+                (b't', Ok(text)) => String::from_utf8(text).ok().map(Frame::Text),
+                (_, Ok(data)) => Some(Frame::Binary(data)),
+                (_, Err(e)) => {
+                    log_warn!("Couldn't decompress a message: {}", e);
+                    None
+                }
+            },
+            _ => Some(Frame::Binary(data)),
+        }
+    }
+
+    pub fn send(&self, ws: &WebSocket) -> Result<(), JsValue> {
+        match self {
+            Frame::Text(text) => ws.send_with_str(text),
+            Frame::Binary(data) => ws.send_with_u8_array(data),
+        }
+    }
+}
+
+// The codec for a connection whose handshake didn't pick one, like through a
+// proxy that drops the header. It reads game state in either binary layout,
+// so it also understands servers that only have the packed one.
+#[cfg(feature = "json")]
+pub const FALLBACK: &dyn Codec = &QuantizedSnapshots;
+#[cfg(not(feature = "json"))]
+pub const FALLBACK: &dyn Codec = &MessagePack;
+
+// Codecs we ask the server for unless told otherwise, preferred first
+#[cfg(feature = "json")]
+const DEFAULT_CODECS: [&dyn Codec; 2] = [&QuantizedSnapshots, &PackedSnapshots];
+#[cfg(not(feature = "json"))]
+const DEFAULT_CODECS: [&dyn Codec; 2] = [&MessagePack, &Bincode];
+
+// Codecs to ask the server for: `preferred`, the name of one, then the defaults
+pub fn requested(preferred: Option<&str>) -> Vec<&'static dyn Codec> {
+    let preferred = preferred.and_then(|name| {
+        let codec = codec::choose([name]);
+        if codec.is_none() {
+            log_warn!("Unknown codec {}, using the default ones", name);
+        }
+        codec
+    });
+    let mut codecs: Vec<&'static dyn Codec> = preferred.into_iter().collect();
+    codecs.extend(DEFAULT_CODECS.into_iter().filter(|codec| preferred.is_none_or(|preferred| preferred.name() != codec.name())));
+    codecs
+}
+
+// The codecs we list in our Join, for a server that didn't get them in the
+// handshake. It switches to one after the Join without telling us first, so
+// these are only ones whose messages the fallback reads too.
+pub fn codec_names() -> Vec<String> {
+    if !cfg!(feature = "json") {
+        return Vec::new();
+    }
+    DEFAULT_CODECS.iter().map(|codec| codec.name().to_string()).collect()
+}
+
+// Compression we can read, listed in our Join
+pub const COMPRESSION: &str = "deflate";
+
+pub fn encode<T: Serialize>(message: &T, codec: &dyn Codec) -> Result<Frame, String> {
+    codec::encode(message, codec).map(Frame::from).map_err(|e| e.to_string())
+}
+
+// Game state in a binary layout comes out as it is, for the replica to read
+pub fn decode(frame: Frame, codec: &dyn Codec) -> Result<ServerMessage, String> {
+    let frame = match frame {
+        Frame::Binary(data) if codec.snapshots() != Snapshots::Messages => return Ok(ServerMessage::Packed { data }),
+        frame => frame,
+    };
+    codec::decode(frame.into(), codec).map_err(|e| e.to_string())
+}
+
+// A socket that asks the server for `codecs`
+pub fn open(url: &str, codecs: &[&dyn Codec]) -> Result<WebSocket, JsValue> {
+    let names = codecs.iter().map(|codec| JsValue::from_str(codec.name())).collect::<js_sys::Array>();
+    let ws = WebSocket::new_with_str_sequence(url, &names)?;
+    ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
+    Ok(ws)
+}
+
+// The codec the server picked in the handshake of an open socket
+pub fn negotiated(ws: &WebSocket) -> &'static dyn Codec {
+    codec::negotiate(&ws.protocol()).unwrap_or(FALLBACK)
+}
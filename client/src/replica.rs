@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use sim::Movement;
+
+use crate::lockstep::Lockstep;
+use crate::packed::{self, Names};
+use crate::prediction::Prediction;
+use crate::{GameState, Map, Player, ServerMessage};
+
+// How long a player takes to slide into the next grid cell
+const GRID_SLIDE_MS: f64 = 100.0;
+
+// A player moving between grid cells, drawn in between until the slide is over
+struct Slide {
+    from: (f64, f64),
+    started_at: f64,
+}
+
+// Our copy of the world, kept up from the server's messages, and where its
+// players get drawn. Nothing in here touches the page or reads the clock, so
+// a recorded trace replays into it the same way every time.
+#[derive(Default)]
+pub struct Replica {
+    pub game_state: GameState,
+    // The one before it, which the next snapshot is read into
+    pub previous: GameState,
+    pub map: Map,
+    // What quantized snapshots call players and colors by
+    pub names: Names,
+    // Last measured round trip time, in milliseconds
+    pub ping_ms: Option<f64>,
+    // Our own player, once the server told us
+    pub player_id: Option<String>,
+    // The second player on this keyboard, if there's one
+    pub second_player_id: Option<String>,
+    // How the server moves players, and where we expect our own to be
+    pub movement: Option<Movement>,
+    predictions: HashMap<String, Prediction>,
+    pub lockstep: Lockstep,
+    slides: HashMap<String, Slide>,
+    // No grid slides, and on the page no teleport flashes (prefers-reduced-motion)
+    pub reduced_motion: bool,
+}
+
+impl Replica {
+    pub fn new(reduced_motion: bool) -> Replica {
+        Replica { reduced_motion, ..Replica::default() }
+    }
+
+    // Take in what a server message says about the world, at `now`. `boost`
+    // multiplies our players' speed, for speed boost events. Messages the page
+    // has something to do with too come back for it; a snapshot that can't be
+    // read is an error, and the world stays as it was.
+    pub fn apply(&mut self, msg: ServerMessage, boost: f32, now: f64) -> Result<Option<ServerMessage>, String> {
+        match msg {
+            ServerMessage::State { game_state } => self.set_game_state(game_state, boost, now),
+            ServerMessage::Packed { data } => {
+                let mut game_state = std::mem::take(&mut self.previous);
+                let unpacked = packed::unpack_into(&data, &self.names, &mut game_state);
+                if unpacked.is_err() {
+                    self.previous = game_state;
+                    return unpacked.map(|_| None);
+                }
+                self.set_game_state(game_state, boost, now);
+            }
+            ServerMessage::Tick { tick, speed, inputs } => self.apply_tick(tick, speed, &inputs, boost, now),
+            ServerMessage::Palette { colors } => self.names.add_colors(colors),
+            ServerMessage::Pong { sent_at } => self.ping_ms = Some(now - sent_at),
+            msg => {
+                match &msg {
+                    ServerMessage::Map { map } => self.map = map.clone(),
+                    ServerMessage::Session { player_id, movement, .. } => {
+                        self.player_id = Some(player_id.clone());
+                        self.movement = *movement;
+                    }
+                    // Ticks come at a new pace, which prediction has to keep up with
+                    ServerMessage::TimeScale { movement, .. } => self.movement = Some(*movement),
+                    ServerMessage::Roster { players } => self.names.set_players(players),
+                    ServerMessage::PlayerJoined { player } => self.names.add_player(player),
+                    ServerMessage::PlayerLeft { player_id } => self.names.remove_player(player_id),
+                    _ => {}
+                }
+                return Ok(Some(msg));
+            }
+        }
+        Ok(None)
+    }
+
+    // Whether a player is played from this browser
+    pub fn is_local(&self, player_id: &str) -> bool {
+        [&self.player_id, &self.second_player_id]
+            .iter()
+            .any(|id| id.as_deref() == Some(player_id))
+    }
+
+    // Where to draw a player, partway into its cell while sliding on a grid
+    pub fn drawn_position(&self, player: &Player, now: f64) -> (f64, f64) {
+        let to = match self.predictions.get(&player.id) {
+            Some(prediction) => (prediction.body.x as f64, prediction.body.y as f64),
+            None => (player.x as f64, player.y as f64),
+        };
+        let Some(slide) = self.slides.get(&player.id) else {
+            return to;
+        };
+        let t = ((now - slide.started_at) / GRID_SLIDE_MS).clamp(0.0, 1.0);
+        (slide.from.0 + (to.0 - slide.from.0) * t, slide.from.1 + (to.1 - slide.from.1) * t)
+    }
+
+    // Take a new game state, starting slides for players that stepped to a
+    // neighboring cell
+    fn set_game_state(&mut self, game_state: GameState, boost: f32, now: f64) {
+        self.slides.retain(|_, slide| now - slide.started_at < GRID_SLIDE_MS);
+        if let (Some(cell_size), false) = (self.map.cell_size, self.reduced_motion) {
+            // Predicted players slide when the prediction steps
+            for player in game_state.players.values().filter(|player| !self.predictions.contains_key(&player.id)) {
+                let Some(old) = self.game_state.players.get(&player.id) else {
+                    continue;
+                };
+                let stepped = (old.x, old.y) != (player.x, player.y)
+                    && old.x.abs_diff(player.x) <= cell_size
+                    && old.y.abs_diff(player.y) <= cell_size;
+                if stepped {
+                    let from = self.drawn_position(old, now);
+                    self.slides.insert(player.id.clone(), Slide { from, started_at: now });
+                }
+            }
+        }
+        self.previous = std::mem::replace(&mut self.game_state, game_state);
+        self.reconcile(boost, now);
+    }
+
+    // Simulate a tick of a lockstep world from the inputs the server relayed
+    fn apply_tick(&mut self, tick: u64, speed: u32, inputs: &HashMap<String, u8>, boost: f32, now: f64) {
+        let mut game_state = self.game_state.clone();
+        self.lockstep.apply(&mut game_state, &self.map, tick, speed, inputs, now);
+        self.set_game_state(game_state, boost, now);
+    }
+
+    // Steps our players take per tick right now
+    fn speed(movement: Movement, boost: f32) -> u32 {
+        (movement.speed as f32 * boost).round() as u32
+    }
+
+    // Move our players with the keys they hold, ahead of the server's snapshots.
+    // `keys` are the first and the second player's.
+    pub fn predict(&mut self, keys: [u8; 2], boost: f32, now: f64) {
+        let Some(movement) = self.movement else {
+            return;
+        };
+        let speed = Replica::speed(movement, boost);
+        let local = [self.player_id.clone(), self.second_player_id.clone()];
+        for (player_id, keys) in local.into_iter().zip(keys) {
+            let Some(player) = player_id.and_then(|id| self.game_state.players.get(&id)).filter(|player| !player.eliminated && !player.frozen) else {
+                continue;
+            };
+            let prediction = self
+                .predictions
+                .entry(player.id.clone())
+                .or_insert_with(|| Prediction::new(player.body(), now));
+            let from = (prediction.body.x as f64, prediction.body.y as f64);
+            let moved = prediction.advance(now, keys, movement, speed, self.map.cell_size, self.map.boundary);
+            if moved && self.map.cell_size.is_some() && !self.reduced_motion {
+                self.slides.insert(player.id.clone(), Slide { from, started_at: now });
+            }
+        }
+    }
+
+    // Check the predictions against a new snapshot; eliminated, frozen and
+    // departed players are drawn where the server has them
+    fn reconcile(&mut self, boost: f32, now: f64) {
+        let Some(movement) = self.movement else {
+            return;
+        };
+        let speed = Replica::speed(movement, boost);
+        let ping_ms = self.ping_ms.unwrap_or_default();
+        let players = &self.game_state.players;
+        self.predictions.retain(|id, _| players.get(id).is_some_and(|player| !player.eliminated && !player.frozen));
+        for (id, prediction) in &mut self.predictions {
+            if let Some(player) = players.get(id) {
+                prediction.reconcile(player.body(), movement, speed, self.map.cell_size, ping_ms, now);
+            }
+        }
+    }
+
+    // Forget everything about the world we were in; the second player's
+    // connection says so itself when it closes
+    pub fn reset(&mut self) {
+        let second_player_id = self.second_player_id.take();
+        *self = Replica { second_player_id, ..Replica::new(self.reduced_motion) };
+    }
+}
+
+// The traces were recorded with the JSON codecs
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::protocol::{self, Frame};
+    use crate::trace::{Entry, Kind, Trace};
+
+    // Traces recorded against a real server, in the format download_trace() saves
+    const WALK: &str = include_str!("../tests/traces/walk.json");
+    const GRID: &str = include_str!("../tests/traces/grid.json");
+
+    fn frame(entry: &Entry) -> Frame {
+        match (&entry.text, &entry.binary) {
+            (Some(text), _) => Frame::Text(text.clone()),
+            (None, Some(hex)) => Frame::Binary(
+                (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect(),
+            ),
+            (None, None) => panic!("an entry without a frame"),
+        }
+    }
+
+    // The keys in one of our Input messages
+    fn input_keys(entry: &Entry) -> Option<u8> {
+        let envelope: Value = serde_json::from_str(entry.text.as_deref()?).ok()?;
+        (envelope["kind"] == "Input").then(|| envelope["payload"]["keys"].as_u64())?.map(|keys| keys as u8)
+    }
+
+    // Feed the first connection of a trace into a replica the way the page
+    // does: server messages as they came in, and a prediction step with the
+    // held keys right before each Input went out. `inspect` is called after
+    // every entry.
+    fn replay(json: &str, reduced_motion: bool, mut inspect: impl FnMut(&Replica, f64)) -> Replica {
+        let trace: Trace = serde_json::from_str(json).unwrap();
+        let mut replica = Replica::new(reduced_motion);
+        for entry in trace.entries.iter().filter(|entry| entry.socket == 1) {
+            match entry.kind {
+                Kind::In => {
+                    let msg = protocol::decode(frame(entry), protocol::FALLBACK).unwrap();
+                    replica.apply(msg, 1.0, entry.time).unwrap();
+                }
+                Kind::Out => {
+                    if let Some(keys) = input_keys(entry) {
+                        replica.predict([keys, 0], 1.0, entry.time);
+                    }
+                }
+                _ => {}
+            }
+            inspect(&replica, entry.time);
+        }
+        replica
+    }
+
+    // Where our own player is drawn, and where the server has them
+    fn own(replica: &Replica, now: f64) -> Option<((f64, f64), (u32, u32))> {
+        let player = replica.game_state.players.get(replica.player_id.as_ref()?)?;
+        Some((replica.drawn_position(player, now), (player.x, player.y)))
+    }
+
+    // Positions drawn for our player along the way, after the first snapshot,
+    // and how far each was from the server's
+    fn drawn_path(json: &str, reduced_motion: bool) -> (Replica, Vec<(f64, f64)>, f64) {
+        let mut path = Vec::new();
+        let mut max_gap: f64 = 0.0;
+        let replica = replay(json, reduced_motion, |replica, now| {
+            if let Some((drawn, server)) = own(replica, now) {
+                max_gap = max_gap.max((drawn.0 - server.0 as f64).abs()).max((drawn.1 - server.1 as f64).abs());
+                path.push(drawn);
+            }
+        });
+        (replica, path, max_gap)
+    }
+
+    #[test]
+    fn quantized_snapshots_resolve_through_the_roster() {
+        let replica = replay(WALK, false, |_, _| {});
+        let player_id = replica.player_id.as_deref().expect("the trace has a Session");
+        assert_eq!(replica.game_state.players[player_id].name, "Rec");
+        assert_eq!(replica.game_state.players[player_id].color, "#0000FF");
+        assert!(replica.ping_ms.is_some_and(|ping_ms| ping_ms >= 0.0));
+    }
+
+    // Walking right for a second: no rubber banding back toward the server
+    // while the keys are held, and once they're let go the prediction settles
+    // where the server has the player
+    #[test]
+    fn prediction_walks_smoothly_and_settles_on_the_server() {
+        let (replica, path, max_gap) = drawn_path(WALK, false);
+        assert!(path.windows(2).all(|pair| pair[1].0 >= pair[0].0), "went back: {:?}", path);
+        // Within a couple of steps of the server on a local connection
+        assert!(max_gap <= 10.0, "{}px off the server: {:?}", max_gap, path);
+        assert!(path.iter().all(|&(_, y)| y == 350.0));
+        let end = *path.last().unwrap();
+        let (drawn, server) = own(&replica, f64::INFINITY).unwrap();
+        assert_eq!(drawn, end);
+        assert_eq!(drawn, (server.0 as f64, server.1 as f64));
+        assert_eq!(server, (750, 350));
+    }
+
+    // On a grid our player steps a whole cell at a time and is drawn sliding
+    // in between, unless the player asked for less motion
+    #[test]
+    fn grid_steps_slide_between_cells() {
+        let (replica, path, max_gap) = drawn_path(GRID, false);
+        assert!(max_gap <= 50.0, "{}px off the server: {:?}", max_gap, path);
+        assert!(path.windows(2).all(|pair| pair[1].0 >= pair[0].0), "went back: {:?}", path);
+        assert!(path.iter().any(|&(x, _)| x % 50.0 != 0.0), "never slid: {:?}", path);
+        assert_eq!(own(&replica, f64::INFINITY).unwrap().0, (350.0, 450.0));
+
+        let (_, path, _) = drawn_path(GRID, true);
+        assert!(path.iter().all(|&(x, y)| x % 50.0 == 0.0 && y % 50.0 == 0.0), "slid: {:?}", path);
+        assert_eq!(path.last(), Some(&(350.0, 450.0)));
+    }
+
+    #[test]
+    fn reset_forgets_the_world() {
+        let mut replica = replay(WALK, true, |_, _| {});
+        replica.second_player_id = Some("player_2".to_string());
+        replica.reset();
+        assert!(replica.game_state.players.is_empty());
+        assert!(replica.player_id.is_none() && replica.movement.is_none() && replica.ping_ms.is_none());
+        assert_eq!(replica.second_player_id.as_deref(), Some("player_2"));
+        assert!(replica.reduced_motion);
+    }
+}
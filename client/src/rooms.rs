@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlButtonElement, HtmlElement, HtmlInputElement, HtmlSelectElement, MouseEvent};
+
+use crate::i18n::{fill, strings};
+use crate::mount::Handles;
+
+// How often the room list is asked for again while it's up
+pub const REFRESH_MS: i32 = 3000;
+
+// Modes a room can be created with - must match the server definition
+const MODES: [&str; 2] = ["free", "trail"];
+
+// The rules of a room - must match the server definition. Left out, the
+// server fills in its defaults.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoomConfig {
+    pub name: String,
+    pub speed: Option<u32>,
+    pub map: Option<String>,
+    pub max_players: Option<usize>,
+    pub mode: String,
+    #[serde(default = "friendly_fire_default")]
+    pub friendly_fire: bool,
+    #[serde(default)]
+    pub private: bool,
+    // A private room's invite code, from the server
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+fn friendly_fire_default() -> bool {
+    true
+}
+
+// What a room's host changes - must match the server definition. Left out,
+// it stays as it is.
+#[derive(Default, Serialize, Deserialize)]
+pub struct RoomSettings {
+    pub speed: Option<u32>,
+    pub max_players: Option<usize>,
+    pub friendly_fire: Option<bool>,
+}
+
+impl RoomSettings {
+    // From "/set <setting> <value>": speed, players or friendlyfire (on or off)
+    pub fn parse(setting: &str, value: &str) -> Option<RoomSettings> {
+        let mut settings = RoomSettings::default();
+        match setting {
+            "speed" => settings.speed = Some(value.parse().ok()?),
+            "players" => settings.max_players = Some(value.parse().ok()?),
+            "friendlyfire" => {
+                settings.friendly_fire = Some(match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return None,
+                })
+            }
+            _ => return None,
+        }
+        Some(settings)
+    }
+}
+
+// A world in the room list - must match the server definition
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoomEntry {
+    pub name: String,
+    pub mode: String,
+    pub players: usize,
+    pub max_players: usize,
+    // Opened by a player, rather than configured
+    #[serde(default)]
+    pub created: bool,
+}
+
+// A room's match as the server last told us about it
+pub struct Match {
+    // "waiting", "countdown" or "playing"
+    pub phase: String,
+    pub ready: Vec<String>,
+    // How many have to be ready for the countdown to start
+    pub needed: usize,
+}
+
+impl Match {
+    pub fn is_playing(&self) -> bool {
+        self.phase == "playing"
+    }
+}
+
+// The worlds on the server we're connected to, with a form to create a room,
+// shown between connecting and joining
+#[derive(Clone)]
+pub struct Lobby {
+    document: Document,
+    panel: HtmlElement,
+    table: Element,
+    refresh: HtmlButtonElement,
+    form: HtmlElement,
+    name: HtmlInputElement,
+    mode: HtmlSelectElement,
+    speed: HtmlInputElement,
+    max_players: HtmlInputElement,
+    friendly_fire: HtmlInputElement,
+    private: HtmlInputElement,
+    create: HtmlButtonElement,
+    status: Element,
+}
+
+impl Lobby {
+    pub fn new(document: &Document, parent: &Element) -> Result<Lobby, JsValue> {
+        let panel = document.create_element("section")?.dyn_into::<HtmlElement>()?;
+        panel.set_class_name("game-lobby");
+        panel.set_hidden(true);
+        let heading = document.create_element("h3")?;
+        heading.set_text_content(Some(strings().rooms));
+        panel.append_child(&heading)?;
+        let button = |label: &str| -> Result<HtmlButtonElement, JsValue> {
+            let button = document.create_element("button")?.dyn_into::<HtmlButtonElement>()?;
+            button.set_text_content(Some(label));
+            Ok(button)
+        };
+        let refresh = button(strings().refresh)?;
+        panel.append_child(&refresh)?;
+        let table = document.create_element("table")?;
+        panel.append_child(&table)?;
+
+        let form = document.create_element("fieldset")?.dyn_into::<HtmlElement>()?;
+        let legend = document.create_element("legend")?;
+        legend.set_text_content(Some(strings().create_room));
+        form.append_child(&legend)?;
+        let field = |label: &str, element: &Element| -> Result<(), JsValue> {
+            let row = document.create_element("label")?;
+            row.set_class_name("game-setting");
+            row.set_text_content(Some(&format!("{} ", label)));
+            row.append_child(element)?;
+            form.append_child(&row)?;
+            Ok(())
+        };
+        let input = |label: &str, input_type: &str| -> Result<HtmlInputElement, JsValue> {
+            let input = document.create_element("input")?.dyn_into::<HtmlInputElement>()?;
+            input.set_type(input_type);
+            field(label, &input)?;
+            Ok(input)
+        };
+        let name = input(strings().room_name, "text")?;
+        name.set_max_length(24);
+        let mode = document.create_element("select")?.dyn_into::<HtmlSelectElement>()?;
+        for value in MODES {
+            let option = document.create_element("option")?;
+            option.set_attribute("value", value)?;
+            option.set_text_content(Some(value));
+            mode.append_child(&option)?;
+        }
+        field(strings().room_mode, &mode)?;
+        // Left empty, the server picks
+        let speed = input(strings().room_speed, "number")?;
+        speed.set_min("1");
+        let max_players = input(strings().room_max_players, "number")?;
+        max_players.set_min("1");
+        let friendly_fire = input(strings().room_friendly_fire, "checkbox")?;
+        friendly_fire.set_checked(true);
+        let private = input(strings().room_private, "checkbox")?;
+        let create = button(strings().create)?;
+        form.append_child(&create)?;
+        panel.append_child(&form)?;
+
+        let status = document.create_element("p")?;
+        panel.append_child(&status)?;
+        parent.append_child(&panel)?;
+
+        Ok(Lobby {
+            document: document.clone(),
+            panel,
+            table,
+            refresh,
+            form,
+            name,
+            mode,
+            speed,
+            max_players,
+            friendly_fire,
+            private,
+            create,
+            status,
+        })
+    }
+
+    // Called with the world to join when one of the Join buttons is clicked
+    pub fn on_join(&self, handles: &Handles, mut on_join: impl FnMut(String) + 'static) -> Result<(), JsValue> {
+        let on_click = Closure::wrap(Box::new(move |e: MouseEvent| {
+            let button = e
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .and_then(|target| target.closest("button").ok().flatten());
+            if let Some(world) = button.and_then(|button| button.get_attribute("data-world")) {
+                on_join(world);
+            }
+        }) as Box<dyn FnMut(MouseEvent)>);
+        handles.listen(&self.table, "click", on_click)
+    }
+
+    // Called with the rules from the form when Create is clicked
+    pub fn on_create(&self, handles: &Handles, mut on_create: impl FnMut(RoomConfig) + 'static) -> Result<(), JsValue> {
+        let lobby = self.clone();
+        let on_click = Closure::wrap(Box::new(move || {
+            let name = lobby.name.value().trim().to_string();
+            if name.is_empty() {
+                lobby.set_status(strings().room_name_missing);
+                return;
+            }
+            lobby.set_status(strings().creating_room);
+            on_create(RoomConfig {
+                name,
+                speed: lobby.speed.value().parse().ok(),
+                map: None,
+                max_players: lobby.max_players.value().parse().ok(),
+                mode: lobby.mode.value(),
+                friendly_fire: lobby.friendly_fire.checked(),
+                private: lobby.private.checked(),
+                code: None,
+            });
+        }) as Box<dyn FnMut()>);
+        handles.listen(&self.create, "click", on_click)
+    }
+
+    pub fn on_refresh(&self, handles: &Handles, on_refresh: impl FnMut() + 'static) -> Result<(), JsValue> {
+        let on_click = Closure::wrap(Box::new(on_refresh) as Box<dyn FnMut()>);
+        handles.listen(&self.refresh, "click", on_click)
+    }
+
+    pub fn open(&self) {
+        self.table.set_inner_html("");
+        self.set_status(strings().loading_rooms);
+        self.panel.set_hidden(false);
+    }
+
+    pub fn close(&self) {
+        self.panel.set_hidden(true);
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.panel.hidden()
+    }
+
+    // Rebuild the table, and hide the form on servers that won't take the room
+    pub fn show(&self, rooms: &[RoomEntry], can_create: bool) -> Result<(), JsValue> {
+        self.table.set_inner_html("");
+        let header = self.document.create_element("tr")?;
+        for column in strings().room_columns.into_iter().chain([""]) {
+            let cell = self.document.create_element("th")?;
+            cell.set_text_content(Some(column));
+            header.append_child(&cell)?;
+        }
+        self.table.append_child(&header)?;
+        for room in rooms {
+            let row = self.document.create_element("tr")?;
+            let players = format!("{}/{}", room.players, room.max_players);
+            for text in [&room.name, &room.mode, &players] {
+                let cell = self.document.create_element("td")?;
+                cell.set_text_content(Some(text));
+                row.append_child(&cell)?;
+            }
+            let join = self.document.create_element("button")?.dyn_into::<HtmlButtonElement>()?;
+            join.set_text_content(Some(strings().join));
+            join.set_attribute("data-world", &room.name)?;
+            join.set_disabled(room.players >= room.max_players);
+            let cell = self.document.create_element("td")?;
+            cell.append_child(&join)?;
+            row.append_child(&cell)?;
+            self.table.append_child(&row)?;
+        }
+        self.form.set_hidden(!can_create);
+        if self.status.text_content().as_deref() == Some(strings().loading_rooms) {
+            self.set_status("");
+        }
+        Ok(())
+    }
+
+    pub fn refused(&self, reason: &str) {
+        self.set_status(&fill(strings().room_refused, &[&reason]));
+    }
+
+    fn set_status(&self, text: &str) {
+        self.status.set_text_content(Some(text));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, MouseEvent};
+
+use crate::i18n::{fill, strings};
+use crate::mount::Handles;
+
+// A connected player as listed in the roster - must match the server definition
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RosterEntry {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    // What quantized snapshots call the player by; servers before it don't send one
+    #[serde(default)]
+    pub number: Option<u32>,
+}
+
+impl RosterEntry {
+    // The name, or the ID for players without one
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            &self.id
+        } else {
+            &self.name
+        }
+    }
+}
+
+// Who's in the world, kept from the server's roster events and shown in a
+// collapsible panel with the player count
+pub struct Roster {
+    players: HashMap<String, RosterEntry>,
+    pings: HashMap<String, u32>,
+    // Who's ready for the room's match, while it hasn't started
+    ready: Option<HashSet<String>>,
+    // The room's host
+    host: Option<String>,
+    document: Document,
+    summary: Element,
+    list: Element,
+    // The player the camera follows instead of our own
+    followed: Option<String>,
+}
+
+impl Roster {
+    pub fn new(document: &Document, parent: &Element) -> Result<Roster, JsValue> {
+        let panel = document.create_element("details")?;
+        let summary = document.create_element("summary")?;
+        panel.append_child(&summary)?;
+        let list = document.create_element("ul")?;
+        list.set_class_name("game-roster");
+        panel.append_child(&list)?;
+        parent.append_child(&panel)?;
+
+        let roster = Roster {
+            players: HashMap::new(),
+            pings: HashMap::new(),
+            ready: None,
+            host: None,
+            document: document.clone(),
+            summary,
+            list,
+            followed: None,
+        };
+        roster.render();
+        Ok(roster)
+    }
+
+    // A player by display name, ignoring case
+    pub fn find_by_name(&self, name: &str) -> Option<&RosterEntry> {
+        self.players
+            .values()
+            .find(|player| player.display_name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn get(&self, player_id: &str) -> Option<&RosterEntry> {
+        self.players.get(player_id)
+    }
+
+    // How many players are in our world, ourselves included
+    pub fn online(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn set_all(&mut self, players: Vec<RosterEntry>) {
+        self.players = players.into_iter().map(|player| (player.id.clone(), player)).collect();
+        self.pings.retain(|id, _| self.players.contains_key(id));
+        self.followed = self.followed.take().filter(|id| self.players.contains_key(id));
+        self.render();
+    }
+
+    pub fn followed(&self) -> Option<&str> {
+        self.followed.as_deref()
+    }
+
+    pub fn follow(&mut self, player_id: Option<String>) {
+        self.followed = player_id;
+        self.render();
+    }
+
+    // Follow the player `step` places after the followed one in the list,
+    // starting from the top (or bottom) of the list when nobody is followed
+    pub fn follow_next(&mut self, step: isize) {
+        let ids: Vec<String> = self.sorted().iter().map(|player| player.id.clone()).collect();
+        if ids.is_empty() {
+            return;
+        }
+        let current = self.followed.as_ref().and_then(|id| ids.iter().position(|other| other == id));
+        let next = match current {
+            Some(index) => (index as isize + step).rem_euclid(ids.len() as isize) as usize,
+            None if step < 0 => ids.len() - 1,
+            None => 0,
+        };
+        self.follow(Some(ids[next].clone()));
+    }
+
+    // Call `on_pick` with the ID of a player whose entry was clicked
+    pub fn on_pick(&self, handles: &Handles, mut on_pick: impl FnMut(String) + 'static) -> Result<(), JsValue> {
+        let on_click = Closure::wrap(Box::new(move |e: MouseEvent| {
+            let item = e
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .and_then(|target| target.closest("li").ok().flatten());
+            if let Some(player_id) = item.and_then(|item| item.get_attribute("data-player-id")) {
+                on_pick(player_id);
+            }
+        }) as Box<dyn FnMut(MouseEvent)>);
+        handles.listen(&self.list, "click", on_click)
+    }
+
+    pub fn add(&mut self, player: RosterEntry) {
+        self.players.insert(player.id.clone(), player);
+        self.render();
+    }
+
+    // The player who left, if we knew them
+    pub fn remove(&mut self, player_id: &str) -> Option<RosterEntry> {
+        self.pings.remove(player_id);
+        let player = self.players.remove(player_id);
+        if self.followed.as_deref() == Some(player_id) {
+            self.followed = None;
+        }
+        self.render();
+        player
+    }
+
+    pub fn set_pings(&mut self, pings: HashMap<String, u32>) {
+        self.pings = pings;
+        self.render();
+    }
+
+    // Mark the ready players, or with None, nobody
+    pub fn set_ready(&mut self, ready: Option<HashSet<String>>) {
+        self.ready = ready;
+        self.render();
+    }
+
+    pub fn set_host(&mut self, host: Option<String>) {
+        self.host = host;
+        self.render();
+    }
+
+    pub fn clear(&mut self) {
+        self.ready = None;
+        self.host = None;
+        self.set_all(Vec::new());
+    }
+
+    fn render(&self) {
+        self.summary
+            .set_text_content(Some(&fill(strings().players_online, &[&self.players.len()])));
+        self.list.set_inner_html("");
+        for player in self.sorted() {
+            let Ok(item) = self.document.create_element("li") else {
+                continue;
+            };
+            let _ = item.set_attribute("data-player-id", &player.id);
+            if self.followed.as_ref() == Some(&player.id) {
+                item.set_class_name("game-followed");
+            }
+            let ping = match self.pings.get(&player.id) {
+                Some(ping) => format!(" ({} ms)", ping),
+                None => String::new(),
+            };
+            // The swatch is a colored square like the player's own
+            let swatch = format!(
+                "<span style=\"display: inline-block; width: 10px; height: 10px; margin-right: 6px; background: {}\"></span>",
+                escape(&player.color)
+            );
+            let ready = match &self.ready {
+                Some(ready) if ready.contains(&player.id) => strings().ready_mark,
+                Some(_) => strings().not_ready_mark,
+                None => "",
+            };
+            let host = if self.host.as_ref() == Some(&player.id) { strings().host_mark } else { "" };
+            item.set_inner_html(&format!("{}{}{}{}{}", swatch, escape(player.display_name()), host, ping, ready));
+            let _ = self.list.append_child(&item);
+        }
+    }
+
+    // By name, like the list shows them
+    fn sorted(&self) -> Vec<&RosterEntry> {
+        let mut players: Vec<&RosterEntry> = self.players.values().collect();
+        players.sort_by_key(|player| player.display_name().to_lowercase());
+        players
+    }
+}
+
+// Names and colors come from other players, so keep them out of the markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,322 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, Element, HtmlInputElement, HtmlSelectElement, KeyboardEvent};
+
+use crate::i18n::strings;
+use crate::json;
+use crate::mount::Handles;
+use crate::shapes::Shape;
+use crate::theme::{self, Theme};
+use crate::{KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP};
+
+// localStorage keys the settings and the server's session token are saved under
+const STORAGE_KEY: &str = "settings";
+const SESSION_KEY: &str = "session";
+
+// Player preferences, kept in localStorage
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Sent with Join; empty lets the server pick
+    pub name: String,
+    pub color: String,
+    pub shape: Shape,
+    pub keys: KeyBindings,
+    pub mute: bool,
+    pub show_ping: bool,
+    pub theme: Theme,
+    // Join with a second player on the arrow keys too
+    pub second_player: bool,
+    // Names of players whose chat is hidden, lowercase
+    pub muted_players: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub up: String,
+    pub left: String,
+    pub down: String,
+    pub right: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            up: "w".to_string(),
+            left: "a".to_string(),
+            down: "s".to_string(),
+            right: "d".to_string(),
+        }
+    }
+}
+
+impl KeyBindings {
+    // The keys of the second player on the same keyboard
+    pub fn arrows() -> Self {
+        KeyBindings {
+            up: "ArrowUp".to_string(),
+            left: "ArrowLeft".to_string(),
+            down: "ArrowDown".to_string(),
+            right: "ArrowRight".to_string(),
+        }
+    }
+
+    pub fn bit(&self, key: &str) -> u8 {
+        let bindings = [
+            (&self.up, KEY_UP),
+            (&self.left, KEY_LEFT),
+            (&self.down, KEY_DOWN),
+            (&self.right, KEY_RIGHT),
+        ];
+        bindings
+            .iter()
+            .filter(|(bound, _)| bound.eq_ignore_ascii_case(key))
+            .fold(0, |bits, (_, bit)| bits | bit)
+    }
+}
+
+impl Settings {
+    // The saved settings, or the defaults with `keys` if there are none (or
+    // they can't be read)
+    pub fn load(keys: KeyBindings) -> Settings {
+        storage()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .and_then(|text| json::parse(&text).ok())
+            .unwrap_or(Settings { keys, ..Settings::default() })
+    }
+
+    pub fn save(&self) {
+        let Some(storage) = storage() else {
+            return;
+        };
+        if let Ok(text) = json::stringify(self) {
+            if storage.set_item(STORAGE_KEY, &text).is_err() {
+                log_warn!("Couldn't save settings");
+            }
+        }
+    }
+
+    // The movement bit bound to a key, 0 for other keys
+    pub fn key_bit(&self, key: &str) -> u8 {
+        self.keys.bit(key)
+    }
+
+    pub fn name(&self) -> Option<String> {
+        let name = self.name.trim();
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    pub fn color(&self) -> Option<String> {
+        (!self.color.is_empty()).then(|| self.color.clone())
+    }
+
+    pub fn is_muted(&self, name: &str) -> bool {
+        self.muted_players.contains(&name.to_lowercase())
+    }
+
+    // Mute or unmute a player by name and save; false if nothing changed
+    pub fn set_muted(&mut self, name: &str, muted: bool) -> bool {
+        let name = name.to_lowercase();
+        if self.muted_players.contains(&name) == muted {
+            return false;
+        }
+        if muted {
+            self.muted_players.push(name);
+        } else {
+            self.muted_players.retain(|muted| *muted != name);
+        }
+        self.save();
+        true
+    }
+}
+
+// Token from the last server we played on, to come back as the same player
+pub fn session_token() -> Option<String> {
+    storage()?.get_item(SESSION_KEY).ok().flatten()
+}
+
+pub fn save_session_token(token: &str) {
+    if let Some(storage) = storage() {
+        if storage.set_item(SESSION_KEY, token).is_err() {
+            log_warn!("Couldn't save session token");
+        }
+    }
+}
+
+// The server's /auth/callback sends logged in players back with #login=<token>
+// in the page URL. The token becomes our session token and leaves the URL.
+pub fn take_login_token(location: &web_sys::Location) {
+    let Ok(hash) = location.hash() else {
+        return;
+    };
+    if let Some(token) = hash.strip_prefix("#login=").filter(|token| !token.is_empty()) {
+        save_session_token(token);
+        let _ = location.set_hash("");
+    }
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+// Add the collapsible settings panel to `parent`. Every change is saved right away;
+// name, color and shape apply the next time the player joins.
+pub fn build_panel(document: &Document, parent: &Element, settings: Rc<RefCell<Settings>>, handles: &Handles) -> Result<(), JsValue> {
+    let panel = document.create_element("details")?;
+    let summary = document.create_element("summary")?;
+    summary.set_text_content(Some(strings().settings));
+    panel.append_child(&summary)?;
+
+    let form = Form { document, parent: &panel, settings: &settings, handles };
+    let current = settings.borrow().clone();
+    form.add_input(strings().name_setting, "text", &current.name, |settings, input| {
+        settings.name = input.value();
+    })?;
+    let color = if current.color.is_empty() { "#ff0000" } else { current.color.as_str() };
+    form.add_input(strings().color_setting, "color", color, |settings, input| {
+        settings.color = input.value();
+    })?;
+    let shapes = Shape::ALL.map(|shape| shape.name());
+    form.add_select(strings().shape_setting, &shapes, current.shape.name(), |settings, value| {
+        if let Some(shape) = Shape::ALL.into_iter().find(|shape| shape.name() == value) {
+            settings.shape = shape;
+        }
+    })?;
+    form.add_key_input(strings().key_up, &current.keys.up, |keys| &mut keys.up)?;
+    form.add_key_input(strings().key_left, &current.keys.left, |keys| &mut keys.left)?;
+    form.add_key_input(strings().key_down, &current.keys.down, |keys| &mut keys.down)?;
+    form.add_key_input(strings().key_right, &current.keys.right, |keys| &mut keys.right)?;
+    form.add_checkbox(strings().mute_sounds, current.mute, |settings, on| settings.mute = on)?;
+    form.add_checkbox(strings().show_ping, current.show_ping, |settings, on| {
+        settings.show_ping = on;
+    })?;
+    form.add_checkbox(strings().second_player, current.second_player, |settings, on| {
+        settings.second_player = on;
+    })?;
+    let themes = Theme::ALL.map(|theme| theme.name());
+    let themed = document.clone();
+    form.add_select(strings().theme_setting, &themes, current.theme.name(), move |settings, value| {
+        if let Some(theme) = Theme::ALL.into_iter().find(|theme| theme.name() == value) {
+            settings.theme = theme;
+            theme::apply(&themed, theme);
+        }
+    })?;
+
+    parent.append_child(&panel)?;
+    Ok(())
+}
+
+// Where the panel's fields go, and what they change
+struct Form<'a> {
+    document: &'a Document,
+    parent: &'a Element,
+    settings: &'a Rc<RefCell<Settings>>,
+    handles: &'a Handles,
+}
+
+impl Form<'_> {
+    // A labeled input on its own line
+    fn labeled_input(&self, label: &str, input_type: &str) -> Result<HtmlInputElement, JsValue> {
+        let row = self.document.create_element("label")?;
+        row.set_class_name("game-setting");
+        row.set_text_content(Some(&format!("{} ", label)));
+        let input = self.document.create_element("input")?.dyn_into::<HtmlInputElement>()?;
+        input.set_type(input_type);
+        row.append_child(&input)?;
+        self.parent.append_child(&row)?;
+        Ok(input)
+    }
+
+    fn add_input(
+        &self,
+        label: &str,
+        input_type: &str,
+        value: &str,
+        apply: impl Fn(&mut Settings, &HtmlInputElement) + 'static,
+    ) -> Result<(), JsValue> {
+        let input = self.labeled_input(label, input_type)?;
+        input.set_value(value);
+        let settings = self.settings.clone();
+        let field = input.clone();
+        let on_change = Closure::wrap(Box::new(move || {
+            let mut settings = settings.borrow_mut();
+            apply(&mut settings, &field);
+            settings.save();
+        }) as Box<dyn FnMut()>);
+        self.handles.listen(&input, "change", on_change)
+    }
+
+    // A drop-down of `options`, passing the picked one to `apply`
+    fn add_select(
+        &self,
+        label: &str,
+        options: &[&str],
+        current: &str,
+        apply: impl Fn(&mut Settings, &str) + 'static,
+    ) -> Result<(), JsValue> {
+        let row = self.document.create_element("label")?;
+        row.set_class_name("game-setting");
+        row.set_text_content(Some(&format!("{} ", label)));
+        let select = self.document.create_element("select")?.dyn_into::<HtmlSelectElement>()?;
+        for name in options {
+            let option = self.document.create_element("option")?;
+            option.set_attribute("value", name)?;
+            option.set_text_content(Some(name));
+            select.append_child(&option)?;
+        }
+        select.set_value(current);
+        row.append_child(&select)?;
+        self.parent.append_child(&row)?;
+
+        let settings = self.settings.clone();
+        let field = select.clone();
+        let on_change = Closure::wrap(Box::new(move || {
+            let mut settings = settings.borrow_mut();
+            apply(&mut settings, &field.value());
+            settings.save();
+        }) as Box<dyn FnMut()>);
+        self.handles.listen(&select, "change", on_change)
+    }
+
+    fn add_checkbox(&self, label: &str, checked: bool, apply: impl Fn(&mut Settings, bool) + 'static) -> Result<(), JsValue> {
+        let input = self.labeled_input(label, "checkbox")?;
+        input.set_checked(checked);
+        let settings = self.settings.clone();
+        let field = input.clone();
+        let on_change = Closure::wrap(Box::new(move || {
+            let mut settings = settings.borrow_mut();
+            apply(&mut settings, field.checked());
+            settings.save();
+        }) as Box<dyn FnMut()>);
+        self.handles.listen(&input, "change", on_change)
+    }
+
+    // A field that binds whatever key is pressed while it has focus
+    fn add_key_input(&self, label: &str, value: &str, binding: fn(&mut KeyBindings) -> &mut String) -> Result<(), JsValue> {
+        let input = self.labeled_input(label, "text")?;
+        input.set_value(value);
+        input.set_size(10);
+        input.set_read_only(true);
+        let settings = self.settings.clone();
+        let field = input.clone();
+        let on_key = Closure::wrap(Box::new(move |e: KeyboardEvent| {
+            // Tab still moves between fields
+            if e.key() == "Tab" {
+                return;
+            }
+            e.prevent_default();
+            // Don't let the game see the key being bound
+            e.stop_propagation();
+            field.set_value(&e.key());
+            let mut settings = settings.borrow_mut();
+            *binding(&mut settings.keys) = e.key();
+            settings.save();
+        }) as Box<dyn FnMut(KeyboardEvent)>);
+        self.handles.listen(&input, "keydown", on_key)
+    }
+}
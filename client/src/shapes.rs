@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use web_sys::CanvasRenderingContext2d;
+
+// What a player is drawn as - must match the server definition
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    #[default]
+    Square,
+    Circle,
+    Triangle,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 3] = [Shape::Square, Shape::Circle, Shape::Triangle];
+
+    // The name used in JoinRequest and the settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Square => "square",
+            Shape::Circle => "circle",
+            Shape::Triangle => "triangle",
+        }
+    }
+}
+
+// Fill the shape inside the square at (x, y) with the current fill style
+pub fn fill(context: &CanvasRenderingContext2d, shape: Shape, x: f64, y: f64, size: f64) {
+    match shape {
+        Shape::Square => context.fill_rect(x, y, size, size),
+        _ => {
+            trace(context, shape, x, y, size);
+            context.fill();
+        }
+    }
+}
+
+// Outline the shape inside the square at (x, y) with the current stroke style
+pub fn stroke(context: &CanvasRenderingContext2d, shape: Shape, x: f64, y: f64, size: f64) {
+    match shape {
+        Shape::Square => context.stroke_rect(x, y, size, size),
+        _ => {
+            trace(context, shape, x, y, size);
+            context.stroke();
+        }
+    }
+}
+
+fn trace(context: &CanvasRenderingContext2d, shape: Shape, x: f64, y: f64, size: f64) {
+    context.begin_path();
+    match shape {
+        Shape::Square => context.rect(x, y, size, size),
+        Shape::Circle => {
+            let _ = context.arc(x + size / 2.0, y + size / 2.0, size / 2.0, 0.0, std::f64::consts::TAU);
+        }
+        Shape::Triangle => {
+            context.move_to(x + size / 2.0, y);
+            context.line_to(x + size, y + size);
+            context.line_to(x, y + size);
+            context.close_path();
+        }
+    }
+}
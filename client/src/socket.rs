@@ -0,0 +1,292 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use js_sys::{Array, Object, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, WebSocket, Worker, WorkerOptions, WorkerType};
+
+use crate::protocol::{self, Codec, Frame};
+use crate::packed;
+use crate::trace::{self, Kind};
+use crate::ServerMessage;
+
+// What happens on a connection
+pub enum Event {
+    Open,
+    Frame(Frame),
+    Close { code: u16, reason: String },
+}
+
+// A connection to the game server. The WebSocket either lives on this thread
+// or in a Web Worker running this same module (see worker.js), which reads
+// and decompresses frames and packs game state sent as JSON, so the page only
+// gets frames that are cheap to read and heavy rendering doesn't hold up the
+// socket. Dropping it closes the connection.
+pub struct Socket {
+    link: Link,
+    // Its number in traces
+    trace: u32,
+    // The one the server picked once it's open
+    codec: Rc<Cell<&'static dyn Codec>>,
+}
+
+enum Link {
+    Direct(Handlers),
+    Worker {
+        worker: Worker,
+        open: Rc<Cell<bool>>,
+        _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    },
+}
+
+// A WebSocket and the closures its events call, which it can't call anymore
+// once they're dropped
+pub struct Handlers {
+    ws: WebSocket,
+    _onopen: Closure<dyn FnMut(JsValue)>,
+    _onmessage: Closure<dyn FnMut(MessageEvent)>,
+    _onclose: Closure<dyn FnMut(web_sys::CloseEvent)>,
+}
+
+impl Drop for Handlers {
+    fn drop(&mut self) {
+        self.ws.set_onopen(None);
+        self.ws.set_onmessage(None);
+        self.ws.set_onclose(None);
+        let _ = self.ws.close();
+    }
+}
+
+impl Socket {
+    // Connect to `url` asking for `codecs`, in a worker started from
+    // `worker_url` if given
+    pub fn open(
+        url: &str,
+        worker_url: Option<&str>,
+        codecs: &[&'static dyn Codec],
+        mut on_event: impl FnMut(Event) + 'static,
+    ) -> Result<Socket, JsValue> {
+        let id = trace::next_socket();
+        trace::record(id, Kind::Connect, Some(url.to_string()));
+        let mut on_event = move |event: Event| {
+            match &event {
+                Event::Open => trace::record(id, Kind::Open, None),
+                Event::Frame(frame) => trace::record_frame(id, Kind::In, frame),
+                Event::Close { code, reason } => trace::record(id, Kind::Close, Some(format!("{} {}", code, reason))),
+            }
+            on_event(event);
+        };
+        let codec = Rc::new(Cell::new(protocol::FALLBACK));
+        let Some(worker_url) = worker_url else {
+            let ws = protocol::open(url, codecs)?;
+            let handlers = listen(ws, codec.clone(), on_event);
+            return Ok(Socket { link: Link::Direct(handlers), trace: id, codec });
+        };
+
+        let options = WorkerOptions::new();
+        options.set_type(WorkerType::Module);
+        let worker = Worker::new_with_options(worker_url, &options)?;
+        let open = Rc::new(Cell::new(false));
+        let message_open = open.clone();
+        let message_worker = worker.clone();
+        let message_codec = codec.clone();
+        let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
+            let data = e.data();
+            let field = |name: &str| Reflect::get(&data, &name.into()).ok();
+            let event = match Frame::of(&data) {
+                Some(frame) => Event::Frame(frame),
+                None => match field("kind").and_then(|kind| kind.as_string()).as_deref() {
+                    Some("open") => {
+                        message_open.set(true);
+                        let negotiated = field("protocol").and_then(|name| name.as_string());
+                        message_codec.set(negotiated.and_then(|name| codec::negotiate(&name)).unwrap_or(protocol::FALLBACK));
+                        Event::Open
+                    }
+                    Some("close") => {
+                        message_open.set(false);
+                        message_worker.terminate();
+                        Event::Close {
+                            code: field("code").and_then(|code| code.as_f64()).unwrap_or_default() as u16,
+                            reason: field("reason").and_then(|reason| reason.as_string()).unwrap_or_default(),
+                        }
+                    }
+                    _ => return,
+                },
+            };
+            on_event(event);
+        }) as Box<dyn FnMut(MessageEvent)>);
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        let names = codecs.iter().map(|codec| JsValue::from_str(codec.name())).collect::<Array>();
+        worker.post_message(&command("open", &[("url", url.into()), ("codecs", names.into())])?)?;
+        Ok(Socket { link: Link::Worker { worker, open, _onmessage: onmessage }, trace: id, codec })
+    }
+
+    // The codec to talk to the server with
+    pub fn codec(&self) -> &'static dyn Codec {
+        self.codec.get()
+    }
+
+    pub fn is_open(&self) -> bool {
+        match &self.link {
+            Link::Direct(handlers) => handlers.ws.ready_state() == WebSocket::OPEN,
+            Link::Worker { open, .. } => open.get(),
+        }
+    }
+
+    pub fn send(&self, frame: &Frame) -> Result<(), JsValue> {
+        trace::record_frame(self.trace, Kind::Out, frame);
+        match &self.link {
+            Link::Direct(handlers) => frame.send(&handlers.ws),
+            Link::Worker { worker, .. } => worker.post_message(&command("send", &[("frame", frame_value(frame))])?),
+        }
+    }
+
+    pub fn close(&self, code: u16, reason: &str) {
+        let _ = match &self.link {
+            Link::Direct(handlers) => handlers.ws.close_with_code_and_reason(code, reason),
+            Link::Worker { worker, .. } => command("close", &[("code", code.into()), ("reason", reason.into())])
+                .and_then(|command| worker.post_message(&command)),
+        };
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        if let Link::Worker { worker, .. } = self {
+            worker.set_onmessage(None);
+            worker.terminate();
+        }
+    }
+}
+
+// Call `on_event` for what happens on the socket, once it's open with the
+// codec the server picked in `codec`
+fn listen(ws: WebSocket, codec: Rc<Cell<&'static dyn Codec>>, on_event: impl FnMut(Event) + 'static) -> Handlers {
+    let on_event = Rc::new(RefCell::new(on_event));
+    let open_event = on_event.clone();
+    let open_ws = ws.clone();
+    let onopen = Closure::wrap(Box::new(move |_| {
+        codec.set(protocol::negotiated(&open_ws));
+        (open_event.borrow_mut())(Event::Open)
+    }) as Box<dyn FnMut(JsValue)>);
+    ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+
+    let message_event = on_event.clone();
+    let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
+        if let Some(frame) = Frame::of(&e.data()) {
+            (message_event.borrow_mut())(Event::Frame(frame));
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+    let onclose = Closure::wrap(Box::new(move |e: web_sys::CloseEvent| {
+        (on_event.borrow_mut())(Event::Close { code: e.code(), reason: e.reason() });
+    }) as Box<dyn FnMut(web_sys::CloseEvent)>);
+    ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+    Handlers { ws, _onopen: onopen, _onmessage: onmessage, _onclose: onclose }
+}
+
+// A message between the page and the worker: `{kind, ...fields}`
+fn command(kind: &str, fields: &[(&str, JsValue)]) -> Result<JsValue, JsValue> {
+    let command = Object::new();
+    Reflect::set(&command, &"kind".into(), &kind.into())?;
+    for (name, value) in fields {
+        Reflect::set(&command, &(*name).into(), value)?;
+    }
+    Ok(command.into())
+}
+
+// A frame as a string or an ArrayBuffer, like a MessageEvent has it
+fn frame_value(frame: &Frame) -> JsValue {
+    match frame {
+        Frame::Text(text) => text.into(),
+        Frame::Binary(data) => js_sys::Uint8Array::from(data.as_slice()).buffer().into(),
+    }
+}
+
+// Entry point of worker.js: run one socket for the page, starting with the
+// commands that came in while the module was loading
+#[wasm_bindgen]
+pub fn run_worker(queued: Array) {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let socket: Rc<RefCell<Option<Handlers>>> = Rc::new(RefCell::new(None));
+    let handle = {
+        let scope = scope.clone();
+        move |message: JsValue| {
+            if let Err(err) = run_command(&scope, &socket, &message) {
+                log_error!("Error in the connection worker: {:?}", err);
+            }
+        }
+    };
+    for message in queued.iter() {
+        handle(message);
+    }
+    let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| handle(e.data())) as Box<dyn FnMut(MessageEvent)>);
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+}
+
+fn run_command(scope: &DedicatedWorkerGlobalScope, socket: &Rc<RefCell<Option<Handlers>>>, message: &JsValue) -> Result<(), JsValue> {
+    let field = |name: &str| Reflect::get(message, &name.into());
+    match field("kind")?.as_string().as_deref() {
+        Some("open") => {
+            let url = field("url")?.as_string().unwrap_or_default();
+            let codecs: Vec<&'static dyn Codec> = Array::from(&field("codecs")?)
+                .iter()
+                .filter_map(|name| codec::choose([name.as_string()?.as_str()]))
+                .collect();
+            let ws = protocol::open(&url, &codecs)?;
+            let codec = Rc::new(Cell::new(protocol::FALLBACK));
+            let event_codec = codec.clone();
+            let scope = scope.clone();
+            let handlers = listen(ws, codec, move |event| {
+                let posted = match event {
+                    Event::Open => command("open", &[("protocol", event_codec.get().name().into())])
+                        .and_then(|open| scope.post_message(&open)),
+                    Event::Frame(frame) => post_frame(&scope, offload(frame, event_codec.get())),
+                    Event::Close { code, reason } => command("close", &[("code", code.into()), ("reason", reason.into())])
+                        .and_then(|close| scope.post_message(&close)),
+                };
+                if let Err(err) = posted {
+                    log_error!("Error passing on a message: {:?}", err);
+                }
+            });
+            *socket.borrow_mut() = Some(handlers);
+        }
+        Some("send") => {
+            if let (Some(handlers), Some(frame)) = (socket.borrow().as_ref(), Frame::of(&field("frame")?)) {
+                frame.send(&handlers.ws)?;
+            }
+        }
+        Some("close") => {
+            if let Some(Handlers { ws, .. }) = socket.borrow().as_ref() {
+                let code = field("code")?.as_f64().unwrap_or(1000.0) as u16;
+                ws.close_with_code_and_reason(code, &field("reason")?.as_string().unwrap_or_default())?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Game state in JSON, like from servers without the packed codec, goes on in
+// the packed layout; everything else as it came
+fn offload(frame: Frame, codec: &dyn Codec) -> Frame {
+    let Frame::Text(text) = frame else {
+        return frame;
+    };
+    match protocol::decode(Frame::Text(text.clone()), codec) {
+        Ok(ServerMessage::State { game_state }) => Frame::Binary(packed::pack(&game_state)),
+        _ => Frame::Text(text),
+    }
+}
+
+// Binary frames are handed over rather than copied
+fn post_frame(scope: &DedicatedWorkerGlobalScope, frame: Frame) -> Result<(), JsValue> {
+    let value = frame_value(&frame);
+    match frame {
+        Frame::Text(_) => scope.post_message(&value),
+        Frame::Binary(_) => scope.post_message_with_transfer(&value, &Array::of1(&value)),
+    }
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+
+use web_sys::AudioContext;
+
+thread_local! {
+    // Created on first use; browsers only allow audio after the page is interacted with
+    static CONTEXT: RefCell<Option<AudioContext>> = const { RefCell::new(None) };
+}
+
+// Play a short sine tone
+pub fn beep(frequency: f32, duration_secs: f64) {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        if context.is_none() {
+            *context = AudioContext::new().ok();
+        }
+        if let Some(context) = context.as_ref() {
+            if let Err(e) = play(context, frequency, duration_secs) {
+                log_debug!("Couldn't play sound: {:?}", e);
+            }
+        }
+    });
+}
+
+fn play(context: &AudioContext, frequency: f32, duration_secs: f64) -> Result<(), wasm_bindgen::JsValue> {
+    let oscillator = context.create_oscillator()?;
+    let gain = context.create_gain()?;
+    oscillator.frequency().set_value(frequency);
+    // Fade out so the tone doesn't click when it stops
+    let now = context.current_time();
+    gain.gain().set_value_at_time(0.2, now)?;
+    gain.gain().linear_ramp_to_value_at_time(0.0, now + duration_secs)?;
+    oscillator.connect_with_audio_node(&gain)?;
+    gain.connect_with_audio_node(&context.destination())?;
+    oscillator.start()?;
+    oscillator.stop_with_when(now + duration_secs)?;
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use web_sys::CanvasRenderingContext2d;
+
+use crate::i18n::{fill, strings};
+use crate::theme;
+use crate::ClientState;
+
+// How long the stats stay up after a round ends, in milliseconds
+pub const ROUND_STATS_MS: f64 = 6000.0;
+
+// What a player did since connecting - must match the server definition
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PlayerStats {
+    pub distance: f64,
+    pub pickups: u32,
+    pub alive_ms: u64,
+    pub messages: u32,
+    pub rounds_won: u32,
+}
+
+// The stats the server sent with the last round's end
+pub struct RoundStats {
+    pub stats: HashMap<String, PlayerStats>,
+    pub shown_at: f64,
+}
+
+// A table of everyone's stats over the middle of the canvas, the farthest
+// traveled first
+pub fn render(context: &CanvasRenderingContext2d, state: &ClientState, round: &RoundStats) {
+    let palette = theme::palette();
+    let mut rows: Vec<(&str, &PlayerStats)> = round
+        .stats
+        .iter()
+        .map(|(id, stats)| (state.display_name(id), stats))
+        .collect();
+    rows.sort_by(|a, b| b.1.distance.total_cmp(&a.1.distance).then_with(|| a.0.cmp(b.0)));
+
+    let height = 70.0 + rows.len() as f64 * 22.0;
+    let top = (600.0 - height).max(0.0) / 2.0;
+    context.set_fill_style_str(palette.banner_background);
+    context.fill_rect(100.0, top, 600.0, height);
+    context.set_fill_style_str(palette.banner_text);
+    context.set_font("bold 18px Arial");
+    let _ = context.fill_text(strings().round_stats, 120.0, top + 28.0);
+
+    let columns = [120.0, 300.0, 400.0, 500.0, 600.0];
+    context.set_font("bold 14px Arial");
+    for (x, header) in columns.iter().zip(strings().stats_columns) {
+        let _ = context.fill_text(header, *x, top + 52.0);
+    }
+    context.set_font("14px Arial");
+    for (i, (name, stats)) in rows.iter().enumerate() {
+        let y = top + 74.0 + i as f64 * 22.0;
+        let cells = [
+            name.to_string(),
+            format!("{:.0}", stats.distance),
+            stats.pickups.to_string(),
+            fill(strings().seconds, &[&(stats.alive_ms / 1000)]),
+            stats.messages.to_string(),
+        ];
+        for (x, cell) in columns.iter().zip(&cells) {
+            let _ = context.fill_text(cell, *x, y);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use web_sys::CanvasRenderingContext2d;
+
+use crate::theme;
+
+// Size of name tags at zoom 1, in world units
+const LABEL_PX: f64 = 14.0;
+// Widest a name tag gets before it's cut short, in multiples of its font size
+const MAX_WIDTH_EMS: f64 = 7.0;
+// Measured widths kept before the cache starts over
+const MAX_CACHED: usize = 1024;
+const ELLIPSIS: &str = "…";
+
+// Text drawn in the world, over players of any color
+pub struct Labels {
+    // Widths measured so far, by font and text
+    widths: RefCell<HashMap<String, f64>>,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Labels { widths: RefCell::new(HashMap::new()) }
+    }
+}
+
+// Font size in world units for the camera's zoom: zoomed out, text shrinks
+// less than the world does so it stays readable; zoomed in, it grows less
+pub fn label_px(zoom: f64) -> f64 {
+    (LABEL_PX / zoom.sqrt()).round()
+}
+
+impl Labels {
+    // Draw `text` with its baseline at (x, y), cut short with an ellipsis past
+    // the widest a tag gets, and outlined so it reads on `backdrop` (the
+    // color it's drawn over, as #rrggbb) and on whatever is next to it
+    pub fn draw(&self, context: &CanvasRenderingContext2d, text: &str, x: f64, y: f64, px: f64, backdrop: &str) {
+        let font = format!("{}px Arial", px);
+        context.set_font(&font);
+        let text = self.fit(context, &font, text, px * MAX_WIDTH_EMS);
+        let (fill, outline) = if is_light(backdrop) {
+            ("#111", "rgba(255, 255, 255, 0.7)")
+        } else {
+            (theme::palette().player_label, "rgba(0, 0, 0, 0.7)")
+        };
+        context.set_line_width(px / 5.0);
+        context.set_line_join("round");
+        context.set_stroke_style_str(outline);
+        let _ = context.stroke_text(&text, x, y);
+        context.set_fill_style_str(fill);
+        let _ = context.fill_text(&text, x, y);
+        context.set_line_width(1.0);
+    }
+
+    // As much of `text` as fits in `max_width`, with an ellipsis if it didn't all fit
+    fn fit<'a>(&self, context: &CanvasRenderingContext2d, font: &str, text: &'a str, max_width: f64) -> Cow<'a, str> {
+        if self.width(context, font, text) <= max_width {
+            return Cow::Borrowed(text);
+        }
+        // The most characters that still fit, found by halving
+        let ends: Vec<usize> = text.char_indices().map(|(i, _)| i).skip(1).collect();
+        let (mut low, mut high) = (0, ends.len());
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            let candidate = format!("{}{}", text[..ends[mid - 1]].trim_end(), ELLIPSIS);
+            if self.width(context, font, &candidate) <= max_width {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let kept = if low == 0 { "" } else { text[..ends[low - 1]].trim_end() };
+        Cow::Owned(format!("{}{}", kept, ELLIPSIS))
+    }
+
+    fn width(&self, context: &CanvasRenderingContext2d, font: &str, text: &str) -> f64 {
+        let key = format!("{}\n{}", font, text);
+        if let Some(width) = self.widths.borrow().get(&key) {
+            return *width;
+        }
+        let width = context.measure_text(text).map_or(0.0, |metrics| metrics.width());
+        let mut widths = self.widths.borrow_mut();
+        if widths.len() >= MAX_CACHED {
+            widths.clear();
+        }
+        widths.insert(key, width);
+        width
+    }
+}
+
+// Whether dark text reads better than light text on `color`, by its
+// relative luminance; anything but #rrggbb counts as dark
+fn is_light(color: &str) -> bool {
+    let Some(hex) = color.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii()) else {
+        return false;
+    };
+    let channel = |i: usize| {
+        let value = u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f64 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(0) + 0.7152 * channel(2) + 0.0722 * channel(4) > 0.179
+}
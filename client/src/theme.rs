@@ -0,0 +1,156 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Document, MediaQueryList};
+
+// Light or dark look for the page (CSS classes in a stylesheet the client
+// injects) and the canvas (a palette the renderer draws with)
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    // Follow prefers-color-scheme
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+// Colors of everything drawn on the canvas
+pub struct Palette {
+    pub background: &'static str,
+    pub world_border: &'static str,
+    pub own_outline: &'static str,
+    pub frozen_outline: &'static str,
+    pub player_label: &'static str,
+    pub text: &'static str,
+    // "r, g, b" of notifications, which fade out
+    pub toast_rgb: &'static str,
+    pub banner_background: &'static str,
+    pub banner_text: &'static str,
+    pub food: &'static str,
+    pub shade: &'static str,
+}
+
+const LIGHT: Palette = Palette {
+    background: "white",
+    world_border: "gray",
+    own_outline: "black",
+    frozen_outline: "#3aa0e8",
+    player_label: "white",
+    text: "black",
+    toast_rgb: "0, 0, 0",
+    banner_background: "rgba(0, 0, 0, 0.6)",
+    banner_text: "white",
+    food: "seagreen",
+    shade: "rgba(0, 0, 0, 0.4)",
+};
+
+const DARK: Palette = Palette {
+    background: "#1e1e1e",
+    world_border: "#777",
+    own_outline: "white",
+    frozen_outline: "#7cc8ff",
+    player_label: "white",
+    text: "#eee",
+    toast_rgb: "238, 238, 238",
+    banner_background: "rgba(255, 255, 255, 0.2)",
+    banner_text: "white",
+    food: "mediumseagreen",
+    shade: "rgba(0, 0, 0, 0.6)",
+};
+
+// Colors and layout of the page around the canvas. Everything the client
+// creates gets one of these classes instead of inline styles.
+const STYLESHEET: &str = r#"
+:root { --game-background: white; --game-text: black; --game-border: #ccc; --game-muted: gray; --game-whisper: rebeccapurple; }
+:root.game-dark { --game-background: #121212; --game-text: #ddd; --game-border: #444; --game-muted: #999; --game-whisper: #c9a7ff; }
+body { background: var(--game-background); color: var(--game-text); }
+.game-canvas { border: 1px solid var(--game-text); width: 800px; height: 600px; }
+.game-chat { width: 800px; margin: 0 auto; }
+.game-chat-log { height: 120px; overflow-y: auto; border: 1px solid var(--game-border); padding: 4px; font-size: 14px; }
+.game-chat-input { width: 100%; box-sizing: border-box; }
+.game-whisper { color: var(--game-whisper); font-style: italic; }
+.game-notice { color: var(--game-muted); }
+.game-feed { max-height: 100px; overflow-y: auto; font-size: 13px; color: var(--game-muted); }
+.game-roster { list-style: none; padding-left: 10px; }
+.game-roster li { cursor: pointer; }
+.game-followed { font-weight: bold; }
+.game-setting { display: block; margin: 4px 0; }
+.game-sr-only { position: absolute; width: 1px; height: 1px; overflow: hidden; clip: rect(0 0 0 0); white-space: nowrap; }
+:root.game-dark input, :root.game-dark select, :root.game-dark button { background: #2a2a2a; color: var(--game-text); border: 1px solid var(--game-border); }
+"#;
+
+thread_local! {
+    // The theme picked last, and whether it came out dark
+    static THEME: Cell<Theme> = const { Cell::new(Theme::System) };
+    static DARK_ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+// ID of the injected stylesheet, which every game on the page shares
+const STYLESHEET_ID: &str = "game-theme";
+
+// Add the stylesheet and apply `theme`, following the system setting as it
+// changes while the theme in use says so
+pub fn install(document: &Document, theme: Theme) -> Result<(), JsValue> {
+    apply(document, theme);
+    if document.get_element_by_id(STYLESHEET_ID).is_some() {
+        return Ok(());
+    }
+    let style = document.create_element("style")?;
+    style.set_id(STYLESHEET_ID);
+    style.set_text_content(Some(STYLESHEET));
+    document.head().ok_or("document has no head")?.append_child(&style)?;
+
+    if let Some(query) = dark_query() {
+        let watched = document.clone();
+        let on_change = Closure::wrap(Box::new(move || {
+            let theme = THEME.with(Cell::get);
+            if theme == Theme::System {
+                apply(&watched, theme);
+            }
+        }) as Box<dyn FnMut()>);
+        query.add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref())?;
+        on_change.forget();
+    }
+    Ok(())
+}
+
+pub fn apply(document: &Document, theme: Theme) {
+    let dark = match theme {
+        Theme::System => dark_query().is_some_and(|query| query.matches()),
+        Theme::Light => false,
+        Theme::Dark => true,
+    };
+    THEME.with(|current| current.set(theme));
+    DARK_ACTIVE.with(|active| active.set(dark));
+    if let Some(root) = document.document_element() {
+        let _ = root.class_list().toggle_with_force("game-dark", dark);
+    }
+}
+
+// The palette for the theme in use
+pub fn palette() -> &'static Palette {
+    if DARK_ACTIVE.with(Cell::get) {
+        &DARK
+    } else {
+        &LIGHT
+    }
+}
+
+fn dark_query() -> Option<MediaQueryList> {
+    web_sys::window()?.match_media("(prefers-color-scheme: dark)").ok()?
+}
@@ -0,0 +1,134 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
+
+use crate::json;
+use crate::protocol::Frame;
+
+// Entries kept unless start_trace() is given another number; older ones make
+// room for new ones
+const DEFAULT_CAPACITY: usize = 10_000;
+
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+    static NEXT_SOCKET: Cell<u32> = const { Cell::new(1) };
+}
+
+// The messages on our connections while tracing, to take a bug home and
+// replay what led up to it
+#[derive(Serialize, Deserialize)]
+pub struct Trace {
+    // Milliseconds since the epoch, like every entry's time
+    pub started: f64,
+    pub capacity: usize,
+    // Entries that were pushed out to make room
+    pub dropped: u64,
+    pub entries: VecDeque<Entry>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    // We're opening a socket; the text is its URL
+    Connect,
+    Open,
+    In,
+    Out,
+    // The text is the close code and reason
+    Close,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub time: f64,
+    // Which of the page's connections, numbered as they were opened
+    pub socket: u32,
+    pub kind: Kind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    // Binary frames in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+}
+
+// A number for a socket that's opening, to tell its entries apart
+pub fn next_socket() -> u32 {
+    NEXT_SOCKET.with(|next| next.replace(next.get() + 1))
+}
+
+pub fn is_tracing() -> bool {
+    TRACE.with(|trace| trace.borrow().is_some())
+}
+
+pub fn record(socket: u32, kind: Kind, text: Option<String>) {
+    push(Entry { time: js_sys::Date::now(), socket, kind, text, binary: None });
+}
+
+// A frame as it went out, or as it came in after decompressing
+pub fn record_frame(socket: u32, kind: Kind, frame: &Frame) {
+    if !is_tracing() {
+        return;
+    }
+    let (text, binary) = match frame {
+        Frame::Text(text) => (Some(text.clone()), None),
+        Frame::Binary(data) => (None, Some(data.iter().map(|byte| format!("{:02x}", byte)).collect())),
+    };
+    push(Entry { time: js_sys::Date::now(), socket, kind, text, binary });
+}
+
+fn push(entry: Entry) {
+    TRACE.with(|trace| {
+        let mut trace = trace.borrow_mut();
+        let Some(trace) = trace.as_mut() else {
+            return;
+        };
+        if trace.entries.len() >= trace.capacity {
+            trace.entries.pop_front();
+            trace.dropped += 1;
+        }
+        trace.entries.push_back(entry);
+    });
+}
+
+// Start recording messages from here on, keeping the last `capacity` of
+// them. Whatever was recorded before is thrown away.
+#[wasm_bindgen]
+pub fn start_trace(capacity: Option<usize>) {
+    let capacity = capacity.filter(|&capacity| capacity > 0).unwrap_or(DEFAULT_CAPACITY);
+    let started = js_sys::Date::now();
+    TRACE.with(|trace| *trace.borrow_mut() = Some(Trace { started, capacity, dropped: 0, entries: VecDeque::new() }));
+    log_info!("Tracing the last {} messages", capacity);
+}
+
+// Stop recording and throw the trace away
+#[wasm_bindgen]
+pub fn stop_trace() {
+    TRACE.with(|trace| trace.borrow_mut().take());
+}
+
+// The trace so far, as JSON; null when not tracing
+#[wasm_bindgen]
+pub fn export_trace() -> Option<String> {
+    TRACE.with(|trace| trace.borrow().as_ref().and_then(|trace| json::stringify(trace).ok()))
+}
+
+// Save the trace so far as a JSON file, through the browser's downloads
+#[wasm_bindgen]
+pub fn download_trace() -> Result<(), JsValue> {
+    let json = export_trace().ok_or_else(|| JsValue::from_str("Not tracing; call start_trace() first"))?;
+    let options = BlobPropertyBag::new();
+    options.set_type("application/json");
+    let blob = Blob::new_with_str_sequence_and_options(&js_sys::Array::of1(&json.into()), &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window().and_then(|window| window.document()).ok_or("no document")?;
+    let link: HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+    link.set_href(&url);
+    link.set_download(&format!("trace-{}.json", js_sys::Date::now() as u64));
+    link.click();
+    Url::revoke_object_url(&url)
+}
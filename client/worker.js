@@ -0,0 +1,9 @@
+// Runs a game connection off the page's thread; pass `worker: "worker.js"` to init()
+import load, { run_worker } from "./pkg/client.js";
+
+// Hold on to what the page sends while the module loads
+const queued = [];
+self.onmessage = (e) => queued.push(e.data);
+
+await load();
+run_worker(queued);
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+use crate::{seal, unseal, Codec, CodecError, Frame, Value};
+
+// Deepest a message read from a peer may nest, like serde_json's limit
+pub(crate) const MAX_DEPTH: usize = 128;
+
+// Envelopes, as MessagePack in binary frames: the JSON envelope's structure
+// without the text, so numbers and field names come out smaller
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn name(&self) -> &'static str {
+        "squaregame.v1.msgpack"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        let data = rmp_serde::to_vec(&seal(message)?).map_err(|e| e.to_string())?;
+        Ok(Frame::Binary(data))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        let Frame::Binary(data) = frame else {
+            return Err("Text messages aren't supported".into());
+        };
+        let mut deserializer = rmp_serde::Deserializer::new(data.as_slice());
+        deserializer.set_max_depth(MAX_DEPTH);
+        unseal(Value::deserialize(&mut deserializer).map_err(|e| e.to_string())?)
+    }
+}
+
+// Envelopes, as bincode in binary frames. Bincode needs the type to read a
+// message back, and the two ends' message types don't line up field for
+// field, so what it encodes is serde's view of the message as nodes.
+pub struct Bincode;
+
+// One value of serde's data model. Lists and maps are followed by their
+// items, so a message is a flat list of nodes bincode reads without
+// recursing, and only build() has to watch how deep a peer nests it.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) enum Node {
+    Unit,
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Char(char),
+    Text(String),
+    Bytes(Vec<u8>),
+    None,
+    // Followed by the value
+    Some,
+    Newtype,
+    // Followed by that many values, or keys and values
+    Seq(u32),
+    Map(u32),
+}
+
+impl Codec for Bincode {
+    fn name(&self) -> &'static str {
+        "squaregame.v1.bincode"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        let mut nodes = Vec::new();
+        flatten(seal(message)?, &mut nodes);
+        let data = bincode::DefaultOptions::new().serialize(&nodes).map_err(|e| e.to_string())?;
+        Ok(Frame::Binary(data))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        let Frame::Binary(data) = frame else {
+            return Err("Text messages aren't supported".into());
+        };
+        let options = bincode::DefaultOptions::new().with_limit(data.len() as u64);
+        let nodes: Vec<Node> = options.deserialize(&data).map_err(|e| e.to_string())?;
+        let mut nodes = nodes.into_iter();
+        let value = build(&mut nodes, 0)?;
+        if nodes.next().is_some() {
+            return Err("nodes left over after the message".into());
+        }
+        unseal(value)
+    }
+}
+
+fn flatten(value: Value, nodes: &mut Vec<Node>) {
+    match value {
+        Value::Unit => nodes.push(Node::Unit),
+        Value::Bool(b) => nodes.push(Node::Bool(b)),
+        Value::U8(n) => nodes.push(Node::Unsigned(n.into())),
+        Value::U16(n) => nodes.push(Node::Unsigned(n.into())),
+        Value::U32(n) => nodes.push(Node::Unsigned(n.into())),
+        Value::U64(n) => nodes.push(Node::Unsigned(n)),
+        Value::I8(n) => nodes.push(Node::Signed(n.into())),
+        Value::I16(n) => nodes.push(Node::Signed(n.into())),
+        Value::I32(n) => nodes.push(Node::Signed(n.into())),
+        Value::I64(n) => nodes.push(Node::Signed(n)),
+        Value::F32(n) => nodes.push(Node::Float(n.into())),
+        Value::F64(n) => nodes.push(Node::Float(n)),
+        Value::Char(c) => nodes.push(Node::Char(c)),
+        Value::String(text) => nodes.push(Node::Text(text)),
+        Value::Bytes(data) => nodes.push(Node::Bytes(data)),
+        Value::Option(None) => nodes.push(Node::None),
+        Value::Option(Some(value)) => {
+            nodes.push(Node::Some);
+            flatten(*value, nodes);
+        }
+        Value::Newtype(value) => {
+            nodes.push(Node::Newtype);
+            flatten(*value, nodes);
+        }
+        Value::Seq(items) => {
+            nodes.push(Node::Seq(items.len() as u32));
+            for item in items {
+                flatten(item, nodes);
+            }
+        }
+        Value::Map(entries) => {
+            nodes.push(Node::Map(entries.len() as u32));
+            for (key, value) in entries {
+                flatten(key, nodes);
+                flatten(value, nodes);
+            }
+        }
+    }
+}
+
+// The value starting at the next node
+fn build(nodes: &mut std::vec::IntoIter<Node>, depth: usize) -> Result<Value, CodecError> {
+    if depth > MAX_DEPTH {
+        return Err("message nested too deeply".into());
+    }
+    let node = nodes.next().ok_or("message ended early")?;
+    Ok(match node {
+        Node::Unit => Value::Unit,
+        Node::Bool(b) => Value::Bool(b),
+        Node::Unsigned(n) => Value::U64(n),
+        Node::Signed(n) => Value::I64(n),
+        Node::Float(n) => Value::F64(n),
+        Node::Char(c) => Value::Char(c),
+        Node::Text(text) => Value::String(text),
+        Node::Bytes(data) => Value::Bytes(data),
+        Node::None => Value::Option(None),
+        Node::Some => Value::Option(Some(Box::new(build(nodes, depth + 1)?))),
+        Node::Newtype => Value::Newtype(Box::new(build(nodes, depth + 1)?)),
+        Node::Seq(len) => {
+            // Every item takes a node, so there can't be more than are left
+            let mut items = Vec::with_capacity((len as usize).min(nodes.len()));
+            for _ in 0..len {
+                items.push(build(nodes, depth + 1)?);
+            }
+            Value::Seq(items)
+        }
+        Node::Map(len) => {
+            let mut entries = BTreeMap::new();
+            for _ in 0..len {
+                let key = build(nodes, depth + 1)?;
+                entries.insert(key, build(nodes, depth + 1)?);
+            }
+            Value::Map(entries)
+        }
+    })
+}
@@ -0,0 +1,87 @@
+use crate::{seal, unseal, Codec, CodecError, Frame, Snapshots, Value};
+
+fn json_error(e: serde_json::Error) -> CodecError {
+    e.to_string().into()
+}
+
+// The original format, a message as serde writes it: `{"Chat": {"text": "hi"}}`
+pub struct BareJson;
+
+impl Codec for BareJson {
+    fn name(&self) -> &'static str {
+        "squaregame.json"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        serde_json::to_string(&message).map(Frame::Text).map_err(json_error)
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        match frame {
+            Frame::Text(text) => serde_json::from_str(&text).map_err(json_error),
+            Frame::Binary(_) => Err("Binary messages aren't supported".into()),
+        }
+    }
+}
+
+// Messages in a versioned envelope: `{"version": 1, "kind": "Chat", "payload": {"text": "hi"}}`.
+// Bare messages are still read, so older peers can switch over gradually.
+pub struct EnvelopeJson;
+
+impl Codec for EnvelopeJson {
+    fn name(&self) -> &'static str {
+        "squaregame.v1.json"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        BareJson.encode(seal(message)?)
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        unseal(BareJson.decode(frame)?)
+    }
+}
+
+// Envelopes, except for game state, which goes in binary frames in the packed
+// layout. The client reads it without a JSON parse per snapshot.
+pub struct PackedSnapshots;
+
+impl Codec for PackedSnapshots {
+    fn name(&self) -> &'static str {
+        "squaregame.v1.packed"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        EnvelopeJson.encode(message)
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        EnvelopeJson.decode(frame)
+    }
+
+    fn snapshots(&self) -> Snapshots {
+        Snapshots::Packed
+    }
+}
+
+// Like PackedSnapshots, but game state is quantized: with players and colors
+// by number, a fraction of the size in worlds with many players
+pub struct QuantizedSnapshots;
+
+impl Codec for QuantizedSnapshots {
+    fn name(&self) -> &'static str {
+        "squaregame.v1.quantized"
+    }
+
+    fn encode(&self, message: Value) -> Result<Frame, CodecError> {
+        EnvelopeJson.encode(message)
+    }
+
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError> {
+        EnvelopeJson.decode(frame)
+    }
+
+    fn snapshots(&self) -> Snapshots {
+        Snapshots::Quantized
+    }
+}
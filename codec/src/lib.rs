@@ -0,0 +1,226 @@
+// Wire formats shared by the server and the client. Codecs work on serde's
+// view of a message, so the game's message types don't need to know about
+// them; each client gets the one it asked for in the WebSocket handshake.
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+pub use serde_value::Value;
+
+mod binary;
+#[cfg(feature = "json")]
+mod json;
+pub mod packed;
+
+pub use binary::{Bincode, MessagePack};
+#[cfg(feature = "json")]
+pub use json::{BareJson, EnvelopeJson, PackedSnapshots, QuantizedSnapshots};
+
+// Version of the envelope and the messages inside it. Bump it for changes old
+// clients can't handle; messages from newer peers are refused.
+pub const PROTOCOL_VERSION: u64 = 1;
+
+// Why a message couldn't be encoded or decoded
+#[derive(Debug)]
+pub struct CodecError(String);
+
+impl std::fmt::Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<String> for CodecError {
+    fn from(reason: String) -> CodecError {
+        CodecError(reason)
+    }
+}
+
+impl From<&str> for CodecError {
+    fn from(reason: &str) -> CodecError {
+        CodecError(reason.to_string())
+    }
+}
+
+// The content of a WebSocket data frame
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+// How a codec sends game state snapshots
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Snapshots {
+    // Like any other message
+    Messages,
+    // In binary frames in the packed layout (packed::Snapshot)
+    Packed,
+    // In binary frames in the quantized layout (the server's packed::quantize)
+    Quantized,
+}
+
+// A wire format
+pub trait Codec: Sync {
+    // Subprotocol clients ask for in the Sec-WebSocket-Protocol header
+    fn name(&self) -> &'static str;
+    fn encode(&self, message: Value) -> Result<Frame, CodecError>;
+    fn decode(&self, frame: Frame) -> Result<Value, CodecError>;
+
+    // Game state snapshots are most of the traffic, so a codec may have them
+    // sent in a layout of their own instead of through serde
+    fn snapshots(&self) -> Snapshots {
+        Snapshots::Messages
+    }
+}
+
+// Every codec, preferred ones first
+#[cfg(feature = "json")]
+pub const CODECS: [&dyn Codec; 6] = [&QuantizedSnapshots, &PackedSnapshots, &MessagePack, &Bincode, &EnvelopeJson, &BareJson];
+#[cfg(not(feature = "json"))]
+pub const CODECS: [&dyn Codec; 2] = [&MessagePack, &Bincode];
+
+// The first codec in a Sec-WebSocket-Protocol header ("a, b") we support
+pub fn negotiate(requested: &str) -> Option<&'static dyn Codec> {
+    choose(requested.split(',').map(str::trim))
+}
+
+// The first of these codec names we support
+pub fn choose<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'static dyn Codec> {
+    names
+        .into_iter()
+        .find_map(|name| CODECS.into_iter().find(|codec| codec.name() == name))
+}
+
+pub fn encode<T: Serialize>(message: &T, codec: &dyn Codec) -> Result<Frame, CodecError> {
+    let value = serde_value::to_value(message).map_err(|e| e.to_string())?;
+    codec.encode(value)
+}
+
+pub fn decode<T: DeserializeOwned>(frame: Frame, codec: &dyn Codec) -> Result<T, CodecError> {
+    Ok(codec.decode(frame)?.deserialize_into().map_err(|e| e.to_string())?)
+}
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_string())
+}
+
+// A message in a versioned envelope: {version: 1, kind: "Chat", payload: {text: "hi"}}.
+// Serde writes a variant with fields as {kind: payload} and one without as "kind".
+fn seal(message: Value) -> Result<Value, CodecError> {
+    let mut envelope = BTreeMap::new();
+    envelope.insert(key("version"), Value::U64(PROTOCOL_VERSION));
+    match message {
+        Value::Map(variant) if variant.len() == 1 => {
+            let (kind, payload) = variant.into_iter().next().expect("checked the length");
+            envelope.insert(key("kind"), kind);
+            envelope.insert(key("payload"), payload);
+        }
+        Value::String(kind) => {
+            envelope.insert(key("kind"), Value::String(kind));
+        }
+        other => return Err(format!("not an enum variant: {:?}", other).into()),
+    }
+    Ok(Value::Map(envelope))
+}
+
+// The message in an envelope, as serde reads it. Anything without a version
+// is taken to be a bare message.
+fn unseal(value: Value) -> Result<Value, CodecError> {
+    let Value::Map(mut envelope) = value else {
+        return Ok(value);
+    };
+    let Some(version) = envelope.get(&key("version")) else {
+        return Ok(Value::Map(envelope));
+    };
+    match unsigned(version) {
+        Some(version) if version <= PROTOCOL_VERSION => {}
+        _ => return Err(format!("unsupported protocol version {:?}", version).into()),
+    }
+    let kind = match envelope.remove(&key("kind")) {
+        Some(Value::String(kind)) => kind,
+        _ => return Err("envelope without a kind".into()),
+    };
+    Ok(match envelope.remove(&key("payload")) {
+        Some(payload) => Value::Map(BTreeMap::from([(Value::String(kind), payload)])),
+        None => Value::String(kind),
+    })
+}
+
+// Formats store small numbers in whatever width fits them
+fn unsigned(value: &Value) -> Option<u64> {
+    match *value {
+        Value::U8(n) => Some(n.into()),
+        Value::U16(n) => Some(n.into()),
+        Value::U32(n) => Some(n.into()),
+        Value::U64(n) => Some(n),
+        Value::I8(n) => u64::try_from(n).ok(),
+        Value::I16(n) => u64::try_from(n).ok(),
+        Value::I32(n) => u64::try_from(n).ok(),
+        Value::I64(n) => u64::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Message {
+        Chat { text: String },
+        Move { x: i32, y: i32, scores: BTreeMap<String, u64> },
+        Leave,
+    }
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::Chat { text: "hi".to_string() },
+            Message::Move { x: -3, y: 400, scores: BTreeMap::from([("alice".to_string(), 7)]) },
+            Message::Leave,
+        ]
+    }
+
+    #[test]
+    fn every_codec_reads_back_what_it_writes() {
+        for codec in CODECS {
+            for message in messages() {
+                let frame = encode(&message, codec).unwrap();
+                assert_eq!(decode::<Message>(frame, codec).unwrap(), message, "{}", codec.name());
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_newer_envelopes() {
+        let mut envelope = BTreeMap::new();
+        envelope.insert(key("version"), Value::U64(PROTOCOL_VERSION + 1));
+        envelope.insert(key("kind"), key("Leave"));
+        let data = rmp_serde::to_vec(&Value::Map(envelope)).unwrap();
+        assert!(decode::<Message>(Frame::Binary(data), &MessagePack).is_err());
+    }
+
+    #[test]
+    fn bincode_refuses_truncated_and_deep_messages() {
+        let Frame::Binary(data) = encode(&messages()[1], &Bincode).unwrap() else {
+            panic!("bincode writes binary frames");
+        };
+        assert!(decode::<Message>(Frame::Binary(data[..data.len() - 1].to_vec()), &Bincode).is_err());
+
+        let mut nodes = vec![binary::Node::Some; binary::MAX_DEPTH + 2];
+        nodes.push(binary::Node::Unit);
+        let data = bincode::Options::serialize(bincode::DefaultOptions::new(), &nodes).unwrap();
+        assert!(Bincode.decode(Frame::Binary(data)).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn envelope_json_reads_bare_messages() {
+        let frame = Frame::Text(r#"{"Chat":{"text":"hi"}}"#.to_string());
+        assert_eq!(decode::<Message>(frame, &EnvelopeJson).unwrap(), messages().remove(0));
+    }
+}
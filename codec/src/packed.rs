@@ -0,0 +1,62 @@
+use rkyv::rancor::Error;
+use rkyv::with::InlineAsBox;
+use rkyv::{Archive, Serialize};
+
+// First bytes of a packed snapshot: 'S' and the layout version
+pub const MAGIC: u8 = b'S';
+pub const LAYOUT_VERSION: u8 = 2;
+
+// Game state in the packed layout, archived with rkyv. The writer borrows the
+// strings and trails from its own game state; the reader gets the archived
+// form, whose fields sit in the received buffer, so reading a snapshot
+// allocates nothing for it. Archived numbers are little-endian and unaligned,
+// so a buffer can be read wherever it starts.
+#[derive(Archive, Serialize)]
+pub struct Snapshot<'a> {
+    pub players: Vec<SnapshotPlayer<'a>>,
+    // x and y
+    pub food: Vec<(u32, u32)>,
+    pub trails: Vec<SnapshotTrail<'a>>,
+}
+
+#[derive(Archive, Serialize)]
+pub struct SnapshotPlayer<'a> {
+    #[rkyv(with = InlineAsBox)]
+    pub id: &'a str,
+    #[rkyv(with = InlineAsBox)]
+    pub name: &'a str,
+    #[rkyv(with = InlineAsBox)]
+    pub color: &'a str,
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    pub score: u32,
+    // 0 square, 1 circle, 2 triangle
+    pub shape: u8,
+    // 1 eliminated, 2 AFK, 4 frozen, 8 ghost
+    pub flags: u8,
+}
+
+#[derive(Archive, Serialize)]
+pub struct SnapshotTrail<'a> {
+    #[rkyv(with = InlineAsBox)]
+    pub owner: &'a str,
+    #[rkyv(with = InlineAsBox)]
+    pub points: &'a [(u32, u32)],
+}
+
+// The magic, the version and the archive
+pub fn write(snapshot: &Snapshot) -> Vec<u8> {
+    let archive = rkyv::to_bytes::<Error>(snapshot).expect("Snapshots always archive");
+    let mut buffer = Vec::with_capacity(2 + archive.len());
+    buffer.extend([MAGIC, LAYOUT_VERSION]);
+    buffer.extend_from_slice(&archive);
+    buffer
+}
+
+// The archived snapshot in what follows the magic and the version, once it's
+// checked to be one: every offset and length has to stay inside `archive`.
+// The lifetime in the type is the writer's and means nothing here.
+pub fn read(archive: &[u8]) -> Result<&ArchivedSnapshot<'static>, String> {
+    rkyv::access::<ArchivedSnapshot, Error>(archive).map_err(|e| e.to_string())
+}
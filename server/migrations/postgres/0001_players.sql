@@ -0,0 +1,13 @@
+-- The whole record is kept as JSON; the scores are copied out for queries
+CREATE TABLE players (
+    id TEXT PRIMARY KEY,
+    best_score BIGINT NOT NULL,
+    total_score BIGINT NOT NULL,
+    record JSONB NOT NULL
+);
+
+-- Unix timestamps in seconds
+CREATE TABLE bans (
+    ip TEXT PRIMARY KEY,
+    banned_until BIGINT NOT NULL
+);
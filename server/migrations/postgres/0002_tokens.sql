@@ -0,0 +1,5 @@
+-- Session tokens, apart from the records since those are public
+CREATE TABLE tokens (
+    player_id TEXT PRIMARY KEY,
+    token TEXT NOT NULL
+);
@@ -0,0 +1,19 @@
+-- The whole record is kept as JSON; the scores are copied out for queries
+CREATE TABLE players (
+    id TEXT PRIMARY KEY,
+    best_score INTEGER NOT NULL,
+    total_score INTEGER NOT NULL,
+    record TEXT NOT NULL
+);
+
+-- Unix timestamps in seconds
+CREATE TABLE bans (
+    ip TEXT PRIMARY KEY,
+    banned_until INTEGER NOT NULL
+);
+
+-- Session tokens, apart from the records since those are public
+CREATE TABLE tokens (
+    player_id TEXT PRIMARY KEY,
+    token TEXT NOT NULL
+);
@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{Event, Subscriber};
+use crate::stats::{PlayerStats, WorldStats};
+use crate::storage::PlayerStore;
+
+// Pixels to move in one session for Traveler
+const TRAVELER_DISTANCE: f64 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    // Ate a piece of food
+    FirstPickup,
+    // Moved 1000 pixels without reconnecting
+    Traveler,
+    // Was the last one standing in a trail round
+    RoundWinner,
+}
+
+const ALL: [Achievement; 3] = [Achievement::FirstPickup, Achievement::Traveler, Achievement::RoundWinner];
+
+impl Achievement {
+    fn earned(self, stats: &PlayerStats) -> bool {
+        match self {
+            Achievement::FirstPickup => stats.pickups > 0,
+            Achievement::Traveler => stats.distance >= TRAVELER_DISTANCE,
+            Achievement::RoundWinner => stats.rounds_won > 0,
+        }
+    }
+}
+
+// Players in a world whose stats may have changed since they were last checked
+#[derive(Default)]
+pub struct Progress {
+    players: Mutex<HashSet<String>>,
+}
+
+impl Subscriber for Progress {
+    fn notify(&self, event: &Event) {
+        let mut players = self.players.lock().unwrap();
+        match event {
+            Event::PlayerLeft { player_id } => {
+                players.remove(player_id);
+            }
+            Event::Moved { .. } | Event::Ate { .. } | Event::RoundWon { .. } | Event::ChatSent { .. } => {
+                players.insert(event.player_id().to_string());
+            }
+            Event::PlayerJoined { .. } | Event::Collision { .. } | Event::Scored { .. } | Event::HostChanged { .. } => {}
+        }
+    }
+}
+
+// Award the achievements players made progress on and earned, if they didn't
+// have them yet, and return them
+pub fn check(progress: &Progress, stats: &WorldStats, store: &PlayerStore) -> Vec<(String, Achievement)> {
+    let players = std::mem::take(&mut *progress.players.lock().unwrap());
+    let mut awarded = Vec::new();
+    for player_id in players {
+        let Some(stats) = stats.get(&player_id) else {
+            continue;
+        };
+        for achievement in ALL.into_iter().filter(|achievement| achievement.earned(&stats)) {
+            if store.award(&player_id, achievement) {
+                awarded.push((player_id.clone(), achievement));
+            }
+        }
+    }
+    awarded
+}
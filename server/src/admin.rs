@@ -0,0 +1,367 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use sim::grid;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::{info, warn};
+
+use crate::chat::Mutes;
+use crate::config::{Config, SharedConfig};
+use crate::drain::{self, Drain};
+use crate::game::clamp_to_world;
+use crate::scripting::ScriptHost;
+use crate::stepping::{self, MAX_TIME_SCALE, MIN_TIME_SCALE};
+use crate::world::{load_map, World, Worlds};
+use crate::broadcast::{broadcast, broadcast_game_state};
+use crate::net::policy_close;
+use crate::protocol::ServerMessage;
+
+// What players are told when the server drains, unless the admin says otherwise
+const DRAIN_TEXT: &str = "The server is restarting";
+
+// Reload the config file and apply anything that affects connected players
+pub fn reload_config(config: &SharedConfig, worlds: &Worlds, scripts: &ScriptHost) {
+    let config = match config.reload() {
+        Ok(config) => config,
+        Err(e) => {
+            warn!("Failed to reload config, keeping the old one: {}", e);
+            return;
+        }
+    };
+    info!("Config reloaded");
+
+    // Recompile the rules script so edits take effect too
+    if let Err(e) = scripts.load(config.script.as_deref()) {
+        warn!("Failed to reload script, keeping the old one: {}", e);
+    }
+
+    // Reload maps and send them to everyone in the world
+    for world_config in &config.worlds {
+        let Some(world) = worlds.get(Some(&world_config.name)) else {
+            continue;
+        };
+        match load_map(world_config) {
+            Ok(map) => {
+                broadcast(&world.clients, &ServerMessage::Map { map: map.clone() });
+                world.set_map(map);
+            }
+            Err(e) => warn!("{}, keeping the old map", e),
+        }
+    }
+
+    // Kick anyone who is banned now
+    for world in worlds.iter() {
+        let clients_map = world.clients.lock().unwrap();
+        for (addr, client) in clients_map.iter() {
+            if config.is_banned(client.ip) {
+                info!("Kicking newly banned player {} ({})", client.ip, addr);
+                let _ = client.sender.send(policy_close("You are banned from this server"));
+            }
+        }
+    }
+}
+
+// Disconnect the client at `addr`; returns whether it was connected
+pub fn kick(worlds: &Worlds, addr: SocketAddr) -> bool {
+    for world in worlds.iter() {
+        if let Some(client) = world.clients.lock().unwrap().get(&addr) {
+            info!("Kicking player {} ({})", client.player_id, addr);
+            let _ = client.sender.send(policy_close("Kicked by an admin"));
+            return true;
+        }
+    }
+    false
+}
+
+// Show a message to every connected player, counting down `countdown` if given.
+// A new announcement replaces a countdown that's still running.
+pub fn announce(worlds: &Worlds, text: &str, countdown: Option<Duration>) {
+    match countdown {
+        Some(countdown) => info!("Announcement: {} (in {}s)", text, countdown.as_secs()),
+        None => info!("Announcement: {}", text),
+    }
+    let remaining_ms = countdown.map(|countdown| countdown.as_millis() as u64);
+    for world in worlds.iter() {
+        *world.countdown.lock().unwrap() = countdown.map(|countdown| (text.to_string(), Instant::now() + countdown));
+        broadcast(&world.clients, &ServerMessage::Announcement { text: text.to_string(), remaining_ms });
+    }
+}
+
+// The countdown announcement still running in a world, for a player joining it
+pub fn running_countdown(world: &World) -> Option<ServerMessage> {
+    let mut countdown = world.countdown.lock().unwrap();
+    let (text, ends) = countdown.as_ref()?;
+    match ends.checked_duration_since(Instant::now()) {
+        Some(remaining) => {
+            Some(ServerMessage::Announcement { text: text.clone(), remaining_ms: Some(remaining.as_millis() as u64) })
+        }
+        None => {
+            *countdown = None;
+            None
+        }
+    }
+}
+
+// What an admin can turn on and off for a player
+#[derive(Clone, Copy, Deserialize)]
+pub enum Status {
+    Frozen,
+    Ghost,
+}
+
+// Freeze a player in place or let them go, or make them a ghost or not;
+// returns whether they're in the world
+pub fn set_status(world: &World, config: &Config, player_id: &str, status: Status, on: bool) -> bool {
+    let found = match world.game_state.lock().unwrap().players.get_mut(player_id) {
+        Some(player) => {
+            match status {
+                Status::Frozen => (player.frozen, player.bounce) = (on, (0, 0)),
+                Status::Ghost => player.ghost = on,
+            }
+            true
+        }
+        None => false,
+    };
+    if found {
+        let (name, verb) = match status {
+            Status::Frozen => ("frozen", "Froze"),
+            Status::Ghost => ("a ghost", "Ghosted"),
+        };
+        match on {
+            true => info!("{} {} in {}", verb, player_id, world.name),
+            false => info!("{} in {} isn't {} anymore", player_id, world.name, name),
+        }
+        show_change(world, config);
+    }
+    found
+}
+
+// Put a player at (x, y), inside the world and on the grid of grid maps;
+// returns whether they're in the world
+pub fn teleport(world: &World, config: &Config, player_id: &str, x: u32, y: u32) -> bool {
+    let cell_size = world.map().cell_size;
+    let moved = world.game_state.lock().unwrap().players.get_mut(player_id).map(|player| {
+        let from = (player.x, player.y);
+        (player.x, player.y, player.bounce) = (x, y, (0, 0));
+        clamp_to_world(player);
+        if let Some(cell_size) = cell_size {
+            player.simulate(|body| grid::snap(body, cell_size));
+        }
+        (from, (player.x, player.y))
+    });
+    let Some((from, to)) = moved else {
+        return false;
+    };
+    info!("Teleported {} in {} from {:?} to {:?}", player_id, world.name, from, to);
+    broadcast(&world.clients, &ServerMessage::Teleported { player_id: player_id.to_string(), from, to });
+    show_change(world, config);
+    true
+}
+
+// Send the world's state right away, even when it's paused or nobody moves
+fn show_change(world: &World, config: &Config) {
+    if let Err(e) = broadcast_game_state(&world.clients, &world.game_state, config.client_max_bytes_per_sec) {
+        warn!("Error sending {}'s state: {}", world.name, e);
+    }
+}
+
+// Drain the server for drain_secs on SIGTERM, and shut down right away on
+// another one
+#[cfg(unix)]
+pub async fn watch_sigterm(config: Arc<SharedConfig>, worlds: Arc<Worlds>, drain: Arc<Drain>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminations = match signal(SignalKind::terminate()) {
+        Ok(terminations) => terminations,
+        Err(e) => {
+            warn!("Can't listen for SIGTERM: {}", e);
+            return;
+        }
+    };
+    while terminations.recv().await.is_some() {
+        info!("Received SIGTERM");
+        if drain.is_draining() {
+            info!("Draining already, shutting down now");
+            drain.start(Duration::ZERO);
+        } else {
+            drain::start(&worlds, &drain, config.get().drain_time(), DRAIN_TEXT);
+        }
+    }
+}
+
+// Reload the config whenever the process receives SIGHUP
+#[cfg(unix)]
+pub async fn watch_sighup(config: Arc<SharedConfig>, worlds: Arc<Worlds>, scripts: Arc<ScriptHost>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            warn!("Can't listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        info!("Received SIGHUP");
+        reload_config(&config, &worlds, &scripts);
+    }
+}
+
+// Read admin commands from stdin
+pub async fn run_console(
+    config: Arc<SharedConfig>,
+    worlds: Arc<Worlds>,
+    scripts: Arc<ScriptHost>,
+    mutes: Arc<Mutes>,
+    drain: Arc<Drain>,
+) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let (command, argument) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "" => {}
+            "reload" => reload_config(&config, &worlds, &scripts),
+            "players" => list_players(&worlds),
+            "kick" => match argument.trim().parse() {
+                Ok(addr) if kick(&worlds, addr) => {}
+                Ok(addr) => println!("No client connected from {}", addr),
+                Err(_) => println!("Usage: kick <ip:port>"),
+            },
+            "say" if !argument.trim().is_empty() => announce(&worlds, argument.trim(), None),
+            "say" => println!("Usage: say <text>"),
+            "countdown" => {
+                let (secs, text) = argument.trim().split_once(' ').unwrap_or((argument.trim(), ""));
+                match secs.parse::<u64>() {
+                    Ok(secs) if !text.trim().is_empty() => announce(&worlds, text.trim(), Some(Duration::from_secs(secs))),
+                    _ => println!("Usage: countdown <seconds> <text>"),
+                }
+            }
+            "drain" => {
+                let (secs, text) = argument.trim().split_once(' ').unwrap_or((argument.trim(), ""));
+                let text = if text.trim().is_empty() { DRAIN_TEXT } else { text.trim() };
+                match secs.parse::<u64>() {
+                    Ok(secs) => drain::start(&worlds, &drain, Duration::from_secs(secs), text),
+                    Err(_) => println!("Usage: drain <seconds> [text]"),
+                }
+            }
+            "undrain" => {
+                if !drain::cancel(&worlds, &drain) {
+                    println!("The server isn't draining");
+                }
+            }
+            "mute" => {
+                let (player_id, minutes) = argument.trim().split_once(' ').unwrap_or((argument.trim(), ""));
+                match minutes.trim().parse::<u64>() {
+                    Ok(minutes) if !player_id.is_empty() => {
+                        mutes.mute(player_id, Duration::from_secs(minutes * 60));
+                        println!("Muted {} for {} minute(s)", player_id, minutes);
+                    }
+                    _ => println!("Usage: mute <player id> <minutes>"),
+                }
+            }
+            "unmute" => match argument.trim() {
+                "" => println!("Usage: unmute <player id>"),
+                player_id if mutes.unmute(player_id) => println!("Unmuted {}", player_id),
+                player_id => println!("{} isn't muted", player_id),
+            },
+            "pause" | "resume" | "step" | "timescale" | "dump" => {
+                let (name, extra) = argument.trim().split_once(' ').unwrap_or((argument.trim(), ""));
+                match worlds.get(Some(name)) {
+                    Some(world) => debug_world(&world, command, extra.trim()),
+                    None => println!("No world named {:?} (try players)", name),
+                }
+            }
+            "freeze" | "unfreeze" | "ghost" | "unghost" | "teleport" => {
+                let mut words = argument.split_whitespace();
+                let (name, player_id) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+                match worlds.get(Some(name)) {
+                    Some(world) => player_command(&world, &config.get(), command, player_id, words.collect()),
+                    None => println!("No world named {:?} (try players)", name),
+                }
+            }
+            "help" => println!(
+                "Commands: reload, players, kick <ip:port>, say <text>, countdown <seconds> <text>, \
+                 drain <seconds> [text], undrain, mute <player id> <minutes>, unmute <player id>, \
+                 pause <world>, resume <world>, step <world> [ticks], timescale <world> <factor>, dump <world>, \
+                 freeze <world> <player id>, unfreeze <world> <player id>, ghost <world> <player id>, \
+                 unghost <world> <player id>, teleport <world> <player id> <x> <y>, help"
+            ),
+            other => println!("Unknown command: {} (try help)", other),
+        }
+    }
+}
+
+// Pause, resume, single-step, speed up or slow down, or dump a world's simulation
+fn debug_world(world: &World, command: &str, argument: &str) {
+    match command {
+        "pause" => {
+            world.stepper.pause();
+            info!("Paused {} at tick {}", world.name, world.stepper.tick());
+        }
+        "resume" => {
+            world.stepper.resume();
+            info!("Resumed {}", world.name);
+        }
+        "step" if !world.stepper.is_paused() => println!("{} isn't paused (pause it first)", world.name),
+        "step" => match if argument.is_empty() { Ok(1) } else { argument.parse::<u32>() } {
+            Ok(ticks) => world.stepper.step(ticks),
+            Err(_) => println!("Usage: step <world> [ticks]"),
+        },
+        "timescale" => match argument.parse::<f64>() {
+            Ok(factor) if factor > 0.0 => {
+                let time_scale = world.stepper.set_time_scale(factor);
+                info!("Time in {} runs at {}x", world.name, time_scale);
+            }
+            _ => println!(
+                "Usage: timescale <world> <factor>, from {} (slow motion) to {} (fast forward)",
+                MIN_TIME_SCALE, MAX_TIME_SCALE
+            ),
+        },
+        _ => match serde_json::to_string_pretty(&stepping::dump(world)) {
+            Ok(json) => println!("{}", json),
+            Err(e) => println!("Couldn't dump {}: {}", world.name, e),
+        },
+    }
+}
+
+// Freeze, ghost or teleport a player in a world
+fn player_command(world: &World, config: &Config, command: &str, player_id: &str, arguments: Vec<&str>) {
+    if player_id.is_empty() {
+        println!("Usage: {} <world> <player id>{}", command, if command == "teleport" { " <x> <y>" } else { "" });
+        return;
+    }
+    let position = match arguments.as_slice() {
+        [x, y] => x.parse().ok().zip(y.parse().ok()),
+        _ => None,
+    };
+    let found = match command {
+        "freeze" => set_status(world, config, player_id, Status::Frozen, true),
+        "unfreeze" => set_status(world, config, player_id, Status::Frozen, false),
+        "ghost" => set_status(world, config, player_id, Status::Ghost, true),
+        "unghost" => set_status(world, config, player_id, Status::Ghost, false),
+        _ => match position {
+            Some((x, y)) => teleport(world, config, player_id, x, y),
+            None => {
+                println!("Usage: teleport <world> <player id> <x> <y>");
+                return;
+            }
+        },
+    };
+    if !found {
+        println!("No player {} in {}", player_id, world.name);
+    }
+}
+
+fn list_players(worlds: &Worlds) {
+    for world in worlds.iter() {
+        for (addr, client) in world.clients.lock().unwrap().iter() {
+            let ping = client.ping.lock().unwrap().map(|ping| ping.as_millis());
+            match ping {
+                Some(ms) => println!("{} {} {} ({} ms)", world.name, addr, client.player_id, ms),
+                None => println!("{} {} {}", world.name, addr, client.player_id),
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::assets;
+use crate::config::SharedConfig;
+use crate::dashboard;
+use crate::drain::Drain;
+use crate::limits::ConnectionLimiter;
+use crate::http::{Handler, Request, Response};
+use crate::listing::Listing;
+use crate::login::Logins;
+use crate::metrics;
+use crate::session::Sessions;
+use crate::spectate;
+use crate::storage::PlayerStore;
+use crate::world::Worlds;
+
+// Entries returned by the leaderboard unless ?limit= asks for another number
+const DEFAULT_LEADERBOARD_SIZE: usize = 10;
+const MAX_LEADERBOARD_SIZE: usize = 100;
+
+// What /metrics counts besides the worlds, to spot leaks, and whether
+// /health should send load balancers elsewhere
+pub struct Tracking {
+    pub sessions: Arc<Sessions>,
+    pub limiter: Arc<ConnectionLimiter>,
+    pub drain: Arc<Drain>,
+}
+
+#[derive(Serialize)]
+struct OnlinePlayer {
+    id: String,
+    name: String,
+    world: String,
+    color: String,
+    score: u32,
+}
+
+// Read-only JSON API:
+//   GET /api/leaderboard?limit=N  top players by best score
+//   GET /api/players/<id>         a player's totals and recent sessions
+//   GET /api/online               players connected right now
+//   GET /api/servers              worlds on this server and announced ones, for the client's server browser
+//   POST /api/announce            another server announcing its worlds (with listing.accept_announcements)
+//   GET /metrics                  timings, counters and what the server holds, in Prometheus format
+//   GET /health                   200 while the server is up, for load balancers; 503 while draining
+//   GET /auth/login               start logging in with the configured OAuth provider
+//   GET /auth/callback            where the provider sends the player back to
+// plus the admin dashboard at /admin, its client list at /admin/clients, the
+// tick profiler's breakdown at /admin/profile and a world's full state at /admin/state.
+// Other GET requests are answered from assets_dir, if it's set.
+pub fn handler(
+    worlds: Arc<Worlds>,
+    store: Arc<PlayerStore>,
+    config: Arc<SharedConfig>,
+    listing: Arc<Listing>,
+    tracking: Tracking,
+) -> Handler {
+    let tracking = Arc::new(tracking);
+    let logins = Arc::new(Logins::default());
+    Arc::new(move |request| {
+        // The login callback waits on the provider; everything else is answered right away
+        if request.method == "GET" && request.path.trim_matches('/') == "auth/callback" {
+            let (store, config, logins) = (store.clone(), config.clone(), logins.clone());
+            return Box::pin(async move {
+                match &config.get().login {
+                    Some(login) => logins.finish(&request, login, &store).await,
+                    None => Response::not_found(),
+                }
+            });
+        }
+        let response = route(&request, &worlds, &store, &config, &listing, &logins, &tracking);
+        // Anything else may be one of the client's files
+        if response.status == 404 && request.method == "GET" {
+            if let Some(dir) = config.get().assets_dir.clone() {
+                return Box::pin(async move { assets::serve(&dir, &request.path).await });
+            }
+        }
+        Box::pin(std::future::ready(response))
+    })
+}
+
+fn route(
+    request: &Request,
+    worlds: &Worlds,
+    store: &PlayerStore,
+    config: &SharedConfig,
+    listing: &Listing,
+    logins: &Logins,
+    tracking: &Tracking,
+) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    if request.method == "POST" && segments == ["api", "announce"] {
+        return listing.accept(request, &config.get());
+    }
+    if request.method != "GET" {
+        return Response::error(405, "Method Not Allowed");
+    }
+    match segments.as_slice() {
+        ["api", "leaderboard"] => {
+            let limit = request
+                .query
+                .get("limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_LEADERBOARD_SIZE)
+                .min(MAX_LEADERBOARD_SIZE);
+            Response::json(&store.leaderboard(limit))
+        }
+        ["api", "players", id] => match store.player(id) {
+            Some(record) => Response::json(&record),
+            None => Response::not_found(),
+        },
+        ["api", "online"] => Response::json(&online_players(worlds)),
+        ["health"] if tracking.drain.is_draining() => {
+            Response { status: 503, ..Response::json(&serde_json::json!({ "status": "draining" })) }
+        }
+        ["health"] => Response::json(&serde_json::json!({ "status": "ok" })),
+        ["api", "servers"] => Response::json(&listing.entries(worlds, &config.get())),
+        ["metrics"] => {
+            let resources = metrics::Resources::count(worlds, &tracking.sessions, &tracking.limiter);
+            Response::text(metrics::render_prometheus(&resources))
+        }
+        ["auth", "login"] => match &config.get().login {
+            Some(login) => logins.start(login),
+            None => Response::not_found(),
+        },
+        ["admin"] => dashboard::page(request, config),
+        ["admin", "clients"] => dashboard::clients(request, worlds, config),
+        ["admin", "profile"] => dashboard::profile(request, worlds, config),
+        ["admin", "state"] => dashboard::state(request, worlds, config),
+        ["spectate"] | ["spectate", _] => spectate::page(request, worlds, config),
+        _ => Response::not_found(),
+    }
+}
+
+fn online_players(worlds: &Worlds) -> Vec<OnlinePlayer> {
+    let mut online = Vec::new();
+    for world in worlds.iter() {
+        let state = world.game_state.lock().unwrap();
+        online.extend(state.players.values().map(|player| OnlinePlayer {
+            id: player.id.clone(),
+            name: player.name.clone(),
+            world: world.name.clone(),
+            color: player.color.clone(),
+            score: player.score,
+        }));
+    }
+    online.sort_by(|a, b| a.id.cmp(&b.id));
+    online
+}
@@ -0,0 +1,41 @@
+use std::path::{Component, Path};
+
+use crate::http::Response;
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+// The file at `path` under `dir`, like the built client; a directory's index.html
+pub async fn serve(dir: &Path, path: &str) -> Response {
+    let mut relative = path.trim_start_matches('/').to_string();
+    if relative.is_empty() || relative.ends_with('/') {
+        relative.push_str("index.html");
+    }
+    // Plain names only, so nothing outside `dir` can be asked for
+    let relative = Path::new(&relative);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Response::not_found();
+    }
+    match tokio::fs::read(dir.join(relative)).await {
+        Ok(body) => Response {
+            status: 200,
+            content_type: content_type(relative),
+            body,
+            location: None,
+            cookie: None,
+            cross_origin: true,
+        },
+        Err(_) => Response::not_found(),
+    }
+}
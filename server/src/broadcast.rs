@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::game::GameStateSync;
+use crate::limits::Traffic;
+use crate::metrics::METRICS;
+use crate::protocol::{Codec, Encoded, ServerMessage};
+use crate::world::World;
+
+// How often the server pings clients to measure their round-trip time
+pub const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+// A connected client as seen by the rest of the server
+pub struct Client {
+    pub sender: UnboundedSender<Message>,
+    pub ip: IpAddr,
+    pub player_id: String,
+    pub codec: &'static dyn Codec,
+    // Last measured round-trip time
+    pub ping: Arc<Mutex<Option<Duration>>>,
+    pub traffic: Arc<Traffic>,
+    // Ends the connection without waiting for the client, when its player joined again elsewhere
+    pub replaced: Arc<Notify>,
+}
+
+// The clients connected to a world, by address
+pub type Clients = Arc<Mutex<HashMap<SocketAddr, Client>>>;
+
+// Send the game state to everyone, except clients over their bandwidth ceiling.
+// Those are caught up with the latest state once they have room again.
+// Returns how long copying and encoding the state took.
+pub fn broadcast_game_state(
+    clients: &Clients,
+    game_state: &GameStateSync,
+    max_bytes_per_sec: Option<u64>,
+) -> Result<Duration, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    // Copy the game state - scope the lock
+    let message = {
+        let state = game_state.lock().unwrap();
+        ServerMessage::State {
+            game_state: state.clone(),
+        }
+    }; // Lock is released here
+    let copying = started.elapsed();
+    let mut encoded = Encoded::new(&message);
+    
+    let clients_map = clients.lock().unwrap();
+    for client in clients_map.values() {
+        if !client.traffic.allow_snapshot(max_bytes_per_sec) {
+            METRICS.snapshots_skipped.fetch_add(1, Ordering::Relaxed);
+            continue;
+        }
+        if let Err(e) = client.sender.send(encoded.get(client.codec)?) {
+            warn_limited!("Error broadcasting message: {}", e);
+        }
+    }
+    METRICS.broadcast.record(started.elapsed());
+    Ok(copying + encoded.encoding())
+}
+
+// Share everyone's ping with the world for the roster
+pub async fn broadcast_pings(world: Arc<World>) {
+    let mut ticker = tokio::time::interval(PING_INTERVAL);
+    while !world.is_closed() {
+        ticker.tick().await;
+        let pings: HashMap<String, u32> = {
+            let clients_map = world.clients.lock().unwrap();
+            clients_map
+                .values()
+                .filter_map(|client| {
+                    let ping = (*client.ping.lock().unwrap())?;
+                    Some((client.player_id.clone(), ping.as_millis() as u32))
+                })
+                .collect()
+        }; // Lock is released here
+        if !pings.is_empty() {
+            broadcast(&world.clients, &ServerMessage::Pings { pings });
+        }
+    }
+}
+
+// Send the latest state to throttled clients that have bandwidth again,
+// returning how long copying and encoding it took
+pub fn catch_up_snapshots(clients: &Clients, game_state: &GameStateSync, max_bytes_per_sec: Option<u64>) -> Duration {
+    let clients_map = clients.lock().unwrap();
+    let behind: Vec<&Client> = clients_map
+        .values()
+        .filter(|client| client.traffic.is_behind() && client.traffic.allow_snapshot(max_bytes_per_sec))
+        .collect();
+    if behind.is_empty() {
+        return Duration::ZERO;
+    }
+    let started = Instant::now();
+    let message = ServerMessage::State {
+        game_state: game_state.lock().unwrap().clone(),
+    };
+    let copying = started.elapsed();
+    let mut encoded = Encoded::new(&message);
+    for client in behind {
+        let frame = encoded.get(client.codec).expect("GameState is always serializable");
+        let _ = client.sender.send(frame);
+    }
+    copying + encoded.encoding()
+}
+
+// Send a message to every client in a world, returning how long encoding it took
+pub fn broadcast(clients: &Clients, message: &ServerMessage) -> Duration {
+    let started = Instant::now();
+    let mut encoded = Encoded::new(message);
+    
+    // Scope the lock
+    let clients_map = clients.lock().unwrap();
+    for client in clients_map.values() {
+        let frame = match encoded.get(client.codec) {
+            Ok(frame) => frame,
+            Err(e) => {
+                error_limited!("Error serializing message: {}", e);
+                return encoded.encoding();
+            }
+        };
+        if let Err(e) = client.sender.send(frame) {
+            warn_limited!("Error broadcasting message: {}", e);
+        }
+    }
+    METRICS.broadcast.record(started.elapsed());
+    encoded.encoding()
+}
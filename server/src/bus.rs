@@ -0,0 +1,109 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use tracing::trace;
+
+// Something that happened in a world, for the subsystems keeping track of it
+#[derive(Debug)]
+pub enum Event {
+    PlayerJoined { player_id: String },
+    PlayerLeft { player_id: String },
+    // Pixels moved in a step, not counting portals and wrapping around the edges
+    Moved { player_id: String, distance: f64 },
+    // A moving player ran into another one; `tagged` if the rules acted on it
+    Collision { by: String, target: String, tagged: bool },
+    // A player's score went up by `gained`, to `score`
+    Scored { player_id: String, score: u32, gained: u32 },
+    Ate { player_id: String, count: u32 },
+    // The last one standing in a trail round
+    RoundWon { player_id: String },
+    // A chat message or whisper got through the filters
+    ChatSent { player_id: String, whisper: bool },
+    // A room's host left, and this player took over from them
+    HostChanged { player_id: String },
+}
+
+impl Event {
+    pub fn player_id(&self) -> &str {
+        match self {
+            Event::PlayerJoined { player_id }
+            | Event::PlayerLeft { player_id }
+            | Event::Moved { player_id, .. }
+            | Event::Scored { player_id, .. }
+            | Event::Ate { player_id, .. }
+            | Event::RoundWon { player_id }
+            | Event::ChatSent { player_id, .. }
+            | Event::HostChanged { player_id } => player_id,
+            Event::Collision { by, .. } => by,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::PlayerJoined { .. } => "player_joined",
+            Event::PlayerLeft { .. } => "player_left",
+            Event::Moved { .. } => "moved",
+            Event::Collision { .. } => "collision",
+            Event::Scored { .. } => "scored",
+            Event::Ate { .. } => "ate",
+            Event::RoundWon { .. } => "round_won",
+            Event::ChatSent { .. } => "chat_sent",
+            Event::HostChanged { .. } => "host_changed",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::PlayerJoined { player_id } => write!(f, "{} joined", player_id),
+            Event::PlayerLeft { player_id } => write!(f, "{} left", player_id),
+            Event::Moved { player_id, distance } => write!(f, "{} moved {:.1}px", player_id, distance),
+            Event::Collision { by, target, tagged: true } => write!(f, "{} tagged {}", by, target),
+            Event::Collision { by, target, tagged: false } => write!(f, "{} ran into {}", by, target),
+            Event::Scored { player_id, score, gained } => write!(f, "{} scored {}, now at {}", player_id, gained, score),
+            Event::Ate { player_id, count } => write!(f, "{} ate {}", player_id, count),
+            Event::RoundWon { player_id } => write!(f, "{} won the round", player_id),
+            Event::ChatSent { player_id, whisper: true } => write!(f, "{} whispered", player_id),
+            Event::ChatSent { player_id, whisper: false } => write!(f, "{} chatted", player_id),
+            Event::HostChanged { player_id } => write!(f, "{} is the host now", player_id),
+        }
+    }
+}
+
+// Called with every event published on the bus it's subscribed to, right
+// away and on the publisher's task - which may hold the game state lock, so
+// subscribers must not take it
+pub trait Subscriber: Send + Sync {
+    fn notify(&self, event: &Event);
+}
+
+// Hands a world's events to whoever subscribed, so the code where things
+// happen doesn't need to know who keeps track of them
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<Arc<dyn Subscriber>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self, subscriber: Arc<dyn Subscriber>) {
+        self.subscribers.write().unwrap().push(subscriber);
+    }
+
+    pub fn publish(&self, event: Event) {
+        for subscriber in self.subscribers.read().unwrap().iter() {
+            subscriber.notify(&event);
+        }
+    }
+}
+
+// Traces a world's events, for following a game in the log with level trace
+pub struct EventLog {
+    pub world: String,
+}
+
+impl Subscriber for EventLog {
+    fn notify(&self, event: &Event) {
+        trace!("{}: {}", self.world, event);
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::ChaosConfig;
+use crate::metrics::{Tracked, METRICS};
+
+// Put a bad network between a client's queue and its socket: messages coming
+// out of the returned receiver were dropped, duplicated, held back behind the
+// next one and delayed at random. Close frames always go through, after
+// whatever was held back.
+pub fn wrap(config: ChaosConfig, mut receiver: UnboundedReceiver<Message>) -> UnboundedReceiver<Message> {
+    let (sender, chaotic) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let _tracked = Tracked::new(&METRICS.connection_tasks);
+        let mut held = None;
+        while let Some(message) = receiver.recv().await {
+            if message.is_close() {
+                if let Some(held) = held.take() {
+                    let _ = sender.send(held);
+                }
+                let _ = sender.send(message);
+                break;
+            }
+            if chance(config.drop) {
+                continue;
+            }
+            if held.is_none() && chance(config.reorder) {
+                held = Some(message);
+                continue;
+            }
+            if chance(config.duplicate) {
+                deliver(&config, &sender, message.clone());
+            }
+            deliver(&config, &sender, message);
+            if let Some(held) = held.take() {
+                deliver(&config, &sender, held);
+            }
+        }
+    });
+    chaotic
+}
+
+// Send now, or later from a task of its own so what's behind it can overtake it
+fn deliver(config: &ChaosConfig, sender: &UnboundedSender<Message>, message: Message) {
+    if !chance(config.delay) {
+        let _ = sender.send(message);
+        return;
+    }
+    let delay = Duration::from_millis(random() % (config.max_delay_ms + 1));
+    let sender = sender.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let _ = sender.send(message);
+    });
+}
+
+fn chance(probability: f64) -> bool {
+    (random() as f64 / u64::MAX as f64) < probability
+}
+
+fn random() -> u64 {
+    // Every RandomState is seeded differently, which is random enough here
+    RandomState::new().build_hasher().finish()
+}
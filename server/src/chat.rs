@@ -0,0 +1,166 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::info;
+
+use crate::config::ChatConfig;
+use crate::protocol::{self, Codec};
+use crate::world::Worlds;
+use crate::protocol::ServerMessage;
+
+// Longest chat message, in characters
+const MAX_CHAT_CHARS: usize = 200;
+
+// The text to send on, without control characters and surrounding whitespace;
+// None if nothing is left
+pub fn sanitize(text: &str) -> Option<String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).take(MAX_CHAT_CHARS).collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// Players muted by an admin, with when the mute ends
+#[derive(Default)]
+pub struct Mutes {
+    until: Mutex<HashMap<String, Instant>>,
+}
+
+impl Mutes {
+    pub fn mute(&self, player_id: &str, duration: Duration) {
+        info!("Muting {} for {:?}", player_id, duration);
+        self.until.lock().unwrap().insert(player_id.to_string(), Instant::now() + duration);
+    }
+
+    // Whether the player was muted
+    pub fn unmute(&self, player_id: &str) -> bool {
+        self.until.lock().unwrap().remove(player_id).is_some()
+    }
+
+    // Every mute still running, with the time left on it
+    pub fn running(&self) -> HashMap<String, Duration> {
+        let now = Instant::now();
+        let until = self.until.lock().unwrap();
+        until.iter().filter(|(_, end)| **end > now).map(|(player_id, end)| (player_id.clone(), *end - now)).collect()
+    }
+
+    // Time left on the player's mute, if they're muted
+    fn remaining(&self, player_id: &str) -> Option<Duration> {
+        let mut until = self.until.lock().unwrap();
+        let now = Instant::now();
+        until.retain(|_, end| *end > now);
+        until.get(player_id).map(|end| *end - now)
+    }
+}
+
+// The chat messages one connection sent recently, for the rate limit
+#[derive(Default)]
+pub struct ChatRate {
+    sent: VecDeque<Instant>,
+}
+
+// Run a message through the filters before it's sent on. Err is the notice
+// telling the player why it wasn't.
+pub fn moderate(text: &str, player_id: &str, config: &ChatConfig, mutes: &Mutes, rate: &mut ChatRate) -> Result<String, String> {
+    if !config.enabled {
+        return Err("Chat is turned off on this server".to_string());
+    }
+    if let Some(remaining) = mutes.remaining(player_id) {
+        return Err(format!("You are muted for another {} minute(s)", remaining.as_secs() / 60 + 1));
+    }
+
+    let now = Instant::now();
+    let window = Duration::from_secs(config.rate_window_secs);
+    while rate.sent.front().is_some_and(|sent| now - *sent >= window) {
+        rate.sent.pop_front();
+    }
+    if rate.sent.len() >= config.rate_limit as usize {
+        return Err("You're sending messages too quickly".to_string());
+    }
+    rate.sent.push_back(now);
+
+    let lowercase = text.to_lowercase();
+    if config
+        .blocked_words
+        .iter()
+        .any(|word| !word.is_empty() && lowercase.contains(&word.to_lowercase()))
+    {
+        return Err("Your message wasn't sent because it contains a blocked word".to_string());
+    }
+
+    let text = if config.strip_links { strip_links(text) } else { text.to_string() };
+    sanitize(&text).ok_or_else(|| "Your message was empty".to_string())
+}
+
+// Replace anything that looks like a URL
+fn strip_links(text: &str) -> String {
+    let words: Vec<&str> = text
+        .split(' ')
+        .map(|word| {
+            let lowercase = word.to_lowercase();
+            let is_link = ["http://", "https://", "www."]
+                .iter()
+                .any(|prefix| lowercase.starts_with(prefix));
+            if is_link {
+                "[link removed]"
+            } else {
+                word
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+// Deliver a private message to `to` in whichever world they are, echoing it back
+// to the sender; the sender gets a notice instead if they're not online
+pub fn whisper(
+    worlds: &Worlds,
+    sender: &UnboundedSender<Message>,
+    codec: &dyn Codec,
+    from: &str,
+    name: &str,
+    to: &str,
+    text: &str,
+) {
+    let Some(text) = sanitize(text) else {
+        return;
+    };
+    let Some((target, target_codec)) = find_player(worlds, to) else {
+        send(sender, codec, &ServerMessage::Notice {
+            text: format!("{} is not online", to),
+        });
+        return;
+    };
+    let message = ServerMessage::Whisper {
+        from: from.to_string(),
+        name: name.to_string(),
+        to: to.to_string(),
+        text,
+    };
+    send(&target, target_codec, &message);
+    if to != from {
+        send(sender, codec, &message);
+    }
+}
+
+// The channel of a connected player, and how to frame messages for them
+fn find_player(worlds: &Worlds, player_id: &str) -> Option<(UnboundedSender<Message>, &'static dyn Codec)> {
+    worlds.iter().find_map(|world| {
+        let clients_map = world.clients.lock().unwrap();
+        clients_map
+            .values()
+            .find(|client| client.player_id == player_id)
+            .map(|client| (client.sender.clone(), client.codec))
+    })
+}
+
+pub fn send(sender: &UnboundedSender<Message>, codec: &dyn Codec, message: &ServerMessage) {
+    match protocol::encode(message, codec) {
+        Ok(frame) => {
+            let _ = sender.send(frame);
+        }
+        Err(e) => error_limited!("Error serializing message: {}", e),
+    }
+}
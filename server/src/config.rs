@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tracing::warn;
+
+use crate::proxy;
+
+// Config file used when no --config argument is given
+const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+// Server configuration, loaded from a TOML file
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Shown in server listings
+    pub name: String,
+    // WebSocket URL players reach this server at, for listings; the host they
+    // fetched the listing from when unset
+    pub public_url: Option<String>,
+    pub bind: String,
+    // Address of the HTTP API; the game port answers HTTP too, so this can be
+    // the same as `bind` to serve everything on one port
+    pub http_bind: String,
+    // Files served on GET requests nothing else answers, like the built client
+    pub assets_dir: Option<PathBuf>,
+    // Simultaneous connections allowed from one client IP
+    pub max_connections_per_ip: usize,
+    // Seconds a new socket gets to finish the WebSocket handshake
+    pub handshake_timeout_secs: u64,
+    // Seconds a connection may then look through the rooms before it sends Join
+    pub lobby_timeout_secs: u64,
+    // Seconds a player may go without sending anything, pongs included, before
+    // the connection is closed; 0 to never close it for that
+    pub idle_timeout_secs: u64,
+    // Milliseconds a client IP has to wait after joining before it may join again; 0 for no wait
+    pub join_cooldown_ms: u64,
+    // Leading zero bits of the proof of work a client has to send before it may
+    // join; 0 for none. Every bit doubles the work, 16 takes a browser a moment.
+    pub join_pow_bits: u32,
+    // Largest message a client may send; bigger ones are dropped and count as a strike
+    pub max_message_bytes: usize,
+    // Frames and messages over this are refused while reading, before they're buffered
+    pub max_frame_bytes: usize,
+    // Protocol violations (malformed, oversized or binary messages) a client may
+    // commit; each gets a warning and the last one closes the connection
+    pub max_strikes: u32,
+    // Refuse the IP of a client disconnected for strikes for this long; 0 to only disconnect
+    pub strike_ban_secs: u64,
+    // Pixels a player moves per Move message
+    pub player_speed: u32,
+    // Players allowed in the game at once
+    pub max_players: usize,
+    // Client IPs refused at connect time
+    pub banned: Vec<IpAddr>,
+    // Server ticks per second
+    pub tick_rate: u32,
+    // Bandwidth ceiling per client; clients over it get game state snapshots less often
+    pub client_max_bytes_per_sec: Option<u64>,
+    // Compress bigger messages for clients that list a compression they read in their Join
+    pub compression: bool,
+    // Worlds hosted by this server; clients join the first one unless they pick another
+    pub worlds: Vec<WorldConfig>,
+    // Optional rhai script with game rule hooks
+    pub script: Option<PathBuf>,
+    // Where to save the world periodically; no snapshots when unset
+    pub snapshot_path: Option<PathBuf>,
+    pub snapshot_interval_secs: u64,
+    // Seconds from SIGTERM to shutting down, draining the server in between;
+    // another SIGTERM shuts down right away
+    pub drain_secs: u64,
+    // Unix socket a newer server started with --take-over <path> gets this
+    // one's worlds and players through
+    pub handoff_socket: Option<PathBuf>,
+    // Where player history and bans are kept
+    pub storage: StorageBackend,
+    // JSON file or SQLite database for the json and sqlite storage backends.
+    // The json one keeps everything in memory only when unset; sqlite needs it.
+    pub stats_path: Option<PathBuf>,
+    // Postgres connection URL for the postgres storage backend
+    pub database_url: Option<String>,
+    // How long a player who disconnected can come back with their session token
+    // and keep their position and score
+    pub session_resume_secs: u64,
+    // Modes of the worlds where a player coming back later, with the token from
+    // their last session, starts where they left off instead of at a new spot
+    pub restore_position: Vec<GameMode>,
+    // What happens when a player joins with the token of a session that's still connected
+    pub duplicate_sessions: DuplicateSessions,
+    // Token required for the admin dashboard on http_bind; the dashboard is off when unset
+    pub admin_token: Option<String>,
+    // OAuth / OpenID Connect login on http_bind; off when unset
+    pub login: Option<LoginConfig>,
+    pub proxy: ProxyConfig,
+    pub listing: ListingConfig,
+    // Posting what happens on the server to a Discord webhook
+    pub webhook: WebhookConfig,
+    // Read-only views of the worlds, for embedding on other sites
+    pub spectate: SpectateConfig,
+    pub mdns: MdnsConfig,
+    pub chat: ChatConfig,
+    pub growth: GrowthConfig,
+    // Worlds players create for themselves, and how far their rules may go
+    pub rooms: RoomsConfig,
+    pub log: LogConfig,
+    // Fault injection for testing clients on a bad network; servers built
+    // without the chaos feature ignore it
+    pub chaos: ChaosConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            name: "Multiplayer Square Game".to_string(),
+            public_url: None,
+            bind: "127.0.0.1:8080".to_string(),
+            http_bind: "127.0.0.1:8081".to_string(),
+            assets_dir: None,
+            max_connections_per_ip: 4,
+            handshake_timeout_secs: 5,
+            lobby_timeout_secs: 300,
+            idle_timeout_secs: 30,
+            join_cooldown_ms: 0,
+            join_pow_bits: 0,
+            max_message_bytes: 4096,
+            max_frame_bytes: 65536,
+            max_strikes: 5,
+            strike_ban_secs: 0,
+            player_speed: 5,
+            max_players: 32,
+            banned: Vec::new(),
+            tick_rate: 20,
+            client_max_bytes_per_sec: None,
+            compression: true,
+            worlds: vec![WorldConfig {
+                name: "main".to_string(),
+                max_players: None,
+                map: None,
+                mode: GameMode::Free,
+                netcode: Netcode::Authoritative,
+            }],
+            script: None,
+            snapshot_path: None,
+            snapshot_interval_secs: 30,
+            drain_secs: 60,
+            handoff_socket: None,
+            storage: StorageBackend::Json,
+            stats_path: None,
+            database_url: None,
+            session_resume_secs: 300,
+            restore_position: vec![GameMode::Free],
+            duplicate_sessions: DuplicateSessions::TakeOver,
+            admin_token: None,
+            login: None,
+            proxy: ProxyConfig::default(),
+            listing: ListingConfig::default(),
+            webhook: WebhookConfig::default(),
+            spectate: SpectateConfig::default(),
+            mdns: MdnsConfig::default(),
+            chat: ChatConfig::default(),
+            growth: GrowthConfig::default(),
+            rooms: RoomsConfig::default(),
+            log: LogConfig::default(),
+            chaos: ChaosConfig::default(),
+        }
+    }
+}
+
+// A named world, selected by URL path (ws://host/<name>) or in the Join message
+#[derive(Clone, Deserialize)]
+pub struct WorldConfig {
+    pub name: String,
+    // Overrides the server-wide max_players for this world
+    pub max_players: Option<usize>,
+    // Map file with the world's portals; an empty map when unset
+    pub map: Option<PathBuf>,
+    #[serde(default)]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub netcode: Netcode,
+}
+
+// Rules a world is played by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    // Walk around, nothing more
+    #[default]
+    Free,
+    // Leave a trail, avoid everyone's trails, be the last one standing
+    Trail,
+}
+
+// A player joining again while still connected, like from a second tab
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateSessions {
+    // The new connection gets the player; the old one is closed
+    #[default]
+    TakeOver,
+    // The new connection is refused
+    Reject,
+}
+
+// Who simulates a world's movement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Netcode {
+    // The server, sending everyone the game state after each change
+    #[default]
+    Authoritative,
+    // Every client, from the inputs the server relays each tick; the server
+    // only sends the state when something else changed or a client desynced
+    Lockstep,
+}
+
+// Storage backends to pick from in the config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    // Gone when the server stops
+    Memory,
+    // The stats_path file
+    #[default]
+    Json,
+    // A SQLite database at stats_path
+    Sqlite,
+    // The Postgres database at database_url
+    Postgres,
+}
+
+// An OAuth 2.0 provider to log players in with, using the authorization code flow
+#[derive(Clone, Deserialize)]
+pub struct LoginConfig {
+    // The provider's endpoints; the server calls the token and userinfo ones itself
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    // Our /auth/callback as registered with the provider
+    pub redirect_url: String,
+    // The game page to send players back to, with their token after #login=
+    pub return_url: String,
+    #[serde(default = "default_login_scope")]
+    pub scope: String,
+    // Refuse players that haven't logged in
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_login_scope() -> String {
+    "openid".to_string()
+}
+
+// How to find the real client address when running behind a reverse proxy
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    // Honor the X-Forwarded-For header of the WebSocket upgrade request
+    pub x_forwarded_for: bool,
+    // Expect a PROXY protocol (v1 or v2) header before the HTTP handshake
+    pub proxy_protocol: bool,
+    // Peers allowed to tell us the client address; everyone else is taken at face value
+    pub trusted: Vec<IpAddr>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        ProxyConfig {
+            x_forwarded_for: false,
+            proxy_protocol: false,
+            trusted: vec![IpAddr::from([127, 0, 0, 1])],
+        }
+    }
+}
+
+impl ProxyConfig {
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.contains(&ip)
+    }
+
+    // Who's behind a connection from `peer`, going by its X-Forwarded-For
+    // header when we honor it and the peer is one of our proxies
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        match forwarded_for {
+            Some(header) if self.x_forwarded_for && self.is_trusted(peer) => {
+                proxy::resolve_forwarded_for(header, &self.trusted).unwrap_or(peer)
+            }
+            _ => peer,
+        }
+    }
+}
+
+// Server listing: announcing this server to a listing service, or being one
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ListingConfig {
+    // HTTP API of the listing service to announce to, like "http://lobby.example.com:8081"
+    pub announce_to: Option<String>,
+    pub announce_interval_secs: u64,
+    // Take announcements from other servers at POST /api/announce and list them in /api/servers
+    pub accept_announcements: bool,
+    // Shared secret sent with announcements and required of them; anyone may announce when unset
+    pub token: Option<String>,
+}
+
+impl Default for ListingConfig {
+    fn default() -> Self {
+        ListingConfig {
+            announce_to: None,
+            announce_interval_secs: 30,
+            accept_announcements: false,
+            token: None,
+        }
+    }
+}
+
+impl ListingConfig {
+    pub fn announce_interval(&self) -> Duration {
+        Duration::from_secs(self.announce_interval_secs.max(1))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    // Discord webhook URL to post to, like "https://discord.com/api/webhooks/<id>/<token>".
+    // Nothing is posted when unset.
+    pub url: Option<String>,
+    // Name posts are made under, instead of the webhook's own
+    pub username: Option<String>,
+    // Seconds between posts at least; what happens in between goes in one post
+    pub interval_secs: u64,
+    // A player's score reaching a multiple of this is posted; 0 posts none
+    pub milestone_points: u32,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: None,
+            username: None,
+            interval_secs: 5,
+            milestone_points: 500,
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SpectateConfig {
+    // Stream worlds at /spectate/<world>, and serve a page showing them there
+    pub enabled: bool,
+    // Milliseconds between snapshots sent to spectators
+    pub interval_ms: u64,
+    // Spectators watching at once, over every world
+    pub max_spectators: usize,
+}
+
+impl Default for SpectateConfig {
+    fn default() -> Self {
+        SpectateConfig {
+            enabled: false,
+            interval_ms: 500,
+            max_spectators: 100,
+        }
+    }
+}
+
+impl SpectateConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.max(50))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct RoomsConfig {
+    // Let players create rooms with the CreateRoom message
+    pub enabled: bool,
+    // Rooms open at once, not counting the configured worlds
+    pub max_rooms: usize,
+    // Highest player cap a room may have
+    pub max_players: usize,
+    // Speeds a room may pick, in pixels per tick
+    pub min_speed: u32,
+    pub max_speed: u32,
+    // Map files rooms may be played on, by the names players pick them with;
+    // rooms without one get an empty map
+    pub maps: HashMap<String, PathBuf>,
+    // Seconds a room is kept after its last player left
+    pub empty_secs: u64,
+    // Seconds a private room's invite code lets new players in
+    pub invite_secs: u64,
+    // Share of a room's players, from 0 to 1, who have to be ready for its
+    // match to count down; at least one always has to be
+    pub ready_quorum: f64,
+    // Seconds from enough players being ready to the match starting
+    pub countdown_secs: u64,
+}
+
+impl Default for RoomsConfig {
+    fn default() -> Self {
+        RoomsConfig {
+            enabled: false,
+            max_rooms: 8,
+            max_players: 16,
+            min_speed: 1,
+            max_speed: 10,
+            maps: HashMap::new(),
+            invite_secs: 3600,
+            ready_quorum: 1.0,
+            countdown_secs: 5,
+            empty_secs: 60,
+        }
+    }
+}
+
+impl RoomsConfig {
+    pub fn empty_time(&self) -> Duration {
+        Duration::from_secs(self.empty_secs)
+    }
+
+    pub fn invite_time(&self) -> Duration {
+        Duration::from_secs(self.invite_secs)
+    }
+
+    pub fn countdown_time(&self) -> Duration {
+        Duration::from_secs(self.countdown_secs)
+    }
+}
+
+// Filters applied to chat messages and whispers before they're delivered
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ChatConfig {
+    // Off, chat messages and whispers are refused and clients hide the chat box
+    pub enabled: bool,
+    // Messages containing any of these (ignoring case) are rejected
+    pub blocked_words: Vec<String>,
+    // Replace links with "[link removed]"
+    pub strip_links: bool,
+    // Messages a player may send per rate_window_secs
+    pub rate_limit: u32,
+    pub rate_window_secs: u64,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig {
+            enabled: true,
+            blocked_words: Vec::new(),
+            strip_links: true,
+            rate_limit: 5,
+            rate_window_secs: 10,
+        }
+    }
+}
+
+// Agar-style growth: food scattered over every world makes players bigger and slower
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct GrowthConfig {
+    pub enabled: bool,
+    // Pellets kept on the field per world
+    pub food: usize,
+    // Added to a player's size per pellet eaten
+    pub growth_per_food: u32,
+    pub max_size: u32,
+    // Players above the starting size shrink by one this often
+    pub shrink_interval_ms: u64,
+}
+
+impl Default for GrowthConfig {
+    fn default() -> Self {
+        GrowthConfig {
+            enabled: false,
+            food: 20,
+            growth_per_food: 4,
+            max_size: 150,
+            shrink_interval_ms: 500,
+        }
+    }
+}
+
+impl GrowthConfig {
+    pub fn shrink_interval(&self) -> Duration {
+        Duration::from_millis(self.shrink_interval_ms)
+    }
+}
+
+// What happens to messages on their way to clients, each a probability from 0 to 1
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+#[cfg_attr(not(feature = "chaos"), allow(dead_code))]
+pub struct ChaosConfig {
+    pub enabled: bool,
+    pub drop: f64,
+    pub duplicate: f64,
+    // Held back and sent after the next message
+    pub reorder: f64,
+    // Sent up to max_delay_ms later, so later messages may overtake it
+    pub delay: f64,
+    pub max_delay_ms: u64,
+}
+
+// LAN discovery over mDNS; read at startup only
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MdnsConfig {
+    // Advertise this server on the local network
+    pub advertise: bool,
+    // Look for servers on the local network and list them in /api/servers
+    pub browse: bool,
+}
+
+// Where and how the server logs; read once at startup
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // Most verbose level written: error, warn, info, debug or trace
+    pub level: String,
+    pub format: LogFormat,
+    // Write logs to files in this directory instead of stdout
+    pub directory: Option<PathBuf>,
+    pub file_name: String,
+    pub rotation: LogRotation,
+    // Size a log file may reach before it's rotated, with rotation = "size"
+    pub max_size_mb: u64,
+    // Rotated files kept besides the current one
+    pub max_files: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    // One JSON object per line
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Minutely,
+    Hourly,
+    Daily,
+    Size,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+            directory: None,
+            file_name: "server.log".to_string(),
+            rotation: LogRotation::Daily,
+            max_size_mb: 10,
+            max_files: 7,
+        }
+    }
+}
+
+impl Config {
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.handshake_timeout_secs)
+    }
+
+    pub fn lobby_timeout(&self) -> Duration {
+        Duration::from_secs(self.lobby_timeout_secs)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+
+    pub fn join_cooldown(&self) -> Duration {
+        Duration::from_millis(self.join_cooldown_ms)
+    }
+
+    pub fn session_resume(&self) -> Duration {
+        Duration::from_secs(self.session_resume_secs)
+    }
+
+    // Size limits for the WebSocket reader
+    pub fn websocket_config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: Some(self.max_frame_bytes),
+            max_frame_size: Some(self.max_frame_bytes),
+            ..WebSocketConfig::default()
+        }
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate.max(1) as f64)
+    }
+
+    pub fn snapshot_interval(&self) -> Duration {
+        Duration::from_secs(self.snapshot_interval_secs.max(1))
+    }
+
+    pub fn drain_time(&self) -> Duration {
+        Duration::from_secs(self.drain_secs)
+    }
+
+    pub fn max_players_in(&self, world: &str) -> usize {
+        self.worlds
+            .iter()
+            .find(|config| config.name == world)
+            .and_then(|config| config.max_players)
+            .unwrap_or(self.max_players)
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.banned.contains(&ip)
+    }
+
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    // Load the given config file, falling back to server.toml in the
+    // working directory and then to the defaults
+    pub fn load_or_default(path: Option<&Path>) -> Result<Config, Box<dyn std::error::Error>> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::load(Path::new(DEFAULT_CONFIG_PATH))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+// The live configuration. Readers take a cheap snapshot with get(), and a
+// reload swaps in a whole new Config so nobody sees a half-applied file.
+pub struct SharedConfig {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn load(path: Option<PathBuf>) -> Result<SharedConfig, Box<dyn std::error::Error>> {
+        let config = Config::load_or_default(path.as_deref())?;
+        Ok(SharedConfig {
+            path,
+            current: RwLock::new(Arc::new(config)),
+        })
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Re-read the config file. On error the old config stays in place.
+    pub fn reload(&self) -> Result<Arc<Config>, Box<dyn std::error::Error>> {
+        let config = Config::load_or_default(self.path.as_deref())?;
+        let old = self.get();
+        if config.bind != old.bind || config.http_bind != old.http_bind {
+            warn!("Config: bind address changes only take effect after a restart");
+        }
+        if config.storage != old.storage || config.stats_path != old.stats_path || config.database_url != old.database_url {
+            warn!("Config: storage, stats_path and database_url changes only take effect after a restart");
+        }
+        if config.log != old.log {
+            warn!("Config: log settings only take effect after a restart");
+        }
+        if config.mdns != old.mdns {
+            warn!("Config: mDNS settings only take effect after a restart");
+        }
+        let names = |config: &Config| config.worlds.iter().map(|world| world.name.clone()).collect::<Vec<_>>();
+        if names(&config) != names(&old) {
+            warn!("Config: adding or removing worlds only takes effect after a restart");
+        }
+        let config = Arc::new(config);
+        *self.current.write().unwrap() = config.clone();
+        Ok(config)
+    }
+}
+
+// Command line arguments
+pub struct Args {
+    pub config_path: Option<PathBuf>,
+    // Start with an empty world instead of restoring the last snapshot
+    pub fresh: bool,
+    // Handoff socket of a running server to take the worlds and players over from
+    pub take_over: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Args, String> {
+        let mut args = Args {
+            config_path: None,
+            fresh: false,
+            take_over: None,
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = iter.next().ok_or("--config requires a path")?;
+                    args.config_path = Some(PathBuf::from(path));
+                }
+                "--fresh" => args.fresh = true,
+                "--take-over" => {
+                    let path = iter.next().ok_or("--take-over requires the other server's handoff socket")?;
+                    args.take_over = Some(PathBuf::from(path));
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::achievements::Achievement;
+use crate::storage::{PlayerRecord, PlayerStore};
+use crate::game::{Player, Shape};
+
+// Color only players who won a round may pick
+const GOLD: &str = "#FFD700";
+// Minutes played over all sessions for the circle
+const CIRCLE_PLAYTIME_MINUTES: u64 = 30;
+
+// A look that has to be unlocked; everything else is free to pick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cosmetic {
+    CircleShape,
+    TriangleShape,
+    GoldColor,
+}
+
+const ALL: [Cosmetic; 3] = [Cosmetic::CircleShape, Cosmetic::TriangleShape, Cosmetic::GoldColor];
+
+impl Cosmetic {
+    fn earned(self, record: &PlayerRecord) -> bool {
+        match self {
+            Cosmetic::CircleShape => record.playtime_secs >= CIRCLE_PLAYTIME_MINUTES * 60,
+            Cosmetic::TriangleShape => record.achievements.contains(&Achievement::Traveler),
+            Cosmetic::GoldColor => record.achievements.contains(&Achievement::RoundWinner),
+        }
+    }
+
+    // The cosmetic a shape needs, if any
+    fn for_shape(shape: Shape) -> Option<Cosmetic> {
+        match shape {
+            Shape::Square => None,
+            Shape::Circle => Some(Cosmetic::CircleShape),
+            Shape::Triangle => Some(Cosmetic::TriangleShape),
+        }
+    }
+
+    fn for_color(color: &str) -> Option<Cosmetic> {
+        color.eq_ignore_ascii_case(GOLD).then_some(Cosmetic::GoldColor)
+    }
+}
+
+// What a player has unlocked, adding (and saving) what they earned since last time
+pub async fn inventory(store: &PlayerStore, player_id: &str) -> Vec<Cosmetic> {
+    let record = store.player(player_id).unwrap_or_default();
+    let earned: Vec<Cosmetic> = ALL.into_iter().filter(|cosmetic| cosmetic.earned(&record)).collect();
+    if earned.iter().all(|cosmetic| record.unlocks.contains(cosmetic)) {
+        return record.unlocks;
+    }
+    let unlocks = store.unlock(player_id, &earned);
+    store.save().await;
+    unlocks
+}
+
+// Swap out a shape or color the player picked but hasn't unlocked
+pub fn enforce(player: &mut Player, unlocks: &[Cosmetic], fallback_color: &str) {
+    if Cosmetic::for_shape(player.shape).is_some_and(|needed| !unlocks.contains(&needed)) {
+        info!("{} picked the locked shape {:?}", player.id, player.shape);
+        player.shape = Shape::default();
+    }
+    if Cosmetic::for_color(&player.color).is_some_and(|needed| !unlocks.contains(&needed)) {
+        info!("{} picked the locked color {}", player.id, player.color);
+        player.color = fallback_color.to_string();
+    }
+}
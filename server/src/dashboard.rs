@@ -0,0 +1,241 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio::time::{interval, Duration};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{info, warn};
+
+use crate::admin::{self, Status};
+use crate::config::SharedConfig;
+use crate::http::{self, Request, Response, Upgrade, UpgradeHandler};
+use crate::map::Portal;
+use crate::profiler::Report;
+use crate::stepping;
+use crate::world::Worlds;
+use crate::game::{Player, CANVAS_HEIGHT, CANVAS_WIDTH, PLAYER_SIZE};
+
+const DASHBOARD_PAGE: &str = include_str!("../admin/dashboard.html");
+// How often the dashboard gets a fresh view of the server
+const UPDATE_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Serialize)]
+struct Overview {
+    width: u32,
+    height: u32,
+    player_size: u32,
+    worlds: Vec<WorldView>,
+    clients: Vec<ClientView>,
+}
+
+#[derive(Serialize)]
+struct WorldView {
+    name: String,
+    players: Vec<Player>,
+    portals: Vec<Portal>,
+}
+
+#[derive(Serialize)]
+struct ClientView {
+    addr: SocketAddr,
+    ip: String,
+    world: String,
+    player_id: String,
+    ping_ms: Option<u64>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    // Whether the bandwidth ceiling is holding back snapshots right now
+    throttled: bool,
+}
+
+#[derive(Serialize)]
+struct WorldProfile {
+    world: String,
+    #[serde(flatten)]
+    report: Report,
+}
+
+// Sent by the dashboard page
+#[derive(Deserialize)]
+enum AdminCommand {
+    Kick { addr: SocketAddr },
+    // Counting down `countdown_secs` on the players' banners, if given
+    Announce {
+        text: String,
+        #[serde(default)]
+        countdown_secs: Option<u64>,
+    },
+    // Turn a status like Frozen or Ghost on or off for a player
+    SetStatus { world: String, player_id: String, status: Status, on: bool },
+    Teleport { world: String, player_id: String, x: u32, y: u32 },
+}
+
+// The cookie the dashboard page leaves the token in for the requests it makes
+const TOKEN_COOKIE: &str = "admin_token";
+
+// Whether the request carries the configured admin token, in an Authorization:
+// Bearer header, the dashboard's cookie or ?token=
+fn authorized(request: &Request, token: &str) -> bool {
+    let bearer = request.headers.get("authorization").and_then(|value| value.strip_prefix("Bearer "));
+    let cookie = request.headers.get("cookie").and_then(|cookies| {
+        let prefix = format!("{}=", TOKEN_COOKIE);
+        cookies
+            .split(';')
+            .find_map(|cookie| cookie.trim().strip_prefix(&prefix).map(str::to_string))
+    });
+    bearer == Some(token)
+        || cookie.as_deref() == Some(http::percent_encode(token).as_str())
+        || request.query.get("token").map(String::as_str) == Some(token)
+}
+
+// Not found while no admin token is configured, forbidden without it
+fn guard(request: &Request, config: &SharedConfig) -> Result<(), Response> {
+    match &config.get().admin_token {
+        Some(token) if authorized(request, token) => Ok(()),
+        Some(_) => Err(Response::error(403, "Forbidden")),
+        None => Err(Response::not_found()),
+    }
+}
+
+// Answer an admin request with `respond` if it may have it. Pages on other
+// origins never get to read the answer.
+fn guarded(request: &Request, config: &SharedConfig, respond: impl FnOnce() -> Response) -> Response {
+    let mut response = match guard(request, config) {
+        Ok(()) => respond(),
+        Err(response) => response,
+    };
+    response.cross_origin = false;
+    response
+}
+
+// GET /admin?token=...
+pub fn page(request: &Request, config: &SharedConfig) -> Response {
+    guarded(request, config, || {
+        let mut response = Response::html(DASHBOARD_PAGE);
+        // The page's socket and reloads then do without the token in the URL
+        if let Some(token) = &config.get().admin_token {
+            let token = http::percent_encode(token);
+            response.cookie = Some(format!("{}={}; Path=/admin; HttpOnly; SameSite=Strict", TOKEN_COOKIE, token));
+        }
+        response
+    })
+}
+
+// GET /admin/clients?token=... lists connected clients with their ping and traffic
+pub fn clients(request: &Request, worlds: &Worlds, config: &SharedConfig) -> Response {
+    guarded(request, config, || Response::json(&overview(worlds).clients))
+}
+
+// GET /admin/profile?token=... breaks each world's latest ticks down by what
+// they spent their time on
+pub fn profile(request: &Request, worlds: &Worlds, config: &SharedConfig) -> Response {
+    guarded(request, config, || {
+        let mut profiles: Vec<WorldProfile> = worlds
+            .iter()
+            .map(|world| WorldProfile { world: world.name.clone(), report: world.profiler.report() })
+            .collect();
+        profiles.sort_by(|a, b| a.world.cmp(&b.world));
+        Response::json(&profiles)
+    })
+}
+
+// GET /admin/state?token=...&world=... dumps everything a world's simulation
+// works from; without world=, the default world's
+pub fn state(request: &Request, worlds: &Worlds, config: &SharedConfig) -> Response {
+    guarded(request, config, || match worlds.get(request.query.get("world").map(String::as_str)) {
+        Some(world) => Response::json(&stepping::dump(&world)),
+        None => Response::not_found(),
+    })
+}
+
+// Accepts the dashboard's WebSocket on /admin/ws
+pub fn upgrade_handler(worlds: Arc<Worlds>, config: Arc<SharedConfig>) -> UpgradeHandler {
+    Arc::new(move |request| {
+        if request.path != "/admin/ws" {
+            return Err(Response::not_found());
+        }
+        if let Err(mut response) = guard(request, &config) {
+            response.cross_origin = false;
+            return Err(response);
+        }
+        let (worlds, config) = (worlds.clone(), config.clone());
+        let upgrade: Upgrade = Box::new(move |ws_stream| Box::pin(run_admin_socket(ws_stream, worlds, config)));
+        Ok(upgrade)
+    })
+}
+
+// Push the overview to the dashboard and carry out its commands until it disconnects
+async fn run_admin_socket(ws_stream: WebSocketStream<TcpStream>, worlds: Arc<Worlds>, config: Arc<SharedConfig>) {
+    info!("Admin dashboard connected");
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let mut updates = interval(UPDATE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = updates.tick() => {
+                let json = serde_json::to_string(&overview(&worlds)).expect("Overview is always serializable");
+                if ws_sender.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            message = ws_receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(AdminCommand::Kick { addr }) => {
+                        admin::kick(&worlds, addr);
+                    }
+                    Ok(AdminCommand::Announce { text, countdown_secs }) => {
+                        admin::announce(&worlds, &text, countdown_secs.map(Duration::from_secs))
+                    }
+                    Ok(AdminCommand::SetStatus { world, player_id, status, on }) => {
+                        if let Some(world) = worlds.get(Some(&world)) {
+                            admin::set_status(&world, &config.get(), &player_id, status, on);
+                        }
+                    }
+                    Ok(AdminCommand::Teleport { world, player_id, x, y }) => {
+                        if let Some(world) = worlds.get(Some(&world)) {
+                            admin::teleport(&world, &config.get(), &player_id, x, y);
+                        }
+                    }
+                    Err(e) => warn!("Invalid admin command: {}", e),
+                },
+                Some(Ok(_)) => {}
+                _ => break,
+            },
+        }
+    }
+    info!("Admin dashboard disconnected");
+}
+
+fn overview(worlds: &Worlds) -> Overview {
+    let mut overview = Overview {
+        width: CANVAS_WIDTH,
+        height: CANVAS_HEIGHT,
+        player_size: PLAYER_SIZE,
+        worlds: Vec::new(),
+        clients: Vec::new(),
+    };
+    for world in worlds.iter() {
+        let players = world.game_state.lock().unwrap().players.values().cloned().collect();
+        overview.worlds.push(WorldView {
+            name: world.name.clone(),
+            players,
+            portals: world.map().portals.clone(),
+        });
+        for (addr, client) in world.clients.lock().unwrap().iter() {
+            overview.clients.push(ClientView {
+                addr: *addr,
+                ip: client.ip.to_string(),
+                world: world.name.clone(),
+                player_id: client.player_id.clone(),
+                ping_ms: client.ping.lock().unwrap().map(|ping| ping.as_millis() as u64),
+                bytes_sent: client.traffic.sent(),
+                bytes_received: client.traffic.received(),
+                throttled: client.traffic.is_behind(),
+            });
+        }
+    }
+    overview.clients.sort_by_key(|client| client.addr);
+    overview
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tracing::{info, warn};
+
+use crate::admin;
+use crate::net::close_frame;
+use crate::world::Worlds;
+
+// How long the players' connections get to close and be recorded once the
+// deadline has come
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Winding the server down for a restart: no new players, a countdown for the
+// ones still here, and an exit once it runs out
+#[derive(Default)]
+pub struct Drain {
+    deadline: Mutex<Option<Instant>>,
+    changed: Notify,
+    // The worlds went to a newer server, which saves them from now on
+    handed_off: AtomicBool,
+}
+
+impl Drain {
+    // Begin draining, or move the deadline of a drain already under way
+    pub fn start(&self, after: Duration) {
+        *self.deadline.lock().unwrap() = Some(Instant::now() + after);
+        self.changed.notify_waiters();
+    }
+
+    // Returns whether the server was draining
+    pub fn cancel(&self) -> bool {
+        if self.is_handed_off() {
+            return false;
+        }
+        let was_draining = self.deadline.lock().unwrap().take().is_some();
+        self.changed.notify_waiters();
+        was_draining
+    }
+
+    // Drain for `after` once the players were handed to another server
+    pub fn hand_off(&self, after: Duration) {
+        self.handed_off.store(true, Ordering::Relaxed);
+        self.start(after);
+    }
+
+    pub fn is_handed_off(&self) -> bool {
+        self.handed_off.load(Ordering::Relaxed)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.deadline.lock().unwrap().is_some()
+    }
+
+    // Completes once a drain reaches its deadline, however often it was moved
+    // or cancelled before
+    pub async fn finished(&self) {
+        loop {
+            let changed = self.changed.notified();
+            let deadline = *self.deadline.lock().unwrap();
+            match deadline {
+                Some(deadline) => tokio::select! {
+                    _ = sleep_until(deadline) => return,
+                    _ = changed => {}
+                },
+                None => changed.await,
+            }
+        }
+    }
+}
+
+// Start draining and count it down on every player's banner
+pub fn start(worlds: &Worlds, drain: &Drain, after: Duration, text: &str) {
+    info!("Draining: no new players, shutting down in {}s", after.as_secs());
+    drain.start(after);
+    admin::announce(worlds, text, Some(after));
+}
+
+pub fn cancel(worlds: &Worlds, drain: &Drain) -> bool {
+    if !drain.cancel() {
+        return false;
+    }
+    info!("Drain cancelled, taking new players again");
+    admin::announce(worlds, "The restart was called off", None);
+    true
+}
+
+// Close every player's connection for the restart, then give them a moment
+// to leave, so their sessions and stats are recorded before we exit
+pub async fn close_all(worlds: &Worlds) {
+    for world in worlds.iter() {
+        for client in world.clients.lock().unwrap().values() {
+            let _ = client.sender.send(close_frame(CloseCode::Restart, "The server is restarting"));
+        }
+    }
+    let gone = async {
+        while worlds.iter().any(|world| !world.clients.lock().unwrap().is_empty()) {
+            sleep(Duration::from_millis(50)).await;
+        }
+    };
+    if tokio::time::timeout(GOODBYE_TIMEOUT, gone).await.is_err() {
+        warn!("Players still connected after {}s, shutting down anyway", GOODBYE_TIMEOUT.as_secs());
+    }
+}
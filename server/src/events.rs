@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+
+use crate::game::{Player, CANVAS_HEIGHT, CANVAS_WIDTH};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    fn overlaps(&self, player: &Player) -> bool {
+        player.x < self.x + self.width
+            && player.x + player.size > self.x
+            && player.y < self.y + self.height
+            && player.y + player.size > self.y
+    }
+
+    // Linear interpolation between two rects, `t` in 0..=1
+    fn lerp(from: Rect, to: Rect, t: f64) -> Rect {
+        let mix = |a: u32, b: u32| (a as f64 + (b as f64 - a as f64) * t).round() as u32;
+        Rect {
+            x: mix(from.x, to.x),
+            y: mix(from.y, to.y),
+            width: mix(from.width, to.width),
+            height: mix(from.height, to.height),
+        }
+    }
+}
+
+// An entry in a map's event schedule
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    pub id: String,
+    // Seconds between the starts of two runs
+    pub every_secs: u64,
+    pub duration_secs: u64,
+    // Delay before the first run; defaults to every_secs
+    pub first_after_secs: Option<u64>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    // Players touching the zone earn points every second
+    PointRush { zone: Rect, points_per_second: u32 },
+    // Everyone moves faster
+    SpeedBoost { multiplier: f32 },
+    // The playable area shrinks from `from` to `to` over the event; players outside are pushed in
+    ShrinkingArea { from: Rect, to: Rect },
+}
+
+// Sent to clients when an event starts (and to players joining while it runs).
+// remaining_ms is 0 when the event has just ended.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldEvent {
+    pub id: String,
+    pub kind: EventKind,
+    pub duration_ms: u64,
+    pub remaining_ms: u64,
+}
+
+struct RunningEvent {
+    event: ScheduledEvent,
+    started: Instant,
+    ends: Instant,
+    next_award: Instant,
+}
+
+impl RunningEvent {
+    fn describe(&self, now: Instant) -> WorldEvent {
+        WorldEvent {
+            id: self.event.id.clone(),
+            kind: self.event.kind.clone(),
+            duration_ms: (self.ends - self.started).as_millis() as u64,
+            remaining_ms: self.ends.saturating_duration_since(now).as_millis() as u64,
+        }
+    }
+}
+
+// Starts and stops a world's scheduled events and applies their effects.
+// State is keyed by event id, so a map reload keeps unchanged events on schedule.
+#[derive(Default)]
+pub struct EventScheduler {
+    next_start: HashMap<String, Instant>,
+    running: HashMap<String, RunningEvent>,
+}
+
+impl EventScheduler {
+    // Start and stop the events due at `now`, returning what clients need to hear about
+    pub fn update(&mut self, schedule: &[ScheduledEvent], now: Instant) -> Vec<WorldEvent> {
+        let mut changes = Vec::new();
+
+        // End events that ran out or were removed from the map
+        let finished: Vec<String> = self
+            .running
+            .iter()
+            .filter(|(id, running)| now >= running.ends || !schedule.iter().any(|event| &event.id == *id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in finished {
+            let running = self.running.remove(&id).unwrap();
+            let next = running.started + Duration::from_secs(running.event.every_secs.max(1));
+            self.next_start.insert(id, next.max(now));
+            changes.push(running.describe(now));
+        }
+
+        // Start events that are due
+        for event in schedule {
+            let first_after = event.first_after_secs.unwrap_or(event.every_secs);
+            let next = *self
+                .next_start
+                .entry(event.id.clone())
+                .or_insert(now + Duration::from_secs(first_after));
+            if now >= next && !self.running.contains_key(&event.id) {
+                let running = RunningEvent {
+                    event: event.clone(),
+                    started: now,
+                    ends: now + Duration::from_secs(event.duration_secs),
+                    next_award: now + Duration::from_secs(1),
+                };
+                changes.push(running.describe(now));
+                self.running.insert(event.id.clone(), running);
+            }
+        }
+        self.next_start.retain(|id, _| schedule.iter().any(|event| &event.id == id));
+
+        changes
+    }
+
+    // Apply running events to the players for this tick; returns whether anyone changed
+    pub fn apply(&mut self, players: &mut HashMap<String, Player>, now: Instant) -> bool {
+        let mut changed = false;
+        for running in self.running.values_mut() {
+            match &running.event.kind {
+                EventKind::PointRush { zone, points_per_second } => {
+                    while now >= running.next_award {
+                        running.next_award += Duration::from_secs(1);
+                        for player in players.values_mut().filter(|player| zone.overlaps(player)) {
+                            player.score += points_per_second;
+                            changed = true;
+                        }
+                    }
+                }
+                EventKind::SpeedBoost { .. } => {}
+                EventKind::ShrinkingArea { from, to } => {
+                    let total = (running.ends - running.started).as_secs_f64();
+                    let progress = if total > 0.0 {
+                        ((now - running.started).as_secs_f64() / total).min(1.0)
+                    } else {
+                        1.0
+                    };
+                    let area = Rect::lerp(*from, *to, progress);
+                    for player in players.values_mut() {
+                        changed |= push_inside(player, area);
+                    }
+                }
+            }
+        }
+        changed
+    }
+
+    // Combined speed multiplier of all running speed boosts
+    pub fn speed_multiplier(&self) -> f32 {
+        self.running
+            .values()
+            .map(|running| match running.event.kind {
+                EventKind::SpeedBoost { multiplier } => multiplier,
+                _ => 1.0,
+            })
+            .product()
+    }
+
+    // Events currently running, for clients that join mid-event
+    pub fn running(&self, now: Instant) -> Vec<WorldEvent> {
+        self.running.values().map(|running| running.describe(now)).collect()
+    }
+}
+
+// Move a player into the area if it's outside; returns whether it moved
+fn push_inside(player: &mut Player, area: Rect) -> bool {
+    let max_x = (area.x + area.width).saturating_sub(player.size).max(area.x).min(CANVAS_WIDTH.saturating_sub(player.size));
+    let max_y = (area.y + area.height).saturating_sub(player.size).max(area.y).min(CANVAS_HEIGHT.saturating_sub(player.size));
+    let (x, y) = (player.x.clamp(area.x.min(max_x), max_x), player.y.clamp(area.y.min(max_y), max_y));
+    let moved = (x, y) != (player.x, player.y);
+    player.x = x;
+    player.y = y;
+    moved
+}
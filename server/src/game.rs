@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use sim::Body;
+
+use crate::growth::Food;
+use crate::wire::WireIds;
+
+pub mod systems;
+
+// Game constants
+pub const CANVAS_WIDTH: u32 = sim::WORLD_WIDTH;
+pub const CANVAS_HEIGHT: u32 = sim::WORLD_HEIGHT;
+pub const PLAYER_SIZE: u32 = sim::PLAYER_SIZE;
+
+// Game state types
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Player {
+    pub id: String,
+    // Display name picked by the player; empty when it didn't pick one
+    #[serde(default)]
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub color: String,
+    #[serde(default)]
+    pub shape: Shape,
+    // Side of the player's square, PLAYER_SIZE unless it grew
+    #[serde(default = "default_size")]
+    pub size: u32,
+    // Out until the round ends, in trail mode
+    #[serde(default)]
+    pub eliminated: bool,
+    // The player's window is in the background; shown dimmed
+    #[serde(default)]
+    pub afk: bool,
+    // Held in place by an admin: their keys, knockback and pushes don't move them
+    #[serde(default)]
+    pub frozen: bool,
+    // Passed through by other players, set by an admin: no collisions, pushes
+    // or trails to run into
+    #[serde(default)]
+    pub ghost: bool,
+    // Knockback after bouncing off an edge, in pixels per tick
+    #[serde(skip)]
+    pub bounce: (i64, i64),
+    #[serde(default)]
+    pub score: u32,
+}
+
+fn default_size() -> u32 {
+    PLAYER_SIZE
+}
+
+impl Player {
+    // The part of the player the shared simulation works on
+    pub fn body(&self) -> Body {
+        Body {
+            x: self.x,
+            y: self.y,
+            size: self.size,
+            bounce: self.bounce,
+        }
+    }
+
+    // Run a piece of the shared simulation on the player
+    pub fn simulate<R>(&mut self, step: impl FnOnce(&mut Body) -> R) -> R {
+        let mut body = self.body();
+        let result = step(&mut body);
+        (self.x, self.y, self.bounce) = (body.x, body.y, body.bounce);
+        result
+    }
+}
+
+// What a player is drawn as, picked at join
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    #[default]
+    Square,
+    Circle,
+    Triangle,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameState {
+    pub players: HashMap<String, Player>,
+    // Pellets to grow with, when [growth] is enabled
+    #[serde(default)]
+    pub food: Vec<Food>,
+    // Segments each player's trail is made of, oldest first, in trail mode
+    #[serde(default)]
+    pub trails: HashMap<String, Vec<(u32, u32)>>,
+    // Numbers snapshots call players and colors by; shared by every copy of the state
+    #[serde(skip)]
+    pub wire: Arc<WireIds>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum GameEvent {
+    // The rules changed `target` when `by` ran into them, like tagging them
+    Tag { by: String, target: String },
+    // A player ate `count` pieces of food
+    Pickup { player_id: String, count: u32 },
+    // A player ran into a trail
+    Eliminated { player_id: String },
+}
+
+pub type GameStateSync = Arc<Mutex<GameState>>;
+
+// Keep a player fully inside the canvas
+pub fn clamp_to_world(player: &mut Player) {
+    player.simulate(sim::clamp_to_world);
+}
+
+// IDs of the players whose square overlaps the given player's
+pub fn colliding_with(state: &GameState, player_id: &str) -> Vec<String> {
+    let Some(player) = state.players.get(player_id).filter(|player| !player.ghost) else {
+        return Vec::new();
+    };
+    state
+        .players
+        .values()
+        .filter(|other| other.id != player.id && !other.ghost)
+        .filter(|other| sim::collision::overlaps(&player.body(), &other.body()))
+        .map(|other| other.id.clone())
+        .collect()
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sim::{grid, KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP};
+use tracing::{info, warn};
+
+use crate::bus::EventBus;
+use crate::broadcast::{broadcast, broadcast_game_state, catch_up_snapshots};
+use crate::config::{Config, GameMode, Netcode, SharedConfig};
+use crate::game::{colliding_with, GameEvent, GameState};
+use crate::matches::Phase;
+use crate::metrics::METRICS;
+use crate::profiler::{Stage, TickProfile};
+use crate::protocol::ServerMessage;
+use crate::scripting::ScriptHost;
+use crate::storage::PlayerStore;
+use crate::world::World;
+use crate::{achievements, bus, growth, lockstep, physics, trail};
+
+// Minimum gap between two "tick over budget" warnings for a world
+const OVERRUN_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+// Advance a world once per tick, broadcasting when events or scripts change it
+pub async fn run_game_loop(world: Arc<World>, config: Arc<SharedConfig>, scripts: Arc<ScriptHost>, store: Arc<PlayerStore>) {
+    // Real time between ticks; each tick is one configured tick interval of game time
+    let mut tick_interval = world.stepper.tick_interval(config.get().tick_interval());
+    let mut ticker = tokio::time::interval(tick_interval);
+    let mut last_overrun_warning: Option<Instant> = None;
+    // Game time since players last shrank
+    let mut since_shrink = Duration::ZERO;
+    let mut tick: u64 = 0;
+    // Tick each player last moved on, for grid maps
+    let mut last_steps: HashMap<String, u64> = HashMap::new();
+    // Each player's score as of the last tick, to publish what they scored
+    let mut scores: HashMap<String, u32> = HashMap::new();
+    loop {
+        let now = ticker.tick().await;
+        if world.is_closed() {
+            break;
+        }
+        if !world.stepper.proceed() {
+            continue;
+        }
+        let tick_started = Instant::now();
+        let mut profile = TickProfile::default();
+        
+        // Start and stop scheduled events, then let them act on the players
+        let (event_changes, mut changed, multiplier) = {
+            let mut events = world.events.lock().unwrap();
+            let event_changes = events.update(&world.map().events, now);
+            let mut state = world.game_state.lock().unwrap();
+            let changed = events.apply(&mut state.players, now);
+            (event_changes, changed, events.speed_multiplier())
+        }; // Locks are released here
+        for event in event_changes {
+            profile.send(|| broadcast(&world.clients, &ServerMessage::WorldEvent { event }));
+        }
+        
+        // Move everyone holding keys down by one step; on a grid, one cell every few ticks.
+        // Lockstep clients do the same from the relayed inputs, so plain moves
+        // don't need a new state sent.
+        tick += 1;
+        world.stepper.ticked(tick);
+        let lockstep = world.netcode == Netcode::Lockstep;
+        // Before a room's match starts nobody moves
+        let playing = world.is_playing();
+        let speed = (world.player_speed(&config.get()) as f32 * multiplier).round() as u32;
+        let mut moves = HashMap::new();
+        let mut knocked_back = false;
+        let messages = {
+            let ticks_per_step = world.map().cell_size.map(|cell_size| grid::ticks_per_step(cell_size, speed) as u64);
+            let inputs = world.inputs.lock().unwrap();
+            let mut state = world.game_state.lock().unwrap();
+            let mut messages = Vec::new();
+            for (player_id, held) in inputs.iter().filter(|(_, held)| held.keys != 0 && playing) {
+                // Frozen players hold their keys for nothing, and aren't relayed to lockstep clients
+                if state.players.get(player_id).is_some_and(|player| player.frozen) {
+                    continue;
+                }
+                if let Some(ticks_per_step) = ticks_per_step {
+                    if last_steps.get(player_id).is_some_and(|last| tick - last < ticks_per_step) {
+                        continue;
+                    }
+                    last_steps.insert(player_id.clone(), tick);
+                }
+                if state.players.get(player_id).is_some_and(|player| !player.eliminated) {
+                    moves.insert(player_id.clone(), held.keys);
+                }
+                let moved = move_player(&mut state, &world, &scripts, player_id, held.keys, speed, &mut profile);
+                // Portals and collision rules aren't simulated by lockstep clients
+                changed |= !lockstep || !moved.is_empty();
+                messages.extend(moved);
+            }
+            last_steps.retain(|player_id, _| inputs.contains_key(player_id));
+            
+            // Players that bounced off an edge keep sliding back for a bit
+            let boundary = world.map().boundary;
+            profile.time(Stage::Physics, || {
+                for player in state.players.values_mut().filter(|player| !player.frozen) {
+                    knocked_back |= player.simulate(|body| sim::apply_knockback(body, boundary));
+                }
+            });
+            changed |= knocked_back && !lockstep;
+            messages
+        }; // Locks are released here
+        if lockstep && (!moves.is_empty() || knocked_back) {
+            // Whatever else changed goes out as a state after the tick, and the
+            // clients' hashes of this tick won't match
+            if !changed {
+                world.hashes.record(tick, lockstep::hash(&world.game_state.lock().unwrap()));
+            }
+            profile.send(|| broadcast(&world.clients, &ServerMessage::Tick { tick, speed, inputs: moves }));
+        }
+        for message in messages {
+            profile.send(|| broadcast(&world.clients, &message));
+        }
+        
+        if let Some(rules) = scripts.get() {
+            let mut state = world.game_state.lock().unwrap();
+            changed |= profile.time(Stage::Rules, || rules.on_tick(&mut state.players));
+        } // Lock is released here
+
+        // Collisions were reported to the rules by now, so players can be pushed apart.
+        // Grid players stay on their cells.
+        let map = world.map();
+        if map.push_players && map.cell_size.is_none() {
+            let mut state = world.game_state.lock().unwrap();
+            changed |= profile.time(Stage::Collision, || physics::separate(&mut state.players));
+        }
+
+        // Eat, grow and shrink
+        since_shrink += config.get().tick_interval();
+        let shrink = since_shrink >= config.get().growth.shrink_interval();
+        if shrink {
+            since_shrink = Duration::ZERO;
+        }
+        let (grown, pickups) = {
+            let mut state = world.game_state.lock().unwrap();
+            profile.time(Stage::Physics, || growth::update(&mut state, &config.get().growth, shrink))
+        };
+        changed |= grown;
+        for event in pickups {
+            if let GameEvent::Pickup { player_id, count } = &event {
+                world.bus.publish(bus::Event::Ate { player_id: player_id.clone(), count: *count });
+            }
+            profile.send(|| broadcast(&world.clients, &ServerMessage::GameEvent { event }));
+        }
+        world.stats.tick(&world.game_state.lock().unwrap(), config.get().tick_interval());
+
+        if world.mode == GameMode::Trail && playing {
+            let (trails_changed, messages) = {
+                let mut state = world.game_state.lock().unwrap();
+                profile.time(Stage::Physics, || trail::update(&mut state, &world.bus, &world.stats, &world.map(), world.friendly_fire()))
+            };
+            changed |= trails_changed;
+            // A room's match ends with its round
+            let round_over = messages.iter().any(|message| matches!(message, ServerMessage::RoundOver { .. }));
+            if let Some(round) = world.round.as_ref().filter(|_| round_over) {
+                round.lock().unwrap().finish();
+            }
+            for message in messages {
+                profile.send(|| broadcast(&world.clients, &message));
+            }
+        }
+
+        // Rooms count down once enough of their players are ready, and start
+        // their match when it runs out; trail rooms on a clear field
+        if let Some(round) = &world.round {
+            let message = {
+                let config = config.get();
+                let mut state = world.game_state.lock().unwrap();
+                let mut round = round.lock().unwrap();
+                let (moved, tell) = round.update(state.players.keys(), &config.rooms);
+                if moved == Some(Phase::Playing) && world.mode == GameMode::Trail {
+                    trail::reset(&mut state, &world.map());
+                    changed = true;
+                }
+                tell.then(|| round.message(state.players.len(), &config.rooms))
+            }; // Locks are released here
+            if let Some(message) = message {
+                profile.send(|| broadcast(&world.clients, &message));
+            }
+        }
+
+        // Whatever raised them - food, rounds, events or the rules - publish the points scored
+        publish_scores(&world.game_state.lock().unwrap(), &mut scores, &world.bus);
+
+        // Save new achievements in the background, so the file write doesn't hold up the tick
+        let awarded = achievements::check(&world.achievements, &world.stats, &store);
+        if !awarded.is_empty() {
+            let store = store.clone();
+            tokio::spawn(async move { store.save().await });
+        }
+        for (player_id, achievement) in awarded {
+            info!("{} earned {:?} in {}", player_id, achievement, world.name);
+            profile.send(|| broadcast(&world.clients, &ServerMessage::Achievement { player_id, achievement }));
+        }
+
+        // New colors go in the palette, announced before a snapshot calls them by index
+        let (wire, colors) = {
+            let state = world.game_state.lock().unwrap();
+            (state.wire.clone(), state.wire.add_colors(state.players.values().map(|player| player.color.as_str())))
+        }; // Lock is released here
+        if !colors.is_empty() {
+            profile.send(|| broadcast(&world.clients, &ServerMessage::Palette { colors: colors.clone() }));
+            wire.announce_colors(&colors);
+        }
+
+        let max_bytes_per_sec = config.get().client_max_bytes_per_sec;
+        if changed {
+            profile.send(|| match broadcast_game_state(&world.clients, &world.game_state, max_bytes_per_sec) {
+                Ok(encoding) => encoding,
+                Err(e) => {
+                    error_limited!("Error broadcasting game state: {}", e);
+                    Duration::ZERO
+                }
+            });
+        }
+        profile.send(|| catch_up_snapshots(&world.clients, &world.game_state, max_bytes_per_sec));
+
+        let tick_duration = tick_started.elapsed();
+        METRICS.tick.record(tick_duration);
+        profile.finish(tick_duration);
+        world.profiler.record(profile);
+        if tick_duration > tick_interval {
+            METRICS.tick_overruns.fetch_add(1, Ordering::Relaxed);
+            if last_overrun_warning.is_none_or(|warned| warned.elapsed() >= OVERRUN_WARNING_INTERVAL) {
+                warn!(
+                    "Tick in {} took {:?}, over the {:?} budget; the server is overloaded",
+                    world.name, tick_duration, tick_interval
+                );
+                last_overrun_warning = Some(Instant::now());
+            }
+        }
+        
+        // Pick up tick rate changes from a config reload or the time scale,
+        // and tell clients so they predict at the new pace
+        let configured = world.stepper.tick_interval(config.get().tick_interval());
+        if configured != tick_interval {
+            tick_interval = configured;
+            ticker = tokio::time::interval(tick_interval);
+            let time_scale = world.stepper.time_scale();
+            let movement = movement(&world, &config.get());
+            broadcast(&world.clients, &ServerMessage::TimeScale { time_scale, movement });
+        }
+    }
+}
+
+fn publish_scores(state: &GameState, scores: &mut HashMap<String, u32>, bus: &EventBus) {
+    scores.retain(|player_id, _| state.players.contains_key(player_id));
+    for player in state.players.values() {
+        // Players are first seen with the score they joined with
+        match scores.insert(player.id.clone(), player.score) {
+            Some(before) if player.score > before => bus.publish(bus::Event::Scored {
+                player_id: player.id.clone(),
+                score: player.score,
+                gained: player.score - before,
+            }),
+            _ => {}
+        }
+    }
+}
+
+// How often a player may move: once per tick, or once per grid step
+pub fn move_interval(world: &World, config: &Config) -> Duration {
+    let tick_interval = world.stepper.tick_interval(config.tick_interval());
+    match world.map().cell_size {
+        Some(cell_size) => grid::step_interval(cell_size, world.player_speed(config), tick_interval),
+        None => tick_interval,
+    }
+}
+
+// What clients predict their player with, at the world's time scale
+pub fn movement(world: &World, config: &Config) -> sim::Movement {
+    sim::Movement {
+        speed: world.player_speed(config),
+        tick_ms: world.stepper.tick_interval(config.tick_interval()).as_millis() as u32,
+    }
+}
+
+// Move a player one step in the directions held in `keys`, then apply portals
+// and collision rules. Returns the teleport to announce, if it went through a portal.
+pub fn move_player(
+    state: &mut GameState,
+    world: &World,
+    scripts: &ScriptHost,
+    player_id: &str,
+    keys: u8,
+    speed: u32,
+    profile: &mut TickProfile,
+) -> Vec<ServerMessage> {
+    let mut messages = Vec::new();
+    let map = world.map();
+    let started = Instant::now();
+    // Frozen players stay put whether they hold keys or send moves
+    if let Some(player) = state.players.get_mut(player_id).filter(|player| !player.eliminated && !player.frozen) {
+        let from = (player.x, player.y);
+        player.simulate(|body| sim::step(body, keys, speed, map.cell_size, map.boundary));
+        // At most one step's length, so wrapping around an edge isn't a long way
+        let (dx, dy) = sim::key_offsets(keys);
+        let step_length = map.cell_size.unwrap_or_else(|| sim::speed(speed, player.size));
+        let step = ((dx * dx + dy * dy) as f64).sqrt() * step_length as f64;
+        let moved = (player.x as f64 - from.0 as f64).hypot(player.y as f64 - from.1 as f64);
+        if moved > 0.0 {
+            world.bus.publish(bus::Event::Moved { player_id: player_id.to_string(), distance: moved.min(step) });
+        }
+
+        // Step through a portal the player just walked onto
+        if map.step_through_portal(from, player).is_some() {
+            if let Some(cell_size) = map.cell_size {
+                player.simulate(|body| grid::snap(body, cell_size));
+            }
+            messages.push(ServerMessage::Teleported {
+                player_id: player_id.to_string(),
+                from,
+                to: (player.x, player.y),
+            });
+        }
+    }
+    
+    profile.add(Stage::Input, started.elapsed());
+    
+    // Let the rules react to the mover touching other players, unless they
+    // can't take each other out here
+    let rules = scripts.get().filter(|_| world.friendly_fire());
+    profile.time(Stage::Collision, || {
+        for other in colliding_with(state, player_id) {
+            let tagged = rules.as_ref().is_some_and(|rules| rules.on_collision(&mut state.players, player_id, &other));
+            world.bus.publish(bus::Event::Collision { by: player_id.to_string(), target: other.clone(), tagged });
+            if tagged {
+                let event = GameEvent::Tag { by: player_id.to_string(), target: other };
+                messages.push(ServerMessage::GameEvent { event });
+            }
+        }
+    });
+    messages
+}
+
+pub fn direction_keys(direction: &str) -> u8 {
+    match direction {
+        "w" => KEY_UP,
+        "a" => KEY_LEFT,
+        "s" => KEY_DOWN,
+        "d" => KEY_RIGHT,
+        _ => 0,
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::GrowthConfig;
+use crate::game::{GameEvent, GameState, Player, CANVAS_HEIGHT, CANVAS_WIDTH, PLAYER_SIZE};
+
+// Side of a food pellet
+pub const FOOD_SIZE: u32 = 10;
+
+// A pellet that makes whoever touches it grow
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Food {
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Food {
+    fn random() -> Food {
+        Food {
+            x: random_below(CANVAS_WIDTH - FOOD_SIZE),
+            y: random_below(CANVAS_HEIGHT - FOOD_SIZE),
+        }
+    }
+
+    fn touches(&self, player: &Player) -> bool {
+        self.x < player.x + player.size
+            && self.x + FOOD_SIZE > player.x
+            && self.y < player.y + player.size
+            && self.y + FOOD_SIZE > player.y
+    }
+}
+
+// Top up the food, let players eat what they touch and, with `shrink`, take a
+// bit off everyone above the starting size. Returns whether anything changed
+// and who ate what.
+pub fn update(state: &mut GameState, config: &GrowthConfig, shrink: bool) -> (bool, Vec<GameEvent>) {
+    if !config.enabled {
+        // Switched off by a reload: back to the plain game
+        let grown = state.players.values().any(|player| player.size != PLAYER_SIZE);
+        let changed = grown || !state.food.is_empty();
+        state.food.clear();
+        for player in state.players.values_mut() {
+            player.size = PLAYER_SIZE;
+        }
+        return (changed, Vec::new());
+    }
+
+    let mut changed = false;
+    let mut pickups = Vec::new();
+    state.food.truncate(config.food);
+    while state.food.len() < config.food {
+        state.food.push(Food::random());
+        changed = true;
+    }
+
+    let max_size = config.max_size.clamp(PLAYER_SIZE, CANVAS_HEIGHT);
+    for player in state.players.values_mut() {
+        let before = state.food.len();
+        state.food.retain(|food| !food.touches(player));
+        let eaten = (before - state.food.len()) as u32;
+        if eaten > 0 {
+            player.size = (player.size + eaten * config.growth_per_food).min(max_size);
+            player.score += eaten;
+            changed = true;
+            pickups.push(GameEvent::Pickup {
+                player_id: player.id.clone(),
+                count: eaten,
+            });
+        }
+        if shrink && player.size > PLAYER_SIZE {
+            player.size -= 1;
+            changed = true;
+        }
+        crate::game::clamp_to_world(player);
+    }
+    (changed, pickups)
+}
+
+fn random_below(bound: u32) -> u32 {
+    // Every RandomState is seeded differently, which is random enough for food
+    let hasher = RandomState::new().build_hasher();
+    (hasher.finish() % bound.max(1) as u64) as u32
+}
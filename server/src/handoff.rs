@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{info, warn};
+
+use crate::chat::Mutes;
+use crate::config::Config;
+use crate::drain::Drain;
+use crate::rooms::RoomConfig;
+use crate::session::Sessions;
+use crate::world::Worlds;
+use crate::broadcast::broadcast;
+use crate::game::{GameState, Player};
+use crate::protocol::ServerMessage;
+
+// How long players get to follow the Reconnect before the old server closes
+// what's left and exits
+#[cfg(unix)]
+const HANDOFF_GRACE: Duration = Duration::from_secs(10);
+
+// What the new server sends to ask for the players
+#[derive(Serialize, Deserialize)]
+struct Request {
+    // Where the players should reconnect to
+    url: String,
+}
+
+// Everything the old server hands over, as one line of JSON
+#[derive(Serialize, Deserialize)]
+pub struct Handoff {
+    // Every world's state, without its players
+    pub worlds: HashMap<String, GameState>,
+    // Players with a session, connected or waiting to resume, who resume on the new server
+    pub players: Vec<HandedOver>,
+    // Mutes still running, with the milliseconds left on them
+    pub mutes: HashMap<String, u64>,
+    // Rooms players created, to open again on the new server
+    #[serde(default)]
+    pub rooms: Vec<RoomConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HandedOver {
+    pub token: String,
+    pub world: String,
+    pub player: Player,
+}
+
+// Wait on the handoff socket for a newer server, give it everything and send
+// our players to it. We drain once that's done, so this serves one handoff.
+#[cfg(unix)]
+pub async fn serve(path: PathBuf, worlds: Arc<Worlds>, sessions: Arc<Sessions>, mutes: Arc<Mutes>, drain: Arc<Drain>) {
+    // A socket file left behind by a server that didn't exit cleanly
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Can't listen for handoffs on {}: {}", path.display(), e);
+            return;
+        }
+    };
+    info!("Waiting for a handoff on {}", path.display());
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        match hand_over(stream, &worlds, &sessions, &mutes, &drain).await {
+            Ok(()) => break,
+            Err(e) => warn!("Handoff failed: {}", e),
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(unix)]
+async fn hand_over(
+    stream: UnixStream,
+    worlds: &Worlds,
+    sessions: &Sessions,
+    mutes: &Mutes,
+    drain: &Drain,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let request: Request = serde_json::from_str(&line)?;
+
+    // Nothing may change after it's been handed over
+    for world in worlds.iter() {
+        world.stepper.pause();
+    }
+    let handoff = export(worlds, sessions, mutes);
+    let mut json = serde_json::to_vec(&handoff)?;
+    json.push(b'\n');
+    if let Err(e) = writer.write_all(&json).await {
+        for world in worlds.iter() {
+            world.stepper.resume();
+        }
+        return Err(e.into());
+    }
+    writer.shutdown().await?;
+
+    info!("Handed {} player(s) over, sending them to {}", handoff.players.len(), request.url);
+    drain.hand_off(HANDOFF_GRACE);
+    for world in worlds.iter() {
+        broadcast(&world.clients, &ServerMessage::Reconnect { url: request.url.clone() });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn export(worlds: &Worlds, sessions: &Sessions, mutes: &Mutes) -> Handoff {
+    let tokens = sessions.connected();
+    let mut players = Vec::new();
+    let mut states = HashMap::new();
+    let mut rooms = Vec::new();
+    for world in worlds.iter() {
+        rooms.extend(world.rules());
+        let mut state = world.game_state.lock().unwrap().clone();
+        for (player_id, player) in std::mem::take(&mut state.players) {
+            if let Some(token) = tokens.get(&player_id) {
+                players.push(HandedOver { token: token.clone(), world: world.name.clone(), player });
+            }
+        }
+        states.insert(world.name.clone(), state);
+    }
+    for (token, world, player) in sessions.left() {
+        players.push(HandedOver { token, world, player });
+    }
+    let mutes = mutes.running().into_iter().map(|(player_id, left)| (player_id, left.as_millis() as u64)).collect();
+    Handoff { worlds: states, players, mutes, rooms }
+}
+
+// Ask the server listening on `path` for its players, to be reconnected to `url`
+#[cfg(unix)]
+pub async fn take_over(path: &Path, url: String) -> Result<Handoff, Box<dyn std::error::Error>> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut request = serde_json::to_vec(&Request { url })?;
+    request.push(b'\n');
+    writer.write_all(&request).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    if line.is_empty() {
+        return Err("the other server hung up without handing anything over".into());
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(not(unix))]
+pub async fn take_over(_path: &Path, _url: String) -> Result<Handoff, Box<dyn std::error::Error>> {
+    Err("handoffs go over Unix sockets, which this platform doesn't have".into())
+}
+
+// Where our players should reconnect to
+pub fn reconnect_url(config: &Config) -> String {
+    config.public_url.clone().unwrap_or_else(|| format!("ws://{}", config.bind))
+}
+
+// Keep the handed over players waiting to resume, as if they had just dropped
+// out, and their mutes running
+pub fn resume(handoff: Handoff, sessions: &Sessions, mutes: &Mutes, config: &Config) {
+    info!("Took over {} player(s)", handoff.players.len());
+    for HandedOver { token, world, player } in handoff.players {
+        sessions.restore(&token, &player.id);
+        sessions.leave(&token, &world, player, config.session_resume());
+    }
+    for (player_id, left) in handoff.mutes {
+        mutes.mute(&player_id, Duration::from_millis(left));
+    }
+}
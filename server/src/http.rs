@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+use reqwest::Method;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::config::SharedConfig;
+
+// Largest request head and body we accept
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+// Time a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// How long to wait for more of a request head that came in pieces
+const PEEK_INTERVAL: Duration = Duration::from_millis(5);
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+// A parsed HTTP request
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    // Header names are lowercased
+    pub headers: HashMap<String, String>,
+    // Empty unless the request had a Content-Length
+    pub body: Vec<u8>,
+    // Address of whoever connected
+    pub peer: IpAddr,
+}
+
+impl Request {
+    fn is_websocket_upgrade(&self) -> bool {
+        self.headers
+            .get("upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+// The start of a request, peeked at without reading it from the stream
+pub struct Head {
+    pub path: String,
+    pub upgrade: bool,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    // Where a redirect goes
+    pub location: Option<String>,
+    // A cookie to set, as the Set-Cookie header's value
+    pub cookie: Option<String>,
+    // Whether pages on other origins may read it
+    pub cross_origin: bool,
+}
+
+impl Response {
+    pub fn json<T: Serialize>(value: &T) -> Response {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response {
+                status: 200,
+                content_type: "application/json",
+                body,
+                location: None,
+                cookie: None,
+                cross_origin: true,
+            },
+            Err(_) => Response::error(500, "Internal Server Error"),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::json!({ "error": message }).to_string().into_bytes(),
+            location: None,
+            cookie: None,
+            cross_origin: true,
+        }
+    }
+
+    pub fn not_found() -> Response {
+        Response::error(404, "Not Found")
+    }
+
+    pub fn text(body: String) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: body.into_bytes(),
+            location: None,
+            cookie: None,
+            cross_origin: true,
+        }
+    }
+
+    pub fn html(body: &str) -> Response {
+        Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+            location: None,
+            cookie: None,
+            cross_origin: true,
+        }
+    }
+
+    pub fn redirect(url: String) -> Response {
+        Response {
+            status: 302,
+            content_type: "text/plain",
+            body: Vec::new(),
+            location: Some(url),
+            cookie: None,
+            cross_origin: true,
+        }
+    }
+}
+
+pub type Handler = Arc<dyn Fn(Request) -> BoxFuture<'static, Response> + Send + Sync>;
+// Decides whether to accept a WebSocket upgrade request; an accepted upgrade
+// takes over the connection after the handshake
+pub type UpgradeHandler = Arc<dyn Fn(&Request) -> Result<Upgrade, Response> + Send + Sync>;
+pub type Upgrade = Box<dyn FnOnce(WebSocketStream<TcpStream>) -> BoxFuture<'static, ()> + Send>;
+
+// Ask each handler in turn, going on to the next while they don't know the path
+pub fn upgrade_handlers(handlers: Vec<UpgradeHandler>) -> UpgradeHandler {
+    Arc::new(move |request| {
+        let mut answer = Err(Response::not_found());
+        for handler in &handlers {
+            answer = handler(request);
+            if !matches!(&answer, Err(response) if response.status == 404) {
+                break;
+            }
+        }
+        answer
+    })
+}
+
+// What HTTP requests are answered with, on whichever listener they come in
+#[derive(Clone)]
+pub struct Site {
+    pub handler: Handler,
+    pub upgrade: UpgradeHandler,
+    // For the size limits of upgraded WebSockets, the same as the game's
+    pub config: Arc<SharedConfig>,
+}
+
+// Serve HTTP/1.1 requests, one request per connection
+pub async fn serve(listener: TcpListener, site: Site) {
+    while let Ok((stream, addr)) = listener.accept().await {
+        let site = site.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(stream, addr.ip(), site).await {
+                warn_limited!("HTTP error from {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+// Answer the one request on a connection from `peer`
+pub async fn handle_request(mut stream: TcpStream, peer: IpAddr, site: Site) -> Result<(), Error> {
+    let Site { handler, upgrade, config } = site;
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream, peer)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            write_response(&mut stream, &Response::error(400, "Bad Request")).await?;
+            return Err(e);
+        }
+        Err(_) => return Ok(()),
+    };
+
+    if request.method == "GET" && request.is_websocket_upgrade() {
+        let Some(key) = request.headers.get("sec-websocket-key") else {
+            return write_response(&mut stream, &Response::error(400, "Bad Request")).await;
+        };
+        let upgrade = match upgrade(&request) {
+            Ok(upgrade) => upgrade,
+            Err(response) => return write_response(&mut stream, &response).await,
+        };
+        let head = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            derive_accept_key(key.as_bytes())
+        );
+        stream.write_all(head.as_bytes()).await?;
+        let ws_stream = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config.get().websocket_config())).await;
+        upgrade(ws_stream).await;
+        return Ok(());
+    }
+
+    let response = handler(request).await;
+    write_response(&mut stream, &response).await
+}
+
+// Look at the request head on a fresh connection, leaving it to be read by
+// whoever handles the connection. None if it isn't HTTP or doesn't arrive in time.
+pub async fn peek_head(stream: &TcpStream) -> Result<Option<Head>, Error> {
+    let mut buffer = vec![0u8; MAX_REQUEST_SIZE];
+    let peeking = async {
+        loop {
+            let peeked = stream.peek(&mut buffer).await?;
+            if peeked == 0 {
+                return Ok(None);
+            }
+            let mut headers = [httparse::EMPTY_HEADER; 32];
+            let mut parsed = httparse::Request::new(&mut headers);
+            match parsed.parse(&buffer[..peeked]) {
+                Ok(httparse::Status::Complete(_)) => {
+                    let upgrade = parsed.headers.iter().any(|header| {
+                        header.name.eq_ignore_ascii_case("upgrade") && header.value.eq_ignore_ascii_case(b"websocket")
+                    });
+                    let target = parsed.path.unwrap_or("/");
+                    let path = target.split_once('?').map_or(target, |(path, _)| path);
+                    return Ok(Some(Head { path: percent_decode(path), upgrade }));
+                }
+                // Peeking returns what's there right away, so give the rest a moment to arrive
+                Ok(httparse::Status::Partial) if peeked < buffer.len() => tokio::time::sleep(PEEK_INTERVAL).await,
+                _ => return Ok(None),
+            }
+        }
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, peeking).await.unwrap_or(Ok(None))
+}
+
+async fn read_request(stream: &mut TcpStream, peer: IpAddr) -> Result<Request, Error> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err("Connection closed before the request was complete".into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut parsed = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(head_len) = parsed.parse(&buffer)? {
+            let method = parsed.method.unwrap_or("").to_string();
+            let target = parsed.path.unwrap_or("/");
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            let headers = parsed
+                .headers
+                .iter()
+                .map(|header| {
+                    let value = String::from_utf8_lossy(header.value).into_owned();
+                    (header.name.to_ascii_lowercase(), value)
+                })
+                .collect::<HashMap<_, _>>();
+            let body_len = match headers.get("content-length") {
+                Some(length) => length.trim().parse::<usize>()?,
+                None => 0,
+            };
+            if body_len > MAX_BODY_SIZE {
+                return Err("Request body too large".into());
+            }
+            let mut body = buffer[head_len..].to_vec();
+            while body.len() < body_len {
+                let read = stream.read(&mut chunk).await?;
+                if read == 0 {
+                    return Err("Connection closed before the body was complete".into());
+                }
+                body.extend_from_slice(&chunk[..read]);
+            }
+            body.truncate(body_len);
+            return Ok(Request {
+                method,
+                path: percent_decode(path),
+                query: parse_query(query),
+                headers,
+                body,
+                peer,
+            });
+        }
+        if buffer.len() > MAX_REQUEST_SIZE {
+            return Err("Request too large".into());
+        }
+    }
+}
+
+async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<(), Error> {
+    let mut extra = String::new();
+    if let Some(url) = &response.location {
+        extra.push_str(&format!("Location: {}\r\n", url));
+    }
+    if let Some(cookie) = &response.cookie {
+        extra.push_str(&format!("Set-Cookie: {}\r\n", cookie));
+    }
+    if response.cross_origin {
+        extra.push_str("Access-Control-Allow-Origin: *\r\n");
+    }
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len(),
+        extra
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        302 => "Found",
+        403 => "Forbidden",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+// POST a JSON body and return the response status
+pub async fn post_json<T: Serialize>(url: &str, bearer: Option<&str>, body: &T) -> Result<u16, Error> {
+    let body = serde_json::to_vec(body)?;
+    let (status, _) = fetch(Method::POST, url, bearer, Some(("application/json", body))).await?;
+    Ok(status)
+}
+
+// POST form fields, returning the status and body
+pub async fn post_form(url: &str, fields: &[(&str, &str)]) -> Result<(u16, Vec<u8>), Error> {
+    let body = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+    fetch(Method::POST, url, None, Some(("application/x-www-form-urlencoded", body.into_bytes()))).await
+}
+
+pub async fn get(url: &str, bearer: Option<&str>) -> Result<(u16, Vec<u8>), Error> {
+    fetch(Method::GET, url, bearer, None).await
+}
+
+// One client for every outgoing request, so connections and TLS sessions to
+// the same host get reused
+fn client() -> Result<&'static reqwest::Client, Error> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+// One request to an http:// or https:// URL. Bodies past MAX_BODY_SIZE are cut
+// off, like the ones we read.
+async fn fetch(method: Method, url: &str, bearer: Option<&str>, body: Option<(&str, Vec<u8>)>) -> Result<(u16, Vec<u8>), Error> {
+    let mut request = client()?.request(method, url).header(reqwest::header::ACCEPT, "application/json");
+    if let Some(token) = bearer {
+        request = request.bearer_auth(token);
+    }
+    if let Some((content_type, body)) = body {
+        request = request.header(reqwest::header::CONTENT_TYPE, content_type).body(body);
+    }
+    let mut response = request.send().await?;
+    let status = response.status().as_u16();
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() < MAX_BODY_SIZE {
+            body.extend_from_slice(&chunk);
+        }
+    }
+    Ok((status, body))
+}
+
+// Escape everything but unreserved characters, for query strings and form bodies
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+// Decode %XX escapes (and + as space), leaving malformed escapes as they are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                std::str::from_utf8(hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (None, b'+') => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (None, byte) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::METRICS;
+
+// Counts open connections per client IP, and when each last joined
+#[derive(Default)]
+pub struct ConnectionLimiter {
+    counts: Mutex<HashMap<IpAddr, usize>>,
+    joins: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+// Held for the lifetime of a connection; frees the slot when dropped
+pub struct ConnectionSlot {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    // Reserve a slot for this IP, or None if it already has `max` connections
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr, max: usize) -> Option<ConnectionSlot> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(ip).or_insert(0);
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionSlot {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    // Note a join from this IP, or return false if its last one was less than `cooldown` ago
+    pub fn try_join(&self, ip: IpAddr, cooldown: Duration) -> bool {
+        let now = Instant::now();
+        let mut joins = self.joins.lock().unwrap();
+        if joins.get(&ip).is_some_and(|last| now - *last < cooldown) {
+            return false;
+        }
+        // Forget IPs that may join again anyway
+        joins.retain(|_, last| now - *last < cooldown);
+        joins.insert(ip, now);
+        true
+    }
+
+    // IPs with open connections or a recent join
+    pub fn tracked_ips(&self) -> usize {
+        self.counts.lock().unwrap().len() + self.joins.lock().unwrap().len()
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.limiter.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+    }
+}
+
+// Bytes moved over one connection, plus a token bucket that decides whether
+// it may receive another game state snapshot
+pub struct Traffic {
+    sent: AtomicU64,
+    received: AtomicU64,
+    allowance: Mutex<Allowance>,
+    // A snapshot was skipped, so the client is behind until it gets the next one
+    behind: AtomicBool,
+    // Messages still waiting in the client's queue when the last one was sent
+    queued: AtomicU64,
+}
+
+struct Allowance {
+    bytes: f64,
+    refilled: Instant,
+}
+
+impl Default for Traffic {
+    fn default() -> Self {
+        Traffic {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            allowance: Mutex::new(Allowance {
+                bytes: 0.0,
+                refilled: Instant::now(),
+            }),
+            behind: AtomicBool::new(false),
+            queued: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Traffic {
+    pub fn record_sent(&self, bytes: usize) {
+        self.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        METRICS.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.allowance.lock().unwrap().bytes -= bytes as f64;
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+        METRICS.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_queued(&self, messages: usize) {
+        self.queued.store(messages as u64, Ordering::Relaxed);
+    }
+
+    pub fn queued(&self) -> u64 {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    // Whether the client may get a snapshot under a ceiling of `max_bytes_per_sec`.
+    // The allowance refills continuously and holds at most one second's worth;
+    // a client that's denied is marked as behind.
+    pub fn allow_snapshot(&self, max_bytes_per_sec: Option<u64>) -> bool {
+        let allowed = match max_bytes_per_sec {
+            None => true,
+            Some(max) => {
+                let max = max as f64;
+                let mut allowance = self.allowance.lock().unwrap();
+                let now = Instant::now();
+                let refill = (now - allowance.refilled).as_secs_f64() * max;
+                // Debt is capped too, so turning the ceiling on doesn't starve old connections
+                allowance.bytes = (allowance.bytes.max(-max) + refill).min(max);
+                allowance.refilled = now;
+                allowance.bytes > 0.0
+            }
+        };
+        self.behind.store(!allowed, Ordering::Relaxed);
+        allowed
+    }
+
+    pub fn is_behind(&self) -> bool {
+        self.behind.load(Ordering::Relaxed)
+    }
+}
+
+// Lets a player's moves through at the tick rate. One move may come early
+// after a pause, so network jitter doesn't eat inputs.
+pub struct InputCooldown {
+    next_allowed: Instant,
+}
+
+impl Default for InputCooldown {
+    fn default() -> Self {
+        InputCooldown {
+            next_allowed: Instant::now(),
+        }
+    }
+}
+
+impl InputCooldown {
+    // Whether a move arriving at `now` should be processed
+    pub fn try_move(&mut self, now: Instant, interval: Duration) -> bool {
+        if now < self.next_allowed {
+            return false;
+        }
+        self.next_allowed = self.next_allowed.max(now - interval) + interval;
+        true
+    }
+}
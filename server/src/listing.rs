@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{interval, Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{Config, SharedConfig};
+use crate::http::{self, Request, Response};
+use crate::world::Worlds;
+
+// Announcements are dropped after this many announce intervals without a new one
+const MISSED_ANNOUNCEMENTS: u32 = 3;
+
+// One joinable world, as shown in the client's server browser
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ServerEntry {
+    pub name: String,
+    // WebSocket URL players connect to; None means the host the listing came from
+    pub url: Option<String>,
+    pub world: String,
+    pub players: usize,
+    pub max_players: usize,
+    // Name of the map file, without extension
+    pub map: Option<String>,
+}
+
+// The worlds hosted by this server
+pub fn local_entries(worlds: &Worlds, config: &Config) -> Vec<ServerEntry> {
+    let mut entries: Vec<ServerEntry> = config
+        .worlds
+        .iter()
+        .filter_map(|world_config| {
+            let world = worlds.get(Some(&world_config.name))?;
+            let players = world.game_state.lock().unwrap().players.len();
+            Some(ServerEntry {
+                name: config.name.clone(),
+                url: config.public_url.clone(),
+                world: world.name.clone(),
+                players,
+                max_players: world.max_players(config),
+                map: world_config
+                    .map
+                    .as_ref()
+                    .and_then(|path| path.file_stem())
+                    .map(|stem| stem.to_string_lossy().into_owned()),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.world.cmp(&b.world));
+    entries
+}
+
+// What a game server posts to the listing service
+#[derive(Serialize, Deserialize)]
+struct Announcement {
+    // Game port, to fill in URLs left empty
+    port: u16,
+    servers: Vec<ServerEntry>,
+}
+
+// Servers that announced themselves to us, when running as a listing service
+#[derive(Default)]
+pub struct Listing {
+    // By URL and world, with when the entry expires
+    announced: Mutex<HashMap<(String, String), (ServerEntry, Instant)>>,
+}
+
+impl Listing {
+    // Our own worlds followed by the announced ones that are still fresh
+    pub fn entries(&self, worlds: &Worlds, config: &Config) -> Vec<ServerEntry> {
+        let mut entries = local_entries(worlds, config);
+        let mut announced = self.announced.lock().unwrap();
+        let now = Instant::now();
+        announced.retain(|_, (_, expires_at)| *expires_at > now);
+        let mut others: Vec<ServerEntry> = announced.values().map(|(entry, _)| entry.clone()).collect();
+        others.sort_by(|a, b| (&a.name, &a.world).cmp(&(&b.name, &b.world)));
+        entries.extend(others);
+        entries
+    }
+
+    // POST /api/announce
+    pub fn accept(&self, request: &Request, config: &Config) -> Response {
+        let listing = &config.listing;
+        if !listing.accept_announcements {
+            return Response::not_found();
+        }
+        if let Some(token) = &listing.token {
+            let expected = format!("Bearer {}", token);
+            if request.headers.get("authorization") != Some(&expected) {
+                return Response::error(401, "Unauthorized");
+            }
+        }
+        let announcement: Announcement = match serde_json::from_slice(&request.body) {
+            Ok(announcement) => announcement,
+            Err(_) => return Response::error(400, "Bad Request"),
+        };
+
+        // Servers that don't know their public URL are reachable where they announced from
+        let fallback_url = format!("ws://{}", std::net::SocketAddr::new(request.peer, announcement.port));
+        let entries = announcement
+            .servers
+            .into_iter()
+            .map(|entry| ServerEntry {
+                url: entry.url.or_else(|| Some(fallback_url.clone())),
+                ..entry
+            })
+            .collect();
+        self.add(entries, listing.announce_interval() * MISSED_ANNOUNCEMENTS);
+        Response::json(&serde_json::json!({ "ok": true }))
+    }
+
+    // List other servers' worlds for `ttl`, replacing what they said before
+    pub fn add(&self, entries: Vec<ServerEntry>, ttl: Duration) {
+        let expires_at = Instant::now() + ttl;
+        let mut announced = self.announced.lock().unwrap();
+        for entry in entries {
+            let key = (entry.url.clone().unwrap_or_default(), entry.world.clone());
+            announced.insert(key, (entry, expires_at));
+        }
+    }
+}
+
+// Post our worlds to the listing service every announce interval, if one is configured
+pub async fn announce_periodically(worlds: Arc<Worlds>, config: Arc<SharedConfig>) {
+    let mut announce_interval = config.get().listing.announce_interval();
+    let mut ticker = interval(announce_interval);
+    let mut failing = false;
+    loop {
+        ticker.tick().await;
+        let config = config.get();
+        if config.listing.announce_interval() != announce_interval {
+            announce_interval = config.listing.announce_interval();
+            ticker = interval(announce_interval);
+            ticker.tick().await;
+        }
+        let Some(url) = &config.listing.announce_to else {
+            continue;
+        };
+        let announcement = Announcement {
+            port: config.bind.rsplit(':').next().and_then(|port| port.parse().ok()).unwrap_or(0),
+            servers: local_entries(&worlds, &config),
+        };
+        let url = format!("{}/api/announce", url.trim_end_matches('/'));
+        // Only log changes, not every failed attempt
+        match http::post_json(&url, config.listing.token.as_deref(), &announcement).await {
+            Ok(200) => {
+                if failing {
+                    info!("Announcing to {} again", url);
+                }
+                failing = false;
+            }
+            Ok(status) if !failing => {
+                warn!("Listing service {} answered {}", url, status);
+                failing = true;
+            }
+            Err(e) if !failing => {
+                warn!("Couldn't announce to {}: {}", url, e);
+                failing = true;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::game::GameState;
+
+// Ticks we keep our hash of, to check the clients' reports against
+const HISTORY: usize = 128;
+
+// Our own hashes of the last ticks relayed to a lockstep world's clients
+#[derive(Default)]
+pub struct Hashes {
+    recent: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl Hashes {
+    pub fn record(&self, tick: u64, hash: u64) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_back((tick, hash));
+        while recent.len() > HISTORY {
+            recent.pop_front();
+        }
+    }
+
+    // Whether a client's hash of `tick` matches ours, or None if we didn't keep that tick
+    pub fn check(&self, tick: u64, hash: u64) -> Option<bool> {
+        let recent = self.recent.lock().unwrap();
+        recent.iter().find(|(recorded, _)| *recorded == tick).map(|(_, ours)| *ours == hash)
+    }
+}
+
+// The hash clients compute the same way from their copy of the world
+pub fn hash(state: &GameState) -> u64 {
+    let mut players: Vec<_> = state.players.values().collect();
+    players.sort_by(|a, b| a.id.cmp(&b.id));
+    sim::hash(players.into_iter().map(|player| (player.id.as_str(), player.body())))
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tracing::{error, info, warn, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::config::{LogConfig, LogFormat, LogRotation};
+
+// Install the global logger. Logs go through a background writer thread;
+// keep the returned guard alive so it flushes on exit.
+pub fn init(config: &LogConfig) -> Result<WorkerGuard, Box<dyn std::error::Error + Send + Sync>> {
+    let level: LevelFilter = config
+        .level
+        .parse()
+        .map_err(|_| format!("Unknown log level {}", config.level))?;
+
+    let (writer, guard) = match &config.directory {
+        None => tracing_appender::non_blocking(io::stdout()),
+        Some(directory) if config.rotation == LogRotation::Size => {
+            let max_bytes = config.max_size_mb.max(1) * 1024 * 1024;
+            let file = SizeRotatingFile::open(directory.join(&config.file_name), max_bytes, config.max_files)?;
+            tracing_appender::non_blocking(file)
+        }
+        Some(directory) => {
+            let rotation = match config.rotation {
+                LogRotation::Minutely => Rotation::MINUTELY,
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never | LogRotation::Size => Rotation::NEVER,
+            };
+            let appender = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(&config.file_name)
+                .max_log_files(config.max_files + 1)
+                .build(directory)?;
+            tracing_appender::non_blocking(appender)
+        }
+    };
+
+    let logger = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer)
+        .with_ansi(config.directory.is_none() && io::stdout().is_terminal());
+    match config.format {
+        LogFormat::Text => logger.try_init()?,
+        LogFormat::Json => logger.json().try_init()?,
+    }
+    Ok(guard)
+}
+
+// Messages one place in the code may log per minute; past that they're
+// counted and summed up when the minute is over
+const BURST: u32 = 5;
+const WINDOW: Duration = Duration::from_secs(60);
+
+// Like tracing's macros, but a flapping client can't flood the log with them
+// and hide everything else: see Limiter
+macro_rules! error_limited {
+    ($($arg:tt)+) => { log_limited!(ERROR, error, $($arg)+) };
+}
+
+macro_rules! warn_limited {
+    ($($arg:tt)+) => { log_limited!(WARN, warn, $($arg)+) };
+}
+
+macro_rules! info_limited {
+    ($($arg:tt)+) => { log_limited!(INFO, info, $($arg)+) };
+}
+
+macro_rules! log_limited {
+    ($level:ident, $log:ident, $($arg:tt)+) => {
+        if $crate::logging::LIMITER.admit(concat!(file!(), ":", line!()), tracing::Level::$level, &|| format!($($arg)+)) {
+            tracing::$log!($($arg)+);
+        }
+    };
+}
+
+// Counts what each call site of the *_limited macros logs in the current
+// minute, and holds back what's over BURST
+pub struct Limiter {
+    sites: Mutex<BTreeMap<&'static str, Site>>,
+}
+
+struct Site {
+    level: Level,
+    logged: u32,
+    held_back: u32,
+    // The latest message held back, to show what they were like
+    last: String,
+}
+
+pub static LIMITER: Limiter = Limiter { sites: Mutex::new(BTreeMap::new()) };
+
+impl Limiter {
+    // Whether the message from `site` may be logged now; if not, it's counted
+    pub fn admit(&self, site: &'static str, level: Level, message: &dyn Fn() -> String) -> bool {
+        let mut sites = self.sites.lock().unwrap();
+        let site = sites.entry(site).or_insert_with(|| Site { level, logged: 0, held_back: 0, last: String::new() });
+        if site.logged < BURST {
+            site.logged += 1;
+            return true;
+        }
+        site.held_back += 1;
+        site.last = message();
+        false
+    }
+
+    // Log how many messages each site held back, and start a new minute
+    fn flush(&self) {
+        let sites = std::mem::take(&mut *self.sites.lock().unwrap());
+        for (site, Site { level, held_back, last, .. }) in sites {
+            if held_back == 0 {
+                continue;
+            }
+            match level {
+                Level::ERROR => error!("{} more like this in the last minute ({}): {}", held_back, site, last),
+                Level::WARN => warn!("{} more like this in the last minute ({}): {}", held_back, site, last),
+                _ => info!("{} more like this in the last minute ({}): {}", held_back, site, last),
+            }
+        }
+    }
+}
+
+// Sum up what the limited macros held back, once a minute
+pub async fn report_held_back() {
+    let mut minutes = tokio::time::interval(WINDOW);
+    minutes.tick().await;
+    loop {
+        minutes.tick().await;
+        LIMITER.flush();
+    }
+}
+
+// A log file that is renamed to <name>.1 once it reaches max_bytes, shifting
+// older files up to <name>.<max_files> and deleting the oldest
+struct SizeRotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl SizeRotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<SizeRotatingFile> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(SizeRotatingFile {
+            path,
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let _ = fs::rename(self.rotated_path(index), self.rotated_path(index + 1));
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
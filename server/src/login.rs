@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tracing::{info, warn};
+
+use crate::config::LoginConfig;
+use crate::http::{self, percent_encode, Request, Response};
+use crate::session;
+use crate::storage::PlayerStore;
+
+// How long a player has to get through the provider's login page
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(600);
+// Player IDs of logged in players start with this; guests get player_<port>
+pub const PLAYER_ID_PREFIX: &str = "user_";
+// Longest subject, once escaped, that goes into a player ID as it is
+const MAX_SUBJECT_LENGTH: usize = 64;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct UserInfo {
+    sub: String,
+}
+
+// Logins in progress, by the state parameter sent to the provider
+#[derive(Default)]
+pub struct Logins {
+    pending: Mutex<HashMap<String, Instant>>,
+}
+
+impl Logins {
+    // GET /auth/login: off to the provider's login page
+    pub fn start(&self, config: &LoginConfig) -> Response {
+        let state = session::new_token();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, started| started.elapsed() < LOGIN_TIMEOUT);
+        pending.insert(state.clone(), Instant::now());
+        let separator = if config.authorize_url.contains('?') { '&' } else { '?' };
+        Response::redirect(format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}",
+            config.authorize_url,
+            separator,
+            percent_encode(&config.client_id),
+            percent_encode(&config.redirect_url),
+            percent_encode(&config.scope),
+            state
+        ))
+    }
+
+    // GET /auth/callback: the provider sent the player back with a code. Trade
+    // it for who they are and send them to the game with a session token.
+    pub async fn finish(&self, request: &Request, config: &LoginConfig, store: &PlayerStore) -> Response {
+        let state = request.query.get("state").map(String::as_str).unwrap_or_default();
+        let started = self.pending.lock().unwrap().remove(state);
+        if started.is_none_or(|started| started.elapsed() >= LOGIN_TIMEOUT) {
+            return Response::error(400, "Unknown or expired login");
+        }
+        let Some(code) = request.query.get("code") else {
+            return Response::error(400, "Login was cancelled");
+        };
+        let subject = match identify(code, config).await {
+            Ok(subject) => subject,
+            Err(e) => {
+                warn!("Login failed at the provider: {}", e);
+                return Response::error(502, "Login failed");
+            }
+        };
+
+        if subject.is_empty() {
+            return Response::error(502, "Login failed");
+        }
+        let player_id = player_id(&subject);
+        let token = session::new_token();
+        store.log_in(&player_id, &token).await;
+        info!("{} logged in", player_id);
+        // After the # the token isn't sent to the game page's server
+        Response::redirect(format!("{}#login={}", config.return_url, token))
+    }
+}
+
+// The player ID for a provider's subject. It's kept to characters that are
+// safe everywhere IDs show up, and different subjects get different IDs: other
+// characters, the _ included, become _ and two hex digits, and subjects too
+// long for that become _h and a hash of them, which no escape starts with.
+fn player_id(subject: &str) -> String {
+    let mut escaped = String::with_capacity(subject.len());
+    for byte in subject.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("_{:02X}", byte)),
+        }
+    }
+    if escaped.len() > MAX_SUBJECT_LENGTH {
+        let hash: String = Sha1::digest(subject.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect();
+        escaped = format!("_h{}", hash);
+    }
+    format!("{}{}", PLAYER_ID_PREFIX, escaped)
+}
+
+// The provider's subject ID for the player who got `code`
+async fn identify(code: &str, config: &LoginConfig) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (status, body) = http::post_form(&config.token_url, &[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("client_secret", &config.client_secret),
+    ])
+    .await?;
+    if status != 200 {
+        return Err(format!("token endpoint answered {}", status).into());
+    }
+    let token: TokenResponse = serde_json::from_slice(&body)?;
+
+    let (status, body) = http::get(&config.userinfo_url, Some(&token.access_token)).await?;
+    if status != 200 {
+        return Err(format!("userinfo endpoint answered {}", status).into());
+    }
+    let user: UserInfo = serde_json::from_slice(&body)?;
+    Ok(user.sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subjects_get_ids_of_their_own() {
+        let subjects = ["ab", "a_b", "a.b", "a b", "a_2Eb", "ä", &"x".repeat(65), &"x".repeat(66)];
+        let ids: std::collections::HashSet<_> = subjects.iter().map(|subject| player_id(subject)).collect();
+        assert_eq!(ids.len(), subjects.len());
+        assert_eq!(player_id("1234-abc"), "user_1234-abc");
+        assert!(ids.iter().all(|id| id.len() <= PLAYER_ID_PREFIX.len() + MAX_SUBJECT_LENGTH));
+    }
+}
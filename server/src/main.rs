@@ -1,236 +1,201 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use futures_util::{SinkExt, StreamExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-use serde::{Serialize, Deserialize};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
 
-// Game constants
-const CANVAS_WIDTH: u32 = 800;
-const CANVAS_HEIGHT: u32 = 600;
-const PLAYER_SIZE: u32 = 50;
-const PLAYER_SPEED: u32 = 5;
+// First, so its macros can be used in every other module
+#[macro_use]
+mod logging;
+mod achievements;
+mod admin;
+mod api;
+mod assets;
+mod broadcast;
+mod bus;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod chat;
+mod config;
+mod cosmetics;
+mod dashboard;
+mod drain;
+mod events;
+mod game;
+mod growth;
+mod handoff;
+mod http;
+mod limits;
+mod listing;
+mod lockstep;
+mod login;
+mod map;
+mod matches;
+mod mdns;
+mod metrics;
+mod net;
+mod packed;
+mod physics;
+mod profiler;
+mod pow;
+mod protocol;
+mod proxy;
+mod rooms;
+mod scripting;
+mod session;
+mod snapshot;
+mod spawn;
+mod spectate;
+mod stats;
+mod stepping;
+mod storage;
+mod trail;
+mod webhook;
+mod wire;
+mod world;
 
-// Game state types
-#[derive(Clone, Serialize, Deserialize)]
-struct Player {
-    id: String,
-    x: u32,
-    y: u32,
-    color: String,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct GameState {
-    players: HashMap<String, Player>,
-}
-
-#[derive(Serialize, Deserialize)]
-enum ClientMessage {
-    Move { direction: String },
-    Join,
-}
-
-#[derive(Serialize, Deserialize)]
-struct ServerMessage {
-    game_state: GameState,
-}
-
-// Shared state between all connections
-type Clients = Arc<Mutex<HashMap<SocketAddr, tokio::sync::mpsc::UnboundedSender<Message>>>>;
-type GameStateSync = Arc<Mutex<GameState>>;
+use chat::Mutes;
+use config::{Args, SharedConfig};
+use drain::Drain;
+use limits::ConnectionLimiter;
+use listing::Listing;
+use net::Shared;
+use scripting::ScriptHost;
+use session::Sessions;
+use storage::PlayerStore;
+use world::Worlds;
 
 #[tokio::main]
 async fn main() {
-    // Create a simple TCP listener on localhost:8080
-    let listener = TcpListener::bind("127.0.0.1:8080").await.expect("Failed to bind");
-    println!("Game server started on 127.0.0.1:8080");
+    // Load configuration
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        eprintln!("Usage: server [--config <path>] [--fresh] [--take-over <handoff socket>]");
+        std::process::exit(2);
+    });
+    let config = Arc::new(SharedConfig::load(args.config_path).expect("Failed to load config"));
+    let log_guard = logging::init(&config.get().log).expect("Failed to set up logging");
+    if config.get().chaos.enabled {
+        if cfg!(feature = "chaos") {
+            warn!("Chaos mode is on: messages to clients get dropped, duplicated, reordered and delayed");
+        } else {
+            warn!("Chaos mode is configured, but this server was built without the chaos feature; ignoring it");
+        }
+    }
 
-    // Create shared state
-    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
-    let game_state = Arc::new(Mutex::new(GameState {
-        players: HashMap::new(),
-    }));
+    // Create a TCP listener on the configured address
+    let bind = config.get().bind.clone();
+    let listener = TcpListener::bind(&bind).await.expect("Failed to bind");
+    info!("Game server started on {}", bind);
 
-    // Accept connections in a loop
-    while let Ok((stream, addr)) = listener.accept().await {
-        // Clone the clients for this connection
-        let clients_clone = clients.clone();
-        let game_state_clone = game_state.clone();
-        
-        // Spawn a task for each inbound connection
-        tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, addr, clients_clone, game_state_clone).await {
-                println!("Error in connection: {}", e);
-            }
-        });
+    // Create shared state
+    let limiter = Arc::new(ConnectionLimiter::default());
+    // Take the worlds over from the server on the other end of --take-over, or
+    // start from the last snapshot
+    let mut handoff = match &args.take_over {
+        Some(path) => {
+            let url = handoff::reconnect_url(&config.get());
+            Some(handoff::take_over(path, url).await.expect("Failed to take over from the running server"))
+        }
+        None => None,
+    };
+    let (mut restored, waiting) = match handoff.as_mut() {
+        Some(handoff) => (std::mem::take(&mut handoff.worlds), Vec::new()),
+        None => snapshot::restore(&config.get(), args.fresh),
+    };
+    // Rooms players created are opened once everything they need is there
+    let handed_rooms: Vec<_> = handoff
+        .as_mut()
+        .map(|handoff| std::mem::take(&mut handoff.rooms))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|room| (restored.remove(&room.name), room))
+        .collect();
+    let worlds = Worlds::new(&config.get().worlds, restored).expect("Failed to create worlds");
+    let worlds = Arc::new(worlds);
+    let scripts = Arc::new(ScriptHost::default());
+    scripts.load(config.get().script.as_deref()).expect("Failed to load script");
+    let store = Arc::new(PlayerStore::open(config.get().storage, config.get().stats_path.clone(), config.get().database_url.as_deref()).await.expect("Failed to open player stats"));
+    let sessions = Arc::new(Sessions::default());
+    let mutes = Arc::new(Mutes::default());
+    if let Some(handoff) = handoff {
+        handoff::resume(handoff, &sessions, &mutes, &config.get());
     }
-}
+    snapshot::resume(waiting, &sessions, &config.get());
+    let drain = Arc::new(Drain::default());
+    let shared = Shared {
+        worlds: worlds.clone(),
+        config: config.clone(),
+        limiter,
+        scripts: scripts.clone(),
+        store: store.clone(),
+        sessions,
+        mutes: mutes.clone(),
+        drain: drain.clone(),
+    };
 
-async fn handle_connection(
-    stream: TcpStream, 
-    addr: SocketAddr, 
-    clients: Clients,
-    game_state: GameStateSync
-) -> Result<(), Box<dyn std::error::Error>> {
-    println!("New player connected: {}", addr);
-    
-    // Generate a unique player ID and random color
-    let player_id = format!("player_{}", addr.port());
-    let colors = ["#FF0000", "#00FF00", "#0000FF", "#FFFF00", "#FF00FF", "#00FFFF"];
-    let color = colors[addr.port() as usize % colors.len()];
-    
-    // Create a new player at a random position
-    let player = Player {
-        id: player_id.clone(),
-        x: 100 + (addr.port() as u32 % 400),
-        y: 100 + (addr.port() as u32 % 300),
-        color: color.to_string(),
+    let listing = Arc::new(Listing::default());
+    let tracking = api::Tracking {
+        sessions: shared.sessions.clone(),
+        limiter: shared.limiter.clone(),
+        drain: drain.clone(),
     };
-    
-    // Add player to game state - scope the lock
-    {
-        let mut state = game_state.lock().unwrap();
-        state.players.insert(player_id.clone(), player);
-    } // Lock is released here
-    
-    // Accept WebSocket connection
-    let ws_stream = accept_async(stream).await?;
-    let (mut tx, mut rx) = ws_stream.split();
-    
-    // Create channel for this client
-    let (client_sender, mut client_receiver) = tokio::sync::mpsc::unbounded_channel();
-    
-    // Store the sender in shared state
-    {
-        let mut clients_map = clients.lock().unwrap();
-        clients_map.insert(addr, client_sender);
-    } // Lock is released here
-    
-    // Send initial game state to the new player
-    let initial_state = {
-        let state = game_state.lock().unwrap();
-        serde_json::to_string(&ServerMessage {
-            game_state: state.clone(),
-        })?
-    }; // Lock is released here
-    
-    tx.send(Message::Text(initial_state)).await?;
-    
-    // Broadcast updated game state to all players
-    broadcast_game_state(&clients, &game_state).await?;
-    
-    // Task to forward messages from other clients to this client
-    let forward_task = tokio::spawn(async move {
-        while let Some(msg) = client_receiver.recv().await {
-            if let Err(e) = tx.send(msg).await {
-                println!("Error sending to {}: {}", addr, e);
-                break;
-            }
-        }
-    });
-    
-    // Listen for messages from this client
-    while let Some(result) = rx.next().await {
-        match result {
-            Ok(msg) => {
-                if let Message::Text(text) = msg {
-                    match serde_json::from_str::<ClientMessage>(&text) {
-                        Ok(ClientMessage::Move { direction }) => {
-                            // Update player position based on direction
-                            {
-                                let mut state = game_state.lock().unwrap();
-                                if let Some(player) = state.players.get_mut(&player_id) {
-                                    match direction.as_str() {
-                                        "w" => {
-                                            if player.y > PLAYER_SPEED {
-                                                player.y -= PLAYER_SPEED;
-                                            }
-                                        },
-                                        "a" => {
-                                            if player.x > PLAYER_SPEED {
-                                                player.x -= PLAYER_SPEED;
-                                            }
-                                        },
-                                        "s" => {
-                                            if player.y < CANVAS_HEIGHT - PLAYER_SIZE - PLAYER_SPEED {
-                                                player.y += PLAYER_SPEED;
-                                            }
-                                        },
-                                        "d" => {
-                                            if player.x < CANVAS_WIDTH - PLAYER_SIZE - PLAYER_SPEED {
-                                                player.x += PLAYER_SPEED;
-                                            }
-                                        },
-                                        _ => {}
-                                    }
-                                }
-                            } // Lock is released here before await
-                            
-                            // Broadcast updated game state
-                            broadcast_game_state(&clients, &game_state).await?;
-                        },
-                        Ok(ClientMessage::Join) => {
-                            // Player has joined, state already updated
-                            println!("Player {} joined the game", player_id);
-                        },
-                        Err(e) => {
-                            println!("Error parsing message from {}: {}", addr, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Error receiving from {}: {}", addr, e);
-                break;
-            }
-        }
+
+    // Serve the HTTP API on the game port, and on its own one unless that's the same
+    let site = http::Site {
+        handler: api::handler(worlds.clone(), store.clone(), config.clone(), listing.clone(), tracking),
+        upgrade: http::upgrade_handlers(vec![
+            dashboard::upgrade_handler(worlds.clone(), config.clone()),
+            spectate::upgrade_handler(worlds.clone(), config.clone(), shared.limiter.clone()),
+        ]),
+        config: config.clone(),
+    };
+    let http_bind = config.get().http_bind.clone();
+    if http_bind != bind {
+        let http_listener = TcpListener::bind(&http_bind).await.expect("Failed to bind HTTP API");
+        info!("HTTP API listening on {}", http_bind);
+        tokio::spawn(http::serve(http_listener, site.clone()));
+    }
+
+    // Run the simulation tick of every world
+    for world in worlds.iter() {
+        info!("Hosting world {}", world.name);
+        tokio::spawn(game::systems::run_game_loop(world.clone(), config.clone(), scripts.clone(), store.clone()));
+        tokio::spawn(broadcast::broadcast_pings(world.clone()));
     }
-    
-    // Client disconnected or error occurred
-    println!("Player disconnected: {}", addr);
-    
-    // Remove player from game state
-    {
-        let mut state = game_state.lock().unwrap();
-        state.players.remove(&player_id);
-    } // Lock is released here
-    
-    // Remove client from clients list
-    {
-        let mut clients_map = clients.lock().unwrap();
-        clients_map.remove(&addr);
-    } // Lock is released here
-    
-    // Broadcast updated game state
-    broadcast_game_state(&clients, &game_state).await?;
-    
-    // Cancel the forward task
-    forward_task.abort();
-    
-    Ok(())
-}
 
-async fn broadcast_game_state(clients: &Clients, game_state: &GameStateSync) -> Result<(), Box<dyn std::error::Error>> {
-    // Get the game state as JSON - scope the lock
-    let state_json = {
-        let state = game_state.lock().unwrap();
-        serde_json::to_string(&ServerMessage {
-            game_state: state.clone(),
-        })?
-    }; // Lock is released here
-    
-    // Broadcast to all clients - scope the lock
-    {
-        let clients_map = clients.lock().unwrap();
-        for (_, client) in clients_map.iter() {
-            if let Err(e) = client.send(Message::Text(state_json.clone())) {
-                println!("Error broadcasting game state: {}", e);
-            }
+    for (state, room) in handed_rooms {
+        let name = room.name.clone();
+        if let Err(e) = rooms::open(&shared, room, state) {
+            warn!("Can't open room {} we took over: {}", name, e);
         }
-    } // Lock is released here
-    
-    Ok(())
+    }
+
+    // Persist the worlds periodically and on shutdown
+    tokio::spawn(snapshot::run_snapshots(worlds.clone(), shared.sessions.clone(), config.clone()));
+    tokio::spawn(rooms::close_empty(worlds.clone(), config.clone()));
+    tokio::spawn(metrics::log_periodically(config.clone()));
+    tokio::spawn(logging::report_held_back());
+    tokio::spawn(listing::announce_periodically(worlds.clone(), config.clone()));
+    tokio::spawn(webhook::post_periodically(worlds.clone(), config.clone()));
+    tokio::spawn(mdns::run(worlds.clone(), config.clone(), listing.clone()));
+
+    // Admin plane: reload on SIGHUP, drain on SIGTERM and commands on stdin
+    #[cfg(unix)]
+    tokio::spawn(admin::watch_sighup(config.clone(), worlds.clone(), scripts.clone()));
+    #[cfg(unix)]
+    tokio::spawn(admin::watch_sigterm(config.clone(), worlds.clone(), drain.clone()));
+    #[cfg(unix)]
+    if let Some(path) = config.get().handoff_socket.clone() {
+        tokio::spawn(handoff::serve(path, worlds.clone(), shared.sessions.clone(), mutes.clone(), drain.clone()));
+    }
+    tokio::spawn(admin::run_console(config.clone(), worlds.clone(), scripts.clone(), mutes, drain.clone()));
+
+    // Accept connections until Ctrl-C or the end of a drain
+    let shutdown = snapshot::save_on_shutdown(worlds.clone(), shared.sessions.clone(), config.clone(), drain);
+    net::serve(listener, shared, site, shutdown).await;
+
+    // Flush the log, then exit without waiting for the console's blocking stdin read
+    drop(log_guard);
+    std::process::exit(0);
 }
+
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use sim::{Boundary, WORLD_HEIGHT, WORLD_WIDTH};
+use crate::events::ScheduledEvent;
+use crate::game::{clamp_to_world, Player};
+
+// Static layout of a world, loaded from a TOML file and sent to clients on join
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Map {
+    pub portals: Vec<Portal>,
+    // Timed events run by the world's scheduler
+    pub events: Vec<ScheduledEvent>,
+    // Side of a grid cell; players move a whole cell at a time when set
+    pub cell_size: Option<u32>,
+    // Push players apart when they bump into each other instead of letting them overlap
+    pub push_players: bool,
+    // What the edges of the world do: "clamp", "bounce" or "wrap"
+    pub boundary: Boundary,
+    // Where players appear when they join or a round starts over; spread over
+    // the world when there are none
+    pub spawns: Vec<SpawnPoint>,
+}
+
+// Top-left corner of a player spawning here
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub x: u32,
+    pub y: u32,
+}
+
+// Walking onto a portal moves the player to the portal it links to
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Portal {
+    pub id: String,
+    pub x: u32,
+    pub y: u32,
+    #[serde(default = "default_portal_size")]
+    pub width: u32,
+    #[serde(default = "default_portal_size")]
+    pub height: u32,
+    // ID of the destination portal
+    pub target: String,
+}
+
+fn default_portal_size() -> u32 {
+    60
+}
+
+impl Portal {
+    pub fn overlaps(&self, x: u32, y: u32, size: u32) -> bool {
+        x < self.x + self.width && x + size > self.x && y < self.y + self.height && y + size > self.y
+    }
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Map, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let map: Map = toml::from_str(&text)?;
+        if map.cell_size == Some(0) {
+            return Err("cell_size must be greater than 0".into());
+        }
+        for spawn in &map.spawns {
+            if spawn.x >= WORLD_WIDTH || spawn.y >= WORLD_HEIGHT {
+                return Err(format!("Spawn point {},{} is outside the world", spawn.x, spawn.y).into());
+            }
+        }
+        for portal in &map.portals {
+            if !map.portals.iter().any(|other| other.id == portal.target) {
+                return Err(format!("Portal {} links to unknown portal {}", portal.id, portal.target).into());
+            }
+        }
+        Ok(map)
+    }
+
+    // If the player just stepped onto a portal (it wasn't on one at `from`),
+    // move it to the center of the linked portal and return where it landed.
+    // Standing on the destination doesn't trigger it until the player walks off and back on.
+    pub fn step_through_portal(&self, from: (u32, u32), player: &mut Player) -> Option<(u32, u32)> {
+        let entered = self
+            .portals
+            .iter()
+            .find(|portal| portal.overlaps(player.x, player.y, player.size) && !portal.overlaps(from.0, from.1, player.size))?;
+        let target = self.portals.iter().find(|portal| portal.id == entered.target)?;
+
+        player.x = (target.x + target.width / 2).saturating_sub(player.size / 2);
+        player.y = (target.y + target.height / 2).saturating_sub(player.size / 2);
+        clamp_to_world(player);
+        Some((player.x, player.y))
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::RoomsConfig;
+use crate::protocol::ServerMessage;
+
+// Where a room's match is. Players only move while it's being played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    // Waiting for enough of the players to say they're ready
+    Waiting,
+    // Enough are, the match starts when the countdown runs out
+    Countdown,
+    Playing,
+}
+
+// What moves a match from one phase to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    // Enough players are ready
+    Ready,
+    // Not enough are any more, since some changed their mind or left
+    Unready,
+    // The countdown ran out
+    Start,
+    // The round was won, or everyone left
+    Over,
+}
+
+impl Phase {
+    // The phase `event` leads to, if it can happen in this one
+    pub fn next(self, event: Event) -> Option<Phase> {
+        match (self, event) {
+            (Phase::Waiting, Event::Ready) => Some(Phase::Countdown),
+            (Phase::Countdown, Event::Unready) => Some(Phase::Waiting),
+            (Phase::Countdown, Event::Start) => Some(Phase::Playing),
+            (Phase::Countdown | Phase::Playing, Event::Over) => Some(Phase::Waiting),
+            _ => None,
+        }
+    }
+}
+
+// A room's match and who's ready for it
+pub struct Match {
+    phase: Phase,
+    // When the phase was entered
+    since: Instant,
+    ready: HashSet<String>,
+    // How many had to be ready when the players were last told
+    needed: usize,
+    // Readiness changed since the players were last told
+    changed: bool,
+    // The host started the countdown, so it runs however many are ready
+    forced: bool,
+}
+
+impl Default for Match {
+    fn default() -> Match {
+        Match {
+            phase: Phase::Waiting,
+            since: Instant::now(),
+            ready: HashSet::new(),
+            needed: 1,
+            changed: false,
+            forced: false,
+        }
+    }
+}
+
+impl Match {
+    pub fn is_playing(&self) -> bool {
+        self.phase == Phase::Playing
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.phase == Phase::Waiting
+    }
+
+    // Only counts before the match starts
+    pub fn set_ready(&mut self, player_id: &str, ready: bool) {
+        if self.is_playing() {
+            return;
+        }
+        self.changed |= match ready {
+            true => self.ready.insert(player_id.to_string()),
+            false => self.ready.remove(player_id),
+        };
+    }
+
+    // The host starts the countdown without waiting for the others
+    pub fn start(&mut self) -> bool {
+        let started = self.advance(Event::Ready).is_some();
+        self.forced = started;
+        self.changed |= started;
+        started
+    }
+
+    // The round was won: everyone gets ready again for the next one
+    pub fn finish(&mut self) {
+        self.changed |= self.advance(Event::Over).is_some();
+    }
+
+    // Move on with the players in the room as of now. Returns the phase it
+    // moved to, or None if it stayed, and whether the players need to be told.
+    pub fn update<'a>(&mut self, players: impl Iterator<Item = &'a String>, limits: &RoomsConfig) -> (Option<Phase>, bool) {
+        let players: HashSet<&String> = players.collect();
+        let before = self.ready.len();
+        self.ready.retain(|player_id| players.contains(player_id));
+        self.changed |= self.ready.len() != before;
+        let needed = needed(players.len(), limits);
+        self.changed |= std::mem::replace(&mut self.needed, needed) != needed;
+
+        let enough = !players.is_empty() && (self.forced || self.ready.len() >= needed);
+        let event = match self.phase {
+            _ if players.is_empty() => Some(Event::Over),
+            Phase::Waiting if enough => Some(Event::Ready),
+            Phase::Countdown if !enough => Some(Event::Unready),
+            Phase::Countdown if self.since.elapsed() >= limits.countdown_time() => Some(Event::Start),
+            _ => None,
+        };
+        let moved = event.and_then(|event| self.advance(event));
+        let changed = std::mem::take(&mut self.changed);
+        (moved, moved.is_some() || changed)
+    }
+
+    // What the players are shown: the phase, who's ready and how many have to be
+    pub fn message(&self, players: usize, limits: &RoomsConfig) -> ServerMessage {
+        let remaining = limits.countdown_time().saturating_sub(self.since.elapsed());
+        let mut ready: Vec<String> = self.ready.iter().cloned().collect();
+        ready.sort();
+        ServerMessage::Match {
+            phase: self.phase,
+            remaining_ms: (self.phase == Phase::Countdown).then_some(remaining.as_millis() as u64),
+            ready,
+            needed: needed(players, limits),
+        }
+    }
+
+    fn advance(&mut self, event: Event) -> Option<Phase> {
+        let next = self.phase.next(event)?;
+        debug!("Match {:?} -> {:?}", self.phase, next);
+        self.forced = false;
+        if event == Event::Over {
+            self.ready.clear();
+        }
+        (self.phase, self.since) = (next, Instant::now());
+        Some(next)
+    }
+}
+
+// How many of `players` have to be ready for the countdown to start
+fn needed(players: usize, limits: &RoomsConfig) -> usize {
+    ((players as f64 * limits.ready_quorum.clamp(0.0, 1.0)).ceil() as usize).max(1)
+}
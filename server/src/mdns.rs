@@ -0,0 +1,383 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::time::{interval, Duration};
+use tracing::{debug, info, warn};
+
+use crate::config::SharedConfig;
+use crate::listing::{self, Listing, ServerEntry};
+use crate::world::Worlds;
+
+// LAN discovery with multicast DNS (RFC 6762) and DNS-SD (RFC 6763). Servers
+// advertise a _squaregame._tcp service; browsing servers list the ones they
+// hear about in /api/servers.
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const SERVICE: &str = "_squaregame._tcp.local";
+// How long others may cache our records, in seconds
+const RECORD_TTL: u32 = 120;
+// How often browsing servers ask who's out there
+const BROWSE_INTERVAL: Duration = Duration::from_secs(30);
+// Browsed servers are dropped after this long without an answer
+const BROWSE_TTL: Duration = Duration::from_secs(90);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// Top bit of the class: "unicast response" in questions, "cache flush" in records
+const CLASS_FLAG: u16 = 0x8000;
+
+// Advertise and/or browse, as configured; returns right away if neither is on
+pub async fn run(worlds: Arc<Worlds>, config: Arc<SharedConfig>, listing: Arc<Listing>) {
+    let settings = config.get().mdns.clone();
+    if !settings.advertise && !settings.browse {
+        return;
+    }
+    let socket = match open_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("mDNS disabled, couldn't open a socket on port {}: {}", MDNS_PORT, e);
+            return;
+        }
+    };
+    let service = Service::new(&config.get().name, config.get().bind.as_str());
+    info!("mDNS: {} at {}:{}", service.instance, service.ip, service.port);
+    let group = SocketAddr::from(SocketAddrV4::new(MDNS_ADDR, MDNS_PORT));
+
+    if settings.advertise {
+        // Tell everyone right away instead of waiting to be asked
+        let packet = service.response(0, None, &listing::local_entries(&worlds, &config.get()));
+        let _ = socket.send_to(&packet, group).await;
+    }
+
+    let mut browse_ticker = interval(BROWSE_INTERVAL);
+    let mut buffer = vec![0u8; 9000];
+    loop {
+        tokio::select! {
+            _ = browse_ticker.tick(), if settings.browse => {
+                let _ = socket.send_to(&query(SERVICE, TYPE_PTR), group).await;
+            }
+            received = socket.recv_from(&mut buffer) => {
+                let Ok((len, from)) = received else {
+                    continue;
+                };
+                let Some(message) = Message::parse(&buffer[..len]) else {
+                    debug!("mDNS: ignoring malformed packet from {}", from);
+                    continue;
+                };
+                if !message.is_response && settings.advertise && service.is_asked_for(&message) {
+                    // Queries not from port 5353 are one-shot and get a unicast reply (RFC 6762 6.7)
+                    let legacy = from.port() != MDNS_PORT;
+                    let entries = listing::local_entries(&worlds, &config.get());
+                    let packet = service.response(message.id, legacy.then_some(&message), &entries);
+                    let _ = socket.send_to(&packet, if legacy { from } else { group }).await;
+                } else if message.is_response && settings.browse {
+                    let found = discovered(&message, &service.instance);
+                    if !found.is_empty() {
+                        listing.add(found, BROWSE_TTL);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn open_socket() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Share the port with the system's own mDNS responder, if there is one
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+// What we advertise
+struct Service {
+    // "<server name>._squaregame._tcp.local"
+    instance: String,
+    // "<address>-<port>.local", unique per server on the network
+    host: String,
+    ip: Ipv4Addr,
+    port: u16,
+}
+
+impl Service {
+    fn new(name: &str, bind: &str) -> Service {
+        let bind: Option<SocketAddr> = bind.parse().ok();
+        let port = bind.map_or(0, |bind| bind.port());
+        let ip = match bind.map(|bind| bind.ip()) {
+            Some(IpAddr::V4(ip)) if !ip.is_unspecified() => ip,
+            _ => lan_address().unwrap_or(Ipv4Addr::LOCALHOST),
+        };
+        // Dots would split the name into several labels
+        let mut label: String = name.replace('.', " ");
+        while label.len() > 63 {
+            label.pop();
+        }
+        Service {
+            instance: format!("{}.{}", label, SERVICE),
+            host: format!("{}-{}.local", ip.to_string().replace('.', "-"), port),
+            ip,
+            port,
+        }
+    }
+
+    fn is_asked_for(&self, message: &Message) -> bool {
+        message.questions.iter().any(|(name, qtype)| {
+            let asks = |wanted: &str, types: &[u16]| name.eq_ignore_ascii_case(wanted) && types.contains(qtype);
+            asks(SERVICE, &[TYPE_PTR, TYPE_ANY])
+                || asks(&self.instance, &[TYPE_SRV, TYPE_TXT, TYPE_ANY])
+                || asks(&self.host, &[TYPE_A, TYPE_ANY])
+        })
+    }
+
+    // All our records in one answer. Legacy unicast replies echo the question.
+    fn response(&self, id: u16, question: Option<&Message>, entries: &[ServerEntry]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        let questions = question.map_or(&[][..], |message| &message.questions[..]);
+        write_header(&mut packet, id, 0x8400, questions.len() as u16, 4);
+        for (name, qtype) in questions {
+            write_name(&mut packet, name);
+            packet.extend_from_slice(&qtype.to_be_bytes());
+            packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+
+        let mut ptr = Vec::new();
+        write_name(&mut ptr, &self.instance);
+        write_record(&mut packet, SERVICE, TYPE_PTR, false, &ptr);
+
+        let mut srv = Vec::new();
+        srv.extend_from_slice(&[0, 0, 0, 0]); // priority and weight
+        srv.extend_from_slice(&self.port.to_be_bytes());
+        write_name(&mut srv, &self.host);
+        write_record(&mut packet, &self.instance, TYPE_SRV, true, &srv);
+
+        // One string per world: w=<world>/<players>/<max players>[/<map>]
+        let mut txt = Vec::new();
+        let name = self.instance[..self.instance.len() - SERVICE.len() - 1].to_string();
+        let mut strings = vec![format!("name={}", name)];
+        for entry in entries {
+            let mut world = format!("w={}/{}/{}", entry.world, entry.players, entry.max_players);
+            if let Some(map) = &entry.map {
+                world.push('/');
+                world.push_str(map);
+            }
+            strings.push(world);
+        }
+        for string in strings {
+            let bytes = &string.as_bytes()[..string.len().min(255)];
+            txt.push(bytes.len() as u8);
+            txt.extend_from_slice(bytes);
+        }
+        write_record(&mut packet, &self.instance, TYPE_TXT, true, &txt);
+
+        write_record(&mut packet, &self.host, TYPE_A, true, &self.ip.octets());
+        packet
+    }
+}
+
+// The address other machines on the LAN reach us at: the one the default route would use.
+// Nothing is sent; connecting a UDP socket only picks the interface.
+fn lan_address() -> Option<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((MDNS_ADDR, MDNS_PORT)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+// Servers described in a response, other than ourselves
+fn discovered(message: &Message, own_instance: &str) -> Vec<ServerEntry> {
+    let find = |name: &str, rtype: u16| {
+        message
+            .records
+            .iter()
+            .find(move |record| record.rtype == rtype && record.name.eq_ignore_ascii_case(name))
+    };
+    let mut entries = Vec::new();
+    for ptr in message.records.iter().filter(|record| record.rtype == TYPE_PTR) {
+        let Some(instance) = ptr.name.eq_ignore_ascii_case(SERVICE).then(|| ptr.name_in_data(message, 0)).flatten() else {
+            continue;
+        };
+        if instance.eq_ignore_ascii_case(own_instance) {
+            continue;
+        }
+        let (Some(srv), Some(txt)) = (find(&instance, TYPE_SRV), find(&instance, TYPE_TXT)) else {
+            continue;
+        };
+        let (Some(port), Some(host)) = (srv.data.get(4..6), srv.name_in_data(message, 6)) else {
+            continue;
+        };
+        let port = u16::from_be_bytes([port[0], port[1]]);
+        let Some(ip) = find(&host, TYPE_A).and_then(|a| <[u8; 4]>::try_from(a.data.as_slice()).ok()) else {
+            continue;
+        };
+        let url = format!("ws://{}:{}", Ipv4Addr::from(ip), port);
+
+        let strings = txt_strings(&txt.data);
+        let name = strings
+            .iter()
+            .find_map(|string| string.strip_prefix("name="))
+            .unwrap_or(&instance)
+            .to_string();
+        for world in strings.iter().filter_map(|string| string.strip_prefix("w=")) {
+            let mut fields = world.split('/');
+            let world = fields.next().unwrap_or_default().to_string();
+            let players = fields.next().and_then(|players| players.parse().ok()).unwrap_or(0);
+            let max_players = fields.next().and_then(|max| max.parse().ok()).unwrap_or(0);
+            entries.push(ServerEntry {
+                name: name.clone(),
+                url: Some(url.clone()),
+                world,
+                players,
+                max_players,
+                map: fields.next().map(str::to_string),
+            });
+        }
+    }
+    entries
+}
+
+fn txt_strings(data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut pos = 0;
+    while let Some(&len) = data.get(pos) {
+        let Some(bytes) = data.get(pos + 1..pos + 1 + len as usize) else {
+            break;
+        };
+        strings.push(String::from_utf8_lossy(bytes).into_owned());
+        pos += 1 + len as usize;
+    }
+    strings
+}
+
+// A parsed DNS message, with just what we need
+struct Message<'a> {
+    packet: &'a [u8],
+    id: u16,
+    is_response: bool,
+    questions: Vec<(String, u16)>,
+    // Answers and additional records together
+    records: Vec<Record>,
+}
+
+struct Record {
+    name: String,
+    rtype: u16,
+    data: Vec<u8>,
+    // Where the data starts in the packet, for names compressed with pointers
+    data_offset: usize,
+}
+
+impl Record {
+    fn name_in_data(&self, message: &Message, offset: usize) -> Option<String> {
+        read_name(message.packet, self.data_offset + offset).map(|(name, _)| name)
+    }
+}
+
+impl<'a> Message<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Message<'a>> {
+        let word = |pos: usize| packet.get(pos..pos + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+        let id = word(0)?;
+        let flags = word(2)?;
+        let counts = [word(4)?, word(6)?, word(8)?, word(10)?];
+        let mut pos = 12;
+
+        let mut questions = Vec::new();
+        for _ in 0..counts[0] {
+            let (name, next) = read_name(packet, pos)?;
+            questions.push((name, word(next)?));
+            pos = next + 4;
+        }
+        let mut records = Vec::new();
+        for _ in 0..counts[1] as usize + counts[2] as usize + counts[3] as usize {
+            let (name, next) = read_name(packet, pos)?;
+            let rtype = word(next)?;
+            let len = word(next + 8)? as usize;
+            let data_offset = next + 10;
+            let data = packet.get(data_offset..data_offset + len)?.to_vec();
+            records.push(Record {
+                name,
+                rtype,
+                data,
+                data_offset,
+            });
+            pos = data_offset + len;
+        }
+        Some(Message {
+            packet,
+            id,
+            is_response: flags & 0x8000 != 0,
+            questions,
+            records,
+        })
+    }
+}
+
+// A dotted name starting at `pos`, and the position right after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Pointers may only go backwards, but a bad packet could still loop
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        }
+        if len & 0xC0 == 0xC0 {
+            let target = (len & 0x3F) << 8 | *packet.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = target;
+            continue;
+        }
+        labels.push(String::from_utf8_lossy(packet.get(pos + 1..pos + 1 + len)?).into_owned());
+        pos += 1 + len;
+    }
+    None
+}
+
+fn query(name: &str, qtype: u16) -> Vec<u8> {
+    let mut packet = Vec::new();
+    write_header(&mut packet, 0, 0, 1, 0);
+    write_name(&mut packet, name);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+fn write_header(packet: &mut Vec<u8>, id: u16, flags: u16, questions: u16, answers: u16) {
+    for word in [id, flags, questions, answers, 0, 0] {
+        packet.extend_from_slice(&word.to_be_bytes());
+    }
+}
+
+// Uncompressed; the instance label may contain spaces but no dots
+fn write_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        packet.push(bytes.len() as u8);
+        packet.extend_from_slice(bytes);
+    }
+    packet.push(0);
+}
+
+fn write_record(packet: &mut Vec<u8>, name: &str, rtype: u16, unique: bool, data: &[u8]) {
+    write_name(packet, name);
+    let class = if unique { CLASS_IN | CLASS_FLAG } else { CLASS_IN };
+    packet.extend_from_slice(&rtype.to_be_bytes());
+    packet.extend_from_slice(&class.to_be_bytes());
+    packet.extend_from_slice(&RECORD_TTL.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+}
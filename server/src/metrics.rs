@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{interval, Duration};
+use tracing::info;
+
+use crate::bus::{Event, Subscriber};
+use crate::config::SharedConfig;
+use crate::limits::ConnectionLimiter;
+use crate::session::Sessions;
+use crate::world::Worlds;
+
+// Bucket i counts durations below 2^i microseconds; the last one catches everything longer
+const BUCKETS: usize = 25;
+// How often the summary line is logged
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+// A lock-free histogram of durations with power-of-two buckets
+pub struct Histogram {
+    counts: [AtomicU64; BUCKETS],
+    sum_micros: AtomicU64,
+}
+
+// A copy of a histogram's counts, so windows can be compared
+#[derive(Clone)]
+pub struct HistogramSnapshot {
+    counts: [u64; BUCKETS],
+    sum_micros: u64,
+}
+
+impl Histogram {
+    const fn new() -> Histogram {
+        Histogram {
+            counts: [const { AtomicU64::new(0) }; BUCKETS],
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+            sum_micros: self.sum_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl HistogramSnapshot {
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // What was recorded since `earlier`
+    pub fn since(&self, earlier: &HistogramSnapshot) -> HistogramSnapshot {
+        HistogramSnapshot {
+            counts: std::array::from_fn(|i| self.counts[i] - earlier.counts[i]),
+            sum_micros: self.sum_micros - earlier.sum_micros,
+        }
+    }
+
+    // Upper bound of the bucket holding the q-th quantile, `q` in 0..=1
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (self.count() as f64 * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros(1 << bucket);
+            }
+        }
+        Duration::ZERO
+    }
+}
+
+// Server-wide performance counters
+pub struct Metrics {
+    // Time spent simulating one world tick
+    pub tick: Histogram,
+    // Time to serialize a message and queue it for everyone in a world
+    pub broadcast: Histogram,
+    // Ticks that took longer than the tick interval
+    pub tick_overruns: AtomicU64,
+    // WebSocket payload bytes to and from players
+    pub bytes_sent: AtomicU64,
+    pub bytes_received: AtomicU64,
+    // Game state snapshots held back from clients over their bandwidth ceiling
+    pub snapshots_skipped: AtomicU64,
+    // Moves ignored because they came faster than the tick rate
+    pub moves_dropped: AtomicU64,
+    // Connections being handled, from accept to cleanup
+    pub connections: AtomicU64,
+    // Tasks spawned for a connection (pinging, forwarding) still running
+    pub connection_tasks: AtomicU64,
+    // Spectator streams open
+    pub spectators: AtomicU64,
+    // Events published on the worlds' buses, by kind
+    pub events: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+pub static METRICS: Metrics = Metrics {
+    tick: Histogram::new(),
+    broadcast: Histogram::new(),
+    tick_overruns: AtomicU64::new(0),
+    bytes_sent: AtomicU64::new(0),
+    bytes_received: AtomicU64::new(0),
+    snapshots_skipped: AtomicU64::new(0),
+    moves_dropped: AtomicU64::new(0),
+    connections: AtomicU64::new(0),
+    connection_tasks: AtomicU64::new(0),
+    spectators: AtomicU64::new(0),
+    events: Mutex::new(BTreeMap::new()),
+};
+
+// Counts every world's events in METRICS
+pub struct EventCounter;
+
+impl Subscriber for EventCounter {
+    fn notify(&self, event: &Event) {
+        *METRICS.events.lock().unwrap().entry(event.kind()).or_default() += 1;
+    }
+}
+
+// Counts itself in a gauge while it's alive
+pub struct Tracked(&'static AtomicU64);
+
+impl Tracked {
+    pub fn new(gauge: &'static AtomicU64) -> Tracked {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Tracked(gauge)
+    }
+
+    // Count in the gauge if it's below `max`, in one step, so two callers
+    // can't both take the last place
+    pub fn reserve(gauge: &'static AtomicU64, max: usize) -> Option<Tracked> {
+        gauge
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| (count < max as u64).then_some(count + 1))
+            .ok()?;
+        Some(Tracked(gauge))
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// How much the server is holding on to, to spot leaks: all of it should go
+// back to zero once everyone has left
+pub struct Resources {
+    pub clients: usize,
+    pub players: usize,
+    pub held_inputs: usize,
+    pub sessions: usize,
+    pub tracked_ips: usize,
+    // Messages waiting in client queues, as of each client's last send
+    pub queued_messages: u64,
+}
+
+impl Resources {
+    pub fn count(worlds: &Worlds, sessions: &Sessions, limiter: &ConnectionLimiter) -> Resources {
+        let mut resources = Resources {
+            clients: 0,
+            players: 0,
+            held_inputs: 0,
+            sessions: sessions.len(),
+            tracked_ips: limiter.tracked_ips(),
+            queued_messages: 0,
+        };
+        for world in worlds.iter() {
+            let clients = world.clients.lock().unwrap();
+            resources.clients += clients.len();
+            resources.queued_messages += clients.values().map(|client| client.traffic.queued()).sum::<u64>();
+            drop(clients);
+            resources.players += world.game_state.lock().unwrap().players.len();
+            resources.held_inputs += world.inputs.lock().unwrap().len();
+        }
+        resources
+    }
+}
+
+// Prometheus text format, served at GET /metrics
+pub fn render_prometheus(resources: &Resources) -> String {
+    let mut out = String::new();
+    for (name, help, histogram) in [
+        ("game_tick_duration_seconds", "Time spent simulating one world tick", &METRICS.tick),
+        ("game_broadcast_duration_seconds", "Time to fan a message out to a world", &METRICS.broadcast),
+    ] {
+        let snapshot = histogram.snapshot();
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for q in [0.5, 0.99] {
+            let _ = writeln!(out, "{}{{quantile=\"{}\"}} {}", name, q, snapshot.quantile(q).as_secs_f64());
+        }
+        let _ = writeln!(out, "{}_sum {}", name, snapshot.sum_micros as f64 / 1e6);
+        let _ = writeln!(out, "{}_count {}", name, snapshot.count());
+    }
+    for (name, help, counter) in [
+        ("game_tick_overruns_total", "Ticks that took longer than the tick interval", &METRICS.tick_overruns),
+        ("game_bytes_sent_total", "WebSocket payload bytes sent to players", &METRICS.bytes_sent),
+        ("game_bytes_received_total", "WebSocket payload bytes received from players", &METRICS.bytes_received),
+        ("game_snapshots_skipped_total", "Snapshots held back by the bandwidth ceiling", &METRICS.snapshots_skipped),
+        ("game_moves_dropped_total", "Moves ignored for coming faster than the tick rate", &METRICS.moves_dropped),
+    ] {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+    }
+    let _ = writeln!(out, "# HELP game_events_total Events in worlds, like moves, collisions and chat messages");
+    let _ = writeln!(out, "# TYPE game_events_total counter");
+    for (kind, count) in METRICS.events.lock().unwrap().iter() {
+        let _ = writeln!(out, "game_events_total{{kind=\"{}\"}} {}", kind, count);
+    }
+    for (name, help, value) in [
+        ("game_connections", "Connections being handled", METRICS.connections.load(Ordering::Relaxed)),
+        ("game_connection_tasks", "Tasks spawned for connections still running", METRICS.connection_tasks.load(Ordering::Relaxed)),
+        ("game_spectators", "Spectator streams open", METRICS.spectators.load(Ordering::Relaxed)),
+        ("game_clients", "Clients registered in worlds", resources.clients as u64),
+        ("game_players", "Players in worlds", resources.players as u64),
+        ("game_held_inputs", "Players with held keys", resources.held_inputs as u64),
+        ("game_sessions", "Sessions kept, connected or waiting to be resumed", resources.sessions as u64),
+        ("game_tracked_ips", "Client IPs the connection limiter keeps", resources.tracked_ips as u64),
+        ("game_queued_messages", "Messages waiting in client queues", resources.queued_messages),
+    ] {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out
+}
+
+// Log tick and broadcast percentiles for the last interval
+pub async fn log_periodically(config: Arc<SharedConfig>) {
+    let mut ticker = interval(LOG_INTERVAL);
+    ticker.tick().await;
+    let (mut ticks, mut broadcasts) = (METRICS.tick.snapshot(), METRICS.broadcast.snapshot());
+    let mut overruns = METRICS.tick_overruns.load(Ordering::Relaxed);
+    loop {
+        ticker.tick().await;
+        let (now_ticks, now_broadcasts) = (METRICS.tick.snapshot(), METRICS.broadcast.snapshot());
+        let now_overruns = METRICS.tick_overruns.load(Ordering::Relaxed);
+        let (tick_window, broadcast_window) = (now_ticks.since(&ticks), now_broadcasts.since(&broadcasts));
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        info!(
+            "Ticks: {} (p50 {:.2} ms, p99 {:.2} ms, budget {:.0} ms, {} over); broadcasts: {} (p50 {:.2} ms, p99 {:.2} ms)",
+            tick_window.count(),
+            ms(tick_window.quantile(0.5)),
+            ms(tick_window.quantile(0.99)),
+            ms(config.get().tick_interval()),
+            now_overruns - overruns,
+            broadcast_window.count(),
+            ms(broadcast_window.quantile(0.5)),
+            ms(broadcast_window.quantile(0.99)),
+        );
+        (ticks, broadcasts, overruns) = (now_ticks, now_broadcasts, now_overruns);
+    }
+}
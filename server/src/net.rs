@@ -0,0 +1,109 @@
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::chat::Mutes;
+use crate::config::{Config, SharedConfig};
+use crate::drain::Drain;
+use crate::http::{self, Site};
+use crate::limits::ConnectionLimiter;
+use crate::proxy;
+use crate::scripting::ScriptHost;
+use crate::session::Sessions;
+use crate::storage::PlayerStore;
+use crate::world::Worlds;
+
+mod connection;
+
+// WebSocket upgrades under these paths are the HTTP site's, like the admin
+// dashboard's and the spectators'
+const HTTP_UPGRADE_PREFIXES: [&str; 2] = ["/admin/", "/spectate"];
+
+// Server-wide state every connection gets a handle to
+#[derive(Clone)]
+pub struct Shared {
+    pub worlds: Arc<Worlds>,
+    pub config: Arc<SharedConfig>,
+    pub limiter: Arc<ConnectionLimiter>,
+    pub scripts: Arc<ScriptHost>,
+    pub store: Arc<PlayerStore>,
+    pub sessions: Arc<Sessions>,
+    pub mutes: Arc<Mutes>,
+    pub drain: Arc<Drain>,
+}
+
+// Accept game connections on `listener` until `shutdown` completes, each in its
+// own task. Plain HTTP requests and the upgrades `site` takes go to `site`, so
+// everything can be served on one port.
+pub async fn serve(listener: TcpListener, shared: Shared, site: Site, shutdown: impl Future<Output = ()>) {
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(_) => break,
+            },
+            _ = &mut shutdown => break,
+        };
+        // Clone the shared state for this connection
+        let shared = shared.clone();
+        let site = site.clone();
+
+        // Spawn a task for each inbound connection
+        tokio::spawn(async move {
+            let mut stream = stream;
+            let settings = shared.config.get();
+            let peer = match tokio::time::timeout(settings.handshake_timeout(), client_behind_proxy(&mut stream, addr, &settings)).await {
+                Ok(Ok(peer)) => peer,
+                Ok(Err(e)) => {
+                    warn_limited!("Error in connection from {}: {}", addr, e);
+                    return;
+                }
+                Err(_) => {
+                    info_limited!("Handshake timed out: {}", addr);
+                    return;
+                }
+            };
+            if let Ok(Some(head)) = http::peek_head(&stream).await {
+                if !head.upgrade || HTTP_UPGRADE_PREFIXES.iter().any(|prefix| head.path.starts_with(prefix)) {
+                    if let Err(e) = http::handle_request(stream, peer, site).await {
+                        warn_limited!("HTTP error from {}: {}", addr, e);
+                    }
+                    return;
+                }
+            }
+            if let Err(e) = connection::handle(stream, addr, peer, shared).await {
+                warn_limited!("Error in connection: {}", e);
+            }
+        });
+    }
+}
+
+// Behind a proxy speaking the PROXY protocol the peer is the proxy itself, so
+// read where the connection really comes from
+async fn client_behind_proxy(stream: &mut TcpStream, addr: SocketAddr, config: &Config) -> io::Result<IpAddr> {
+    if config.proxy.proxy_protocol && config.proxy.is_trusted(addr.ip()) {
+        if let Some(source) = proxy::read_proxy_header(stream).await? {
+            return Ok(source.ip());
+        }
+    }
+    Ok(addr.ip())
+}
+
+// Close frame telling the client why it's being turned away
+pub fn policy_close(reason: &'static str) -> Message {
+    close_frame(CloseCode::Policy, reason)
+}
+
+pub fn close_frame(code: CloseCode, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
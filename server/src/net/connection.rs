@@ -0,0 +1,1249 @@
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, timeout_at, Instant};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Error as WsError;
+use tokio_tungstenite::{accept_hdr_async_with_config, tungstenite::Message, WebSocketStream};
+use tracing::{debug, info, warn};
+
+use crate::admin;
+use crate::chat::{self, ChatRate};
+use crate::config::{Config, DuplicateSessions};
+use crate::cosmetics::{self, Cosmetic};
+use crate::limits::{ConnectionSlot, InputCooldown, Traffic};
+use crate::metrics::{Tracked, METRICS};
+use crate::profiler::TickProfile;
+use crate::rooms::{self, RoomConfig, RoomSettings};
+use crate::protocol::{self, Codec, Compression, Snapshots};
+use crate::session::{self, Resumed, Sessions};
+use crate::storage::{self, LastPosition, SessionRecord};
+use crate::world::{HeldKeys, World, Worlds};
+use crate::broadcast::{broadcast, broadcast_game_state, Client, PING_INTERVAL};
+use crate::game::systems::{direction_keys, move_interval, move_player, movement};
+use crate::game::{clamp_to_world, Player, CANVAS_HEIGHT, CANVAS_WIDTH, PLAYER_SIZE};
+use crate::net::{close_frame, policy_close, Shared};
+use crate::protocol::{ClientMessage, Features, JoinRequest, RosterEntry, ServerMessage};
+use crate::{bus, login, pow, spawn};
+
+// How long a connection may take to get a player once it sent Join: resuming
+// its session, taking over from another connection, looking up its unlocks
+const JOINED_TIMEOUT: Duration = Duration::from_secs(10);
+// How long leaving may take: saving the session and sending what's still queued
+const LEAVING_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a disconnecting client's queued messages may take to send
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+// How often, and how many times, to check whether a replaced connection has let go of its player
+const TAKE_OVER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const TAKE_OVER_POLLS: u32 = 40;
+
+// Where a connection is in its life. Each state has a time limit; a
+// connection that overstays it is closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    // Becoming a WebSocket
+    Handshaking,
+    // Looking through and creating rooms, then sending Join, with a proof of work if asked
+    Browsing,
+    // Getting a player: picking the world, resuming the session
+    Joined,
+    // In the world, until it leaves or goes quiet
+    Playing,
+    // Taking the player out of the world, saving the session, flushing what's queued
+    Leaving,
+    Closed,
+}
+
+// What moves a connection from one state to the next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    // The client is a WebSocket now, and may ask about rooms
+    Accept,
+    // A valid Join came in
+    Join,
+    // The player was added to the world
+    Spawn,
+    // The client left, disconnected, was kicked or replaced, or went quiet
+    Leave,
+    // Turned away, or timed out, before it got a player
+    Reject,
+    // Leaving is done
+    Finish,
+}
+
+impl State {
+    // The state `event` leads to, if it can happen in this one
+    pub fn next(self, event: Event) -> Option<State> {
+        match (self, event) {
+            (State::Handshaking, Event::Accept) => Some(State::Browsing),
+            (State::Browsing, Event::Join) => Some(State::Joined),
+            (State::Joined, Event::Spawn) => Some(State::Playing),
+            (State::Handshaking | State::Browsing | State::Joined, Event::Reject) => Some(State::Closed),
+            (State::Playing, Event::Leave) => Some(State::Leaving),
+            (State::Leaving, Event::Finish) => Some(State::Closed),
+            _ => None,
+        }
+    }
+
+    // How long a connection may stay in this state; while playing, how long
+    // it may go without sending anything (pongs included)
+    pub fn time_limit(self, config: &Config) -> Option<Duration> {
+        match self {
+            State::Handshaking => Some(config.handshake_timeout()),
+            State::Browsing => Some(config.lobby_timeout()),
+            State::Joined => Some(JOINED_TIMEOUT),
+            State::Playing => config.idle_timeout(),
+            State::Leaving => Some(LEAVING_TIMEOUT),
+            State::Closed => None,
+        }
+    }
+}
+
+// A connection's state, and the clock its time limit runs on
+pub struct Lifecycle {
+    addr: SocketAddr,
+    state: State,
+    // When the state was entered, or while playing, when the client last sent something
+    since: Instant,
+}
+
+impl Lifecycle {
+    pub fn new(addr: SocketAddr) -> Lifecycle {
+        Lifecycle {
+            addr,
+            state: State::Handshaking,
+            since: Instant::now(),
+        }
+    }
+
+    // Move on to the state `event` leads to. One that can't happen here is a
+    // bug in the caller; it's logged and the state stays.
+    pub fn advance(&mut self, event: Event) {
+        match self.state.next(event) {
+            Some(next) => {
+                debug!("{}: {:?} -> {:?}", self.addr, self.state, next);
+                self.state = next;
+                self.since = Instant::now();
+            }
+            None => warn!("{}: {:?} can't happen while {:?}", self.addr, event, self.state),
+        }
+    }
+
+    // The client sent something, which restarts the clock while playing
+    pub fn touch(&mut self) {
+        if self.state == State::Playing {
+            self.since = Instant::now();
+        }
+    }
+
+    pub fn deadline(&self, config: &Config) -> Option<Instant> {
+        self.state.time_limit(config).map(|limit| self.since + limit)
+    }
+
+    // Run `future` until the current state's time is up; None if it was first
+    pub async fn within<F: Future>(&self, config: &Config, future: F) -> Option<F::Output> {
+        match self.deadline(config) {
+            Some(deadline) => timeout_at(deadline, future).await.ok(),
+            None => Some(future.await),
+        }
+    }
+}
+
+type Sink = SplitSink<WebSocketStream<TcpStream>, Message>;
+type Stream = SplitStream<WebSocketStream<TcpStream>>;
+
+// A WebSocket that was let in, before it joined
+struct Lobby {
+    tx: Sink,
+    rx: Stream,
+    client_ip: IpAddr,
+    path_world: Option<String>,
+    // The codec asked for in the WebSocket handshake, if any
+    negotiated: Option<&'static dyn Codec>,
+    slot: ConnectionSlot,
+}
+
+// A connection that sent a valid Join
+struct Handshake {
+    tx: Sink,
+    rx: Stream,
+    client_ip: IpAddr,
+    // World named in the URL path
+    path_world: Option<String>,
+    join: JoinRequest,
+    codec: &'static dyn Codec,
+    // Held until the connection ends
+    _slot: ConnectionSlot,
+}
+
+// A connection with a player in a world
+struct Seat {
+    addr: SocketAddr,
+    client_ip: IpAddr,
+    codec: &'static dyn Codec,
+    world: Arc<World>,
+    player_id: String,
+    // What other players see in chat
+    display_name: String,
+    // A resumed player brings their score along; only what's earned now counts for this session
+    starting_score: u32,
+    session_token: String,
+    unlocks: Vec<Cosmetic>,
+    compression: Compression,
+    joined_at: u64,
+}
+
+// How a playing connection is reached by the rest of the server
+struct Link {
+    sender: UnboundedSender<Message>,
+    ping: Arc<Mutex<Option<Duration>>>,
+    traffic: Arc<Traffic>,
+    replaced: Arc<Notify>,
+    connected_at: Instant,
+    ping_task: JoinHandle<()>,
+    forward_task: Option<JoinHandle<()>>,
+}
+
+// Run a connection through its states until it's closed
+pub async fn handle(stream: TcpStream, addr: SocketAddr, peer: IpAddr, shared: Shared) -> Result<(), Box<dyn std::error::Error>> {
+    let _tracked = Tracked::new(&METRICS.connections);
+    let mut lifecycle = Lifecycle::new(addr);
+
+    // Settings for the handshake; gameplay reads the live config as it goes
+    let settings = shared.config.get();
+    let lobby = match lifecycle.within(&settings, handshake(stream, addr, peer, &shared, &settings)).await {
+        Some(Ok(Some(lobby))) => lobby,
+        rejected => {
+            if rejected.is_none() {
+                info!("Handshake timed out: {}", addr);
+            }
+            lifecycle.advance(Event::Reject);
+            return rejected.unwrap_or(Ok(None)).map(|_| ());
+        }
+    };
+    lifecycle.advance(Event::Accept);
+
+    let mut handshake = match lifecycle.within(&settings, browse(lobby, addr, &shared, &settings)).await {
+        Some(Ok(Some(handshake))) => handshake,
+        rejected => {
+            if rejected.is_none() {
+                info!("No Join in time from {}", addr);
+            }
+            lifecycle.advance(Event::Reject);
+            return rejected.unwrap_or(Ok(None)).map(|_| ());
+        }
+    };
+    lifecycle.advance(Event::Join);
+
+    let seat = match lifecycle.within(&settings, join(&mut handshake, addr, &shared)).await {
+        Some(Ok(Some(seat))) => seat,
+        rejected => {
+            if rejected.is_none() {
+                info!("Joining timed out: {}", addr);
+            }
+            lifecycle.advance(Event::Reject);
+            return rejected.unwrap_or(Ok(None)).map(|_| ());
+        }
+    };
+    lifecycle.advance(Event::Spawn);
+    info!("New player connected to {}: {} ({})", seat.world.name, seat.client_ip, addr);
+
+    let Handshake { tx, mut rx, _slot, .. } = handshake;
+    let (mut link, receiver) = connect(&seat);
+    // Leaving happens whatever playing ended with; the error is kept as text
+    // since it can't be held across leaving's awaits
+    let played = play(tx, &mut rx, &seat, &mut link, receiver, &shared, &mut lifecycle).await.map_err(|e| e.to_string());
+
+    lifecycle.advance(Event::Leave);
+    info!("Player disconnected: {} ({})", seat.client_ip, addr);
+    if lifecycle.within(&shared.config.get(), leave(&seat, link, &shared)).await.is_none() {
+        warn!("Leaving took too long for {}, dropping the rest", addr);
+    }
+    lifecycle.advance(Event::Finish);
+    Ok(played?)
+}
+
+// Become a WebSocket the server lets in. None if the client was turned away.
+async fn handshake(
+    stream: TcpStream,
+    addr: SocketAddr,
+    peer: IpAddr,
+    shared: &Shared,
+    settings: &Config,
+) -> Result<Option<Lobby>, Box<dyn std::error::Error>> {
+    let (ws_stream, client_ip, path_world, negotiated) = accept_client(stream, peer, settings).await?;
+    let (mut tx, rx) = ws_stream.split();
+
+    if settings.is_banned(client_ip) || shared.store.is_banned(client_ip) {
+        info_limited!("Rejecting banned client {} ({})", client_ip, addr);
+        tx.send(policy_close("You are banned from this server")).await?;
+        return Ok(None);
+    }
+
+    // Enforce the per-IP connection limit; the slot is freed when the connection ends
+    let Some(slot) = shared.limiter.try_acquire(client_ip, settings.max_connections_per_ip) else {
+        info_limited!("Too many connections from {}, rejecting {}", client_ip, addr);
+        tx.send(policy_close("Too many connections from your address")).await?;
+        return Ok(None);
+    };
+
+    Ok(Some(Lobby { tx, rx, client_ip, path_world, negotiated, slot }))
+}
+
+// Answer questions about rooms until a Join the server accepts comes in. None
+// if the client was turned away.
+async fn browse(lobby: Lobby, addr: SocketAddr, shared: &Shared, settings: &Config) -> Result<Option<Handshake>, Box<dyn std::error::Error>> {
+    let Lobby { mut tx, mut rx, client_ip, path_world, negotiated, slot } = lobby;
+
+    // The client has to join before it gets a player
+    let Some((join, codec)) = wait_for_join(&mut rx, &mut tx, negotiated, shared, settings.max_message_bytes).await? else {
+        info!("Expected Join from {}, closing connection", addr);
+        return Ok(None);
+    };
+
+    // Slow down clients joining over and over, then make them work for their seat
+    if !shared.limiter.try_join(client_ip, settings.join_cooldown()) {
+        info_limited!("{} joined again too soon, closing connection", addr);
+        tx.send(policy_close("Joining too often, try again in a moment")).await?;
+        return Ok(None);
+    }
+    let join = if settings.join_pow_bits > 0 {
+        let challenge = session::new_token();
+        let bits = settings.join_pow_bits;
+        tx.send(protocol::encode(&ServerMessage::Challenge { challenge: challenge.clone(), bits }, codec)?).await?;
+        let answer = wait_for_join(&mut rx, &mut tx, Some(codec), shared, settings.max_message_bytes).await?;
+        match answer {
+            Some((join, _)) if join.proof.is_some_and(|proof| pow::verify(&challenge, proof, bits)) => join,
+            _ => {
+                info_limited!("No valid proof of work from {}, closing connection", addr);
+                tx.send(policy_close("Missing or wrong proof of work")).await?;
+                return Ok(None);
+            }
+        }
+    } else {
+        join
+    };
+
+    Ok(Some(Handshake { tx, rx, client_ip, path_world, join, codec, _slot: slot }))
+}
+
+// Find the world and the player for a joined connection, new or resumed, and
+// add it to the world. None if the client was turned away.
+async fn join(handshake: &mut Handshake, addr: SocketAddr, shared: &Shared) -> Result<Option<Seat>, Box<dyn std::error::Error>> {
+    let Shared { worlds, config, scripts, store, sessions, .. } = shared;
+    let Handshake { tx, join, client_ip, .. } = handshake;
+    let settings = config.get();
+    let compression = match settings.compression {
+        true => Compression::choose(join.compression.iter().map(String::as_str)),
+        false => Compression::None,
+    };
+
+    // An invite picks its room; without one, the world named in the Join
+    // message, else the URL path, else the default
+    let world = match &join.invite {
+        Some(code) => worlds.by_invite(code),
+        None => worlds.get(join.world.as_deref().or(handshake.path_world.as_deref())),
+    };
+    let Some(world) = world else {
+        info!("{} asked for an unknown world, closing connection", addr);
+        let reason = if join.invite.is_some() { "No room has this invite code" } else { "No such world" };
+        tx.send(policy_close(reason)).await?;
+        return Ok(None);
+    };
+
+    // Resume the player behind the session token, if it's still valid
+    let resumed = match &join.session {
+        Some(token) => sessions.resume(token, &world.name),
+        None => Resumed::None,
+    };
+    // The same player joining again while still connected, from another tab or device
+    let resumed = match (resumed, &join.session) {
+        (Resumed::Connected(player_id), Some(token)) => {
+            let taken_over = match settings.duplicate_sessions {
+                DuplicateSessions::TakeOver => take_over(worlds, sessions, token, &player_id, &world.name).await,
+                DuplicateSessions::Reject => None,
+            };
+            let Some(resumed) = taken_over else {
+                info!("{} is already connected, rejecting {}", player_id, addr);
+                tx.send(policy_close("You're already playing somewhere else")).await?;
+                return Ok(None);
+            };
+            info!("{} joined again from {}, took over their old connection", player_id, addr);
+            resumed
+        }
+        (resumed, _) => resumed,
+    };
+    // While draining only players who just dropped out may come back
+    if shared.drain.is_draining() && !matches!(resumed, Resumed::Player(_)) {
+        info!("Draining, rejecting {}", addr);
+        tx.send(close_frame(CloseCode::Restart, "The server is about to restart")).await?;
+        return Ok(None);
+    }
+    // Private rooms let in players with an invite that hasn't expired, and players coming back
+    let invited = join.invite.as_deref().is_some_and(|code| world.accepts_invite(code));
+    if world.is_private() && !invited && matches!(resumed, Resumed::None) {
+        info!("{} has no valid invite to {}, closing connection", addr, world.name);
+        tx.send(policy_close("This room needs an invite, or its invite has expired")).await?;
+        return Ok(None);
+    }
+    // Past the resume window, or after a restart, the player record still knows the token
+    let resumed = match (resumed, &join.session) {
+        (Resumed::None, Some(token)) if !sessions.contains(token) => {
+            store.player_by_token(token).map_or(Resumed::None, Resumed::Stored)
+        }
+        (resumed, _) => resumed,
+    };
+    let session_token = match &resumed {
+        Resumed::None => None,
+        Resumed::Stored(record) => {
+            let token = join.session.clone().expect("stored sessions come from a token");
+            sessions.restore(&token, &record.id);
+            Some(token)
+        }
+        _ => join.session.clone(),
+    };
+
+    // Color for players that didn't pick one
+    let colors = ["#FF0000", "#00FF00", "#0000FF", "#FFFF00", "#FF00FF", "#00FFFF"];
+    let color = colors[addr.port() as usize % colors.len()];
+    let mut player = match resumed {
+        Resumed::Player(mut player) => {
+            // Name, color and shape may have been changed in the meantime
+            player.name = join.name().unwrap_or(player.name);
+            player.color = join.color().unwrap_or(player.color);
+            player.shape = join.shape().unwrap_or(player.shape);
+            player.afk = false;
+            player
+        }
+        resumed => {
+            // Generate a unique player ID (unless resuming one)
+            let (player_id, record) = match resumed {
+                Resumed::Identity(player_id) => (player_id, None),
+                Resumed::Stored(record) => (record.id.clone(), Some(record)),
+                _ => (format!("player_{}", addr.port()), None),
+            };
+
+            // Create a new player at a free spawn point
+            let (x, y) = {
+                let state = world.game_state.lock().unwrap();
+                spawn::pick(&world.map(), PLAYER_SIZE, state.players.values(), addr.port() as u64)
+            }; // Lock is released here
+            let mut player = Player {
+                id: player_id,
+                name: join.name().unwrap_or_default(),
+                x,
+                y,
+                color: join.color().unwrap_or_else(|| color.to_string()),
+                shape: join.shape().unwrap_or_default(),
+                size: PLAYER_SIZE,
+                eliminated: false,
+                afk: false,
+                frozen: false,
+                ghost: false,
+                bounce: (0, 0),
+                score: 0,
+            };
+            if let Some(rules) = scripts.get() {
+                rules.on_join(&mut player);
+            }
+            // Back where they last left this world, where the mode allows it
+            let last_position = record
+                .and_then(|record| record.last_position)
+                .filter(|last| last.world == world.name && config.get().restore_position.contains(&world.mode));
+            if let Some(last) = last_position {
+                (player.x, player.y, player.score) = (last.x, last.y, last.score);
+                clamp_to_world(&mut player);
+            }
+            if let Some(cell_size) = world.map().cell_size {
+                player.simulate(|body| sim::grid::snap(body, cell_size));
+            }
+            player
+        }
+    };
+    // Locked cosmetics are checked after the script had its say, in case it picked one
+    let unlocks = cosmetics::inventory(store, &player.id).await;
+    cosmetics::enforce(&mut player, &unlocks, color);
+    let player_id = player.id.clone();
+    let login_required = config.get().login.as_ref().is_some_and(|login| login.required);
+    if login_required && !player_id.starts_with(login::PLAYER_ID_PREFIX) {
+        info!("{} hasn't logged in, closing connection", addr);
+        tx.send(policy_close("Log in to play on this server")).await?;
+        return Ok(None);
+    }
+    let display_name = if player.name.is_empty() { player.id.clone() } else { player.name.clone() };
+    let starting_score = player.score;
+    let session_token = session_token.unwrap_or_else(|| sessions.start(&player_id));
+
+    // Add player to game state unless the server is full - scope the lock
+    let rejected = {
+        let mut state = world.game_state.lock().unwrap();
+        if state.players.len() < world.max_players(&config.get()) {
+            state.players.insert(player_id.clone(), player);
+            state.wire.assign(&player_id);
+            world.bus.publish(bus::Event::PlayerJoined { player_id: player_id.clone() });
+            None
+        } else {
+            Some(player)
+        }
+    }; // Lock is released here
+    if let Some(player) = rejected {
+        info!("World {} full, rejecting {} ({})", world.name, client_ip, addr);
+        // Keep the session so the player can try again
+        sessions.leave(&session_token, &world.name, player, config.get().session_resume());
+        tx.send(policy_close("This world is full")).await?;
+        return Ok(None);
+    }
+
+    Ok(Some(Seat {
+        addr,
+        client_ip: *client_ip,
+        codec: handshake.codec,
+        world,
+        player_id,
+        display_name,
+        starting_score,
+        session_token,
+        unlocks,
+        compression,
+        joined_at: storage::unix_time(),
+    }))
+}
+
+// Make a new player reachable: its queue, the pings it gets, and its entry
+// in the world's clients
+fn connect(seat: &Seat) -> (Link, UnboundedReceiver<Message>) {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+    // Ping the client regularly; the payload is the send time in ms since it connected
+    let connected_at = Instant::now();
+    let ping_sender = sender.clone();
+    let ping_task = tokio::spawn(async move {
+        let _tracked = Tracked::new(&METRICS.connection_tasks);
+        let mut ticker = tokio::time::interval(PING_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let sent = connected_at.elapsed().as_millis() as u64;
+            if ping_sender.send(Message::Ping(sent.to_be_bytes().to_vec())).is_err() {
+                break;
+            }
+        }
+    });
+
+    let link = Link {
+        sender,
+        ping: Arc::new(Mutex::new(None)),
+        traffic: Arc::new(Traffic::default()),
+        replaced: Arc::new(Notify::new()),
+        connected_at,
+        ping_task,
+        forward_task: None,
+    };
+    seat.world.clients.lock().unwrap().insert(seat.addr, Client {
+        sender: link.sender.clone(),
+        ip: seat.client_ip,
+        player_id: seat.player_id.clone(),
+        codec: seat.codec,
+        ping: link.ping.clone(),
+        traffic: link.traffic.clone(),
+        replaced: link.replaced.clone(),
+    });
+    (link, receiver)
+}
+
+// Welcome the player, then handle what the client sends until it leaves,
+// disconnects, breaks the rules or goes quiet for too long
+async fn play(
+    mut tx: Sink,
+    rx: &mut Stream,
+    seat: &Seat,
+    link: &mut Link,
+    receiver: UnboundedReceiver<Message>,
+    shared: &Shared,
+    lifecycle: &mut Lifecycle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Shared { worlds, config, scripts, store, mutes, .. } = shared;
+    let Seat { addr, client_ip, codec, world, player_id, display_name, compression, .. } = seat;
+    let (clients, game_state) = (&world.clients, &world.game_state);
+    let (addr, codec, compression) = (*addr, *codec, *compression);
+
+    match lifecycle.within(&config.get(), welcome(&mut tx, seat, &link.traffic, &config.get())).await {
+        Some(result) => result?,
+        None => {
+            info!("{} didn't take the welcome in time, closing connection", addr);
+            return Ok(());
+        }
+    }
+    broadcast_game_state(clients, game_state, config.get().client_max_bytes_per_sec)?;
+
+    // Task to forward messages from other clients to this client
+    #[cfg(feature = "chaos")]
+    let receiver = match config.get().chaos.clone() {
+        chaos if chaos.enabled => crate::chaos::wrap(chaos, receiver),
+        _ => receiver,
+    };
+    let mut receiver = receiver;
+    let forward_traffic = link.traffic.clone();
+    link.forward_task = Some(tokio::spawn(async move {
+        let _tracked = Tracked::new(&METRICS.connection_tasks);
+        while let Some(msg) = receiver.recv().await {
+            forward_traffic.record_queued(receiver.len());
+            let msg = compression.apply(msg);
+            forward_traffic.record_sent(msg.len());
+            let closing = msg.is_close();
+            if let Err(e) = tx.send(msg).await {
+                warn_limited!("Error sending to {}: {}", addr, e);
+                break;
+            }
+            if closing {
+                break;
+            }
+        }
+    }));
+
+    // Listen for messages from this client
+    let client_sender = &link.sender;
+    let mut strikes = 0;
+    let mut cooldown = InputCooldown::default();
+    let mut chat_rate = ChatRate::default();
+    loop {
+        let quiet = lifecycle.deadline(&config.get());
+        let result = tokio::select! {
+            result = rx.next() => result,
+            _ = link.replaced.notified() => break,
+            _ = sleep_until(quiet) => {
+                info!("{} went quiet, closing connection", addr);
+                let _ = client_sender.send(close_frame(CloseCode::Away, "Timed out"));
+                break;
+            }
+        };
+        let Some(result) = result else {
+            break;
+        };
+        lifecycle.touch();
+        match result {
+            Ok(msg) => {
+                link.traffic.record_received(msg.len());
+                let max_message_bytes = config.get().max_message_bytes;
+                // Anything the client shouldn't have sent counts as a strike
+                let mut violation = None;
+                if (msg.is_text() || msg.is_binary()) && msg.len() > max_message_bytes {
+                    violation = Some(format!("Messages may be at most {} bytes", max_message_bytes));
+                } else if let Message::Pong(payload) = &msg {
+                    // Answer to one of our pings
+                    if let Ok(sent) = <[u8; 8]>::try_from(payload.as_slice()) {
+                        let now = link.connected_at.elapsed().as_millis() as u64;
+                        let round_trip = now.saturating_sub(u64::from_be_bytes(sent));
+                        *link.ping.lock().unwrap() = Some(Duration::from_millis(round_trip));
+                    }
+                } else if let Some(frame) = protocol::frame(msg) {
+                    match protocol::decode::<ClientMessage>(frame, codec) {
+                        Ok(ClientMessage::Move { .. }) if !world.is_playing() => {
+                            // A room's match hasn't started yet
+                        },
+                        Ok(ClientMessage::Move { .. }) if !cooldown.try_move(std::time::Instant::now(), move_interval(world, &config.get())) => {
+                            // Faster than the tick rate; moving more often would make the player faster
+                            METRICS.moves_dropped.fetch_add(1, Ordering::Relaxed);
+                        },
+                        Ok(ClientMessage::Move { direction }) => {
+                            // Update player position based on direction
+                            let messages = {
+                                let multiplier = world.events.lock().unwrap().speed_multiplier();
+                                let speed = (world.player_speed(&config.get()) as f32 * multiplier).round() as u32;
+                                let mut state = game_state.lock().unwrap();
+                                move_player(&mut state, world, scripts, player_id, direction_keys(&direction), speed, &mut TickProfile::default())
+                            }; // Lock is released here
+
+                            // Broadcast updated game state
+                            for message in messages {
+                                broadcast(clients, &message);
+                            }
+                            broadcast_game_state(clients, game_state, config.get().client_max_bytes_per_sec)?;
+                        },
+                        Ok(ClientMessage::Input { seq, keys }) => {
+                            // Applied by the world's tick, so sending more often doesn't move faster
+                            let mut inputs = world.inputs.lock().unwrap();
+                            let held = inputs.entry(player_id.clone()).or_default();
+                            if seq >= held.seq {
+                                *held = HeldKeys { seq, keys };
+                            }
+                        },
+                        Ok(ClientMessage::Join(_) | ClientMessage::CreateRoom(_) | ClientMessage::ListRooms) => {
+                            // Player has already joined, nothing to do
+                        },
+                        Ok(ClientMessage::Hash { tick, hash }) => {
+                            // Out of sync: start the client over from our state
+                            if world.hashes.check(tick, hash) == Some(false) {
+                                warn_limited!("{} desynced at tick {} in {}, resending the state", player_id, tick, world.name);
+                                let game_state = world.game_state.lock().unwrap().clone();
+                                let state = protocol::encode(&ServerMessage::State { game_state }, codec)?;
+                                let _ = client_sender.send(state);
+                            }
+                        },
+                        Ok(ClientMessage::Afk { afk }) => {
+                            // Keys held when the window went away are let go
+                            if afk {
+                                world.inputs.lock().unwrap().remove(player_id);
+                            }
+                            let changed = match game_state.lock().unwrap().players.get_mut(player_id) {
+                                Some(player) if player.afk != afk => {
+                                    player.afk = afk;
+                                    true
+                                }
+                                _ => false,
+                            };
+                            if changed {
+                                broadcast_game_state(clients, game_state, config.get().client_max_bytes_per_sec)?;
+                            }
+                        },
+                        Ok(ClientMessage::Ready { ready }) => {
+                            // Everyone's told on the room's next tick; outside rooms there's nothing to get ready for
+                            if let Some(round) = &world.round {
+                                round.lock().unwrap().set_ready(player_id, ready);
+                            }
+                        },
+                        Ok(ClientMessage::Kick { player_id: kicked }) => {
+                            if let Err(text) = kick(world, player_id, &kicked) {
+                                chat::send(client_sender, codec, &ServerMessage::Notice { text });
+                            }
+                        },
+                        Ok(ClientMessage::StartMatch) => {
+                            let refusal = host_refusal(world, player_id).or_else(|| {
+                                let started = world.round.as_ref().is_some_and(|round| round.lock().unwrap().start());
+                                (!started).then(|| "The match is already starting".to_string())
+                            });
+                            if let Some(text) = refusal {
+                                chat::send(client_sender, codec, &ServerMessage::Notice { text });
+                            }
+                        },
+                        Ok(ClientMessage::ChangeRoom(settings)) => {
+                            if let Err(text) = change_room(world, player_id, settings, &config.get()) {
+                                chat::send(client_sender, codec, &ServerMessage::Notice { text });
+                            }
+                        },
+                        Ok(ClientMessage::Ping { sent_at }) => {
+                            let pong = protocol::encode(&ServerMessage::Pong { sent_at }, codec)?;
+                            let _ = client_sender.send(pong);
+                        },
+                        Ok(ClientMessage::Chat { text }) => {
+                            match chat::moderate(&text, player_id, &config.get().chat, mutes, &mut chat_rate) {
+                                Ok(text) => {
+                                    world.bus.publish(bus::Event::ChatSent { player_id: player_id.clone(), whisper: false });
+                                    broadcast(clients, &ServerMessage::Chat {
+                                        from: player_id.clone(),
+                                        name: display_name.clone(),
+                                        text,
+                                    });
+                                },
+                                Err(text) => chat::send(client_sender, codec, &ServerMessage::Notice { text }),
+                            }
+                        },
+                        Ok(ClientMessage::Whisper { to, text }) => {
+                            match chat::moderate(&text, player_id, &config.get().chat, mutes, &mut chat_rate) {
+                                Ok(text) => {
+                                    world.bus.publish(bus::Event::ChatSent { player_id: player_id.clone(), whisper: true });
+                                    chat::whisper(worlds, client_sender, codec, player_id, display_name, &to, &text);
+                                },
+                                Err(text) => chat::send(client_sender, codec, &ServerMessage::Notice { text }),
+                            }
+                        },
+                        Ok(ClientMessage::Leave) => {
+                            info!("{} left", addr);
+                            let _ = client_sender.send(close_frame(CloseCode::Normal, "Bye"));
+                            break;
+                        },
+                        Err(e) => {
+                            violation = Some(format!("Invalid message: {}", e));
+                        }
+                    }
+                }
+
+                if let Some(reason) = violation {
+                    strikes += 1;
+                    let settings = config.get();
+                    warn_limited!("Strike {}/{} for {}: {}", strikes, settings.max_strikes, addr, reason);
+                    if strikes >= settings.max_strikes {
+                        info!("{} sent too many invalid messages, closing connection", addr);
+                        if settings.strike_ban_secs > 0 {
+                            store.ban(*client_ip, Duration::from_secs(settings.strike_ban_secs)).await;
+                        }
+                        let _ = client_sender.send(policy_close("Too many invalid messages"));
+                        break;
+                    }
+                    let warning = protocol::encode(&ServerMessage::Warning { text: reason }, codec)?;
+                    let _ = client_sender.send(warning);
+                }
+            }
+            Err(WsError::Capacity(e)) => {
+                // Too big to even read, so no second chances
+                info!("Closing connection from {}: {}", addr, e);
+                let settings = config.get();
+                if settings.strike_ban_secs > 0 {
+                    store.ban(*client_ip, Duration::from_secs(settings.strike_ban_secs)).await;
+                }
+                let _ = client_sender.send(close_frame(CloseCode::Size, "Message too big"));
+                break;
+            }
+            Err(e) => {
+                warn_limited!("Error receiving from {}: {}", addr, e);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The map, the session, the world as it is, running events and who's in it,
+// sent straight to a new player before anything else
+async fn welcome(tx: &mut Sink, seat: &Seat, traffic: &Traffic, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Seat { world, player_id, codec, compression, .. } = seat;
+    let mut messages = vec![
+        ServerMessage::Map { map: (*world.map()).clone() },
+        ServerMessage::Session {
+            player_id: player_id.clone(),
+            token: seat.session_token.clone(),
+            unlocks: seat.unlocks.clone(),
+            movement: movement(world, config),
+            codec: codec.name().to_string(),
+            compression: compression.name().to_string(),
+            features: Features {
+                chat: config.chat.enabled,
+                binary_snapshots: codec.snapshots() != Snapshots::Messages,
+                compression: *compression != Compression::None,
+                mode: world.mode,
+                netcode: world.netcode,
+                growth: config.growth.enabled,
+                login: config.login.is_some(),
+                login_required: config.login.as_ref().is_some_and(|login| login.required),
+                world_size: (CANVAS_WIDTH, CANVAS_HEIGHT),
+            },
+        },
+    ];
+    // The rules a player-created room is played by, who hosts it and where its
+    // match is; a player finding it empty hosts it
+    if let Some(room) = world.rules() {
+        messages.push(ServerMessage::RoomConfig { config: room });
+    }
+    if let Some(members) = &world.members {
+        let mut members = members.lock().unwrap();
+        if members.arrive(player_id) {
+            info!("{} hosts room {}", player_id, world.name);
+        }
+        messages.push(ServerMessage::Host { player_id: members.host().cloned() });
+    }
+    if let Some(round) = &world.round {
+        let players = world.game_state.lock().unwrap().players.len();
+        messages.push(round.lock().unwrap().message(players, &config.rooms));
+    }
+    // The state is encoded before the palette and roster are read, so they
+    // cover every number it uses, and sent after them
+    let game_state = world.game_state.lock().unwrap().clone();
+    let wire = game_state.wire.clone();
+    let state = protocol::encode(&ServerMessage::State { game_state }, *codec)?;
+    messages.push(ServerMessage::Palette { colors: wire.palette() });
+    let joined = {
+        let state = world.game_state.lock().unwrap();
+        messages.push(ServerMessage::Roster {
+            players: state.players.values().map(|player| RosterEntry::of(player, &wire)).collect(),
+        });
+        state.players.get(player_id).map(|player| RosterEntry::of(player, &wire))
+    }; // Lock is released here
+    let mut frames = messages.iter().map(|message| protocol::encode(message, *codec)).collect::<Result<Vec<_>, _>>()?;
+    frames.push(state);
+    // Tell the new player about events already in progress
+    let running_events = world.events.lock().unwrap().running(Instant::now());
+    for event in running_events {
+        frames.push(protocol::encode(&ServerMessage::WorldEvent { event }, *codec)?);
+    }
+    if let Some(announcement) = admin::running_countdown(world) {
+        frames.push(protocol::encode(&announcement, *codec)?);
+    }
+    for frame in frames {
+        let frame = compression.apply(frame);
+        traffic.record_sent(frame.len());
+        tx.send(frame).await?;
+    }
+
+    // Tell everyone else; from now on snapshots may call the player by number
+    if let Some(player) = joined {
+        broadcast(&world.clients, &ServerMessage::PlayerJoined { player });
+        wire.announce(player_id);
+    }
+    Ok(())
+}
+
+// Why `player_id` can't use a host's privileges here, if they can't
+fn host_refusal(world: &World, player_id: &str) -> Option<String> {
+    match &world.members {
+        None => Some("Only rooms players created have a host".to_string()),
+        Some(members) if !members.lock().unwrap().is_host(player_id) => Some("Only the room's host can do that".to_string()),
+        Some(_) => None,
+    }
+}
+
+// Close the connection of `kicked` for the room's host. Returns why not if it can't.
+fn kick(world: &World, player_id: &str, kicked: &str) -> Result<(), String> {
+    if let Some(reason) = host_refusal(world, player_id) {
+        return Err(reason);
+    }
+    if kicked == player_id {
+        return Err("You can't kick yourself, leave instead".to_string());
+    }
+    let clients = world.clients.lock().unwrap();
+    let Some(client) = clients.values().find(|client| client.player_id == kicked) else {
+        return Err("That player isn't in this room".to_string());
+    };
+    info!("{} kicked {} from room {}", player_id, kicked, world.name);
+    let _ = client.sender.send(policy_close("Kicked by the room's host"));
+    Ok(())
+}
+
+// Change a room's rules for its host and tell everyone in it; the speed also
+// changes how clients predict. Returns why not if it can't.
+fn change_room(world: &World, player_id: &str, settings: RoomSettings, config: &Config) -> Result<(), String> {
+    if let Some(reason) = host_refusal(world, player_id) {
+        return Err(reason);
+    }
+    if world.round.as_ref().is_some_and(|round| !round.lock().unwrap().is_waiting()) {
+        return Err("The rules can't change once the match is starting".to_string());
+    }
+    let rules = world.rules().expect("rooms have rules");
+    let changed = rules.change(settings, &config.rooms, config)?;
+    info!("{} changed the rules of room {}: {:?}", player_id, world.name, changed);
+    let speed_changed = changed.speed != rules.speed;
+    world.set_rules(changed.clone());
+    broadcast(&world.clients, &ServerMessage::RoomConfig { config: changed });
+    if speed_changed {
+        let movement = movement(world, config);
+        broadcast(&world.clients, &ServerMessage::TimeScale { time_scale: world.stepper.time_scale(), movement });
+    }
+    Ok(())
+}
+
+// Take the player out of the world, keep its session to resume and record it for
+// the leaderboard, then give what's still queued (like a close frame) a moment to go out
+async fn leave(seat: &Seat, link: Link, shared: &Shared) {
+    let Shared { config, store, sessions, .. } = shared;
+    let Seat { addr, world, player_id, .. } = seat;
+
+    world.inputs.lock().unwrap().remove(player_id);
+    let left_player = {
+        let mut state = world.game_state.lock().unwrap();
+        state.wire.release(player_id);
+        state.players.remove(player_id)
+    }; // Lock is released here
+    world.bus.publish(bus::Event::PlayerLeft { player_id: player_id.clone() });
+    world.clients.lock().unwrap().remove(addr);
+
+    // Broadcast updated game state
+    if left_player.is_some() {
+        broadcast(&world.clients, &ServerMessage::PlayerLeft { player_id: player_id.clone() });
+    }
+    // The room's host hands over to whoever has been in it the longest
+    let host = world.members.as_ref().and_then(|members| {
+        let mut members = members.lock().unwrap();
+        members.depart(player_id).then(|| members.host().cloned())
+    });
+    if let Some(host) = host {
+        if let Some(host) = &host {
+            info!("{} left room {}, {} hosts it now", player_id, world.name, host);
+            world.bus.publish(bus::Event::HostChanged { player_id: host.clone() });
+        }
+        broadcast(&world.clients, &ServerMessage::Host { player_id: host });
+    }
+    if let Err(e) = broadcast_game_state(&world.clients, &world.game_state, config.get().client_max_bytes_per_sec) {
+        warn_limited!("Error broadcasting game state: {}", e);
+    }
+
+    if let Some(player) = left_player {
+        let session = SessionRecord {
+            world: world.name.clone(),
+            score: player.score.saturating_sub(seat.starting_score),
+            joined_at: seat.joined_at,
+            left_at: storage::unix_time(),
+        };
+        let position = LastPosition {
+            world: world.name.clone(),
+            x: player.x,
+            y: player.y,
+            score: player.score,
+        };
+        sessions.leave(&seat.session_token, &world.name, player, config.get().session_resume());
+        store.record_session(player_id, session, position, &seat.session_token).await;
+    }
+
+    // Stop pinging, then let the forward task flush
+    let Link { sender, ping_task, forward_task, .. } = link;
+    ping_task.abort();
+    drop(sender);
+    if let Some(mut forward_task) = forward_task {
+        if timeout(FLUSH_TIMEOUT, &mut forward_task).await.is_err() {
+            forward_task.abort();
+        }
+    }
+}
+
+// Perform the WebSocket handshake with `peer`, who may be a proxy. Returns the
+// stream together with the real client IP, the world named in the URL path
+// and the codec picked from the client's subprotocols, if any.
+async fn accept_client(
+    stream: TcpStream,
+    peer: IpAddr,
+    config: &Config,
+) -> Result<(WebSocketStream<TcpStream>, IpAddr, Option<String>, Option<&'static dyn Codec>), Box<dyn std::error::Error>> {
+    // Accept WebSocket connection, keeping the X-Forwarded-For header if present
+    let mut forwarded_for = None;
+    let mut path_world = None;
+    let mut codec = None;
+    #[allow(clippy::result_large_err)]
+    let ws_stream = accept_hdr_async_with_config(stream, |request: &Request, mut response: Response| {
+        let path = request.uri().path().trim_matches('/');
+        if !path.is_empty() {
+            path_world = Some(path.to_string());
+        }
+        forwarded_for = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        codec = request
+            .headers()
+            .get("sec-websocket-protocol")
+            .and_then(|value| value.to_str().ok())
+            .and_then(protocol::negotiate);
+        if let Some(codec) = codec {
+            response.headers_mut().insert("sec-websocket-protocol", codec.name().parse().expect("codec names are valid headers"));
+        }
+        Ok(response)
+    }, Some(config.websocket_config()))
+    .await?;
+    let client_ip = config.proxy.client_ip(peer, forwarded_for.as_deref());
+    Ok((ws_stream, client_ip, path_world, codec))
+}
+
+// Close the connection `player_id` is playing on, and once it's gone, resume
+// its session for a new connection. None if it's still there after a while.
+async fn take_over(worlds: &Worlds, sessions: &Sessions, token: &str, player_id: &str, world: &str) -> Option<Resumed> {
+    for world in worlds.iter() {
+        for client in world.clients.lock().unwrap().values().filter(|client| client.player_id == player_id) {
+            let _ = client.sender.send(policy_close("You joined from somewhere else"));
+            client.replaced.notify_one();
+        }
+    }
+    for _ in 0..TAKE_OVER_POLLS {
+        match sessions.resume(token, world) {
+            Resumed::Connected(_) => tokio::time::sleep(TAKE_OVER_POLL_INTERVAL).await,
+            resumed => return Some(resumed),
+        }
+    }
+    None
+}
+
+// Wait for a Join, returning it along with the codec to talk to the client
+// with: the negotiated one, else the first one it listed that we support, else
+// the one its Join came in. Rooms are listed and opened on the way, and pings
+// answered; what only means something in a world is ignored, anything else
+// ends the wait with None.
+async fn wait_for_join(
+    rx: &mut Stream,
+    tx: &mut Sink,
+    negotiated: Option<&'static dyn Codec>,
+    shared: &Shared,
+    max_message_bytes: usize,
+) -> Result<Option<(JoinRequest, &'static dyn Codec)>, Box<dyn std::error::Error>> {
+    while let Some(msg) = rx.next().await {
+        match msg? {
+            msg @ (Message::Text(_) | Message::Binary(_)) if msg.len() <= max_message_bytes => {
+                let Some(frame) = protocol::frame(msg) else {
+                    return Ok(None);
+                };
+                let detected = negotiated.unwrap_or_else(|| protocol::detect(&frame));
+                let answer = match protocol::decode::<ClientMessage>(frame, detected) {
+                    Ok(ClientMessage::Join(join)) => {
+                        let listed = protocol::choose(join.codecs.iter().map(String::as_str));
+                        let codec = negotiated.or(listed).unwrap_or(detected);
+                        return Ok(Some((join, codec)));
+                    }
+                    // Invite codes are the server's to hand out
+                    Ok(ClientMessage::CreateRoom(room)) => match rooms::open(shared, RoomConfig { code: None, ..room }, None) {
+                        Ok(config) => ServerMessage::RoomConfig { config },
+                        Err(reason) => ServerMessage::RoomRefused { reason },
+                    },
+                    Ok(ClientMessage::ListRooms) => rooms::list(shared),
+                    Ok(ClientMessage::Ping { sent_at }) => ServerMessage::Pong { sent_at },
+                    Ok(
+                        ClientMessage::Move { .. }
+                        | ClientMessage::Input { .. }
+                        | ClientMessage::Hash { .. }
+                        | ClientMessage::Afk { .. }
+                        | ClientMessage::Ready { .. }
+                        | ClientMessage::Kick { .. }
+                        | ClientMessage::StartMatch
+                        | ClientMessage::ChangeRoom(_),
+                    ) => continue,
+                    _ => return Ok(None),
+                };
+                tx.send(protocol::encode(&answer, detected)?).await?;
+            }
+            Message::Ping(_) | Message::Pong(_) => continue,
+            _ => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+// Sleep until `deadline`, or forever without one
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GameMode, Netcode};
+    use crate::map::Map;
+    use crate::scripting::ScriptHost;
+    use crate::world;
+
+    const EVENTS: [Event; 6] = [Event::Accept, Event::Join, Event::Spawn, Event::Leave, Event::Reject, Event::Finish];
+
+    fn lifecycle() -> Lifecycle {
+        Lifecycle::new("127.0.0.1:9000".parse().unwrap())
+    }
+
+    #[test]
+    fn plays_through_to_closed() {
+        let mut lifecycle = lifecycle();
+        for (event, state) in [
+            (Event::Accept, State::Browsing),
+            (Event::Join, State::Joined),
+            (Event::Spawn, State::Playing),
+            (Event::Leave, State::Leaving),
+            (Event::Finish, State::Closed),
+        ] {
+            lifecycle.advance(event);
+            assert_eq!(lifecycle.state, state);
+        }
+    }
+
+    #[test]
+    fn rejected_before_playing() {
+        assert_eq!(State::Handshaking.next(Event::Reject), Some(State::Closed));
+        assert_eq!(State::Browsing.next(Event::Reject), Some(State::Closed));
+        assert_eq!(State::Joined.next(Event::Reject), Some(State::Closed));
+        assert_eq!(State::Playing.next(Event::Reject), None);
+    }
+
+    #[test]
+    fn closed_is_final() {
+        for event in EVENTS {
+            assert_eq!(State::Closed.next(event), None);
+        }
+    }
+
+    #[test]
+    fn only_one_way_out_of_each_state() {
+        for state in [State::Handshaking, State::Browsing, State::Joined, State::Playing, State::Leaving] {
+            let ways = EVENTS.iter().filter(|&&event| state.next(event).is_some_and(|next| next != State::Closed)).count();
+            assert!(ways <= 1, "{:?} goes on in {} ways", state, ways);
+        }
+    }
+
+    #[test]
+    fn invalid_event_keeps_the_state() {
+        let mut lifecycle = lifecycle();
+        lifecycle.advance(Event::Spawn);
+        lifecycle.advance(Event::Finish);
+        lifecycle.advance(Event::Join);
+        assert_eq!(lifecycle.state, State::Handshaking);
+        lifecycle.advance(Event::Accept);
+        lifecycle.advance(Event::Join);
+        lifecycle.advance(Event::Join);
+        assert_eq!(lifecycle.state, State::Joined);
+    }
+
+    #[test]
+    fn time_limits() {
+        let config = Config { handshake_timeout_secs: 3, lobby_timeout_secs: 60, idle_timeout_secs: 20, ..Config::default() };
+        assert_eq!(State::Handshaking.time_limit(&config), Some(Duration::from_secs(3)));
+        assert_eq!(State::Browsing.time_limit(&config), Some(Duration::from_secs(60)));
+        assert_eq!(State::Joined.time_limit(&config), Some(JOINED_TIMEOUT));
+        assert_eq!(State::Playing.time_limit(&config), Some(Duration::from_secs(20)));
+        assert_eq!(State::Leaving.time_limit(&config), Some(LEAVING_TIMEOUT));
+        assert_eq!(State::Closed.time_limit(&config), None);
+
+        let config = Config { idle_timeout_secs: 0, ..Config::default() };
+        assert_eq!(State::Playing.time_limit(&config), None);
+    }
+
+    #[test]
+    fn touch_only_restarts_the_clock_while_playing() {
+        let config = Config::default();
+        let mut lifecycle = lifecycle();
+        let long_ago = Instant::now() - Duration::from_secs(60);
+
+        lifecycle.since = long_ago;
+        lifecycle.touch();
+        assert_eq!(lifecycle.deadline(&config), Some(long_ago + config.handshake_timeout()));
+
+        lifecycle.advance(Event::Accept);
+        lifecycle.advance(Event::Join);
+        lifecycle.advance(Event::Spawn);
+        lifecycle.since = long_ago;
+        lifecycle.touch();
+        assert!(lifecycle.deadline(&config).unwrap() > Instant::now());
+    }
+
+    #[tokio::test]
+    async fn within_gives_up_at_the_deadline() {
+        let config = Config::default();
+        let mut lifecycle = lifecycle();
+        assert_eq!(lifecycle.within(&config, async { 1 }).await, Some(1));
+
+        lifecycle.since = Instant::now() - config.handshake_timeout();
+        assert_eq!(lifecycle.within(&config, std::future::pending::<()>()).await, None);
+    }
+
+    #[test]
+    fn frozen_players_ignore_moves() {
+        let world = World::new("main", GameMode::Free, Netcode::Authoritative, Map::default(), world::empty_state(), None);
+        let mut state = world::empty_state();
+        let player = Player {
+            id: "frozen".to_string(),
+            name: String::new(),
+            x: 100,
+            y: 100,
+            color: "#FF0000".to_string(),
+            shape: Default::default(),
+            size: PLAYER_SIZE,
+            eliminated: false,
+            afk: false,
+            frozen: true,
+            ghost: false,
+            bounce: (0, 0),
+            score: 0,
+        };
+        state.players.insert(player.id.clone(), player);
+
+        let scripts = ScriptHost::default();
+        move_player(&mut state, &world, &scripts, "frozen", direction_keys("d"), 5, &mut TickProfile::default());
+        assert_eq!((state.players["frozen"].x, state.players["frozen"].y), (100, 100));
+
+        state.players.get_mut("frozen").unwrap().frozen = false;
+        move_player(&mut state, &world, &scripts, "frozen", direction_keys("d"), 5, &mut TickProfile::default());
+        assert!(state.players["frozen"].x > 100);
+    }
+
+}
@@ -0,0 +1,138 @@
+use crate::game::{GameState, Player, Shape};
+
+use codec::packed::{Snapshot, SnapshotPlayer, SnapshotTrail};
+
+// Bits of a player's flags byte
+const FLAG_ELIMINATED: u8 = 1;
+const FLAG_AFK: u8 = 2;
+const FLAG_FROZEN: u8 = 4;
+const FLAG_GHOST: u8 = 8;
+
+// Game state as an rkyv archive (codec::packed) that clients read in place,
+// without parsing JSON or copying it into structs first
+pub fn pack(state: &GameState) -> Vec<u8> {
+    let players = state
+        .players
+        .values()
+        .map(|player| SnapshotPlayer {
+            id: &player.id,
+            name: &player.name,
+            color: &player.color,
+            x: player.x,
+            y: player.y,
+            size: player.size,
+            score: player.score,
+            shape: shape(player.shape),
+            flags: flags(player),
+        })
+        .collect();
+    let food = state.food.iter().map(|food| (food.x, food.y)).collect();
+    let trails = state.trails.iter().map(|(owner, points)| SnapshotTrail { owner, points }).collect();
+    codec::packed::write(&Snapshot { players, food, trails })
+}
+
+fn shape(shape: Shape) -> u8 {
+    match shape {
+        Shape::Square => 0,
+        Shape::Circle => 1,
+        Shape::Triangle => 2,
+    }
+}
+
+// A player's flags byte; the quantized layout only has room for four bits
+fn flags(player: &Player) -> u8 {
+    [
+        (player.eliminated, FLAG_ELIMINATED),
+        (player.afk, FLAG_AFK),
+        (player.frozen, FLAG_FROZEN),
+        (player.ghost, FLAG_GHOST),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |flags, (_, flag)| flags | flag)
+}
+
+// First bytes of a quantized snapshot
+const QUANTIZED_MAGIC: u8 = b'Q';
+const QUANTIZED_VERSION: u8 = 2;
+
+// Game state packed as small as it goes, for worlds with many players.
+// Positions are u16 fractions of the world's size (lossless for whole pixels,
+// since the world is far narrower than 65536), counts and numbers are LEB128
+// varints, strings a varint length and UTF-8. Players and colors are referred
+// to by the numbers in the state's WireIds: the number plus one, or 0 and the
+// string for ones the clients weren't told about yet.
+//
+//   magic u8, version u8
+//   players: count, then player (0 is followed by id and name), x u16, y u16,
+//            size varint, score varint, shape and flags u8 (shape in bits 0-1,
+//            flags in bits 4-7), color
+//   food: count, then x u16, y u16
+//   trails: count, then owner, point count, then x u16, y u16 per point
+pub fn quantize(state: &GameState) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(16 + state.players.len() * 16 + state.food.len() * 4);
+    buffer.extend([QUANTIZED_MAGIC, QUANTIZED_VERSION]);
+    let wire = state.wire.lock();
+
+    put_varint(&mut buffer, state.players.len() as u32);
+    for player in state.players.values() {
+        if !put_reference(&mut buffer, wire.player(&player.id), &player.id) {
+            put_short_str(&mut buffer, &player.name);
+        }
+        put_position(&mut buffer, player.x, player.y);
+        put_varint(&mut buffer, player.size);
+        put_varint(&mut buffer, player.score);
+        buffer.push(shape(player.shape) | flags(player) << 4);
+        put_reference(&mut buffer, wire.color(&player.color), &player.color);
+    }
+
+    put_varint(&mut buffer, state.food.len() as u32);
+    for food in &state.food {
+        put_position(&mut buffer, food.x, food.y);
+    }
+
+    put_varint(&mut buffer, state.trails.len() as u32);
+    for (owner, points) in &state.trails {
+        put_reference(&mut buffer, wire.player(owner), owner);
+        put_varint(&mut buffer, points.len() as u32);
+        for &(x, y) in points {
+            put_position(&mut buffer, x, y);
+        }
+    }
+    buffer
+}
+
+// A number plus one, or 0 and the string; true if it was the number
+fn put_reference(buffer: &mut Vec<u8>, number: Option<u32>, text: &str) -> bool {
+    match number {
+        Some(number) => put_varint(buffer, number + 1),
+        None => {
+            buffer.push(0);
+            put_short_str(buffer, text);
+        }
+    }
+    number.is_some()
+}
+
+// A coordinate as a u16 fraction of `extent`, rounded to the nearest step
+fn quantize_coordinate(value: u32, extent: u32) -> u16 {
+    ((value.min(extent) as u64 * u16::MAX as u64 + extent as u64 / 2) / extent as u64) as u16
+}
+
+fn put_position(buffer: &mut Vec<u8>, x: u32, y: u32) {
+    buffer.extend(quantize_coordinate(x, sim::WORLD_WIDTH).to_le_bytes());
+    buffer.extend(quantize_coordinate(y, sim::WORLD_HEIGHT).to_le_bytes());
+}
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn put_short_str(buffer: &mut Vec<u8>, text: &str) {
+    put_varint(buffer, text.len() as u32);
+    buffer.extend(text.as_bytes());
+}
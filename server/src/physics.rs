@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use sim::collision;
+
+use crate::game::{clamp_to_world, Player};
+
+// Push overlapping players apart along the axis they overlap least on. The
+// smaller player gives way more, in proportion to the other's size. Ghosts
+// aren't pushed or pushing, and frozen players stay put. Returns whether
+// anyone moved.
+pub fn separate(players: &mut HashMap<String, Player>) -> bool {
+    let mut ids: Vec<String> = players.keys().cloned().collect();
+    ids.sort();
+    let mut moved = false;
+    for (i, a_id) in ids.iter().enumerate() {
+        for b_id in &ids[i + 1..] {
+            let (Some(a), Some(b)) = (players.get(a_id), players.get(b_id)) else {
+                continue;
+            };
+            if a.ghost || b.ghost {
+                continue;
+            }
+            let Some((dx_a, dy_a, dx_b, dy_b)) = collision::push_apart(&a.body(), &b.body()) else {
+                continue;
+            };
+            for (id, dx, dy) in [(a_id, dx_a, dy_a), (b_id, dx_b, dy_b)] {
+                if let Some(player) = players.get_mut(id).filter(|player| !player.frozen) {
+                    player.x = (player.x as i64 + dx).max(0) as u32;
+                    player.y = (player.y as i64 + dy).max(0) as u32;
+                    clamp_to_world(player);
+                    moved = true;
+                }
+            }
+        }
+    }
+    moved
+}
@@ -0,0 +1,21 @@
+use sha1::{Digest, Sha1};
+
+// A lightweight proof of work for joining: the client has to find a number
+// that, written out after the server's challenge, SHA-1 hashes to at least
+// `bits` leading zero bits. One costs a browser a moment and the server a
+// single hash, but a script filling the player cap has to pay it for every seat.
+pub fn verify(challenge: &str, proof: u64, bits: u32) -> bool {
+    zero_bits(challenge, proof) >= bits
+}
+
+fn zero_bits(challenge: &str, proof: u64) -> u32 {
+    let digest = Sha1::new().chain_update(challenge).chain_update(proof.to_string()).finalize();
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+// Ticks the breakdown covers, the latest ones
+const WINDOW_TICKS: usize = 600;
+
+// The parts of a world's tick that are timed
+#[derive(Clone, Copy)]
+pub enum Stage {
+    // Applying held keys and portals
+    Input,
+    // Knockback, eating and growing, trails
+    Physics,
+    // Finding who touches whom, the rules' collision hook and pushing players apart
+    Collision,
+    // The rules' tick hook
+    Rules,
+    // Copying and serializing what gets sent
+    Encode,
+    // Queuing it for each client
+    Broadcast,
+}
+
+const STAGES: [Stage; 6] = [Stage::Input, Stage::Physics, Stage::Collision, Stage::Rules, Stage::Encode, Stage::Broadcast];
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::Physics => "physics",
+            Stage::Collision => "collision",
+            Stage::Rules => "rules",
+            Stage::Encode => "encode",
+            Stage::Broadcast => "broadcast",
+        }
+    }
+}
+
+// Where one tick's time went
+#[derive(Clone, Default)]
+pub struct TickProfile {
+    spent: [Duration; STAGES.len()],
+    // The whole tick, including what isn't in a stage
+    total: Duration,
+}
+
+impl TickProfile {
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        self.spent[stage as usize] += duration;
+    }
+
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.add(stage, started.elapsed());
+        result
+    }
+
+    // Time sending something out: `f` returns how long of it went into
+    // encoding, the rest counts as broadcasting
+    pub fn send(&mut self, f: impl FnOnce() -> Duration) {
+        let started = Instant::now();
+        let encoding = f();
+        let total = started.elapsed();
+        self.add(Stage::Encode, encoding);
+        self.add(Stage::Broadcast, total.saturating_sub(encoding));
+    }
+
+    pub fn finish(&mut self, total: Duration) {
+        self.total = total;
+    }
+}
+
+// The profiles of a world's latest ticks
+#[derive(Default)]
+pub struct Profiler {
+    ticks: Mutex<VecDeque<TickProfile>>,
+}
+
+// Timings of one stage over the window, in microseconds
+#[derive(Serialize)]
+pub struct StageReport {
+    stage: &'static str,
+    mean_us: u64,
+    p99_us: u64,
+    max_us: u64,
+    // Of all the time ticks took
+    share: f64,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    ticks: usize,
+    tick: StageReport,
+    stages: Vec<StageReport>,
+}
+
+impl Profiler {
+    pub fn record(&self, profile: TickProfile) {
+        let mut ticks = self.ticks.lock().unwrap();
+        if ticks.len() >= WINDOW_TICKS {
+            ticks.pop_front();
+        }
+        ticks.push_back(profile);
+    }
+
+    // Each stage's timings, plus "other" for the time ticks spent outside
+    // them (world events, stats, achievements)
+    pub fn report(&self) -> Report {
+        let ticks = self.ticks.lock().unwrap();
+        let totals: Vec<Duration> = ticks.iter().map(|tick| tick.total).collect();
+        let all: Duration = totals.iter().sum();
+        let mut stages: Vec<StageReport> = STAGES
+            .iter()
+            .map(|&stage| summarize(stage.name(), ticks.iter().map(|tick| tick.spent[stage as usize]).collect(), all))
+            .collect();
+        let other = ticks.iter().map(|tick| tick.total.saturating_sub(tick.spent.iter().sum())).collect();
+        stages.push(summarize("other", other, all));
+        Report {
+            ticks: ticks.len(),
+            tick: summarize("tick", totals, all),
+            stages,
+        }
+    }
+}
+
+fn summarize(stage: &'static str, mut durations: Vec<Duration>, all: Duration) -> StageReport {
+    durations.sort();
+    let sum: Duration = durations.iter().sum();
+    let micros = |duration: Duration| duration.as_micros() as u64;
+    let p99 = durations.len().saturating_sub(1) * 99 / 100;
+    StageReport {
+        stage,
+        mean_us: micros(sum) / durations.len().max(1) as u64,
+        p99_us: durations.get(p99).copied().map_or(0, micros),
+        max_us: durations.last().copied().map_or(0, micros),
+        share: if all.is_zero() { 0.0 } else { sum.as_secs_f64() / all.as_secs_f64() },
+    }
+}
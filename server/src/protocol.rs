@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::packed;
+
+mod messages;
+
+pub use codec::{choose, negotiate, BareJson, Codec, CodecError, EnvelopeJson, Frame, Snapshots};
+pub use messages::{ClientMessage, Features, JoinRequest, RosterEntry, ServerMessage};
+
+// The content of a WebSocket data frame; None for control frames
+pub fn frame(message: Message) -> Option<Frame> {
+    match message {
+        Message::Text(text) => Some(Frame::Text(text)),
+        Message::Binary(data) => Some(Frame::Binary(data)),
+        _ => None,
+    }
+}
+
+fn to_message(frame: Frame) -> Message {
+    match frame {
+        Frame::Text(text) => Message::Text(text),
+        Frame::Binary(data) => Message::Binary(data),
+    }
+}
+
+// The codec for a client that didn't ask for one: whatever its first message looks like
+pub fn detect(frame: &Frame) -> &'static dyn Codec {
+    match frame {
+        Frame::Text(text) if serde_json::from_str::<Map<String, Value>>(text).is_ok_and(|message| message.contains_key("version")) => {
+            &EnvelopeJson
+        }
+        _ => &BareJson,
+    }
+}
+
+pub fn encode(message: &ServerMessage, codec: &dyn Codec) -> Result<Message, CodecError> {
+    if let ServerMessage::State { game_state } = message {
+        match codec.snapshots() {
+            Snapshots::Packed => return Ok(Message::Binary(packed::pack(game_state))),
+            Snapshots::Quantized => return Ok(Message::Binary(packed::quantize(game_state))),
+            Snapshots::Messages => {}
+        }
+    }
+    Ok(to_message(codec::encode(message, codec)?))
+}
+
+pub fn decode<T: DeserializeOwned>(frame: Frame, codec: &dyn Codec) -> Result<T, CodecError> {
+    codec::decode(frame, codec)
+}
+
+// Frames smaller than this aren't worth compressing
+const COMPRESS_MIN_BYTES: usize = 256;
+// miniz_oxide's fastest level, since every client's frames are compressed on their own
+const DEFLATE_LEVEL: u8 = 1;
+
+// Compression for the frames sent to one client, picked from the ones it
+// listed in its Join. A compressed frame is a binary frame of b'Z', then b't'
+// or b'b' for whether the original was text or binary, then the original as
+// raw DEFLATE.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Deflate => "deflate",
+        }
+    }
+
+    // The first of the requested ones we support, else none
+    pub fn choose<'a>(requested: impl IntoIterator<Item = &'a str>) -> Compression {
+        requested
+            .into_iter()
+            .find_map(|name| [Compression::Deflate].into_iter().find(|compression| compression.name() == name))
+            .unwrap_or(Compression::None)
+    }
+
+    pub fn apply(self, message: Message) -> Message {
+        let (kind, data) = match &message {
+            _ if self == Compression::None || message.len() < COMPRESS_MIN_BYTES => return message,
+            Message::Text(text) => (b't', text.as_bytes()),
+            Message::Binary(data) => (b'b', data.as_slice()),
+            _ => return message,
+        };
+        let mut frame = vec![b'Z', kind];
+        frame.extend(miniz_oxide::deflate::compress_to_vec(data, DEFLATE_LEVEL));
+        if frame.len() >= message.len() {
+            return message;
+        }
+        Message::Binary(frame)
+    }
+}
+
+// A message encoded on demand for each codec in use, so a broadcast encodes it once per codec
+pub struct Encoded<'a> {
+    message: &'a ServerMessage,
+    frames: Vec<(&'static str, Message)>,
+    // Time spent encoding so far, for the tick profiler
+    encoding: Duration,
+}
+
+impl<'a> Encoded<'a> {
+    pub fn new(message: &'a ServerMessage) -> Encoded<'a> {
+        Encoded {
+            message,
+            frames: Vec::new(),
+            encoding: Duration::ZERO,
+        }
+    }
+
+    pub fn get(&mut self, codec: &dyn Codec) -> Result<Message, CodecError> {
+        if let Some((_, frame)) = self.frames.iter().find(|(name, _)| *name == codec.name()) {
+            return Ok(frame.clone());
+        }
+        let started = Instant::now();
+        let frame = encode(self.message, codec)?;
+        self.encoding += started.elapsed();
+        self.frames.push((codec.name(), frame.clone()));
+        Ok(frame)
+    }
+
+    pub fn encoding(&self) -> Duration {
+        self.encoding
+    }
+}
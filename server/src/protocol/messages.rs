@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sim::Movement;
+
+use crate::achievements::Achievement;
+use crate::config::{GameMode, Netcode};
+use crate::cosmetics::Cosmetic;
+use crate::events::WorldEvent;
+use crate::game::{GameEvent, GameState, Player, Shape};
+use crate::map::Map;
+use crate::matches::Phase;
+use crate::rooms::{RoomConfig, RoomEntry, RoomSettings};
+use crate::stats::PlayerStats;
+use crate::wire::WireIds;
+
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+    // A single step in one direction: "w", "a", "s" or "d"
+    Move { direction: String },
+    // The keys currently held down (KEY_* bits), sent at a fixed rate. `seq`
+    // increases with every message so a late one can't override a newer one.
+    Input { seq: u32, keys: u8 },
+    Join(JoinRequest),
+    // Answered with ServerMessage::Pong so the client can show its ping
+    Ping { sent_at: f64 },
+    // The player is leaving; the server closes the connection
+    Leave,
+    // A chat message for everyone in the world
+    Chat { text: String },
+    // A private message for the player with ID `to`, in any world
+    Whisper { to: String, text: String },
+    // What the client's state hashed to after `tick`, in lockstep worlds
+    Hash { tick: u64, hash: u64 },
+    // The player's window lost focus or was hidden (true), or is back (false)
+    Afk { afk: bool },
+    // Before joining: open a room with these rules, to join with Join after.
+    // Answered with ServerMessage::RoomConfig, or RoomRefused.
+    CreateRoom(RoomConfig),
+    // Before joining: which worlds there are. Answered with ServerMessage::Rooms.
+    ListRooms,
+    // In a room, before its match: whether the player is ready to start
+    Ready { ready: bool },
+    // For a room's host: close the connection of the player with ID `player_id`
+    Kick { player_id: String },
+    // For a room's host: count down to the match whoever is ready
+    StartMatch,
+    // For a room's host, before its match: change the rules. Everyone in the
+    // room gets the new ServerMessage::RoomConfig.
+    ChangeRoom(RoomSettings),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JoinRequest {
+    // World to join; overrides the URL path
+    pub world: Option<String>,
+    // Invite code of a private room, which picks the world instead
+    pub invite: Option<String>,
+    // Display name and "#rrggbb" color; invalid ones are ignored
+    pub name: Option<String>,
+    pub color: Option<String>,
+    // "square", "circle" or "triangle"; anything else is ignored
+    pub shape: Option<String>,
+    // Token from an earlier ServerMessage::Session, to pick up where that player left off
+    pub session: Option<String>,
+    // Answer to ServerMessage::Challenge
+    pub proof: Option<u64>,
+    // Codecs the client reads, preferred first, for clients that can't ask in
+    // the WebSocket handshake
+    pub codecs: Vec<String>,
+    // Compression the client reads, preferred first; "deflate" is the only one
+    pub compression: Vec<String>,
+}
+
+// Longest display name, in characters
+const MAX_NAME_CHARS: usize = 16;
+
+impl JoinRequest {
+    pub fn name(&self) -> Option<String> {
+        let name = self.name.as_deref()?.trim();
+        let valid = !name.is_empty() && name.chars().count() <= MAX_NAME_CHARS && !name.chars().any(char::is_control);
+        valid.then(|| name.to_string())
+    }
+
+    pub fn color(&self) -> Option<String> {
+        let color = self.color.as_deref()?;
+        let valid = color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        valid.then(|| color.to_string())
+    }
+
+    pub fn shape(&self) -> Option<Shape> {
+        match self.shape.as_deref()? {
+            "square" => Some(Shape::Square),
+            "circle" => Some(Shape::Circle),
+            "triangle" => Some(Shape::Triangle),
+            _ => None,
+        }
+    }
+}
+
+// A connected player as listed in the roster
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RosterEntry {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    // What quantized snapshots call the player by
+    pub number: u32,
+}
+
+impl RosterEntry {
+    pub fn of(player: &Player, wire: &WireIds) -> RosterEntry {
+        RosterEntry {
+            id: player.id.clone(),
+            name: player.name.clone(),
+            color: player.color.clone(),
+            number: wire.number(&player.id).unwrap_or_default(),
+        }
+    }
+}
+
+// What this server and world have on, so the client shows what goes with it
+#[derive(Serialize, Deserialize)]
+pub struct Features {
+    pub chat: bool,
+    // Game state comes in binary frames rather than JSON
+    pub binary_snapshots: bool,
+    // Bigger messages come deflated
+    pub compression: bool,
+    pub mode: GameMode,
+    pub netcode: Netcode,
+    // Food to eat and grow with
+    pub growth: bool,
+    // Logging in with the OAuth provider, and whether players have to
+    pub login: bool,
+    pub login_required: bool,
+    pub world_size: (u32, u32),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ServerMessage {
+    State { game_state: GameState },
+    Map { map: Map },
+    // A player went through a portal; clients show an effect at both ends
+    Teleported { player_id: String, from: (u32, u32), to: (u32, u32) },
+    // A timed event started or ended
+    WorldEvent { event: WorldEvent },
+    // Sent after joining; the client keeps the token to resume as this player later.
+    // `unlocks` are the cosmetics the player may pick, `movement` what the
+    // client needs to predict its own player. `codec` and `compression` are what
+    // the server talks to it with from now on, `features` what's on.
+    Session {
+        player_id: String,
+        token: String,
+        unlocks: Vec<Cosmetic>,
+        movement: Movement,
+        codec: String,
+        compression: String,
+        features: Features,
+    },
+    // The world's time now runs `time_scale` times as fast as real time, and
+    // ticks come at the pace in `movement`
+    TimeScale { time_scale: f64, movement: Movement },
+    // Reply to a Join when the server wants a proof of work: the client joins
+    // again with a `proof` that hashes to `bits` leading zero bits after `challenge`
+    Challenge { challenge: String, bits: u32 },
+    // Everyone in the world, sent after joining; kept up to date with the next three
+    Roster { players: Vec<RosterEntry> },
+    PlayerJoined { player: RosterEntry },
+    PlayerLeft { player_id: String },
+    // Colors quantized snapshots call by their index from now on, sent before
+    // the first snapshot that does; a joining player gets all of them
+    Palette { colors: Vec<(u32, String)> },
+    // Round trip times of the players in the world, in milliseconds
+    Pings { pings: HashMap<String, u32> },
+    // Chat from the player with ID `from`; `name` is their display name
+    Chat { from: String, name: String, text: String },
+    // A private message, sent to both ends
+    Whisper { from: String, name: String, to: String, text: String },
+    // Something only this player needs to know, like a whisper that couldn't be delivered
+    Notice { text: String },
+    // Reply to ClientMessage::Ping
+    Pong { sent_at: f64 },
+    // Something happened in the game, for the clients' event feeds
+    GameEvent { event: GameEvent },
+    // A trail mode round ended, won by the last player standing (if any), with
+    // the stats of everyone in the world so far
+    RoundOver { winner: Option<String>, stats: HashMap<String, PlayerStats> },
+    // A player earned an achievement for the first time
+    Achievement { player_id: String, achievement: Achievement },
+    // A message from the server admin, shown to everyone; with remaining_ms,
+    // counting down to something like a restart
+    Announcement {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        remaining_ms: Option<u64>,
+    },
+    // Lockstep worlds: in tick `tick`, the players in `inputs` took a step with
+    // the keys they held. Clients simulate it themselves at `speed`.
+    Tick { tick: u64, speed: u32, inputs: HashMap<String, u8> },
+    // The client did something it shouldn't; repeating it gets the connection closed
+    Warning { text: String },
+    // This server is handing its players over to another one: connect to `url`
+    // and join with the same session token to carry on where you were
+    Reconnect { url: String },
+    // The rules of a room a player created, with the server's defaults filled
+    // in: to its creator once it's open, and to everyone joining it
+    RoomConfig { config: RoomConfig },
+    // A room couldn't be opened, and why
+    RoomRefused { reason: String },
+    // The worlds a client can join, and whether it may create a room
+    Rooms { rooms: Vec<RoomEntry>, can_create: bool },
+    // A room's match: its phase, how long the countdown has left, the players
+    // who are ready and how many have to be for it to start
+    Match { phase: Phase, remaining_ms: Option<u64>, ready: Vec<String>, needed: usize },
+    // Who a room's host is: to players joining it, and to everyone when the
+    // host left and the player in the room the longest took over
+    Host { player_id: Option<String> },
+}
@@ -0,0 +1,109 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+// PROXY protocol v2 signature
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+// Longest possible v1 header line, including the trailing CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+fn invalid(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid PROXY header: {}", reason))
+}
+
+// Read a PROXY protocol header (v1 or v2) from the start of the stream.
+// Returns the original client address, or None if the proxy didn't know it
+// (v1 UNKNOWN, v2 LOCAL, or a non-IP address family).
+pub async fn read_proxy_header(stream: &mut TcpStream) -> Result<Option<SocketAddr>, Error> {
+    // Both versions are at least as long as the v2 signature
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        return read_v2(stream).await;
+    }
+    if !start.starts_with(b"PROXY ") {
+        return Err(invalid("missing signature"));
+    }
+
+    // Read the rest of the v1 line byte by byte so we don't consume the HTTP request
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("line too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("not UTF-8"))?;
+    parse_v1(line)
+}
+
+fn parse_v1(line: &str) -> Result<Option<SocketAddr>, Error> {
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source.parse().map_err(|_| invalid("bad source address"))?;
+            let port: u16 = source_port.parse().map_err(|_| invalid("bad source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed v1 line")),
+    }
+}
+
+async fn read_v2(stream: &mut TcpStream) -> Result<Option<SocketAddr>, Error> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    // Always consume the whole header, including any TLVs we don't use
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    // LOCAL command: health checks from the proxy itself
+    if version_command & 0x0F == 0 {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        // AF_INET
+        1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let port = u16::from_be_bytes([body[8], body[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6
+        2 if body.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            let port = u16::from_be_bytes([body[32], body[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)))
+        }
+        1 | 2 => Err(invalid("address block too short")),
+        // AF_UNSPEC or AF_UNIX
+        _ => Ok(None),
+    }
+}
+
+// Find the client address in an X-Forwarded-For header. Each proxy appends
+// the address it received the request from, so walk from the right and take
+// the first hop that isn't one of our own trusted proxies. Anything left of
+// that was written by the client, so a hop that doesn't parse ends the walk,
+// and a header naming only trusted proxies tells us nothing.
+pub fn resolve_forwarded_for(header: &str, trusted: &[IpAddr]) -> Option<IpAddr> {
+    for hop in header.split(',').rev() {
+        let ip: IpAddr = hop.trim().parse().ok()?;
+        if !trusted.contains(&ip) {
+            return Some(ip);
+        }
+    }
+    None
+}
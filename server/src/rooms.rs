@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::config::{Config, GameMode, Netcode, RoomsConfig, SharedConfig};
+use crate::map::Map;
+use crate::net::Shared;
+use crate::world::{empty_state, World, Worlds};
+use crate::broadcast::broadcast_pings;
+use crate::game::{systems, GameState};
+use crate::protocol::ServerMessage;
+use crate::session;
+
+// Longest room name, in characters
+const MAX_NAME_CHARS: usize = 24;
+
+// Invite codes are this many of these, which can't be mistaken for each other
+const INVITE_CHARS: usize = 5;
+const INVITE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+// How often rooms are checked for having emptied
+const CLOSE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// The rules of a room, from the player creating it. What's left out is the
+// server's default; the copy sent back, and to everyone joining, has it
+// filled in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomConfig {
+    // What it's joined by, like a configured world
+    pub name: String,
+    // Pixels per tick
+    #[serde(default)]
+    pub speed: Option<u32>,
+    // One of the server's [rooms] maps, by name
+    #[serde(default)]
+    pub map: Option<String>,
+    #[serde(default)]
+    pub max_players: Option<usize>,
+    #[serde(default)]
+    pub mode: GameMode,
+    // Whether players can take each other out; with it off the rules never
+    // hear of collisions, and only your own trail eliminates you
+    #[serde(default = "friendly_fire_default")]
+    pub friendly_fire: bool,
+    // Left out of the room list and only joined with its invite code
+    #[serde(default)]
+    pub private: bool,
+    // A private room's invite code, handed out by the server
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+// A world as the room list shows it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoomEntry {
+    pub name: String,
+    pub mode: GameMode,
+    pub players: usize,
+    pub max_players: usize,
+    // Opened by a player, rather than configured
+    pub created: bool,
+}
+
+fn friendly_fire_default() -> bool {
+    true
+}
+
+// What a room's host may change once it's open; what's left out stays as it is
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomSettings {
+    pub speed: Option<u32>,
+    pub max_players: Option<usize>,
+    pub friendly_fire: Option<bool>,
+}
+
+// The players in a room, longest connected first. The first one is its host,
+// who may kick players, start the match and change the rules.
+#[derive(Default)]
+pub struct Members(Vec<String>);
+
+impl Members {
+    pub fn host(&self) -> Option<&String> {
+        self.0.first()
+    }
+
+    pub fn is_host(&self, player_id: &str) -> bool {
+        self.host().is_some_and(|host| host == player_id)
+    }
+
+    // Returns whether they're the host, having found the room empty
+    pub fn arrive(&mut self, player_id: &str) -> bool {
+        if !self.0.iter().any(|member| member == player_id) {
+            self.0.push(player_id.to_string());
+        }
+        self.is_host(player_id)
+    }
+
+    // Returns whether they were the host, so it moved on to whoever's been in
+    // the room the longest now
+    pub fn depart(&mut self, player_id: &str) -> bool {
+        let was_host = self.is_host(player_id);
+        self.0.retain(|member| member != player_id);
+        was_host
+    }
+}
+
+impl RoomConfig {
+    // Check the rules against the server's limits and fill in its defaults
+    pub fn validate(mut self, limits: &RoomsConfig, config: &Config) -> Result<RoomConfig, String> {
+        let name = self.name.trim();
+        let valid_name = !name.is_empty()
+            && name.chars().count() <= MAX_NAME_CHARS
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!("A room's name is 1 to {} letters, digits, - or _", MAX_NAME_CHARS));
+        }
+        self.name = name.to_string();
+
+        let speed = self.speed.unwrap_or(config.player_speed.clamp(limits.min_speed, limits.max_speed));
+        if !(limits.min_speed..=limits.max_speed).contains(&speed) {
+            return Err(format!("A room's speed is {} to {}", limits.min_speed, limits.max_speed));
+        }
+        let max_players = self.max_players.unwrap_or(config.max_players.min(limits.max_players));
+        if !(1..=limits.max_players).contains(&max_players) {
+            return Err(format!("A room holds 1 to {} players", limits.max_players));
+        }
+        if let Some(map) = self.map.as_ref().filter(|map| !limits.maps.contains_key(*map)) {
+            return Err(format!("There's no map called {:?}", map));
+        }
+        (self.speed, self.max_players) = (Some(speed), Some(max_players));
+        Ok(self)
+    }
+
+    // The rules with the host's changes, checked like new ones
+    pub fn change(&self, settings: RoomSettings, limits: &RoomsConfig, config: &Config) -> Result<RoomConfig, String> {
+        let changed = RoomConfig {
+            speed: settings.speed.or(self.speed),
+            max_players: settings.max_players.or(self.max_players),
+            friendly_fire: settings.friendly_fire.unwrap_or(self.friendly_fire),
+            ..self.clone()
+        };
+        changed.validate(limits, config)
+    }
+}
+
+// Open a room with the rules a player asked for, picking up `state` if it has
+// one, and start simulating it. Returns the rules with the defaults filled in,
+// or why the room can't be opened.
+pub fn open(shared: &Shared, room: RoomConfig, state: Option<GameState>) -> Result<RoomConfig, String> {
+    let config = shared.config.get();
+    if let Some(reason) = refusal(shared, &config) {
+        return Err(reason);
+    }
+    let mut room = room.validate(&config.rooms, &config)?;
+    // A room handed over from another server keeps its code
+    if room.private && room.code.is_none() {
+        room.code = Some(new_invite(&shared.worlds));
+    }
+    let map = match &room.map {
+        Some(name) => Map::load(&config.rooms.maps[name]).map_err(|e| {
+            warn!("Failed to load map {} for room {}: {}", name, room.name, e);
+            format!("The map {} can't be loaded", name)
+        })?,
+        None => Map::default(),
+    };
+
+    let state = state.unwrap_or_else(empty_state);
+    let mut world = World::new(&room.name, room.mode, Netcode::Authoritative, map, state, Some(room.clone()));
+    world.invite_expires = room.private.then(|| std::time::Instant::now() + config.rooms.invite_time());
+    let world = Arc::new(world);
+    if !shared.worlds.insert(world.clone()) {
+        return Err(format!("There's a world called {} already", room.name));
+    }
+    info!(
+        "Opened room {}: {:?} mode, speed {}, up to {} players",
+        room.name,
+        room.mode,
+        world.player_speed(&config),
+        world.max_players(&config)
+    );
+    let Shared { config, scripts, store, .. } = shared;
+    tokio::spawn(systems::run_game_loop(world.clone(), config.clone(), scripts.clone(), store.clone()));
+    tokio::spawn(broadcast_pings(world));
+    Ok(room)
+}
+
+// An invite code no open room has
+fn new_invite(worlds: &Worlds) -> String {
+    loop {
+        let token = session::new_token();
+        let mut bits = u64::from_str_radix(&token[..16], 16).expect("tokens are hex");
+        let code: String = (0..INVITE_CHARS)
+            .map(|_| {
+                let c = INVITE_ALPHABET[(bits % INVITE_ALPHABET.len() as u64) as usize];
+                bits /= INVITE_ALPHABET.len() as u64;
+                c as char
+            })
+            .collect();
+        if worlds.by_invite(&code).is_none() {
+            return code;
+        }
+    }
+}
+
+// Why no room can be opened right now, if it can't
+fn refusal(shared: &Shared, config: &Config) -> Option<String> {
+    if !config.rooms.enabled {
+        return Some("This server doesn't let players create rooms".to_string());
+    }
+    if shared.drain.is_draining() {
+        return Some("The server is about to restart".to_string());
+    }
+    if shared.worlds.rooms() >= config.rooms.max_rooms {
+        return Some("Too many rooms are open, try again later".to_string());
+    }
+    None
+}
+
+// Every world players can join, configured ones first, and whether they could
+// open another one
+pub fn list(shared: &Shared) -> ServerMessage {
+    let config = shared.config.get();
+    let mut rooms: Vec<RoomEntry> = shared
+        .worlds
+        .iter()
+        .filter(|world| !world.is_private())
+        .map(|world| RoomEntry {
+            name: world.name.clone(),
+            mode: world.mode,
+            players: world.game_state.lock().unwrap().players.len(),
+            max_players: world.max_players(&config),
+            created: world.room.is_some(),
+        })
+        .collect();
+    rooms.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+    ServerMessage::Rooms { rooms, can_create: refusal(shared, &config).is_none() }
+}
+
+// Close rooms that have been empty for empty_secs
+pub async fn close_empty(worlds: Arc<Worlds>, config: Arc<SharedConfig>) {
+    let mut empty_since: HashMap<String, Instant> = HashMap::new();
+    let mut ticker = tokio::time::interval(CLOSE_CHECK_INTERVAL);
+    loop {
+        let now = ticker.tick().await;
+        let rooms: Vec<Arc<World>> = worlds.iter().filter(|world| world.room.is_some()).collect();
+        empty_since.retain(|name, _| rooms.iter().any(|room| &room.name == name));
+        for room in rooms {
+            if !room.clients.lock().unwrap().is_empty() {
+                empty_since.remove(&room.name);
+                continue;
+            }
+            let since = *empty_since.entry(room.name.clone()).or_insert(now);
+            if now - since >= config.get().rooms.empty_time() {
+                info!("Closing room {}, nobody has been in it for {}s", room.name, (now - since).as_secs());
+                worlds.remove(&room.name);
+                empty_since.remove(&room.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use rhai::{Array, Dynamic, Engine, FuncArgs, Scope, AST};
+use tracing::warn;
+
+use crate::game::{clamp_to_world, Player};
+
+// Upper bound on script work per hook call, so a runaway loop can't stall the server
+const MAX_OPERATIONS: u64 = 100_000;
+
+// Game rules loaded from a rhai script. Every hook is optional:
+//   fn on_join(player)     -> changed player, or () to keep it
+//   fn on_tick(players)    -> array of changed players, or ()
+//   fn on_collision(a, b)  -> array of changed players, or ()
+// Players are passed as maps with id, name, x, y, color, shape, size and score; ids, names, shapes and sizes can't be changed.
+pub struct Rules {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Rules, Box<dyn std::error::Error>> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile_file(path.to_path_buf())?;
+        Ok(Rules { engine, ast })
+    }
+
+    fn has_hook(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    // Call a hook, logging script errors instead of failing the game
+    fn call(&self, name: &str, args: impl FuncArgs) -> Option<Dynamic> {
+        if !self.has_hook(name) {
+            return None;
+        }
+        match self.engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args) {
+            Ok(result) if result.is_unit() => None,
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Script error in {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    pub fn on_join(&self, player: &mut Player) {
+        if let Some(result) = self.call("on_join", (to_dynamic(player),)) {
+            apply_change(player, &result);
+        }
+    }
+
+    // Returns whether the script changed anything
+    pub fn on_tick(&self, players: &mut HashMap<String, Player>) -> bool {
+        if !self.has_hook("on_tick") {
+            return false;
+        }
+        let list: Array = players.values().map(to_dynamic).collect();
+        match self.call("on_tick", (list,)) {
+            Some(result) => apply_changes(players, result),
+            None => false,
+        }
+    }
+
+    // Returns whether the script actually changed either player, like tagging
+    // them, rather than handing them back as they were
+    pub fn on_collision(&self, players: &mut HashMap<String, Player>, a: &str, b: &str) -> bool {
+        let (Some(first), Some(second)) = (players.get(a), players.get(b)) else {
+            return false;
+        };
+        let Some(result) = self.call("on_collision", (to_dynamic(first), to_dynamic(second))) else {
+            return false;
+        };
+        let looks = |players: &HashMap<String, Player>| {
+            [a, b].map(|id| players.get(id).map(|p| (p.x, p.y, p.color.clone(), p.score)))
+        };
+        let before = looks(players);
+        apply_changes(players, result);
+        looks(players) != before
+    }
+}
+
+// The currently loaded rules, if a script is configured
+#[derive(Default)]
+pub struct ScriptHost {
+    rules: RwLock<Option<Arc<Rules>>>,
+}
+
+impl ScriptHost {
+    pub fn get(&self) -> Option<Arc<Rules>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    // Load (or unload, with None) the script. On error the old rules stay in place.
+    pub fn load(&self, path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let rules = match path {
+            Some(path) => Some(Arc::new(Rules::load(path)?)),
+            None => None,
+        };
+        *self.rules.write().unwrap() = rules;
+        Ok(())
+    }
+}
+
+fn to_dynamic(player: &Player) -> Dynamic {
+    rhai::serde::to_dynamic(player).expect("Player is always representable")
+}
+
+// Copy the fields a script may change from a returned player map
+fn apply_change(player: &mut Player, value: &Dynamic) {
+    match rhai::serde::from_dynamic::<Player>(value) {
+        Ok(updated) => {
+            player.x = updated.x;
+            player.y = updated.y;
+            player.color = updated.color;
+            player.score = updated.score;
+            clamp_to_world(player);
+        }
+        Err(e) => warn!("Script returned an invalid player: {}", e),
+    }
+}
+
+fn apply_changes(players: &mut HashMap<String, Player>, result: Dynamic) -> bool {
+    let Some(list) = result.try_cast::<Array>() else {
+        warn!("Script hook should return an array of players");
+        return false;
+    };
+    let mut changed = false;
+    for value in &list {
+        let id = value
+            .read_lock::<rhai::Map>()
+            .and_then(|map| map.get("id").map(|id| id.to_string()));
+        if let Some(player) = id.and_then(|id| players.get_mut(&id)) {
+            apply_change(player, value);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::storage::PlayerRecord;
+use crate::game::Player;
+
+// Tokens handed to clients so a reconnecting player gets their old identity back
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+struct Session {
+    player_id: String,
+    // Where the player was when they left, None while they're connected
+    left: Option<LeftPlayer>,
+}
+
+struct LeftPlayer {
+    world: String,
+    player: Player,
+    expires_at: Instant,
+}
+
+// What a Join with a session token resumes
+pub enum Resumed {
+    // The token is unknown or expired
+    None,
+    // The token's player is connected right now, from another tab or device
+    Connected(String),
+    // Not a current session, but the player record the token was stored with
+    Stored(PlayerRecord),
+    // Same player ID; the player left a different world
+    Identity(String),
+    // Same player, back where they left off
+    Player(Player),
+}
+
+impl Sessions {
+    // Claim the session behind `token` for a player joining `world`
+    pub fn resume(&self, token: &str, world: &str) -> Resumed {
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        let Some(session) = sessions.get_mut(token) else {
+            return Resumed::None;
+        };
+        match session.left.take() {
+            None => Resumed::Connected(session.player_id.clone()),
+            Some(left) if left.world == world => Resumed::Player(left.player),
+            Some(_) => Resumed::Identity(session.player_id.clone()),
+        }
+    }
+
+    // Sessions kept, connected or waiting to be resumed
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    // Whether a token belongs to a session, connected or not
+    pub fn contains(&self, token: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(token)
+    }
+
+    // The tokens of connected players, by player ID
+    pub fn connected(&self) -> HashMap<String, String> {
+        let sessions = self.sessions.lock().unwrap();
+        let connected = sessions.iter().filter(|(_, session)| session.left.is_none());
+        connected.map(|(token, session)| (session.player_id.clone(), token.clone())).collect()
+    }
+
+    // Players who left and may still resume, as their token, world and player
+    pub fn left(&self) -> Vec<(String, String, Player)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        prune(&mut sessions);
+        let left = sessions.iter().filter_map(|(token, session)| Some((token, session.left.as_ref()?)));
+        left.map(|(token, left)| (token.clone(), left.world.clone(), left.player.clone())).collect()
+    }
+
+    // A new token for a player who just joined
+    pub fn start(&self, player_id: &str) -> String {
+        let token = new_token();
+        self.restore(&token, player_id);
+        token
+    }
+
+    // Take up a stored token again
+    pub fn restore(&self, token: &str, player_id: &str) {
+        let session = Session {
+            player_id: player_id.to_string(),
+            left: None,
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        // Players who never come back would pile up otherwise
+        prune(&mut sessions);
+        sessions.insert(token.to_string(), session);
+    }
+
+    // Keep a player who disconnected around for `keep`, so they can resume
+    pub fn leave(&self, token: &str, world: &str, player: Player, keep: Duration) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(token) {
+            session.left = Some(LeftPlayer {
+                world: world.to_string(),
+                player,
+                expires_at: Instant::now() + keep,
+            });
+        }
+    }
+}
+
+// Forget players who left longer ago than they could resume
+fn prune(sessions: &mut HashMap<String, Session>) {
+    let now = Instant::now();
+    sessions.retain(|_, session| session.left.as_ref().is_none_or(|left| left.expires_at > now));
+}
+
+// 128 random bits from the OS as hex. Session tokens, login states and
+// invite codes are made of them, so they mustn't be guessable.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).expect("The OS has no random numbers to give");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::config::{Config, SharedConfig};
+use crate::drain::{self, Drain};
+use crate::handoff::HandedOver;
+use crate::session::Sessions;
+use crate::world::Worlds;
+use crate::game::{GameState, Player};
+
+// A world as saved: its state without players, and the players with a session,
+// connected or waiting to resume, who get their score back when they resume
+#[derive(Serialize, Deserialize)]
+pub struct Saved {
+    #[serde(flatten)]
+    pub state: GameState,
+    // By session token; snapshots from before this have none
+    #[serde(default)]
+    pub sessions: HashMap<String, Player>,
+}
+
+// Write all worlds to disk, keyed by world name. The file is written next to the
+// target and then renamed over it, so a crash mid-write never leaves a truncated snapshot.
+pub async fn save(path: &Path, states: &HashMap<String, Saved>) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_vec(states)?;
+    let temp_path = path.with_extension("tmp");
+    tokio::fs::write(&temp_path, json).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+// Read the last snapshot, or None if there isn't one yet
+pub fn load(path: &Path) -> Result<Option<HashMap<String, Saved>>, Box<dyn std::error::Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = std::fs::read(path)?;
+    let mut states: HashMap<String, Saved> = serde_json::from_slice(&json)?;
+    // Players belong to connections that didn't survive the restart; those
+    // with a session come back through it
+    for saved in states.values_mut() {
+        saved.state.players.clear();
+    }
+    Ok(Some(states))
+}
+
+// Save the world every snapshot_interval_secs while snapshots are configured
+pub async fn run_snapshots(worlds: Arc<Worlds>, sessions: Arc<Sessions>, config: Arc<SharedConfig>) {
+    loop {
+        tokio::time::sleep(config.get().snapshot_interval()).await;
+        if let Some(path) = config.get().snapshot_path.clone() {
+            save_now(&path, &worlds, &sessions).await;
+        }
+    }
+}
+
+// Wait for Ctrl-C or the end of a drain and save one last snapshot; the server
+// exits once this returns
+pub async fn save_on_shutdown(worlds: Arc<Worlds>, sessions: Arc<Sessions>, config: Arc<SharedConfig>, drain: Arc<Drain>) {
+    let interrupted = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            warn!("Can't listen for Ctrl-C, no snapshot will be saved on it");
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::select! {
+        _ = interrupted => {}
+        _ = drain.finished() => drain::close_all(&worlds).await,
+    }
+    if drain.is_handed_off() {
+        info!("Not saving the world, the server we handed it to does that now");
+        return;
+    }
+    if let Some(path) = config.get().snapshot_path.clone() {
+        save_now(&path, &worlds, &sessions).await;
+        info!("World saved to {}", path.display());
+    }
+}
+
+// Rooms players created are gone after a restart, so only configured worlds are saved
+async fn save_now(path: &Path, worlds: &Worlds, sessions: &Sessions) {
+    let tokens = sessions.connected();
+    let mut states: HashMap<String, Saved> = HashMap::new();
+    for world in worlds.iter().filter(|world| world.room.is_none()) {
+        let mut state = world.game_state.lock().unwrap().clone();
+        let players = std::mem::take(&mut state.players).into_values();
+        let sessions = players.filter_map(|player| Some((tokens.get(&player.id)?.clone(), player))).collect();
+        states.insert(world.name.clone(), Saved { state, sessions });
+    }
+    for (token, world, player) in sessions.left() {
+        if let Some(saved) = states.get_mut(&world) {
+            saved.sessions.insert(token, player);
+        }
+    }
+    if let Err(e) = save(path, &states).await {
+        error!("Failed to save world snapshot: {}", e);
+    }
+}
+
+// Load the last snapshot of all worlds unless asked to start fresh, as the
+// worlds' states and the players waiting to resume in them
+pub fn restore(config: &Config, fresh: bool) -> (HashMap<String, GameState>, Vec<HandedOver>) {
+    let Some(path) = config.snapshot_path.as_deref() else {
+        return Default::default();
+    };
+    if fresh {
+        info!("Starting with fresh worlds, ignoring {}", path.display());
+        return Default::default();
+    }
+    let states = match load(path) {
+        Ok(Some(states)) => {
+            info!("Restored worlds from {}", path.display());
+            states
+        }
+        Ok(None) => return Default::default(),
+        Err(e) => panic!("Failed to restore world from {} (use --fresh to start over): {}", path.display(), e),
+    };
+    let mut players = Vec::new();
+    let states = states
+        .into_iter()
+        .map(|(world, saved)| {
+            let waiting = saved.sessions.into_iter().map(|(token, player)| HandedOver { token, world: world.clone(), player });
+            players.extend(waiting);
+            (world, saved.state)
+        })
+        .collect();
+    (states, players)
+}
+
+// Keep the saved players waiting to resume, as if they had just dropped out
+pub fn resume(players: Vec<HandedOver>, sessions: &Sessions, config: &Config) {
+    for HandedOver { token, world, player } in players {
+        sessions.restore(&token, &player.id);
+        sessions.leave(&token, &world, player, config.session_resume());
+    }
+}
@@ -0,0 +1,62 @@
+use sim::collision::overlaps;
+use sim::{Body, WORLD_HEIGHT, WORLD_WIDTH};
+
+use crate::map::Map;
+use crate::game::Player;
+
+// Gap kept to the edges when a map has no spawn points
+const MARGIN: u32 = 50;
+
+// Where a player of `size` should (re)appear: at one of the map's spawn
+// points, or on an even spread over the world when it has none. Spots no
+// other player stands on and that aren't on a portal come first, tried from
+// `seed` on so players joining one after another spread out; when they're
+// all taken, it's the spot furthest from everyone.
+pub fn pick<'a>(map: &Map, size: u32, others: impl Iterator<Item = &'a Player> + Clone, seed: u64) -> (u32, u32) {
+    let candidates = candidates(map, size);
+    let body = |(x, y): (u32, u32)| Body { x, y, size, bounce: (0, 0) };
+    let start = (seed % candidates.len() as u64) as usize;
+    let mut ordered = candidates[start..].iter().chain(&candidates[..start]);
+    let free = ordered.clone().find(|&&spot| {
+        let spawned = body(spot);
+        !others.clone().any(|other| overlaps(&spawned, &other.body()))
+            && !map.portals.iter().any(|portal| portal.overlaps(spot.0, spot.1, size))
+    });
+    if let Some(&spot) = free {
+        return spot;
+    }
+    // Squared distance between centers to the closest other player
+    let room = |spot: (u32, u32)| {
+        let center = |x: u32, y: u32, size: u32| ((x + size / 2) as i64, (y + size / 2) as i64);
+        let (x, y) = center(spot.0, spot.1, size);
+        others
+            .clone()
+            .map(|other| {
+                let (other_x, other_y) = center(other.x, other.y, other.size);
+                (x - other_x).pow(2) + (y - other_y).pow(2)
+            })
+            .min()
+            .unwrap_or(i64::MAX)
+    };
+    let first = *ordered.next().expect("there's always a candidate");
+    ordered.fold(first, |best, &spot| if room(spot) > room(best) { spot } else { best })
+}
+
+// The spots a player may spawn at, inside the world
+fn candidates(map: &Map, size: u32) -> Vec<(u32, u32)> {
+    let max_x = WORLD_WIDTH.saturating_sub(size);
+    let max_y = WORLD_HEIGHT.saturating_sub(size);
+    if !map.spawns.is_empty() {
+        return map.spawns.iter().map(|spawn| (spawn.x.min(max_x), spawn.y.min(max_y))).collect();
+    }
+    // A grid two players apart, every other row offset so neighbors aren't in line
+    let spacing = (size * 2).max(1) as usize;
+    let (min_x, min_y) = (MARGIN.min(max_x), MARGIN.min(max_y));
+    let (last_x, last_y) = (max_x.saturating_sub(MARGIN).max(min_x), max_y.saturating_sub(MARGIN).max(min_y));
+    let mut spots = Vec::new();
+    for (row, y) in (min_y..=last_y).step_by(spacing).enumerate() {
+        let offset = if row % 2 == 1 { size } else { 0 };
+        spots.extend((min_x + offset..=last_x).step_by(spacing).map(|x| (x, y)));
+    }
+    spots
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, info};
+
+use crate::config::{Config, SharedConfig};
+use crate::http::{Request, Response, Upgrade, UpgradeHandler};
+use crate::limits::{ConnectionLimiter, ConnectionSlot};
+use crate::metrics::{Tracked, METRICS};
+use crate::net::close_frame;
+use crate::protocol::{self, EnvelopeJson, ServerMessage};
+use crate::world::{World, Worlds};
+
+const SPECTATE_PAGE: &str = include_str!("../spectate.html");
+
+// The world a /spectate or /spectate/<world> request is for, if it may be watched
+fn watched(request: &Request, worlds: &Worlds, config: &Config) -> Result<Arc<World>, Response> {
+    let name = match request.path.trim_matches('/').split('/').collect::<Vec<_>>().as_slice() {
+        ["spectate"] => None,
+        ["spectate", name] => Some(name.to_string()),
+        _ => return Err(Response::not_found()),
+    };
+    if !config.spectate.enabled {
+        return Err(Response::not_found());
+    }
+    // Private rooms aren't for outsiders
+    worlds.get(name.as_deref()).filter(|world| !world.is_private()).ok_or_else(Response::not_found)
+}
+
+// The page showing a world's stream, to open or put in an iframe
+pub fn page(request: &Request, worlds: &Worlds, config: &SharedConfig) -> Response {
+    match watched(request, worlds, &config.get()) {
+        Ok(_) => Response::html(SPECTATE_PAGE),
+        Err(response) => response,
+    }
+}
+
+// Accepts spectators' WebSockets on /spectate/<world>. A spectator takes one
+// of its IP's connections, like a player, and its place among the spectators
+// is taken here, before the handshake, and given back when the upgrade ends
+// or never happens.
+pub fn upgrade_handler(worlds: Arc<Worlds>, config: Arc<SharedConfig>, limiter: Arc<ConnectionLimiter>) -> UpgradeHandler {
+    Arc::new(move |request| {
+        let settings = config.get();
+        let world = watched(request, &worlds, &settings)?;
+        let forwarded_for = request.headers.get("x-forwarded-for").map(String::as_str);
+        let client_ip = settings.proxy.client_ip(request.peer, forwarded_for);
+        let Some(slot) = limiter.try_acquire(client_ip, settings.max_connections_per_ip) else {
+            return Err(Response::error(503, "Too many connections from your address"));
+        };
+        let Some(place) = Tracked::reserve(&METRICS.spectators, settings.spectate.max_spectators) else {
+            return Err(Response::error(503, "Too many spectators, try again later"));
+        };
+        let config = config.clone();
+        let upgrade: Upgrade = Box::new(move |ws_stream| Box::pin(stream(ws_stream, world, config, place, slot)));
+        Ok(upgrade)
+    })
+}
+
+// Send the map, then the world's state every interval_ms, until the spectator
+// leaves or the world closes. Whatever the spectator sends is ignored.
+async fn stream(
+    ws_stream: WebSocketStream<TcpStream>,
+    world: Arc<World>,
+    config: Arc<SharedConfig>,
+    _place: Tracked,
+    _slot: ConnectionSlot,
+) {
+    info!("Spectator watching {}", world.name);
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let map = ServerMessage::Map { map: (*world.map()).clone() };
+    let Ok(map) = protocol::encode(&map, &EnvelopeJson) else {
+        return;
+    };
+    if ws_sender.send(map).await.is_err() {
+        return;
+    }
+
+    let mut period = config.get().spectate.interval();
+    let mut updates = interval(period);
+    // A spectator who fell behind gets the latest state, not every one it missed
+    updates.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = updates.tick() => {
+                if world.is_closed() {
+                    let _ = ws_sender.send(close_frame(CloseCode::Away, "The room closed")).await;
+                    break;
+                }
+                let game_state = world.game_state.lock().unwrap().clone();
+                let Ok(state) = protocol::encode(&ServerMessage::State { game_state }, &EnvelopeJson) else {
+                    break;
+                };
+                if ws_sender.send(state).await.is_err() {
+                    break;
+                }
+                // Pick up a new rate from a config reload
+                let configured = config.get().spectate.interval();
+                if configured != period {
+                    period = configured;
+                    updates = interval(period);
+                    updates.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                }
+            }
+            message = ws_receiver.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(message)) => debug!("Ignoring {} bytes from a spectator", message.len()),
+            },
+        }
+    }
+    info!("Spectator of {} left", world.name);
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{Event, Subscriber};
+use crate::game::GameState;
+
+// What a player did since connecting
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PlayerStats {
+    // Pixels moved, not counting portals and wrapping around the edges
+    pub distance: f64,
+    // Pieces of food eaten
+    pub pickups: u32,
+    // Time spent in the world without being eliminated
+    pub alive_ms: u64,
+    // Chat messages and whispers that got through the filters
+    pub messages: u32,
+    // Trail rounds ended as the last one standing
+    pub rounds_won: u32,
+}
+
+// Stats of the players connected to a world, by player ID
+#[derive(Default)]
+pub struct WorldStats {
+    players: Mutex<HashMap<String, PlayerStats>>,
+}
+
+impl WorldStats {
+    pub fn start(&self, player_id: &str) {
+        self.players.lock().unwrap().insert(player_id.to_string(), PlayerStats::default());
+    }
+
+    // The player's stats, which aren't kept after they leave
+    pub fn finish(&self, player_id: &str) -> Option<PlayerStats> {
+        self.players.lock().unwrap().remove(player_id)
+    }
+
+    pub fn update(&self, player_id: &str, change: impl FnOnce(&mut PlayerStats)) {
+        if let Some(stats) = self.players.lock().unwrap().get_mut(player_id) {
+            change(stats);
+        }
+    }
+
+    pub fn get(&self, player_id: &str) -> Option<PlayerStats> {
+        self.players.lock().unwrap().get(player_id).cloned()
+    }
+
+    pub fn all(&self) -> HashMap<String, PlayerStats> {
+        self.players.lock().unwrap().clone()
+    }
+
+    // Another tick went by: players still in the game were alive for it
+    pub fn tick(&self, state: &GameState, tick: Duration) {
+        let mut players = self.players.lock().unwrap();
+        for player in state.players.values().filter(|player| !player.eliminated) {
+            if let Some(stats) = players.get_mut(&player.id) {
+                stats.alive_ms += tick.as_millis() as u64;
+            }
+        }
+    }
+}
+
+impl Subscriber for WorldStats {
+    fn notify(&self, event: &Event) {
+        match event {
+            Event::PlayerJoined { player_id } => self.start(player_id),
+            Event::PlayerLeft { player_id } => {
+                self.finish(player_id);
+            }
+            Event::Moved { player_id, distance } => self.update(player_id, |stats| stats.distance += distance),
+            Event::Ate { player_id, count } => self.update(player_id, |stats| stats.pickups += count),
+            Event::RoundWon { player_id } => self.update(player_id, |stats| stats.rounds_won += 1),
+            Event::ChatSent { player_id, .. } => self.update(player_id, |stats| stats.messages += 1),
+            Event::Collision { .. } | Event::Scored { .. } | Event::HostChanged { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::game::GameState;
+use crate::world::World;
+
+// Slowest and fastest a world's time may run, relative to real time
+pub const MIN_TIME_SCALE: f64 = 0.1;
+pub const MAX_TIME_SCALE: f64 = 10.0;
+
+// Lets an admin stop a world's simulation and run it a tick at a time, or
+// run it in slow motion or fast forward
+pub struct Stepper {
+    paused: AtomicBool,
+    // Ticks left to run before stopping again, while paused
+    steps: AtomicU32,
+    // The latest tick the world ran
+    tick: AtomicU64,
+    // Game time per real time, as f64 bits. Ticks come this much more often
+    // and each still covers one configured tick interval of game time.
+    time_scale: AtomicU64,
+}
+
+impl Default for Stepper {
+    fn default() -> Stepper {
+        Stepper {
+            paused: AtomicBool::new(false),
+            steps: AtomicU32::new(0),
+            tick: AtomicU64::new(0),
+            time_scale: AtomicU64::new(1f64.to_bits()),
+        }
+    }
+}
+
+impl Stepper {
+    pub fn pause(&self) {
+        self.steps.store(0, Ordering::Relaxed);
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // Run `count` more ticks of a paused world, one per tick interval
+    pub fn step(&self, count: u32) {
+        self.steps.fetch_add(count, Ordering::Relaxed);
+    }
+
+    // Whether the game loop should run this tick
+    pub fn proceed(&self) -> bool {
+        if !self.is_paused() {
+            return true;
+        }
+        self.steps.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps| steps.checked_sub(1)).is_ok()
+    }
+
+    pub fn ticked(&self, tick: u64) {
+        self.tick.store(tick, Ordering::Relaxed);
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick.load(Ordering::Relaxed)
+    }
+
+    // Returns the scale that took effect, kept within MIN/MAX_TIME_SCALE
+    pub fn set_time_scale(&self, time_scale: f64) -> f64 {
+        let time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        self.time_scale.store(time_scale.to_bits(), Ordering::Relaxed);
+        time_scale
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        f64::from_bits(self.time_scale.load(Ordering::Relaxed))
+    }
+
+    // Real time between two ticks, given the configured tick interval
+    pub fn tick_interval(&self, configured: Duration) -> Duration {
+        configured.div_f64(self.time_scale())
+    }
+}
+
+// Everything the simulation works from, for debugging it
+#[derive(Serialize)]
+pub struct Dump {
+    world: String,
+    tick: u64,
+    paused: bool,
+    time_scale: f64,
+    // Keys each player is holding down, as sim's KEY_* bits
+    inputs: BTreeMap<String, u8>,
+    state: GameState,
+}
+
+pub fn dump(world: &World) -> Dump {
+    let inputs = world.inputs.lock().unwrap().iter().map(|(player_id, held)| (player_id.clone(), held.keys)).collect();
+    Dump {
+        world: world.name.clone(),
+        tick: world.stepper.tick(),
+        paused: world.stepper.is_paused(),
+        time_scale: world.stepper.time_scale(),
+        inputs,
+        state: world.game_state.lock().unwrap().clone(),
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use tracing::{debug, error, info, warn};
+
+use crate::achievements::Achievement;
+use crate::config::StorageBackend;
+use crate::cosmetics::Cosmetic;
+
+// Sessions kept per player; older ones are dropped
+const MAX_SESSIONS_PER_PLAYER: usize = 50;
+// Connections the Postgres backend keeps open at most
+const MAX_DATABASE_CONNECTIONS: u32 = 5;
+// How long to wait for a Postgres connection before giving up on it
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(5);
+// How often to try a Postgres database again that was read-only at startup
+const DATABASE_RETRY: Duration = Duration::from_secs(30);
+
+// One stint of a player in a world, recorded when they disconnect
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub world: String,
+    pub score: u32,
+    // Unix timestamps in seconds
+    pub joined_at: u64,
+    pub left_at: u64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    pub id: String,
+    pub best_score: u32,
+    pub total_score: u64,
+    pub sessions: Vec<SessionRecord>,
+    #[serde(default)]
+    pub achievements: Vec<Achievement>,
+    // Time spent in the game over all sessions, not just the kept ones
+    #[serde(default)]
+    pub playtime_secs: u64,
+    #[serde(default)]
+    pub unlocks: Vec<Cosmetic>,
+    // Where the player was when they last left
+    #[serde(default)]
+    pub last_position: Option<LastPosition>,
+}
+
+impl PlayerRecord {
+    // This record with what's in `since` added to it, a record of the same
+    // player that started out empty
+    fn with(mut self, since: PlayerRecord) -> PlayerRecord {
+        self.best_score = self.best_score.max(since.best_score);
+        self.total_score += since.total_score;
+        self.playtime_secs += since.playtime_secs;
+        self.sessions.extend(since.sessions);
+        let dropped = self.sessions.len().saturating_sub(MAX_SESSIONS_PER_PLAYER);
+        self.sessions.drain(..dropped);
+        for achievement in since.achievements {
+            if !self.achievements.contains(&achievement) {
+                self.achievements.push(achievement);
+            }
+        }
+        for cosmetic in since.unlocks {
+            if !self.unlocks.contains(&cosmetic) {
+                self.unlocks.push(cosmetic);
+            }
+        }
+        self.last_position = since.last_position.or(self.last_position);
+        self
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LastPosition {
+    pub world: String,
+    pub x: u32,
+    pub y: u32,
+    pub score: u32,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    pub id: String,
+    pub best_score: u32,
+    pub total_score: u64,
+}
+
+// Where player records and bans are kept. Reads and writes are served from
+// memory; flush() makes the writes durable, for backends that need I/O for that.
+pub trait Storage: Send + Sync {
+    fn get_player(&self, id: &str) -> Option<PlayerRecord>;
+    fn put_player(&self, record: PlayerRecord);
+    // Players with the highest best score first
+    fn top_scores(&self, limit: usize) -> Vec<LeaderboardEntry>;
+    // When the ban on an IP ends, as a Unix timestamp in seconds
+    fn get_ban(&self, ip: IpAddr) -> Option<u64>;
+    fn put_ban(&self, ip: IpAddr, until: u64);
+    // The player a session token was last handed to
+    fn get_token(&self, token: &str) -> Option<String>;
+    fn put_token(&self, token: &str, player_id: &str);
+    fn flush(&self) -> BoxFuture<'_, ()>;
+}
+
+// Everything a backend keeps
+#[derive(Default, Serialize, Deserialize)]
+struct Stored {
+    players: HashMap<String, PlayerRecord>,
+    #[serde(default)]
+    bans: HashMap<IpAddr, u64>,
+    // Session tokens by the player ID they belong to, kept apart from the
+    // records since those are public
+    #[serde(default)]
+    tokens: HashMap<String, String>,
+}
+
+// Kept until the server stops
+#[derive(Default)]
+pub struct MemoryStorage {
+    stored: Mutex<Stored>,
+}
+
+impl Storage for MemoryStorage {
+    fn get_player(&self, id: &str) -> Option<PlayerRecord> {
+        self.stored.lock().unwrap().players.get(id).cloned()
+    }
+
+    fn put_player(&self, record: PlayerRecord) {
+        self.stored.lock().unwrap().players.insert(record.id.clone(), record);
+    }
+
+    fn top_scores(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        let stored = self.stored.lock().unwrap();
+        let mut entries: Vec<LeaderboardEntry> = stored
+            .players
+            .values()
+            .map(|record| LeaderboardEntry {
+                id: record.id.clone(),
+                best_score: record.best_score,
+                total_score: record.total_score,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.best_score.cmp(&a.best_score).then_with(|| a.id.cmp(&b.id)));
+        entries.truncate(limit);
+        entries
+    }
+
+    fn get_ban(&self, ip: IpAddr) -> Option<u64> {
+        self.stored.lock().unwrap().bans.get(&ip).copied()
+    }
+
+    fn put_ban(&self, ip: IpAddr, until: u64) {
+        let mut stored = self.stored.lock().unwrap();
+        let now = unix_time();
+        stored.bans.retain(|_, until| *until > now);
+        stored.bans.insert(ip, until);
+    }
+
+    fn get_token(&self, token: &str) -> Option<String> {
+        let stored = self.stored.lock().unwrap();
+        stored.tokens.iter().find(|(_, known)| *known == token).map(|(player_id, _)| player_id.clone())
+    }
+
+    fn put_token(&self, token: &str, player_id: &str) {
+        self.stored.lock().unwrap().tokens.insert(player_id.to_string(), token.to_string());
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(std::future::ready(()))
+    }
+}
+
+// The file's layout before bans were stored: just the players
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFile {
+    Current(Stored),
+    PlayersOnly(HashMap<String, PlayerRecord>),
+}
+
+// Kept in memory and written to a JSON file on every flush
+pub struct JsonStorage {
+    path: PathBuf,
+    memory: MemoryStorage,
+    // Serializes file writes so an older copy never overwrites a newer one
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl JsonStorage {
+    // Load existing records if the file exists
+    pub fn open(path: PathBuf) -> Result<JsonStorage, Box<dyn std::error::Error>> {
+        let stored = if path.exists() {
+            match serde_json::from_slice(&std::fs::read(&path)?)? {
+                StoredFile::Current(stored) => stored,
+                StoredFile::PlayersOnly(players) => Stored { players, ..Stored::default() },
+            }
+        } else {
+            Stored::default()
+        };
+        Ok(JsonStorage {
+            path,
+            memory: MemoryStorage { stored: Mutex::new(stored) },
+            write_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+}
+
+impl Storage for JsonStorage {
+    fn get_player(&self, id: &str) -> Option<PlayerRecord> {
+        self.memory.get_player(id)
+    }
+
+    fn put_player(&self, record: PlayerRecord) {
+        self.memory.put_player(record);
+    }
+
+    fn top_scores(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        self.memory.top_scores(limit)
+    }
+
+    fn get_ban(&self, ip: IpAddr) -> Option<u64> {
+        self.memory.get_ban(ip)
+    }
+
+    fn put_ban(&self, ip: IpAddr, until: u64) {
+        self.memory.put_ban(ip, until);
+    }
+
+    fn get_token(&self, token: &str) -> Option<String> {
+        self.memory.get_token(token)
+    }
+
+    fn put_token(&self, token: &str, player_id: &str) {
+        self.memory.put_token(token, player_id);
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let _writing = self.write_lock.lock().await;
+            let json = serde_json::to_vec(&*self.memory.stored.lock().unwrap());
+            if let Ok(json) = json {
+                let temp_path = self.path.with_extension("tmp");
+                let written = match tokio::fs::write(&temp_path, json).await {
+                    Ok(()) => tokio::fs::rename(&temp_path, &self.path).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = written {
+                    error!("Failed to save player stats: {}", e);
+                }
+            }
+        })
+    }
+}
+
+// What was put since the last flush, by key
+#[derive(Default)]
+struct Dirty {
+    players: HashSet<String>,
+    bans: HashSet<IpAddr>,
+    // By player ID
+    tokens: HashSet<String>,
+}
+
+// The changed rows a flush writes to a database
+#[derive(Default)]
+struct Changes {
+    players: Vec<PlayerRecord>,
+    bans: Vec<(IpAddr, u64)>,
+    // Player ID and token
+    tokens: Vec<(String, String)>,
+}
+
+// A database that takes the changes from a flush, all or none of them
+trait Database: Send + Sync {
+    fn write<'a>(&'a self, changes: &'a Changes) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+}
+
+// Loaded from a database at startup and kept in memory, with every flush
+// writing what changed since the one before
+struct SqlStorage<D> {
+    database: D,
+    memory: MemoryStorage,
+    dirty: Mutex<Dirty>,
+    // Serializes flushes so an older copy of a row never overwrites a newer one
+    write_lock: tokio::sync::Mutex<()>,
+    // Not written to while set, since what's in memory isn't what the
+    // database has; what's put meanwhile stays dirty for when it's cleared
+    read_only: AtomicBool,
+}
+
+impl<D: Database> SqlStorage<D> {
+    fn new(database: D, stored: Stored) -> SqlStorage<D> {
+        SqlStorage {
+            database,
+            memory: MemoryStorage { stored: Mutex::new(stored) },
+            dirty: Mutex::new(Dirty::default()),
+            write_lock: tokio::sync::Mutex::new(()),
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    // Take in what the database has, which couldn't be read at startup. The
+    // records put since then started out empty, so they're added to its ones.
+    fn take_in(&self, stored: Stored) {
+        let mut memory = self.memory.stored.lock().unwrap();
+        for (id, record) in stored.players {
+            let record = match memory.players.remove(&id) {
+                Some(since) => record.with(since),
+                None => record,
+            };
+            memory.players.insert(id, record);
+        }
+        for (ip, until) in stored.bans {
+            let banned_until = memory.bans.entry(ip).or_insert(until);
+            *banned_until = (*banned_until).max(until);
+        }
+        for (player_id, token) in stored.tokens {
+            memory.tokens.entry(player_id).or_insert(token);
+        }
+    }
+
+    // The rows behind the dirty keys, as they are now
+    fn changes(&self, dirty: &Dirty) -> Changes {
+        let stored = self.memory.stored.lock().unwrap();
+        Changes {
+            players: dirty.players.iter().filter_map(|id| stored.players.get(id).cloned()).collect(),
+            bans: dirty.bans.iter().filter_map(|ip| Some((*ip, *stored.bans.get(ip)?))).collect(),
+            tokens: dirty
+                .tokens
+                .iter()
+                .filter_map(|player_id| Some((player_id.clone(), stored.tokens.get(player_id)?.clone())))
+                .collect(),
+        }
+    }
+}
+
+impl<D: Database> Storage for SqlStorage<D> {
+    fn get_player(&self, id: &str) -> Option<PlayerRecord> {
+        self.memory.get_player(id)
+    }
+
+    fn put_player(&self, record: PlayerRecord) {
+        self.dirty.lock().unwrap().players.insert(record.id.clone());
+        self.memory.put_player(record);
+    }
+
+    fn top_scores(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        self.memory.top_scores(limit)
+    }
+
+    fn get_ban(&self, ip: IpAddr) -> Option<u64> {
+        self.memory.get_ban(ip)
+    }
+
+    fn put_ban(&self, ip: IpAddr, until: u64) {
+        self.dirty.lock().unwrap().bans.insert(ip);
+        self.memory.put_ban(ip, until);
+    }
+
+    fn get_token(&self, token: &str) -> Option<String> {
+        self.memory.get_token(token)
+    }
+
+    fn put_token(&self, token: &str, player_id: &str) {
+        self.dirty.lock().unwrap().tokens.insert(player_id.to_string());
+        self.memory.put_token(token, player_id);
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let _writing = self.write_lock.lock().await;
+            if self.read_only.load(Ordering::Relaxed) {
+                return;
+            }
+            let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+            let changes = self.changes(&dirty);
+            if let Err(e) = self.database.write(&changes).await {
+                error!("Failed to save player stats: {}", e);
+                // Try again with the next flush
+                let mut still_dirty = self.dirty.lock().unwrap();
+                still_dirty.players.extend(dirty.players);
+                still_dirty.bans.extend(dirty.bans);
+                still_dirty.tokens.extend(dirty.tokens);
+            }
+        })
+    }
+}
+
+// A SQLite database file, created with its tables if it doesn't exist yet
+async fn open_sqlite(path: PathBuf) -> Result<SqlStorage<SqlitePool>, Box<dyn std::error::Error>> {
+    let options = SqliteConnectOptions::new().filename(&path).create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await?;
+    sqlx::migrate!("./migrations/sqlite").run(&pool).await?;
+
+    let mut stored = Stored::default();
+    for (record,) in sqlx::query_as::<_, (String,)>("SELECT record FROM players").fetch_all(&pool).await? {
+        let record: PlayerRecord = serde_json::from_str(&record)?;
+        stored.players.insert(record.id.clone(), record);
+    }
+    for (ip, until) in sqlx::query_as::<_, (String, i64)>("SELECT ip, banned_until FROM bans").fetch_all(&pool).await? {
+        stored.bans.insert(ip.parse()?, until as u64);
+    }
+    stored.tokens = sqlx::query_as::<_, (String, String)>("SELECT player_id, token FROM tokens").fetch_all(&pool).await?.into_iter().collect();
+    info!("Loaded {} player(s) from {}", stored.players.len(), path.display());
+    Ok(SqlStorage::new(pool, stored))
+}
+
+impl Database for SqlitePool {
+    fn write<'a>(&'a self, changes: &'a Changes) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            let mut transaction = self.begin().await?;
+            for record in &changes.players {
+                sqlx::query(
+                    "INSERT INTO players (id, best_score, total_score, record) VALUES (?, ?, ?, ?) \
+                     ON CONFLICT (id) DO UPDATE SET best_score = excluded.best_score, \
+                     total_score = excluded.total_score, record = excluded.record",
+                )
+                .bind(&record.id)
+                .bind(record.best_score as i64)
+                .bind(record.total_score as i64)
+                .bind(serde_json::to_string(record).expect("Player records are always serializable"))
+                .execute(&mut *transaction)
+                .await?;
+            }
+            for (ip, until) in &changes.bans {
+                sqlx::query("INSERT INTO bans (ip, banned_until) VALUES (?, ?) ON CONFLICT (ip) DO UPDATE SET banned_until = excluded.banned_until")
+                    .bind(ip.to_string())
+                    .bind(*until as i64)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            if !changes.bans.is_empty() {
+                sqlx::query("DELETE FROM bans WHERE banned_until <= ?").bind(unix_time() as i64).execute(&mut *transaction).await?;
+            }
+            for (player_id, token) in &changes.tokens {
+                sqlx::query("INSERT INTO tokens (player_id, token) VALUES (?, ?) ON CONFLICT (player_id) DO UPDATE SET token = excluded.token")
+                    .bind(player_id)
+                    .bind(token)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await
+        })
+    }
+}
+
+// A Postgres database, with its tables migrated to this version. If it can't
+// be reached, or only read, the server starts anyway: with what could be read,
+// or nothing, and without writing to it until recover_postgres() gets through.
+async fn open_postgres(url: &str, updating: Arc<Mutex<()>>) -> Result<Arc<SqlStorage<PgPool>>, Box<dyn std::error::Error>> {
+    let options = PgPoolOptions::new()
+        .max_connections(MAX_DATABASE_CONNECTIONS)
+        .acquire_timeout(DATABASE_TIMEOUT);
+    let pool = match options.clone().connect(url).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            warn!("Can't reach the player database, running read-only without stats for now: {}", e);
+            let storage = Arc::new(SqlStorage::new(options.connect_lazy(url)?, Stored::default()));
+            storage.read_only.store(true, Ordering::Relaxed);
+            tokio::spawn(recover_postgres(storage.clone(), false, updating));
+            return Ok(storage);
+        }
+    };
+    let migrated = sqlx::migrate!("./migrations/postgres").run(&pool).await;
+    if let Err(e) = &migrated {
+        warn!("Can't migrate the player database, running read-only for now: {}", e);
+    }
+    let stored = match load_postgres(&pool).await {
+        Ok(stored) => Some(stored),
+        Err(e) if migrated.is_err() => {
+            warn!("Can't read the player database either: {}", e);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let loaded = stored.is_some();
+    let stored = stored.unwrap_or_default();
+    info!("Loaded {} player(s) from the player database", stored.players.len());
+    let storage = Arc::new(SqlStorage::new(pool, stored));
+    if migrated.is_err() {
+        storage.read_only.store(true, Ordering::Relaxed);
+        tokio::spawn(recover_postgres(storage.clone(), loaded, updating));
+    }
+    Ok(storage)
+}
+
+// Try a database that was read-only at startup again every DATABASE_RETRY
+// until its tables migrate. Then take in its records, unless they were read
+// at startup, and write everything that changed meanwhile.
+async fn recover_postgres(storage: Arc<SqlStorage<PgPool>>, loaded: bool, updating: Arc<Mutex<()>>) {
+    loop {
+        tokio::time::sleep(DATABASE_RETRY).await;
+        if let Err(e) = sqlx::migrate!("./migrations/postgres").run(&storage.database).await {
+            debug!("Player database still read-only: {}", e);
+            continue;
+        }
+        if !loaded {
+            let stored = match load_postgres(&storage.database).await {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("Can't read the player database: {}", e);
+                    continue;
+                }
+            };
+            info!("Loaded {} player(s) from the player database", stored.players.len());
+            // Holds off PlayerStore::update, which would put back a record read before
+            let _updating = updating.lock().unwrap();
+            storage.take_in(stored);
+        }
+        storage.read_only.store(false, Ordering::Relaxed);
+        info!("Saving player stats to the player database again");
+        storage.flush().await;
+        return;
+    }
+}
+
+async fn load_postgres(pool: &PgPool) -> Result<Stored, Box<dyn std::error::Error>> {
+    let mut stored = Stored::default();
+    for (record,) in sqlx::query_as::<_, (String,)>("SELECT record::text FROM players").fetch_all(pool).await? {
+        let record: PlayerRecord = serde_json::from_str(&record)?;
+        stored.players.insert(record.id.clone(), record);
+    }
+    for (ip, until) in sqlx::query_as::<_, (String, i64)>("SELECT ip, banned_until FROM bans").fetch_all(pool).await? {
+        stored.bans.insert(ip.parse()?, until as u64);
+    }
+    stored.tokens = sqlx::query_as::<_, (String, String)>("SELECT player_id, token FROM tokens").fetch_all(pool).await?.into_iter().collect();
+    Ok(stored)
+}
+
+impl Database for PgPool {
+    fn write<'a>(&'a self, changes: &'a Changes) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            let mut transaction = self.begin().await?;
+            for record in &changes.players {
+                sqlx::query(
+                    "INSERT INTO players (id, best_score, total_score, record) VALUES ($1, $2, $3, $4::jsonb) \
+                     ON CONFLICT (id) DO UPDATE SET best_score = EXCLUDED.best_score, \
+                     total_score = EXCLUDED.total_score, record = EXCLUDED.record",
+                )
+                .bind(&record.id)
+                .bind(record.best_score as i64)
+                .bind(record.total_score as i64)
+                .bind(serde_json::to_string(record).expect("Player records are always serializable"))
+                .execute(&mut *transaction)
+                .await?;
+            }
+            for (ip, until) in &changes.bans {
+                sqlx::query("INSERT INTO bans (ip, banned_until) VALUES ($1, $2) ON CONFLICT (ip) DO UPDATE SET banned_until = EXCLUDED.banned_until")
+                    .bind(ip.to_string())
+                    .bind(*until as i64)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            if !changes.bans.is_empty() {
+                sqlx::query("DELETE FROM bans WHERE banned_until <= $1").bind(unix_time() as i64).execute(&mut *transaction).await?;
+            }
+            for (player_id, token) in &changes.tokens {
+                sqlx::query("INSERT INTO tokens (player_id, token) VALUES ($1, $2) ON CONFLICT (player_id) DO UPDATE SET token = EXCLUDED.token")
+                    .bind(player_id)
+                    .bind(token)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await
+        })
+    }
+}
+
+// Player history and bans, on top of the configured backend
+pub struct PlayerStore {
+    storage: Arc<dyn Storage>,
+    // Serializes read-modify-write of records so no change gets lost
+    updating: Arc<Mutex<()>>,
+}
+
+impl PlayerStore {
+    // Open the configured backend. The JSON one falls back to memory without
+    // a path; the SQLite one needs a path and the Postgres one a database URL.
+    pub async fn open(backend: StorageBackend, path: Option<PathBuf>, database_url: Option<&str>) -> Result<PlayerStore, Box<dyn std::error::Error>> {
+        let updating = Arc::new(Mutex::new(()));
+        let storage: Arc<dyn Storage> = match (backend, path, database_url) {
+            (StorageBackend::Memory, _, _) | (StorageBackend::Json, None, _) => Arc::new(MemoryStorage::default()),
+            (StorageBackend::Json, Some(path), _) => Arc::new(JsonStorage::open(path)?),
+            (StorageBackend::Sqlite, Some(path), _) => Arc::new(open_sqlite(path).await?),
+            (StorageBackend::Sqlite, None, _) => return Err("storage = \"sqlite\" needs a stats_path".into()),
+            (StorageBackend::Postgres, _, Some(url)) => open_postgres(url, updating.clone()).await?,
+            (StorageBackend::Postgres, _, None) => return Err("storage = \"postgres\" needs a database_url".into()),
+        };
+        Ok(PlayerStore { storage, updating })
+    }
+
+    // Change a player's record, creating it if there's none yet; save() writes it out
+    fn update<T>(&self, player_id: &str, change: impl FnOnce(&mut PlayerRecord) -> T) -> T {
+        let _updating = self.updating.lock().unwrap();
+        let mut record = self.storage.get_player(player_id).unwrap_or_else(|| PlayerRecord {
+            id: player_id.to_string(),
+            ..PlayerRecord::default()
+        });
+        let result = change(&mut record);
+        self.storage.put_player(record);
+        result
+    }
+
+    // Record a session that ended, with where the player left it and the
+    // token they can come back with
+    pub async fn record_session(&self, player_id: &str, session: SessionRecord, position: LastPosition, token: &str) {
+        self.storage.put_token(token, player_id);
+        self.update(player_id, |record| {
+            record.last_position = Some(position);
+            record.best_score = record.best_score.max(session.score);
+            record.total_score += session.score as u64;
+            record.playtime_secs += session.left_at.saturating_sub(session.joined_at);
+            record.sessions.push(session);
+            if record.sessions.len() > MAX_SESSIONS_PER_PLAYER {
+                record.sessions.remove(0);
+            }
+        });
+        self.save().await;
+    }
+
+    // The record of the player a session token belongs to, for joins after
+    // the session expired or the server restarted
+    pub fn player_by_token(&self, token: &str) -> Option<PlayerRecord> {
+        let player_id = self.storage.get_token(token)?;
+        self.storage.get_player(&player_id)
+    }
+
+    // Hand a logged in player a session token, creating their record if it's
+    // their first time
+    pub async fn log_in(&self, player_id: &str, token: &str) {
+        self.update(player_id, |_| ());
+        self.storage.put_token(token, player_id);
+        self.save().await;
+    }
+
+    // Give a player an achievement, returning whether it's new
+    pub fn award(&self, player_id: &str, achievement: Achievement) -> bool {
+        self.update(player_id, |record| {
+            if record.achievements.contains(&achievement) {
+                return false;
+            }
+            record.achievements.push(achievement);
+            true
+        })
+    }
+
+    // Add cosmetics to a player's inventory, returning the whole inventory
+    pub fn unlock(&self, player_id: &str, cosmetics: &[Cosmetic]) -> Vec<Cosmetic> {
+        self.update(player_id, |record| {
+            for cosmetic in cosmetics {
+                if !record.unlocks.contains(cosmetic) {
+                    record.unlocks.push(*cosmetic);
+                }
+            }
+            record.unlocks.clone()
+        })
+    }
+
+    pub async fn save(&self) {
+        self.storage.flush().await;
+    }
+
+    pub fn leaderboard(&self, limit: usize) -> Vec<LeaderboardEntry> {
+        self.storage.top_scores(limit)
+    }
+
+    pub fn player(&self, id: &str) -> Option<PlayerRecord> {
+        self.storage.get_player(id)
+    }
+
+    // Refuse new connections from this IP for a while
+    pub async fn ban(&self, ip: IpAddr, duration: Duration) {
+        info!("Banning {} for {:?}", ip, duration);
+        self.storage.put_ban(ip, unix_time() + duration.as_secs());
+        self.save().await;
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.storage.get_ban(ip).is_some_and(|until| until > unix_time())
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
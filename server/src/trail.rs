@@ -0,0 +1,117 @@
+use sim::grid;
+
+use crate::bus::{Event, EventBus};
+use crate::map::Map;
+use crate::spawn;
+use crate::stats::WorldStats;
+use crate::game::{GameEvent, GameState};
+use crate::protocol::ServerMessage;
+
+// Distance a player moves before its trail gets another segment
+const SEGMENT_SPACING: u32 = 10;
+// Longest trail, in segments; the oldest ones fall off
+const MAX_SEGMENTS: usize = 400;
+// Segments behind the player's center that it can't run into, since it just left them.
+// Players with a shorter trail can't be eliminated, so spawning on a trail isn't fatal.
+const SAFE_SEGMENTS: usize = 8;
+
+// Trail mode: every player drags a trail of segments behind its center. Touching
+// any trail eliminates a player until the round ends, which happens when at most
+// one is left; the last one standing scores and everyone starts over.
+// Without friendly fire only their own trail eliminates a player.
+// Returns whether anything changed and the eliminations and end of the round
+// to tell the clients about.
+pub fn update(
+    state: &mut GameState,
+    bus: &EventBus,
+    stats: &WorldStats,
+    map: &Map,
+    friendly_fire: bool,
+) -> (bool, Vec<ServerMessage>) {
+    let mut changed = false;
+
+    // Extend the trails of players that moved far enough
+    for player in state.players.values().filter(|player| !player.eliminated) {
+        let center = (player.x + player.size / 2, player.y + player.size / 2);
+        let trail = state.trails.entry(player.id.clone()).or_default();
+        let far_enough = trail
+            .last()
+            .is_none_or(|last| last.0.abs_diff(center.0) + last.1.abs_diff(center.1) >= SEGMENT_SPACING);
+        if far_enough {
+            trail.push(center);
+            if trail.len() > MAX_SEGMENTS {
+                trail.remove(0);
+            }
+            changed = true;
+        }
+    }
+
+    // Eliminate players touching any trail; ghosts pass over them
+    let mut eliminated = Vec::new();
+    for player in state.players.values().filter(|player| !player.eliminated && !player.ghost) {
+        if state.trails.get(&player.id).is_none_or(|trail| trail.len() < SAFE_SEGMENTS) {
+            continue;
+        }
+        let hit = state.trails.iter().filter(|(owner, _)| friendly_fire || **owner == player.id).any(|(owner, trail)| {
+            let segments = if *owner == player.id {
+                &trail[..trail.len().saturating_sub(SAFE_SEGMENTS)]
+            } else {
+                &trail[..]
+            };
+            segments.iter().any(|&(x, y)| {
+                x >= player.x && x < player.x + player.size && y >= player.y && y < player.y + player.size
+            })
+        });
+        if hit {
+            eliminated.push(player.id.clone());
+        }
+    }
+    let mut messages = Vec::new();
+    for player_id in eliminated {
+        if let Some(player) = state.players.get_mut(&player_id) {
+            player.eliminated = true;
+            changed = true;
+            messages.push(ServerMessage::GameEvent {
+                event: GameEvent::Eliminated { player_id },
+            });
+        }
+    }
+
+    // A round needs two players to have a winner; alone you just play until you crash
+    let alive: Vec<&String> = state.players.values().filter(|p| !p.eliminated).map(|p| &p.id).collect();
+    let round_over = match state.players.len() {
+        0 => false,
+        1 => alive.is_empty(),
+        _ => alive.len() <= 1,
+    };
+    if !round_over {
+        state.trails.retain(|owner, _| state.players.contains_key(owner));
+        return (changed, messages);
+    }
+
+    let winner = if state.players.len() > 1 { alive.first().map(|id| id.to_string()) } else { None };
+    if let Some(player) = winner.as_ref().and_then(|id| state.players.get_mut(id)) {
+        player.score += 1;
+        bus.publish(Event::RoundWon { player_id: player.id.clone() });
+    }
+    reset(state, map);
+    messages.push(ServerMessage::RoundOver { winner, stats: stats.all() });
+    (true, messages)
+}
+
+// Bring everyone back at a spawn point each and clear the field for the next round
+pub fn reset(state: &mut GameState, map: &Map) {
+    state.trails.clear();
+    let mut ids: Vec<String> = state.players.keys().cloned().collect();
+    ids.sort();
+    for (index, id) in ids.iter().enumerate() {
+        let others = state.players.values().filter(|other| other.id != *id);
+        let size = state.players[id].size;
+        let (x, y) = spawn::pick(map, size, others, index as u64);
+        let player = state.players.get_mut(id).expect("listed above");
+        (player.x, player.y, player.eliminated) = (x, y, false);
+        if let Some(cell_size) = map.cell_size {
+            player.simulate(|body| grid::snap(body, cell_size));
+        }
+    }
+}
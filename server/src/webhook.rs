@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+use crate::bus::{Event, Subscriber};
+use crate::config::SharedConfig;
+use crate::http;
+use crate::world::Worlds;
+
+// Events waiting to be posted; once this many are, new ones are dropped
+const MAX_QUEUED: usize = 100;
+// Discord refuses messages longer than this
+const MAX_CONTENT_CHARS: usize = 2000;
+// Longest wait after Discord asked us to slow down
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+// Something worth telling the webhook about, with players still by ID
+enum Post {
+    Started,
+    RoundWon { world: String, player_id: String },
+    Milestone { world: String, player_id: String, score: u32 },
+}
+
+// Events on their way to the webhook. Worlds only ever add to it, without
+// waiting, and the poster takes them at its own pace.
+pub struct Outbox {
+    queue: Mutex<VecDeque<Post>>,
+    enabled: AtomicBool,
+    // A score reaching a multiple of it is a milestone; 0 for none
+    milestone_points: AtomicU32,
+    dropped: AtomicU64,
+}
+
+pub static OUTBOX: Outbox = Outbox {
+    queue: Mutex::new(VecDeque::new()),
+    enabled: AtomicBool::new(false),
+    milestone_points: AtomicU32::new(0),
+    dropped: AtomicU64::new(0),
+};
+
+impl Outbox {
+    fn push(&self, post: Post) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= MAX_QUEUED {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        queue.push_back(post);
+    }
+
+    fn take(&self) -> Vec<Post> {
+        self.queue.lock().unwrap().drain(..).collect()
+    }
+}
+
+// Hands a world's round wins and score milestones to the outbox
+pub struct WebhookEvents {
+    pub world: String,
+}
+
+impl Subscriber for WebhookEvents {
+    fn notify(&self, event: &Event) {
+        let world = self.world.clone();
+        match event {
+            Event::RoundWon { player_id } => OUTBOX.push(Post::RoundWon { world, player_id: player_id.clone() }),
+            Event::Scored { player_id, score, gained } => {
+                let every = OUTBOX.milestone_points.load(Ordering::Relaxed);
+                // Crossed a multiple of `every` on the way up
+                if every > 0 && score / every > (score - gained) / every {
+                    let score = score / every * every;
+                    OUTBOX.push(Post::Milestone { world, player_id: player_id.clone(), score });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// What Discord takes; mentions are never pinged, whatever players call themselves
+#[derive(Serialize)]
+struct Message<'a> {
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<&'a str>,
+    allowed_mentions: AllowedMentions,
+}
+
+#[derive(Serialize)]
+struct AllowedMentions {
+    parse: [&'static str; 0],
+}
+
+// Post what's in the outbox to the configured webhook, one message every
+// interval_secs at most: whatever came in since goes in it, and what doesn't
+// fit, or Discord asked us to hold back, in the next ones.
+pub async fn post_periodically(worlds: Arc<Worlds>, config: Arc<SharedConfig>) {
+    let mut started = false;
+    let mut backoff = None;
+    let mut failing = false;
+    let mut unsent: VecDeque<String> = VecDeque::new();
+    loop {
+        let settings = config.get();
+        let webhook = &settings.webhook;
+        OUTBOX.enabled.store(webhook.url.is_some(), Ordering::Relaxed);
+        OUTBOX.milestone_points.store(webhook.milestone_points, Ordering::Relaxed);
+        if webhook.url.is_some() && !started {
+            OUTBOX.push(Post::Started);
+            started = true;
+        }
+        sleep(backoff.take().unwrap_or(webhook.interval())).await;
+
+        let Some(url) = &webhook.url else {
+            OUTBOX.take();
+            unsent.clear();
+            continue;
+        };
+        let dropped = OUTBOX.dropped.swap(0, Ordering::Relaxed);
+        let mut lines: Vec<String> = OUTBOX.take().into_iter().map(|post| describe(&post, &worlds, &settings.name)).collect();
+        if dropped > 0 {
+            lines.push(format!("...and {} more that didn't fit", dropped));
+        }
+        unsent.extend(chunks(lines));
+        unsent.truncate(MAX_QUEUED);
+        if let Some(content) = unsent.pop_front() {
+            let message = Message {
+                content,
+                username: webhook.username.as_deref(),
+                allowed_mentions: AllowedMentions { parse: [] },
+            };
+            // Only log changes, not every failed post
+            match http::post_json(url, None, &message).await {
+                Ok(200..=299) => {
+                    if failing {
+                        info!("Posting to the webhook again");
+                    }
+                    failing = false;
+                }
+                Ok(429) => {
+                    let wait = (webhook.interval() * 4).min(MAX_BACKOFF);
+                    warn!("The webhook asked us to slow down, waiting {}s", wait.as_secs());
+                    unsent.push_front(message.content);
+                    backoff = Some(wait);
+                }
+                Ok(status) => {
+                    // Discord's own trouble passes; a post it refused would be refused again
+                    if status >= 500 {
+                        unsent.push_front(message.content);
+                    }
+                    if !failing {
+                        warn!("The webhook answered {}", status);
+                        failing = true;
+                    }
+                }
+                Err(e) => {
+                    unsent.push_front(message.content);
+                    if !failing {
+                        warn!("Couldn't post to the webhook: {}", e);
+                        failing = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// One line for a post, with players by the names they play under
+fn describe(post: &Post, worlds: &Worlds, server: &str) -> String {
+    let name = |world: &str, player_id: &str| {
+        worlds
+            .get(Some(world))
+            .and_then(|world| world.game_state.lock().unwrap().players.get(player_id).map(|player| player.name.clone()))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| player_id.to_string())
+    };
+    match post {
+        Post::Started => {
+            let mut names: Vec<String> = worlds.iter().map(|world| world.name.clone()).collect();
+            names.sort();
+            format!("{} started, hosting {}", server, names.join(", "))
+        }
+        Post::RoundWon { world, player_id } => format!("{} won a round in {}", name(world, player_id), world),
+        Post::Milestone { world, player_id, score } => format!("{} reached {} points in {}", name(world, player_id), score, world),
+    }
+}
+
+// The lines joined into messages short enough for Discord
+fn chunks(lines: Vec<String>) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    for line in lines {
+        let line: String = line.chars().take(MAX_CONTENT_CHARS).collect();
+        match messages.last_mut() {
+            Some(last) if last.chars().count() + 1 + line.chars().count() <= MAX_CONTENT_CHARS => {
+                last.push('\n');
+                last.push_str(&line);
+            }
+            _ => messages.push(line),
+        }
+    }
+    messages
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+// Colors kept in a world's palette; past that, new ones go as text
+const MAX_PALETTE: usize = 1024;
+
+// Small numbers standing in for the strings snapshots would repeat: player IDs
+// and colors. Clients learn what a number stands for from the roster and from
+// Palette messages, and a snapshot only uses it once they were told; until
+// then it spells the string out.
+#[derive(Default)]
+pub struct WireIds {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default)]
+pub struct Tables {
+    // Number of each player in the world, and whether everyone was told it
+    players: HashMap<String, Entry>,
+    next_player: u32,
+    // Index of each color in the palette, and whether everyone was told it
+    colors: HashMap<String, Entry>,
+}
+
+struct Entry {
+    number: u32,
+    announced: bool,
+}
+
+impl WireIds {
+    // Give a player that's joining a number; numbers aren't reused, so a
+    // client can't mistake a new player for one that left
+    pub fn assign(&self, player_id: &str) {
+        let mut tables = self.tables.lock().unwrap();
+        let number = tables.next_player;
+        tables.next_player += 1;
+        tables.players.insert(player_id.to_string(), Entry { number, announced: false });
+    }
+
+    // The player's number, told to clients or not, for the roster
+    pub fn number(&self, player_id: &str) -> Option<u32> {
+        self.tables.lock().unwrap().players.get(player_id).map(|entry| entry.number)
+    }
+
+    // Everyone got a roster entry with the player's number
+    pub fn announce(&self, player_id: &str) {
+        if let Some(entry) = self.tables.lock().unwrap().players.get_mut(player_id) {
+            entry.announced = true;
+        }
+    }
+
+    pub fn release(&self, player_id: &str) {
+        self.tables.lock().unwrap().players.remove(player_id);
+    }
+
+    // Put the colors that aren't in the palette yet in it, returning them with
+    // their indexes for the caller to send, then to `announce_colors`
+    pub fn add_colors<'a>(&self, colors: impl Iterator<Item = &'a str>) -> Vec<(u32, String)> {
+        let mut tables = self.tables.lock().unwrap();
+        let mut added = Vec::new();
+        for color in colors {
+            if tables.colors.len() >= MAX_PALETTE || tables.colors.contains_key(color) {
+                continue;
+            }
+            let number = tables.colors.len() as u32;
+            tables.colors.insert(color.to_string(), Entry { number, announced: false });
+            added.push((number, color.to_string()));
+        }
+        added
+    }
+
+    pub fn announce_colors(&self, colors: &[(u32, String)]) {
+        let mut tables = self.tables.lock().unwrap();
+        for (_, color) in colors {
+            if let Some(entry) = tables.colors.get_mut(color) {
+                entry.announced = true;
+            }
+        }
+    }
+
+    // The colors clients were told about, for a client that's joining
+    pub fn palette(&self) -> Vec<(u32, String)> {
+        let tables = self.tables.lock().unwrap();
+        let mut palette: Vec<(u32, String)> = tables
+            .colors
+            .iter()
+            .filter(|(_, entry)| entry.announced)
+            .map(|(color, entry)| (entry.number, color.clone()))
+            .collect();
+        palette.sort();
+        palette
+    }
+
+    // The tables as they are, for encoding a snapshot
+    pub fn lock(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap()
+    }
+}
+
+impl Tables {
+    // What a snapshot may call this player or color by
+    pub fn player(&self, player_id: &str) -> Option<u32> {
+        self.players.get(player_id).filter(|entry| entry.announced).map(|entry| entry.number)
+    }
+
+    pub fn color(&self, color: &str) -> Option<u32> {
+        self.colors.get(color).filter(|entry| entry.announced).map(|entry| entry.number)
+    }
+}
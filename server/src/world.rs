@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use crate::achievements::Progress;
+use crate::bus::{EventBus, EventLog};
+use crate::config::{Config, GameMode, Netcode, WorldConfig};
+use crate::events::EventScheduler;
+use crate::lockstep::Hashes;
+use crate::map::Map;
+use crate::matches::Match;
+use crate::metrics::EventCounter;
+use crate::profiler::Profiler;
+use crate::rooms::{Members, RoomConfig};
+use crate::stats::WorldStats;
+use crate::stepping::Stepper;
+use crate::webhook::WebhookEvents;
+use crate::broadcast::Clients;
+use crate::game::{GameState, GameStateSync};
+
+// One independently simulated world with its own players and clients
+pub struct World {
+    pub name: String,
+    pub mode: GameMode,
+    pub netcode: Netcode,
+    pub clients: Clients,
+    pub game_state: GameStateSync,
+    pub events: Mutex<EventScheduler>,
+    // Keys each player is holding, applied every tick
+    pub inputs: Mutex<HashMap<String, HeldKeys>>,
+    // Where what happens in the world is published, for the stats, achievements and metrics
+    pub bus: EventBus,
+    pub stats: Arc<WorldStats>,
+    pub achievements: Arc<Progress>,
+    // What the state hashed to after the last relayed ticks, in lockstep worlds
+    pub hashes: Hashes,
+    // Where its latest ticks spent their time
+    pub profiler: Profiler,
+    // Pausing and single-stepping by an admin
+    pub stepper: Stepper,
+    // An admin's announcement counting down, and when it reaches zero, for
+    // players joining before it does
+    pub countdown: Mutex<Option<(String, Instant)>>,
+    // The rules picked by the player who created it, as its host last changed
+    // them, for rooms; None for the configured worlds
+    pub room: Option<RwLock<RoomConfig>>,
+    // Who's in a room, and which of them is its host
+    pub members: Option<Mutex<Members>>,
+    // When a private room's invite code stops letting new players in
+    pub invite_expires: Option<Instant>,
+    // Rooms play matches, started once their players are ready
+    pub round: Option<Mutex<Match>>,
+    // Set once a room is closed, so its tasks stop
+    closed: AtomicBool,
+    map: RwLock<Arc<Map>>,
+}
+
+// The latest Input message of a player
+#[derive(Clone, Copy, Default)]
+pub struct HeldKeys {
+    pub seq: u32,
+    pub keys: u8,
+}
+
+impl World {
+    pub fn new(name: &str, mode: GameMode, netcode: Netcode, map: Map, state: GameState, room: Option<RoomConfig>) -> World {
+        let stats = Arc::new(WorldStats::default());
+        let achievements = Arc::new(Progress::default());
+        let bus = EventBus::default();
+        bus.subscribe(stats.clone());
+        bus.subscribe(achievements.clone());
+        bus.subscribe(Arc::new(EventCounter));
+        bus.subscribe(Arc::new(EventLog { world: name.to_string() }));
+        bus.subscribe(Arc::new(WebhookEvents { world: name.to_string() }));
+        World {
+            name: name.to_string(),
+            mode,
+            netcode,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            game_state: Arc::new(Mutex::new(state)),
+            events: Mutex::new(EventScheduler::default()),
+            inputs: Mutex::new(HashMap::new()),
+            bus,
+            stats,
+            achievements,
+            hashes: Hashes::default(),
+            profiler: Profiler::default(),
+            stepper: Stepper::default(),
+            countdown: Mutex::new(None),
+            round: room.is_some().then(Mutex::default),
+            members: room.is_some().then(Mutex::default),
+            room: room.map(RwLock::new),
+            invite_expires: None,
+            closed: AtomicBool::new(false),
+            map: RwLock::new(Arc::new(map)),
+        }
+    }
+
+    // Pixels per tick players move at here
+    pub fn player_speed(&self, config: &Config) -> u32 {
+        self.room.as_ref().and_then(|room| room.read().unwrap().speed).unwrap_or(config.player_speed)
+    }
+
+    pub fn max_players(&self, config: &Config) -> usize {
+        self.room.as_ref().and_then(|room| room.read().unwrap().max_players).unwrap_or_else(|| config.max_players_in(&self.name))
+    }
+
+    // Whether players can take each other out: tag each other through the
+    // rules, and be eliminated by each other's trails
+    pub fn friendly_fire(&self) -> bool {
+        self.room.as_ref().is_none_or(|room| room.read().unwrap().friendly_fire)
+    }
+
+    // A room only players with its invite can find and join
+    pub fn is_private(&self) -> bool {
+        self.room.as_ref().is_some_and(|room| room.read().unwrap().private)
+    }
+
+    // Whether `code` is this room's invite, and still lets players in
+    pub fn accepts_invite(&self, code: &str) -> bool {
+        let matches = self.rules().and_then(|room| room.code).is_some_and(|own| own.eq_ignore_ascii_case(code));
+        matches && self.invite_expires.is_some_and(|expires| Instant::now() < expires)
+    }
+
+    // A room's rules as they are now
+    pub fn rules(&self) -> Option<RoomConfig> {
+        self.room.as_ref().map(|room| room.read().unwrap().clone())
+    }
+
+    pub fn set_rules(&self, rules: RoomConfig) {
+        if let Some(room) = &self.room {
+            *room.write().unwrap() = rules;
+        }
+    }
+
+    // Whether players move; in rooms, only once the match started
+    pub fn is_playing(&self) -> bool {
+        self.round.as_ref().is_none_or(|round| round.lock().unwrap().is_playing())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn map(&self) -> Arc<Map> {
+        self.map.read().unwrap().clone()
+    }
+
+    pub fn set_map(&self, map: Map) {
+        *self.map.write().unwrap() = Arc::new(map);
+    }
+}
+
+// Load the map a world is configured with
+pub fn load_map(config: &WorldConfig) -> Result<Map, Box<dyn std::error::Error>> {
+    match &config.map {
+        Some(path) => Map::load(path).map_err(|e| format!("Failed to load map {}: {}", path.display(), e).into()),
+        None => Ok(Map::default()),
+    }
+}
+
+// All worlds hosted by this server: the configured ones, fixed at startup,
+// and rooms players created
+pub struct Worlds {
+    worlds: RwLock<HashMap<String, Arc<World>>>,
+    default: String,
+}
+
+impl Worlds {
+    // Create the configured worlds, picking up restored state where there is some
+    pub fn new(
+        configs: &[WorldConfig],
+        mut restored: HashMap<String, GameState>,
+    ) -> Result<Worlds, Box<dyn std::error::Error>> {
+        let default = configs.first().ok_or("At least one world must be configured")?.name.clone();
+        let mut worlds = HashMap::new();
+        for config in configs {
+            let state = restored.remove(&config.name).unwrap_or_else(empty_state);
+            let world = World::new(&config.name, config.mode, config.netcode, load_map(config)?, state, None);
+            worlds.insert(config.name.clone(), Arc::new(world));
+        }
+        Ok(Worlds { worlds: RwLock::new(worlds), default })
+    }
+
+    // Look up a world by name; None means the default (first configured) world
+    pub fn get(&self, name: Option<&str>) -> Option<Arc<World>> {
+        self.worlds.read().unwrap().get(name.unwrap_or(&self.default)).cloned()
+    }
+
+    // The worlds as of now; rooms opened or closed meanwhile don't show up
+    pub fn iter(&self) -> impl Iterator<Item = Arc<World>> {
+        self.worlds.read().unwrap().values().cloned().collect::<Vec<_>>().into_iter()
+    }
+
+    // Add a room, unless there's a world by that name already
+    pub fn insert(&self, world: Arc<World>) -> bool {
+        let mut worlds = self.worlds.write().unwrap();
+        if worlds.contains_key(&world.name) {
+            return false;
+        }
+        worlds.insert(world.name.clone(), world);
+        true
+    }
+
+    // Close a room, stopping its tasks
+    pub fn remove(&self, name: &str) {
+        if let Some(world) = self.worlds.write().unwrap().remove(name) {
+            world.closed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // The room with this invite code, whether or not it has expired
+    pub fn by_invite(&self, code: &str) -> Option<Arc<World>> {
+        let worlds = self.worlds.read().unwrap();
+        let code = Some(code.to_ascii_uppercase());
+        worlds.values().find(|world| world.rules().is_some_and(|room| room.code == code)).cloned()
+    }
+
+    // Rooms open right now
+    pub fn rooms(&self) -> usize {
+        self.worlds.read().unwrap().values().filter(|world| world.room.is_some()).count()
+    }
+}
+
+pub fn empty_state() -> GameState {
+    GameState {
+        players: HashMap::new(),
+        food: Vec::new(),
+        trails: HashMap::new(),
+        wire: Default::default(),
+    }
+}
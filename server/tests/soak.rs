@@ -0,0 +1,195 @@
+// Bots joining and leaving a real server over and over, checking that what
+// the server holds on to (per /metrics) and its memory stay bounded, and that
+// everything is let go once they're gone. Runs for a few seconds by default;
+// soak for longer with e.g. SOAK_SECS=14400 SOAK_BOTS=32.
+
+use std::collections::HashMap;
+use std::net::TcpListener as StdListener;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// How long the server gets to let go of everything after the last bot left
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+// Growth in resident memory allowed between the end of the warm-up and the end
+const RSS_SLACK_KB: u64 = 32 * 1024;
+
+// The server process, killed when the test ends either way
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn free_port() -> u16 {
+    StdListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn start_server(game_port: u16, http_port: u16) -> Server {
+    let config = std::env::temp_dir().join(format!("soak-{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        format!(
+            "bind = \"127.0.0.1:{}\"\nhttp_bind = \"127.0.0.1:{}\"\nstorage = \"memory\"\n\
+             max_connections_per_ip = 256\nmax_players = 256\nsession_resume_secs = 1\n",
+            game_port, http_port
+        ),
+    )
+    .unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("--config")
+        .arg(&config)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start the server");
+    Server(child)
+}
+
+// The gauges and counters of GET /metrics
+async fn metrics(http_port: u16) -> Option<HashMap<String, f64>> {
+    let mut stream = TcpStream::connect(("127.0.0.1", http_port)).await.ok()?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.ok()?;
+    let (_, body) = response.split_once("\r\n\r\n")?;
+    Some(
+        body.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+            .collect(),
+    )
+}
+
+// Resident memory of a process, where /proc has it
+fn rss_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+// Join, move around for a bit, then leave one of three ways, until `until`
+async fn bot(url: String, id: u64, until: Instant) -> u64 {
+    let mut cycles = 0;
+    while Instant::now() < until {
+        cycles += 1;
+        let Ok((mut ws, _)) = connect_async(&url).await else {
+            sleep(Duration::from_millis(50)).await;
+            continue;
+        };
+        let join = format!("{{\"Join\":{{\"name\":\"bot{}\"}}}}", id);
+        if ws.send(Message::Text(join)).await.is_err() {
+            continue;
+        }
+        for seq in 0..(5 + (id + cycles) % 10) as u32 {
+            let input = format!("{{\"Input\":{{\"seq\":{},\"keys\":{}}}}}", seq, 1 << (seq % 4));
+            if ws.send(Message::Text(input)).await.is_err() {
+                break;
+            }
+            if seq % 4 == 0 {
+                let chat = format!("{{\"Chat\":{{\"text\":\"hello from bot{}\"}}}}", id);
+                let _ = ws.send(Message::Text(chat)).await;
+            }
+            // Read whatever came in meanwhile
+            while let Ok(Some(Ok(_))) = timeout(Duration::from_millis(5), ws.next()).await {}
+        }
+        match (id + cycles) % 3 {
+            // Leave properly and wait for the server to close
+            0 => {
+                let _ = ws.send(Message::Text("\"Leave\"".to_string())).await;
+                let _ = timeout(Duration::from_secs(2), async { while let Some(Ok(_)) = ws.next().await {} }).await;
+            }
+            // Close from our end
+            1 => {
+                let _ = ws.close(None).await;
+            }
+            // Just go away, like a crashed tab
+            _ => drop(ws),
+        }
+    }
+    cycles
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_lets_go_of_bots_that_left() {
+    let soak = Duration::from_secs(env_or("SOAK_SECS", 5));
+    let bots = env_or("SOAK_BOTS", 8);
+    let (game_port, http_port) = (free_port(), free_port());
+    let server = start_server(game_port, http_port);
+    let pid = server.0.id();
+
+    let started = Instant::now();
+    while metrics(http_port).await.is_none() {
+        assert!(started.elapsed() < Duration::from_secs(10), "The server didn't come up");
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    let url = format!("ws://127.0.0.1:{}/", game_port);
+    let until = Instant::now() + soak;
+    let handles: Vec<_> = (0..bots).map(|id| tokio::spawn(bot(url.clone(), id, until))).collect();
+
+    // While they come and go, nothing may grow past what the bots can account for
+    let warmed_up = Instant::now() + soak / 3;
+    let mut baseline_rss = None;
+    while Instant::now() < until {
+        sleep(SAMPLE_INTERVAL).await;
+        let metrics = metrics(http_port).await.expect("No metrics");
+        let gauge = |name: &str| metrics.get(name).copied().unwrap_or_default();
+        assert!(gauge("game_clients") <= bots as f64, "{} clients for {} bots", gauge("game_clients"), bots);
+        assert!(gauge("game_players") <= bots as f64, "{} players for {} bots", gauge("game_players"), bots);
+        // A few connections may be on their way out while their bot is on its way back
+        assert!(gauge("game_connections") <= 2.0 * bots as f64, "{} connections", gauge("game_connections"));
+        assert!(gauge("game_connection_tasks") <= 2.0 * gauge("game_connections"), "{} tasks", gauge("game_connection_tasks"));
+        assert!(gauge("game_queued_messages") < 1000.0, "{} queued messages", gauge("game_queued_messages"));
+        if baseline_rss.is_none() && Instant::now() >= warmed_up {
+            baseline_rss = rss_kb(pid);
+        }
+    }
+    let mut cycles = 0;
+    for handle in handles {
+        cycles += handle.await.unwrap();
+    }
+    assert!(cycles > bots, "The bots only managed {} joins", cycles);
+
+    // Once everyone's gone and their sessions ran out, one last join prunes them
+    sleep(Duration::from_millis(1500)).await;
+    bot(url, bots, Instant::now() + Duration::from_millis(1)).await;
+
+    let settled = Instant::now() + SETTLE_TIMEOUT;
+    let metrics = loop {
+        let metrics = metrics(http_port).await.expect("No metrics");
+        let held = ["game_connections", "game_connection_tasks", "game_clients", "game_players", "game_held_inputs"]
+            .iter()
+            .any(|name| metrics.get(*name).copied().unwrap_or_default() > 0.0);
+        if !held || Instant::now() > settled {
+            break metrics;
+        }
+        sleep(Duration::from_millis(100)).await;
+    };
+    for name in ["game_connections", "game_connection_tasks", "game_clients", "game_players", "game_held_inputs", "game_queued_messages"] {
+        assert_eq!(metrics.get(name).copied(), Some(0.0), "{} after {} joins", name, cycles);
+    }
+    // The last bot's session waits to be resumed; its IP's last join is remembered
+    assert!(metrics["game_sessions"] <= 1.0, "{} sessions kept", metrics["game_sessions"]);
+    assert!(metrics["game_tracked_ips"] <= 1.0, "{} IPs tracked", metrics["game_tracked_ips"]);
+
+    if let (Some(baseline), Some(end)) = (baseline_rss, rss_kb(pid)) {
+        assert!(end <= baseline + baseline / 2 + RSS_SLACK_KB, "Memory grew from {} kB to {} kB", baseline, end);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Body, WORLD_HEIGHT, WORLD_WIDTH};
+
+// Speed a player is knocked back with after bouncing off an edge, in pixels per tick
+const BOUNCE_SPEED: i64 = 12;
+
+// What happens when a player runs into the edge of the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    // Stop at the edge
+    #[default]
+    Clamp,
+    // Reflect off the edge and get knocked back
+    Bounce,
+    // Come out at the opposite edge
+    Wrap,
+}
+
+impl Boundary {
+    // Move the body by (dx, dy), applying the edge rule on each axis
+    pub fn move_by(self, body: &mut Body, dx: i64, dy: i64) {
+        let max_x = WORLD_WIDTH.saturating_sub(body.size) as i64;
+        let max_y = WORLD_HEIGHT.saturating_sub(body.size) as i64;
+        let (x, bounce_x) = self.apply(body.x as i64 + dx, max_x);
+        let (y, bounce_y) = self.apply(body.y as i64 + dy, max_y);
+        body.x = x as u32;
+        body.y = y as u32;
+        if bounce_x != 0 {
+            body.bounce.0 = bounce_x * BOUNCE_SPEED;
+        }
+        if bounce_y != 0 {
+            body.bounce.1 = bounce_y * BOUNCE_SPEED;
+        }
+    }
+
+    // Where a coordinate ends up, and which way it bounced (-1, 1 or 0 for not at all)
+    fn apply(self, value: i64, max: i64) -> (i64, i64) {
+        match self {
+            Boundary::Clamp => (value.clamp(0, max), 0),
+            Boundary::Bounce if value < 0 => ((-value).min(max), 1),
+            Boundary::Bounce if value > max => ((2 * max - value).max(0), -1),
+            Boundary::Bounce => (value, 0),
+            Boundary::Wrap => (value.rem_euclid(max + 1), 0),
+        }
+    }
+}
+
+// Carry on a knockback from bouncing off an edge, slowing down every tick.
+// Returns whether the body moved.
+pub fn apply_knockback(body: &mut Body, boundary: Boundary) -> bool {
+    let (dx, dy) = body.bounce;
+    if (dx, dy) == (0, 0) {
+        return false;
+    }
+    body.bounce = (dx * 3 / 4, dy * 3 / 4);
+    boundary.move_by(body, dx, dy);
+    true
+}
@@ -0,0 +1,35 @@
+use crate::Body;
+
+// Whether two bodies' squares overlap
+pub fn overlaps(a: &Body, b: &Body) -> bool {
+    a.x < b.x + b.size && b.x < a.x + a.size && a.y < b.y + b.size && b.y < a.y + a.size
+}
+
+// How far to move each of two bodies so they just touch, or None if they don't
+// overlap. They're pushed along the axis they overlap least on, and the smaller
+// one gives way more, in proportion to the other's size.
+pub fn push_apart(a: &Body, b: &Body) -> Option<(i64, i64, i64, i64)> {
+    let (ax, ay, a_size) = (a.x as i64, a.y as i64, a.size as i64);
+    let (bx, by, b_size) = (b.x as i64, b.y as i64, b.size as i64);
+    let overlap_x = (ax + a_size).min(bx + b_size) - ax.max(bx);
+    let overlap_y = (ay + a_size).min(by + b_size) - ay.max(by);
+    if overlap_x <= 0 || overlap_y <= 0 {
+        return None;
+    }
+
+    // a's share of the push; b takes the rest so the overlap is fully resolved
+    let total = (a_size + b_size).max(1);
+    let split = |overlap: i64| {
+        let a_share = (overlap * b_size + total / 2) / total;
+        (a_share, overlap - a_share)
+    };
+    if overlap_x <= overlap_y {
+        let (a_share, b_share) = split(overlap_x);
+        let direction = if ax + a_size / 2 <= bx + b_size / 2 { -1 } else { 1 };
+        Some((direction * a_share, 0, -direction * b_share, 0))
+    } else {
+        let (a_share, b_share) = split(overlap_y);
+        let direction = if ay + a_size / 2 <= by + b_size / 2 { -1 } else { 1 };
+        Some((0, direction * a_share, 0, -direction * b_share))
+    }
+}
@@ -0,0 +1,46 @@
+use core::time::Duration;
+
+use crate::boundary::Boundary;
+use crate::{key_offsets, Body, WORLD_HEIGHT, WORLD_WIDTH};
+
+// Ticks between two steps of a player on a grid of `cell_size`, so it covers
+// ground about as fast as at `speed` pixels per tick without a grid
+pub fn ticks_per_step(cell_size: u32, speed: u32) -> u32 {
+    cell_size.div_ceil(speed.max(1)).max(1)
+}
+
+pub fn step_interval(cell_size: u32, speed: u32, tick_interval: Duration) -> Duration {
+    tick_interval * ticks_per_step(cell_size, speed)
+}
+
+// Move the body one cell in the held directions. Stepping off the grid wraps
+// around with Boundary::Wrap and goes nowhere otherwise.
+pub fn step(body: &mut Body, keys: u8, cell_size: u32, boundary: Boundary) {
+    let (max_x, max_y) = last_cell(body, cell_size);
+    let (dx, dy) = key_offsets(keys);
+    let step_axis = |value: u32, direction: i64, max: u32| {
+        let next = value as i64 + direction * cell_size as i64;
+        match boundary {
+            Boundary::Wrap if next < 0 => max,
+            Boundary::Wrap if next > max as i64 => 0,
+            _ => next.clamp(0, max as i64) as u32,
+        }
+    };
+    body.x = step_axis(body.x, dx, max_x);
+    body.y = step_axis(body.y, dy, max_y);
+}
+
+// Put the body on the nearest cell, like after spawning or a teleport
+pub fn snap(body: &mut Body, cell_size: u32) {
+    let (max_x, max_y) = last_cell(body, cell_size);
+    let nearest = |value: u32| (value + cell_size / 2) / cell_size * cell_size;
+    body.x = nearest(body.x).min(max_x);
+    body.y = nearest(body.y).min(max_y);
+}
+
+// Top left corner of the last cell the body still fits into
+fn last_cell(body: &Body, cell_size: u32) -> (u32, u32) {
+    let max_x = WORLD_WIDTH.saturating_sub(body.size) / cell_size * cell_size;
+    let max_y = WORLD_HEIGHT.saturating_sub(body.size) / cell_size * cell_size;
+    (max_x, max_y)
+}
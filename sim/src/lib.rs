@@ -0,0 +1,93 @@
+// Movement and collision rules shared by the server and the client, so the
+// client can predict its own player with the same code the server runs. No
+// std, no allocation and no floats, so both ends get the same numbers.
+#![no_std]
+
+use serde::{Deserialize, Serialize};
+
+pub mod boundary;
+pub mod collision;
+pub mod grid;
+
+pub use boundary::{apply_knockback, Boundary};
+
+// Size of the world in pixels
+pub const WORLD_WIDTH: u32 = 800;
+pub const WORLD_HEIGHT: u32 = 600;
+// Side of a player's square when it joins
+pub const PLAYER_SIZE: u32 = 50;
+
+// Bits of the keys a player holds down
+pub const KEY_UP: u8 = 1;
+pub const KEY_LEFT: u8 = 2;
+pub const KEY_DOWN: u8 = 4;
+pub const KEY_RIGHT: u8 = 8;
+
+// The part of a player the simulation moves
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+    // Knockback after bouncing off an edge, in pixels per tick
+    pub bounce: (i64, i64),
+}
+
+// How fast players move, sent to clients so they can predict it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Movement {
+    // Pixels per tick at PLAYER_SIZE, before speed boosts
+    pub speed: u32,
+    pub tick_ms: u32,
+}
+
+// The x and y direction (-1, 0 or 1) the held keys point in
+pub fn key_offsets(keys: u8) -> (i64, i64) {
+    let axis = |negative: u8, positive: u8| (keys & positive != 0) as i64 - (keys & negative != 0) as i64;
+    (axis(KEY_LEFT, KEY_RIGHT), axis(KEY_UP, KEY_DOWN))
+}
+
+// Steps get shorter as a player grows, but never stop
+pub fn speed(speed: u32, size: u32) -> u32 {
+    (speed * PLAYER_SIZE / size.max(1)).max(1)
+}
+
+// Move a body one step in the held directions: `speed` pixels (less when it's
+// grown), or one cell on grid maps
+pub fn step(body: &mut Body, keys: u8, speed: u32, cell_size: Option<u32>, boundary: Boundary) {
+    match cell_size {
+        Some(cell_size) => grid::step(body, keys, cell_size, boundary),
+        None => {
+            let speed = self::speed(speed, body.size) as i64;
+            let (dx, dy) = key_offsets(keys);
+            boundary.move_by(body, dx * speed, dy * speed);
+        }
+    }
+}
+
+// Keep a body fully inside the world
+pub fn clamp_to_world(body: &mut Body) {
+    body.x = body.x.min(WORLD_WIDTH.saturating_sub(body.size));
+    body.y = body.y.min(WORLD_HEIGHT.saturating_sub(body.size));
+}
+
+// A fingerprint of where everyone is, to tell whether two simulations still
+// agree. Both ends have to pass the players sorted by ID.
+pub fn hash<'a>(players: impl IntoIterator<Item = (&'a str, Body)>) -> u64 {
+    // 64-bit FNV-1a
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for (id, body) in players {
+        add(id.as_bytes());
+        add(&[0]);
+        add(&body.x.to_le_bytes());
+        add(&body.y.to_le_bytes());
+        add(&body.size.to_le_bytes());
+    }
+    hash
+}